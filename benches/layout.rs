@@ -0,0 +1,270 @@
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    collections::BTreeMap,
+    hint::black_box,
+    sync::{
+        Arc, RwLock,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::Instant,
+};
+
+use criterion::{Criterion, criterion_group, criterion_main};
+use swayipc::Rect;
+
+use sway_shell::{
+    atlas::ImageAtlas,
+    config::{Config, FontConfig},
+    font::{FontContainer, FontSystem},
+    renderer::{ExtentPool, Renderable, Renderer},
+    state::State,
+    sway::Workspace,
+};
+
+/// The system allocator, counting the allocations so the benches can check
+/// that a frame like the last one doesn't allocate for its instances
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+/// How many times f allocated or reallocated
+fn allocations(f: impl FnOnce()) -> usize {
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    f();
+    ALLOCATIONS.load(Ordering::Relaxed) - before
+}
+
+/// A font container sharing the default fallback chain, the same the
+/// renderer starts out with minus the preloaded chars
+fn font() -> (FontContainer, Arc<FontSystem>) {
+    let fonts = FontSystem::from_config(&FontConfig::default()).expect("The embedded font to load");
+    (FontContainer::new("", fonts.clone()), fonts)
+}
+
+fn text(text: String) -> Vec<Renderable> {
+    vec![Renderable::Text {
+        text: text.into(),
+        fg: 0xffffffff,
+        bg: 0x00000000,
+    }]
+}
+
+/// Shapes the text of the renderables and the regions in them
+fn shape(fonts: &FontSystem, renderables: &[Renderable]) {
+    for renderable in renderables {
+        match renderable {
+            Renderable::Text { text, .. } => {
+                black_box(fonts.shape(text));
+            }
+            Renderable::Region { children, .. } => shape(fonts, children),
+            _ => {}
+        }
+    }
+}
+
+/// Lays the renderables out once so the glyphs are loaded and the vectors
+/// grown, then measures the steady state every frame after the first one is
+/// in. Shaped text is cached, so a steady frame may allocate no more than
+/// shaping the text once would
+fn bench_layout(c: &mut Criterion, name: &str, renderables: &[Renderable]) {
+    let (mut font_sdf, fonts) = font();
+    let mut images = ImageAtlas::default();
+    let mut instances = Vec::new();
+    let mut regions = Vec::new();
+    let mut pool = ExtentPool::default();
+    let mut layout = |instances: &mut Vec<_>, regions: &mut Vec<_>| {
+        instances.clear();
+        pool.reclaim(regions.drain(..));
+        Renderer::layout(
+            &mut font_sdf,
+            &fonts,
+            &mut images,
+            black_box(renderables),
+            0.,
+            instances,
+            regions,
+            &mut pool,
+            None,
+        )
+    };
+    layout(&mut instances, &mut regions);
+
+    let shaping = allocations(|| {
+        shape(&fonts, renderables);
+    });
+    let steady = allocations(|| {
+        layout(&mut instances, &mut regions);
+    });
+    assert!(
+        steady <= shaping,
+        "{name}: a steady frame allocated {steady} times, shaping alone {shaping}"
+    );
+
+    c.bench_function(name, |b| {
+        b.iter(|| black_box(layout(&mut instances, &mut regions)))
+    });
+}
+
+/// Everything but text, none of which may allocate once the instances are
+/// grown
+fn shapes(c: &mut Criterion) {
+    let boxes = (0..64)
+        .map(|i| Renderable::Box {
+            fg: 0xffffffff,
+            bg: 0x00000000,
+            width: 0.1,
+            height: i as f32 / 64.,
+            skip: 0.2,
+        })
+        .collect::<Vec<_>>();
+    let renderables = vec![
+        Renderable::Pill {
+            bg: 0xff333333,
+            radius: 0.5,
+            border: 0xffffffff,
+            border_width: 0.05,
+            children: vec![Renderable::Pulse(boxes.clone())],
+        },
+        Renderable::Space(1.),
+        Renderable::Slide {
+            bg: 0xff333333,
+            from: 0,
+            to: 63,
+            since: Instant::now(),
+            children: boxes.clone(),
+        },
+        Renderable::Bold(boxes),
+    ];
+    bench_layout(c, "layout shapes", &renderables);
+}
+
+fn long_ascii(c: &mut Criterion) {
+    let title = "The quick brown fox jumps over the lazy dog - Mozilla Firefox ".repeat(16);
+    bench_layout(c, "layout long ascii", &text(title));
+}
+
+fn mixed_scripts(c: &mut Criterion) {
+    let title = "Привет мир — 日本語のタイトル — مرحبا بالعالم — नमस्ते — ½ ≠ ∞ ".repeat(8);
+    bench_layout(c, "layout mixed scripts", &text(title));
+}
+
+/// Clickable text like the workspaces, which records its extents
+fn regions(c: &mut Criterion) {
+    let renderables = (1..=32)
+        .map(|i| Renderable::Region {
+            tooltip: None,
+            on_click: None,
+            on_scroll: None,
+            children: text(format!("{i}: workspace")),
+        })
+        .collect::<Vec<_>>();
+    bench_layout(c, "layout regions", &renderables);
+}
+
+/// Cold layout, every glyph is outlined and pushed into the curve buffers
+fn glyph_loading(c: &mut Criterion) {
+    let title = "Привет мир — 日本語のタイトル — The quick brown fox".to_string();
+    let renderables = text(title);
+    c.bench_function("layout glyph loading", |b| {
+        b.iter_batched(
+            font,
+            |(mut font_sdf, fonts)| {
+                black_box(Renderer::layout(
+                    &mut font_sdf,
+                    &fonts,
+                    &mut ImageAtlas::default(),
+                    &renderables,
+                    0.,
+                    &mut Vec::new(),
+                    &mut Vec::new(),
+                    &mut ExtentPool::default(),
+                    None,
+                ));
+            },
+            criterion::BatchSize::LargeInput,
+        )
+    });
+}
+
+fn workspace(num: i32) -> Workspace {
+    Workspace {
+        id: num as i64,
+        num,
+        name: Some(format!("{num}: workspace")),
+        layout: "splith".to_string(),
+        visible: num == 1,
+        focused: num == 1,
+        urgent: false,
+        representation: None,
+        orientation: "horizontal".to_string(),
+        rect: Rect {
+            x: 0,
+            y: 0,
+            width: 1920,
+            height: 1080,
+        },
+        output: "eDP-1".to_string(),
+        focus: vec![],
+    }
+}
+
+/// Building the state and laying out its left section, which is where the
+/// workspaces go by default
+fn many_workspaces(c: &mut Criterion) {
+    let mut state = State::new(
+        Config::default(),
+        Arc::new(RwLock::new(Vec::new())),
+        BTreeMap::new(),
+    );
+    state.workspaces = (1..=50).map(workspace).collect();
+    let (mut font_sdf, fonts) = font();
+    let mut images = ImageAtlas::default();
+    let mut instances = Vec::new();
+    c.bench_function("state and layout of 50 workspaces", |b| {
+        b.iter(|| {
+            let render_state = state.to_renderable_state();
+            instances.clear();
+            black_box(Renderer::layout(
+                &mut font_sdf,
+                &fonts,
+                &mut images,
+                &render_state.left,
+                0.,
+                &mut instances,
+                &mut Vec::new(),
+                &mut ExtentPool::default(),
+                None,
+            ));
+        })
+    });
+}
+
+criterion_group!(
+    benches,
+    shapes,
+    long_ascii,
+    mixed_scripts,
+    regions,
+    glyph_loading,
+    many_workspaces
+);
+criterion_main!(benches);
@@ -0,0 +1,9 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use sway_shell::config::Config;
+
+// A bad config is logged and replaced by the defaults, it must never panic
+fuzz_target!(|data: &str| {
+    let _ = Config::parse(data);
+});
@@ -0,0 +1,9 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use sway_shell::ipc::parse_command;
+
+// Any process of the user can write lines to the control socket
+fuzz_target!(|data: &str| {
+    let _ = parse_command(data);
+});
@@ -0,0 +1,9 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use sway_shell::markup;
+
+// Custom, watch and MQTT modules put whatever their source says through it
+fuzz_target!(|data: &str| {
+    let _ = markup::parse(data, 0xffffffff, 0xff000000);
+});
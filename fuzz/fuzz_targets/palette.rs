@@ -0,0 +1,13 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use sway_shell::palette::{display_number, evaluate, fuzzy_score};
+
+// Everything typed into the palette goes through both of these
+fuzz_target!(|data: &str| {
+    if let Some(result) = evaluate(data) {
+        display_number(result);
+    }
+    let (query, candidate) = data.split_once('\n').unwrap_or((data, data));
+    let _ = fuzzy_score(query, candidate);
+});
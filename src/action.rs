@@ -0,0 +1,154 @@
+use std::{
+    hash::{Hash, Hasher},
+    process::Command,
+    thread,
+};
+
+use serde::Deserialize;
+
+use crate::{
+    backlight, compositor, night_light::NightLightAction, palette::PaletteMode, timer::TimerAction,
+};
+
+/// Things that can happen when the user interacts with a widget, written
+/// like { spawn = "pavucontrol" } in the mouse bindings of the config
+#[derive(Debug, Clone, PartialEq, Hash, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    /// Runs the command through `sh -c` without waiting for it to exit
+    Spawn(String),
+    /// Runs the program with the arguments after it, without a shell to
+    /// interpret them, so they may come from outside the bar
+    Exec(Vec<String>),
+    /// Runs a sway command over IPC, like workspace back_and_forth. On
+    /// Hyprland those the bar uses are translated, others are sent as they are
+    SwayCommand(String),
+    /// Shows or hides a module of the bar, handled by State::run_action as
+    /// it changes the state
+    ToggleModule(String),
+    /// Changes the brightness of a /sys/class/backlight device by a percent
+    /// of its maximum
+    AdjustBrightness { device: String, percent: i32 },
+    /// Stops the low battery suspend countdown, handled by State::run_action
+    CancelSuspend,
+    /// Moves the CPU entry of the thermal module on to its next view,
+    /// handled by State::run_action
+    CycleCpuView,
+    /// Opens the palette in the mode or closes it, handled by
+    /// State::run_action
+    TogglePalette(PaletteMode),
+    /// Closes the palette whatever mode it is in, handled by
+    /// State::run_action
+    ClosePalette,
+    /// Opens a menu of the entries of the palette mode below the click, or
+    /// closes it, handled by State::run_action
+    ToggleMenu(PaletteMode),
+    /// Closes the menu, handled by State::run_action
+    CloseMenu,
+    /// Focuses the workspace this many after the focused one, handled by
+    /// State::run_action as it goes by the workspaces of the state
+    CycleWorkspace(i32),
+    /// Starts or stops capturing the default source for the level meter,
+    /// handled by State::run_action
+    ToggleMicMeter,
+    /// Asks in the palette before running the action, handled by
+    /// State::run_action
+    Confirm { label: String, action: Box<Action> },
+    /// Starts, pauses, resets or adjusts the timer module, handled by
+    /// State::run_action
+    Timer(TimerAction),
+    /// Toggles the night light or changes its temperature, handled by
+    /// State::run_action
+    NightLight(NightLightAction),
+    /// Left click on a module from another crate by its name, handled by
+    /// State::run_action as the state has the module
+    #[serde(skip)]
+    ModuleClick(String),
+}
+
+/// What the buttons do on a module, in place of what they do on their own
+#[derive(Debug, Clone, Default, PartialEq, Hash, Deserialize)]
+#[serde(default)]
+pub struct MouseBindings {
+    pub left: Option<Action>,
+    pub middle: Option<Action>,
+    pub right: Option<Action>,
+}
+
+impl MouseBindings {
+    /// These bindings, with the buttons they leave unbound taken from defaults
+    pub fn or(self, defaults: MouseBindings) -> Self {
+        Self {
+            left: self.left.or(defaults.left),
+            middle: self.middle.or(defaults.middle),
+            right: self.right.or(defaults.right),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.left.is_none() && self.middle.is_none() && self.right.is_none()
+    }
+}
+
+/// What scrolling over a widget does, run once per scroll step
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScrollActions {
+    pub up: Action,
+    pub down: Action,
+    /// Multiplies touchpad scroll distances, mouse wheels always take a
+    /// step per notch
+    pub sensitivity: f32,
+}
+
+impl Hash for ScrollActions {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (&self.up, &self.down, self.sensitivity.to_bits()).hash(state)
+    }
+}
+
+impl Action {
+    pub fn run(&self) {
+        match self {
+            Action::Spawn(command) => {
+                match Command::new("sh").arg("-c").arg(command).spawn() {
+                    // Reap the child in the background so it doesn't stay a zombie
+                    Ok(mut child) => {
+                        thread::spawn(move || child.wait());
+                    }
+                    Err(e) => log::error!("Could not spawn {command:?}: {e}"),
+                }
+            }
+            Action::Exec(argv) => {
+                let Some((program, args)) = argv.split_first() else {
+                    log::error!("Tried running an empty command, ignoring it");
+                    return;
+                };
+                match Command::new(program).args(args).spawn() {
+                    Ok(mut child) => {
+                        thread::spawn(move || child.wait());
+                    }
+                    Err(e) => log::error!("Could not spawn {argv:?}: {e}"),
+                }
+            }
+            Action::SwayCommand(command) => compositor::run_command(command.clone()),
+            Action::AdjustBrightness { device, percent } => {
+                backlight::adjust_brightness(device, *percent);
+            }
+            Action::ToggleModule(_)
+            | Action::CancelSuspend
+            | Action::CycleCpuView
+            | Action::TogglePalette(_)
+            | Action::ClosePalette
+            | Action::ToggleMenu(_)
+            | Action::CloseMenu
+            | Action::CycleWorkspace(_)
+            | Action::ToggleMicMeter
+            | Action::Confirm { .. }
+            | Action::Timer(_)
+            | Action::NightLight(_)
+            | Action::ModuleClick(_) => {
+                log::error!("Tried running {self:?} outside of the state, ignoring it")
+            }
+        }
+    }
+}
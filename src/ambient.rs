@@ -0,0 +1,444 @@
+use std::{collections::BTreeMap, time::Duration};
+
+use smithay_client_toolkit::{
+    delegate_shm,
+    shm::{
+        CreatePoolError, Shm, ShmHandler,
+        slot::{CreateBufferError, SlotPool},
+    },
+};
+use tokio::{
+    runtime::Handle,
+    sync::{
+        mpsc::{Sender, channel, error::SendError},
+        watch,
+    },
+};
+use tokio_stream::wrappers::ReceiverStream;
+use wayland_client::{
+    ConnectError, Connection, Dispatch, DispatchError, EventQueue, Proxy, QueueHandle, WEnum,
+    delegate_noop,
+    globals::{BindError, GlobalError, GlobalListContents, registry_queue_init},
+    protocol::{
+        wl_output::{self, WlOutput},
+        wl_registry::{self, WlRegistry},
+        wl_shm,
+    },
+};
+use wayland_protocols_wlr::screencopy::v1::client::{
+    zwlr_screencopy_frame_v1::{self, ZwlrScreencopyFrameV1},
+    zwlr_screencopy_manager_v1::ZwlrScreencopyManagerV1,
+};
+
+use crate::{
+    config::{AmbientConfig, BarPosition},
+    layer::BAR_HEIGHT,
+    state::Message,
+    supervisor::{Backoff, sleep_unless_resumed, supervise_blocking},
+    theme::Color,
+};
+
+/// Logical pixels of the strip next to the bar that is sampled
+const STRIP: i32 = 4;
+
+/// Only every this many pixels of the strip go into the average, along and
+/// across it
+const SAMPLE_STEP: usize = 4;
+
+#[derive(Debug)]
+pub enum AmbientError {
+    ConnectError(ConnectError),
+    GlobalError(GlobalError),
+    BindError(BindError),
+    DispatchError(DispatchError),
+    CreatePoolError(CreatePoolError),
+    CreateBufferError(CreateBufferError),
+    SendError(SendError<Message>),
+    /// The compositor has no wlr-screencopy
+    Unsupported,
+    /// There is no output, or none by the name in the config
+    NoOutput,
+}
+
+impl std::fmt::Display for AmbientError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AmbientError::ConnectError(e) => write!(f, "Could not connect to the compositor: {e}"),
+            AmbientError::GlobalError(e) => write!(f, "Could not list the globals: {e}"),
+            AmbientError::BindError(e) => write!(f, "Could not bind a global: {e}"),
+            AmbientError::DispatchError(e) => write!(f, "Wayland dispatch error: {e}"),
+            AmbientError::CreatePoolError(e) => write!(f, "Could not create the shm pool: {e}"),
+            AmbientError::CreateBufferError(e) => {
+                write!(f, "Could not create the screencopy buffer: {e}")
+            }
+            AmbientError::SendError(e) => write!(f, "Channel Error: {e}"),
+            AmbientError::Unsupported => write!(f, "The compositor has no wlr-screencopy"),
+            AmbientError::NoOutput => write!(f, "No output to sample"),
+        }
+    }
+}
+
+impl From<ConnectError> for AmbientError {
+    fn from(value: ConnectError) -> Self {
+        Self::ConnectError(value)
+    }
+}
+
+impl From<GlobalError> for AmbientError {
+    fn from(value: GlobalError) -> Self {
+        Self::GlobalError(value)
+    }
+}
+
+impl From<BindError> for AmbientError {
+    fn from(value: BindError) -> Self {
+        Self::BindError(value)
+    }
+}
+
+impl From<DispatchError> for AmbientError {
+    fn from(value: DispatchError) -> Self {
+        Self::DispatchError(value)
+    }
+}
+
+impl From<CreatePoolError> for AmbientError {
+    fn from(value: CreatePoolError) -> Self {
+        Self::CreatePoolError(value)
+    }
+}
+
+impl From<CreateBufferError> for AmbientError {
+    fn from(value: CreateBufferError) -> Self {
+        Self::CreateBufferError(value)
+    }
+}
+
+impl From<SendError<Message>> for AmbientError {
+    fn from(value: SendError<Message>) -> Self {
+        Self::SendError(value)
+    }
+}
+
+#[derive(Debug)]
+pub enum AmbientMessage {
+    /// The smoothed color of what is next to the bar
+    Color(Color),
+}
+
+#[derive(Debug)]
+struct Output {
+    proxy: WlOutput,
+    name: String,
+    /// Of the current mode, in pixels
+    width: i32,
+    height: i32,
+    scale: i32,
+}
+
+/// Where a capture is at
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Frame {
+    /// Waiting for the compositor to say what buffer it copies into
+    Pending,
+    Buffer {
+        format: WEnum<wl_shm::Format>,
+        width: u32,
+        height: u32,
+        stride: u32,
+    },
+    Copying,
+    Ready,
+    Failed,
+}
+
+struct Ambient {
+    shm: Shm,
+    outputs: BTreeMap<u32, Output>,
+    frame: Frame,
+}
+
+impl Ambient {
+    fn bind_output(
+        &mut self,
+        registry: &WlRegistry,
+        name: u32,
+        version: u32,
+        qh: &QueueHandle<Self>,
+    ) {
+        // Version 4 brings the name
+        let proxy = registry.bind::<WlOutput, _, _>(name, version.min(4), qh, name);
+        self.outputs.insert(
+            name,
+            Output {
+                proxy,
+                name: String::new(),
+                width: 0,
+                height: 0,
+                scale: 1,
+            },
+        );
+    }
+
+    fn output(&self, name: Option<&str>) -> Option<&Output> {
+        self.outputs
+            .values()
+            .find(|output| name.is_none_or(|name| output.name == name))
+    }
+}
+
+/// The strip of the output next to the bar, in logical pixels. The size
+/// in logical pixels is taken as the mode over the integer scale, which
+/// stays on the output under fractional scales
+fn region(output: &Output, position: BarPosition) -> (i32, i32, i32, i32) {
+    let scale = output.scale.max(1);
+    let (width, height) = (output.width / scale, output.height / scale);
+    let bar = BAR_HEIGHT as i32;
+    match position {
+        BarPosition::Top => (0, bar, width, STRIP),
+        BarPosition::Left => (bar, 0, STRIP, height),
+        BarPosition::Right => ((width - bar - STRIP).max(0), 0, STRIP, height),
+    }
+}
+
+/// The average color of a captured frame, None for pixel formats other than
+/// the 8 bit ones compositors copy into
+fn average(
+    canvas: &[u8],
+    format: wl_shm::Format,
+    width: u32,
+    height: u32,
+    stride: u32,
+) -> Option<[f32; 3]> {
+    let bgr = match format {
+        wl_shm::Format::Xrgb8888 | wl_shm::Format::Argb8888 => true,
+        wl_shm::Format::Xbgr8888 | wl_shm::Format::Abgr8888 => false,
+        _ => return None,
+    };
+    let mut sum = [0.; 3];
+    let mut count = 0;
+    for y in (0..height as usize).step_by(SAMPLE_STEP) {
+        for x in (0..width as usize).step_by(SAMPLE_STEP) {
+            let offset = y * stride as usize + x * 4;
+            let Some(&[first, green, third, _]) = canvas.get(offset..offset + 4) else {
+                continue;
+            };
+            let (red, blue) = if bgr { (third, first) } else { (first, third) };
+            sum[0] += red as f32;
+            sum[1] += green as f32;
+            sum[2] += blue as f32;
+            count += 1;
+        }
+    }
+    (count > 0).then(|| sum.map(|channel| channel / count as f32))
+}
+
+/// Copies the strip next to the bar and averages it, None when the
+/// compositor could not copy it
+fn sample(
+    ambient: &mut Ambient,
+    event_queue: &mut EventQueue<Ambient>,
+    manager: &ZwlrScreencopyManagerV1,
+    pool: &mut SlotPool,
+    config: &AmbientConfig,
+    position: BarPosition,
+) -> Result<Option<[f32; 3]>, AmbientError> {
+    let qh = event_queue.handle();
+    let output = ambient
+        .output(config.output.as_deref())
+        .ok_or(AmbientError::NoOutput)?;
+    let (x, y, width, height) = region(output, position);
+    let frame = manager.capture_output_region(0, &output.proxy, x, y, width, height, &qh, ());
+    ambient.frame = Frame::Pending;
+    while ambient.frame == Frame::Pending {
+        event_queue.blocking_dispatch(ambient)?;
+    }
+    let Frame::Buffer {
+        format: WEnum::Value(format),
+        width,
+        height,
+        stride,
+    } = ambient.frame
+    else {
+        frame.destroy();
+        return Ok(None);
+    };
+    let (buffer, _) = pool.create_buffer(width as i32, height as i32, stride as i32, format)?;
+    ambient.frame = Frame::Copying;
+    frame.copy(buffer.wl_buffer());
+    while ambient.frame == Frame::Copying {
+        event_queue.blocking_dispatch(ambient)?;
+    }
+    frame.destroy();
+    if ambient.frame != Frame::Ready {
+        return Ok(None);
+    }
+    Ok(buffer
+        .canvas(pool)
+        .and_then(|canvas| average(canvas, format, width, height, stride)))
+}
+
+fn ambient_generator(
+    sender: &Sender<Message>,
+    config: &AmbientConfig,
+    position: BarPosition,
+    resumes: &mut watch::Receiver<()>,
+) -> Result<(), AmbientError> {
+    let conn = Connection::connect_to_env()?;
+    let (globals, mut event_queue) = registry_queue_init::<Ambient>(&conn)?;
+    let qh = event_queue.handle();
+    let manager = globals
+        .bind::<ZwlrScreencopyManagerV1, _, _>(&qh, 1..=1, ())
+        .map_err(|_| AmbientError::Unsupported)?;
+    let mut ambient = Ambient {
+        shm: Shm::bind(&globals, &qh)?,
+        outputs: BTreeMap::new(),
+        frame: Frame::Pending,
+    };
+    globals.contents().with_list(|list| {
+        for global in list {
+            if global.interface == WlOutput::interface().name {
+                ambient.bind_output(globals.registry(), global.name, global.version, &qh);
+            }
+        }
+    });
+    event_queue.roundtrip(&mut ambient)?;
+    let mut pool = SlotPool::new(4096, &ambient.shm)?;
+    let smoothing = config.smoothing.clamp(0., 0.99);
+    let mut smoothed: Option<[f32; 3]> = None;
+    let mut sent = None;
+    loop {
+        if let Some(color) = sample(
+            &mut ambient,
+            &mut event_queue,
+            &manager,
+            &mut pool,
+            config,
+            position,
+        )? {
+            let color = match smoothed {
+                Some(old) => [0, 1, 2].map(|i| old[i] * smoothing + color[i] * (1. - smoothing)),
+                None => color,
+            };
+            smoothed = Some(color);
+            let [red, green, blue] = color.map(|channel| channel.round() as u8);
+            let color = Color::rgba(red, green, blue, 0xff);
+            if sent != Some(color) {
+                sender.blocking_send(Message::Ambient(AmbientMessage::Color(color)))?;
+                sent = Some(color);
+            }
+        }
+        sleep_unless_resumed(resumes, Duration::from_secs(config.interval.max(1)));
+    }
+}
+
+/// Samples the strip of the output next to the bar every interval, nothing
+/// at all unless enabled in the config
+pub fn ambient_subscription(
+    rt: Handle,
+    config: AmbientConfig,
+    position: BarPosition,
+    mut resumes: watch::Receiver<()>,
+) -> ReceiverStream<Message> {
+    let (sender, receiver) = channel(1);
+    if !config.enabled {
+        return ReceiverStream::new(receiver);
+    }
+    supervise_blocking(&rt, "ambient", Backoff::DEFAULT, sender, move |sender| {
+        ambient_generator(&sender, &config, position, &mut resumes)
+    });
+    ReceiverStream::new(receiver)
+}
+
+impl Dispatch<WlRegistry, GlobalListContents> for Ambient {
+    fn event(
+        state: &mut Self,
+        registry: &WlRegistry,
+        event: wl_registry::Event,
+        _: &GlobalListContents,
+        _: &Connection,
+        qh: &QueueHandle<Self>,
+    ) {
+        match event {
+            wl_registry::Event::Global {
+                name,
+                interface,
+                version,
+            } if interface == WlOutput::interface().name => {
+                state.bind_output(registry, name, version, qh);
+            }
+            wl_registry::Event::GlobalRemove { name } => {
+                state.outputs.remove(&name);
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<WlOutput, u32> for Ambient {
+    fn event(
+        state: &mut Self,
+        _: &WlOutput,
+        event: wl_output::Event,
+        name: &u32,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        let Some(output) = state.outputs.get_mut(name) else {
+            return;
+        };
+        match event {
+            wl_output::Event::Mode {
+                flags: WEnum::Value(flags),
+                width,
+                height,
+                ..
+            } if flags.contains(wl_output::Mode::Current) => {
+                output.width = width;
+                output.height = height;
+            }
+            wl_output::Event::Scale { factor } => output.scale = factor,
+            wl_output::Event::Name { name } => output.name = name,
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<ZwlrScreencopyFrameV1, ()> for Ambient {
+    fn event(
+        state: &mut Self,
+        _: &ZwlrScreencopyFrameV1,
+        event: zwlr_screencopy_frame_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        match event {
+            zwlr_screencopy_frame_v1::Event::Buffer {
+                format,
+                width,
+                height,
+                stride,
+            } => {
+                state.frame = Frame::Buffer {
+                    format,
+                    width,
+                    height,
+                    stride,
+                };
+            }
+            zwlr_screencopy_frame_v1::Event::Ready { .. } => state.frame = Frame::Ready,
+            zwlr_screencopy_frame_v1::Event::Failed => state.frame = Frame::Failed,
+            _ => {}
+        }
+    }
+}
+
+impl ShmHandler for Ambient {
+    fn shm_state(&mut self) -> &mut Shm {
+        &mut self.shm
+    }
+}
+
+delegate_noop!(Ambient: ignore ZwlrScreencopyManagerV1);
+delegate_shm!(Ambient);
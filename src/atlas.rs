@@ -0,0 +1,112 @@
+use std::{
+    hash::{DefaultHasher, Hash, Hasher},
+    sync::Arc,
+};
+
+use image::imageops::FilterType;
+
+/// Side in pixels of the square every image is scaled to, plenty for a
+/// thumbnail a bar height tall on high DPI outputs
+pub const IMAGE_SIZE: u32 = 64;
+
+/// Images along each side of the atlas texture
+pub const ATLAS_SLOTS_PER_SIDE: u32 = 4;
+
+/// Side in pixels of the atlas texture
+pub const ATLAS_SIZE: u32 = IMAGE_SIZE * ATLAS_SLOTS_PER_SIDE;
+
+/// Bytes the atlas takes up, whatever images are in it
+pub const ATLAS_BYTES: usize = (ATLAS_SIZE * ATLAS_SIZE * 4) as usize;
+
+/// An RGBA image of IMAGE_SIZE by IMAGE_SIZE pixels, like an album cover
+#[derive(Debug)]
+pub struct Image {
+    /// Hash of the pixels, so the same image sent again isn't uploaded again
+    pub id: u64,
+    pub pixels: Vec<u8>,
+}
+
+impl Image {
+    pub fn new(pixels: Vec<u8>) -> Self {
+        let mut hasher = DefaultHasher::new();
+        pixels.hash(&mut hasher);
+        Self {
+            id: hasher.finish(),
+            pixels,
+        }
+    }
+
+    /// Decodes a JPEG or PNG, cropping it to a square from the center
+    pub fn decode(data: &[u8]) -> Result<Self, image::ImageError> {
+        let image = image::load_from_memory(data)?
+            .resize_to_fill(IMAGE_SIZE, IMAGE_SIZE, FilterType::Triangle)
+            .into_rgba8();
+        Ok(Self::new(image.into_raw()))
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Slot {
+    id: u64,
+    /// Frame the image was last laid out in
+    last_used: u64,
+}
+
+/// Which image is in which slot of the atlas texture. Like the glyphs of
+/// FontContainer, images are loaded during layout and uploading them is up
+/// to the renderer
+#[derive(Debug, Default)]
+pub struct ImageAtlas {
+    slots: Vec<Slot>,
+    frame: u64,
+    /// Slots whose image changed since the last upload
+    pending: Vec<(u32, Arc<Image>)>,
+}
+
+impl ImageAtlas {
+    pub fn begin_frame(&mut self) {
+        self.frame += 1;
+    }
+
+    /// The slot holding the image, putting it in the least recently used
+    /// slot once the atlas is full
+    pub fn load(&mut self, image: &Arc<Image>) -> u32 {
+        let frame = self.frame;
+        if let Some(index) = self.slots.iter().position(|slot| slot.id == image.id) {
+            self.slots[index].last_used = frame;
+            return index as u32;
+        }
+        let slot = Slot {
+            id: image.id,
+            last_used: frame,
+        };
+        let index = if self.slots.len() < (ATLAS_SLOTS_PER_SIDE * ATLAS_SLOTS_PER_SIDE) as usize {
+            self.slots.push(slot);
+            self.slots.len() - 1
+        } else {
+            let (index, _) = self
+                .slots
+                .iter()
+                .enumerate()
+                .min_by_key(|(_, slot)| slot.last_used)
+                .expect("The atlas to have slots");
+            self.slots[index] = slot;
+            index
+        };
+        self.pending.push((index as u32, image.clone()));
+        index as u32
+    }
+
+    /// Images to upload, with the slot each goes in
+    pub fn take_pending(&mut self) -> Vec<(u32, Arc<Image>)> {
+        std::mem::take(&mut self.pending)
+    }
+
+    /// Top left pixel of the slot in the atlas texture
+    pub fn slot_origin(slot: u32) -> (u32, u32) {
+        (
+            (slot % ATLAS_SLOTS_PER_SIDE) * IMAGE_SIZE,
+            (slot / ATLAS_SLOTS_PER_SIDE) * IMAGE_SIZE,
+        )
+    }
+}
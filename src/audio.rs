@@ -1,5 +1,5 @@
 use std::cell::RefCell;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::rc::Rc;
 
 use libspa::pod::deserialize::PodDeserializer;
@@ -7,6 +7,7 @@ use libspa::utils::Id;
 use pipewire;
 
 use pipewire::context::ContextRc;
+use pipewire::device::Device;
 use pipewire::main_loop::MainLoopRc;
 use pipewire::metadata::Metadata;
 use pipewire::node::Node;
@@ -16,15 +17,28 @@ use pipewire::spa::param::ParamType;
 use libspa::pod::{Pod, Value, ValueArray};
 use pipewire::proxy::ProxyListener;
 use tokio::runtime::Handle;
-use tokio::sync::mpsc::{Sender, channel};
+use tokio::sync::mpsc::{Receiver, Sender, channel};
+use tokio::time::MissedTickBehavior;
 
-use crate::state::Message;
+use crate::action::Action;
+use crate::renderer::Renderable;
+use crate::state::{Message, State};
+use crate::supervisor::{Backoff, supervise_blocking};
+use crate::widget::Widget;
 
 #[derive(Debug)]
-enum AudioError {
+pub enum AudioError {
     PipewireError(pipewire::Error),
 }
 
+impl std::fmt::Display for AudioError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AudioError::PipewireError(e) => write!(f, "PipeWire Error: {e}"),
+        }
+    }
+}
+
 impl From<pipewire::Error> for AudioError {
     fn from(value: pipewire::Error) -> Self {
         Self::PipewireError(value)
@@ -33,14 +47,219 @@ impl From<pipewire::Error> for AudioError {
 
 #[derive(Debug, Clone, Default)]
 pub struct AudioState {
-    pub sink_volume: Vec<f32>,
+    /// Description of the default sink, or its node name without one
+    pub default_sink_name: Option<String>,
+    /// Channel volumes of the default sink, linear
+    pub volume: Vec<f32>,
+    pub muted: bool,
     pub source_volume: Vec<f32>,
+    pub bluetooth_devices: Vec<BluetoothDevice>,
+    /// Pipewire global id of the card the default sink is on
+    pub default_sink_device: Option<u32>,
+    /// Description of the port the sound currently comes out of on each card
+    /// by its global id, e.g. Headphones
+    pub output_routes: BTreeMap<u32, String>,
+    /// Shown briefly after a jack is plugged in or out
+    pub jack_osd: Option<(String, std::time::Instant)>,
 }
 
 #[derive(Debug)]
 pub enum AudioMessage {
+    /// The default sink changed, or was renamed or removed. Its volume and
+    /// mute state follow
+    DefaultSink(Option<String>),
+    /// Channel volumes of the default sink
     SinkVolume(Vec<f32>),
+    SinkMute(bool),
+    /// The card of the default sink, None for sinks without one
+    DefaultSinkDevice(Option<u32>),
     SourceVolume(Vec<f32>),
+    BluetoothDevice(BluetoothDevice),
+    /// Sent for every removed global, not only bluetooth devices
+    GlobalRemoved(u32),
+    JackChanged { description: String, connected: bool },
+    /// Sent JACK_OSD_DURATION after every JackChanged
+    JackOsdExpired,
+    OutputRoute { device: u32, description: String },
+}
+
+/// How long the jack OSD stays on the bar
+pub const JACK_OSD_DURATION: std::time::Duration = std::time::Duration::from_secs(2);
+
+#[derive(Debug, Clone, Default)]
+pub struct BluetoothDevice {
+    /// Pipewire global id, what wpctl expects
+    pub id: u32,
+    pub description: String,
+    pub profiles: Vec<DeviceProfile>,
+    /// Index of the active profile
+    pub active: Option<i32>,
+}
+
+#[derive(Debug, Clone)]
+pub struct DeviceProfile {
+    pub index: i32,
+    /// e.g. a2dp-sink-aac, headset-head-unit-msbc, off
+    pub name: String,
+    pub description: String,
+}
+
+impl BluetoothDevice {
+    pub fn active_profile(&self) -> Option<&DeviceProfile> {
+        self.profiles.iter().find(|v| Some(v.index) == self.active)
+    }
+
+    /// The profile to switch to when toggling between call and music quality,
+    /// the best codec is listed first by pipewire
+    pub fn toggled_profile(&self) -> Option<&DeviceProfile> {
+        let target = match self.active_profile() {
+            Some(profile) if profile.name.starts_with("a2dp-sink") => "headset-head-unit",
+            _ => "a2dp-sink",
+        };
+        self.profiles.iter().find(|v| v.name.starts_with(target))
+    }
+}
+
+/// SPA_PARAM_PROFILE_index, SPA_PARAM_PROFILE_name and
+/// SPA_PARAM_PROFILE_description
+const PROFILE_INDEX: u32 = 1;
+const PROFILE_NAME: u32 = 2;
+const PROFILE_DESCRIPTION: u32 = 3;
+
+fn deserialize_prop(pod: &Pod, key: u32) -> Option<Value> {
+    let prop = pod.as_object().ok()?.find_prop(Id(key))?;
+    PodDeserializer::deserialize_from::<Value>(prop.value().as_bytes())
+        .ok()
+        .map(|(_, value)| value)
+}
+
+/// SPA_PARAM_ROUTE_index, SPA_PARAM_ROUTE_direction,
+/// SPA_PARAM_ROUTE_description and SPA_PARAM_ROUTE_available
+const ROUTE_INDEX: u32 = 1;
+const ROUTE_DIRECTION: u32 = 2;
+const ROUTE_DESCRIPTION: u32 = 5;
+const ROUTE_AVAILABLE: u32 = 7;
+
+/// A port on a card, e.g. the headphone jack or the internal speakers
+struct Route {
+    index: i32,
+    output: bool,
+    description: String,
+    /// None when the card has no jack detection for this port
+    available: Option<bool>,
+}
+
+fn parse_route(pod: &Pod) -> Option<Route> {
+    let index = match deserialize_prop(pod, ROUTE_INDEX)? {
+        Value::Int(index) => index,
+        _ => return None,
+    };
+    // SPA_DIRECTION_OUTPUT
+    let output = matches!(deserialize_prop(pod, ROUTE_DIRECTION)?, Value::Id(Id(1)));
+    let description = match deserialize_prop(pod, ROUTE_DESCRIPTION)? {
+        Value::String(description) => description,
+        _ => return None,
+    };
+    // SPA_PARAM_AVAILABILITY_no and SPA_PARAM_AVAILABILITY_yes
+    let available = match deserialize_prop(pod, ROUTE_AVAILABLE) {
+        Some(Value::Id(Id(1))) => Some(false),
+        Some(Value::Id(Id(2))) => Some(true),
+        _ => None,
+    };
+    Some(Route {
+        index,
+        output,
+        description,
+        available,
+    })
+}
+
+fn parse_profile(pod: &Pod) -> Option<DeviceProfile> {
+    let index = match deserialize_prop(pod, PROFILE_INDEX)? {
+        Value::Int(index) => index,
+        _ => return None,
+    };
+    let name = match deserialize_prop(pod, PROFILE_NAME)? {
+        Value::String(name) => name,
+        _ => return None,
+    };
+    let description = match deserialize_prop(pod, PROFILE_DESCRIPTION) {
+        Some(Value::String(description)) => description,
+        _ => name.clone(),
+    };
+    Some(DeviceProfile {
+        index,
+        name,
+        description,
+    })
+}
+
+/// SPA_PROP_mute and SPA_PROP_channelVolumes
+const PROP_MUTE: u32 = 0x10004;
+const PROP_CHANNEL_VOLUMES: u32 = 0x10008;
+
+/// Channel volumes and mute state out of a Props param, either can be
+/// missing when only the other one changed
+fn parse_props(pod: &Pod) -> (Option<Vec<f32>>, Option<bool>) {
+    let volume = match deserialize_prop(pod, PROP_CHANNEL_VOLUMES) {
+        Some(Value::ValueArray(ValueArray::Float(volume))) => Some(volume),
+        _ => None,
+    };
+    let muted = match deserialize_prop(pod, PROP_MUTE) {
+        Some(Value::Bool(muted)) => Some(muted),
+        _ => None,
+    };
+    (volume, muted)
+}
+
+#[derive(Debug, Clone, Default)]
+struct Sink {
+    /// node.name, what the metadata names the default sink by
+    name: String,
+    description: Option<String>,
+    /// device.id, the card the sink is on
+    device: Option<u32>,
+    volume: Vec<f32>,
+    muted: bool,
+}
+
+/// Every audio sink by its global id, so the one named default by the
+/// metadata can be looked up whichever of the two shows up first
+#[derive(Debug, Default)]
+struct Sinks {
+    sinks: HashMap<u32, Sink>,
+    default_name: Option<String>,
+}
+
+impl Sinks {
+    fn is_default(&self, id: u32) -> bool {
+        self.sinks
+            .get(&id)
+            .is_some_and(|sink| Some(&sink.name) == self.default_name.as_ref())
+    }
+
+    fn default_sink(&self) -> Option<&Sink> {
+        let name = self.default_name.as_ref()?;
+        self.sinks.values().find(|sink| &sink.name == name)
+    }
+
+    /// Everything the bar shows of the default sink
+    fn send_default(&self, output: &Sender<Message>) {
+        let sink = self.default_sink();
+        let messages = [
+            AudioMessage::DefaultSink(
+                sink.map(|sink| sink.description.clone().unwrap_or_else(|| sink.name.clone())),
+            ),
+            AudioMessage::SinkVolume(sink.map(|sink| sink.volume.clone()).unwrap_or_default()),
+            AudioMessage::SinkMute(sink.is_some_and(|sink| sink.muted)),
+            AudioMessage::DefaultSinkDevice(sink.and_then(|sink| sink.device)),
+        ];
+        for message in messages {
+            if let Err(e) = output.blocking_send(Message::Audio(message)) {
+                log::error!("Audio Error: {:?}", e);
+            }
+        }
+    }
 }
 
 struct Proxies {
@@ -100,8 +319,12 @@ fn audio_generator(output: Sender<Message>, _rt: Handle) -> Result<(), AudioErro
     let registry = core.get_registry_rc()?;
     let registry_weak = registry.downgrade();
     let proxies = Rc::new(RefCell::new(Proxies::new()));
-    let default_sink = Rc::new(RefCell::new(None));
-    let default_sink_id = Rc::new(RefCell::new(None));
+    let sinks = Rc::new(RefCell::new(Sinks::default()));
+    let remove_sinks = sinks.clone();
+    let remove_output = output.clone();
+    // The devices the state keeps profiles and routes of, by global id
+    let devices = Rc::new(RefCell::new(HashSet::new()));
+    let remove_devices = devices.clone();
     let _listener = registry
         .add_listener_local()
         .global(move |global| {
@@ -109,95 +332,213 @@ fn audio_generator(output: Sender<Message>, _rt: Handle) -> Result<(), AudioErro
                 use pipewire::types::ObjectType;
                 let p: Option<(Rc<dyn ProxyT>, Rc<dyn Listener>)> = match global.type_ {
                     ObjectType::Node => {
-                        let node: Node = registry.bind(global).unwrap();
+                        let media_class = global.props.and_then(|props| props.get("media.class"));
+                        if media_class != Some("Audio/Sink") {
+                            return;
+                        }
+                        let id = global.id;
+                        // Gone again by the time it is bound, or otherwise odd
+                        let node: Node = match registry.bind(global) {
+                            Ok(node) => node,
+                            Err(e) => {
+                                log::error!("Could not bind sink {}: {e}", global.id);
+                                return;
+                            }
+                        };
+                        let info_output = output.clone();
                         let output = output.clone();
-                        let default_sink_id = default_sink_id.clone();
-                        let default_sink = default_sink.clone();
+                        let info_sinks = sinks.clone();
+                        let sinks = sinks.clone();
                         let original_node = Rc::new(node);
-                        let node = original_node.clone();
 
                         let obj_listener = original_node
                             .clone()
                             .add_listener_local()
                             .info(move |node_info| {
-                                let props = if let Some(props) = node_info.props() {
-                                    props
-                                } else {
+                                let Some(props) = node_info.props() else {
                                     return;
                                 };
-                                let name = if let Some(name) = props.get("node.name") {
-                                    name
-                                } else {
+                                let Some(name) = props.get("node.name") else {
                                     return;
                                 };
-                                if Some(String::from(name)) == *default_sink.borrow() {
-                                    node.subscribe_params(&[ParamType::Props]);
-                                    default_sink_id
-                                        .replace(props.get("object.id").map(String::from).clone());
+                                let mut sinks = info_sinks.borrow_mut();
+                                let sink = sinks.sinks.entry(id).or_default();
+                                sink.name = name.to_string();
+                                sink.description = props.get("node.description").map(String::from);
+                                sink.device = props.get("device.id").and_then(|id| id.parse().ok());
+                                if sinks.is_default(id) {
+                                    sinks.send_default(&info_output);
                                 }
                             })
                             .param(move |_seq, param_type, _index, _next, param| {
-                                match param_type {
-                                    ParamType::Props => {}
-                                    _ => unreachable!(),
-                                }
-                                let param_object = match param.map(Pod::as_object) {
-                                    Some(v) => v,
-                                    None => unreachable!(),
+                                let Some(param) = param.filter(|_| param_type == ParamType::Props)
+                                else {
+                                    return;
                                 };
-                                let param_object = match param_object {
-                                    Ok(v) => v,
-                                    Err(e) => {
-                                        log::error!("{}", e);
-                                        unreachable!();
+                                let (volume, muted) = parse_props(param);
+                                let mut sinks = sinks.borrow_mut();
+                                let sink = sinks.sinks.entry(id).or_default();
+                                if let Some(volume) = volume.clone() {
+                                    sink.volume = volume;
+                                }
+                                if let Some(muted) = muted {
+                                    sink.muted = muted;
+                                }
+                                if !sinks.is_default(id) {
+                                    return;
+                                }
+                                let messages = volume
+                                    .map(AudioMessage::SinkVolume)
+                                    .into_iter()
+                                    .chain(muted.map(AudioMessage::SinkMute));
+                                for message in messages {
+                                    if let Err(e) = output.blocking_send(Message::Audio(message)) {
+                                        log::error!("Audio Error: {:?}", e);
                                     }
-                                };
-                                let volume_prop =
-                                    if let Some(volume_prop) = param_object.find_prop(Id(65544)) {
-                                        volume_prop
-                                    } else {
-                                        return;
-                                    };
-                                let volume_bytes = volume_prop.value().as_bytes();
-
-                                let volume_value = if let Ok((_, value)) =
-                                    PodDeserializer::deserialize_from::<Value>(volume_bytes)
+                                }
+                            })
+                            .register();
+                        // Every sink is followed, so switching the default one
+                        // doesn't have to wait for its params
+                        original_node.subscribe_params(&[ParamType::Props]);
+                        Some((original_node, Rc::new(obj_listener)))
+                    }
+                    ObjectType::Device => {
+                        let api = global.props.and_then(|props| props.get("device.api"));
+                        if api != Some("bluez5") && api != Some("alsa") {
+                            return;
+                        }
+                        let is_bluetooth = api == Some("bluez5");
+                        let device_id = global.id;
+                        let device: Device = match registry.bind(global) {
+                            Ok(device) => device,
+                            Err(e) => {
+                                log::error!("Could not bind device {}: {e}", global.id);
+                                return;
+                            }
+                        };
+                        devices.borrow_mut().insert(device_id);
+                        let output = output.clone();
+                        let bluetooth_device = Rc::new(RefCell::new(BluetoothDevice {
+                            id: global.id,
+                            ..Default::default()
+                        }));
+                        let route_availability = RefCell::new(HashMap::new());
+                        let info_device = bluetooth_device.clone();
+                        let original_device = Rc::new(device);
+
+                        let obj_listener = original_device
+                            .clone()
+                            .add_listener_local()
+                            .info(move |device_info| {
+                                if let Some(description) = device_info
+                                    .props()
+                                    .and_then(|props| props.get("device.description"))
                                 {
-                                    value
-                                } else {
-                                    return;
-                                };
-                                let volume_array = match volume_value {
-                                    Value::ValueArray(v) => v,
-                                    _ => unreachable!(),
+                                    info_device.borrow_mut().description = description.to_string();
+                                }
+                            })
+                            .param(move |_seq, param_type, _index, _next, param| {
+                                let param = match param {
+                                    Some(param) => param,
+                                    None => return,
                                 };
-                                let volume_float_array = match volume_array {
-                                    ValueArray::Float(v) => v,
-                                    _ => unreachable!(),
+                                let message = match param_type {
+                                    ParamType::EnumProfile | ParamType::Profile if is_bluetooth => {
+                                        let profile = match parse_profile(param) {
+                                            Some(profile) => profile,
+                                            None => return,
+                                        };
+                                        let mut bluetooth_device = bluetooth_device.borrow_mut();
+                                        if param_type == ParamType::EnumProfile {
+                                            // Profiles are enumerated again whenever they change
+                                            bluetooth_device.profiles.retain(|v| v.index != profile.index);
+                                            bluetooth_device.profiles.push(profile);
+                                            bluetooth_device.profiles.sort_by_key(|v| v.index);
+                                        } else {
+                                            bluetooth_device.active = Some(profile.index);
+                                        }
+                                        AudioMessage::BluetoothDevice(bluetooth_device.clone())
+                                    }
+                                    ParamType::EnumRoute => {
+                                        let route = match parse_route(param) {
+                                            Some(route) => route,
+                                            None => return,
+                                        };
+                                        let available = match route.available {
+                                            Some(available) => available,
+                                            None => return,
+                                        };
+                                        let previous = route_availability
+                                            .borrow_mut()
+                                            .insert(route.index, available);
+                                        // The first enumeration is the initial state, not a plug event
+                                        if previous.is_none_or(|previous| previous == available) {
+                                            return;
+                                        }
+                                        let osd_output = output.clone();
+                                        std::thread::spawn(move || {
+                                            std::thread::sleep(JACK_OSD_DURATION);
+                                            if let Err(e) = osd_output.blocking_send(Message::Audio(AudioMessage::JackOsdExpired)) {
+                                                log::error!("Audio Error: {:?}", e);
+                                            }
+                                        });
+                                        AudioMessage::JackChanged {
+                                            description: route.description,
+                                            connected: available,
+                                        }
+                                    }
+                                    ParamType::Route => {
+                                        let route = match parse_route(param) {
+                                            Some(route) if route.output => route,
+                                            _ => return,
+                                        };
+                                        AudioMessage::OutputRoute {
+                                            device: device_id,
+                                            description: route.description,
+                                        }
+                                    }
+                                    _ => return,
                                 };
-                                if let Err(e) = output.blocking_send(Message::Audio(
-                                    AudioMessage::SinkVolume(volume_float_array),
-                                )) {
+                                if let Err(e) = output.blocking_send(Message::Audio(message)) {
                                     log::error!("Audio Error: {:?}", e);
                                 };
                             })
                             .register();
-                        Some((original_node, Rc::new(obj_listener)))
+                        if is_bluetooth {
+                            original_device.subscribe_params(&[
+                                ParamType::EnumProfile,
+                                ParamType::Profile,
+                                ParamType::EnumRoute,
+                                ParamType::Route,
+                            ]);
+                        } else {
+                            original_device.subscribe_params(&[ParamType::EnumRoute, ParamType::Route]);
+                        }
+                        Some((original_device, Rc::new(obj_listener)))
                     }
                     ObjectType::Metadata => {
-                        let metadata: Metadata = registry.bind(global).unwrap();
-                        let default_sink = default_sink.clone();
+                        let metadata: Metadata = match registry.bind(global) {
+                            Ok(metadata) => metadata,
+                            Err(e) => {
+                                log::error!("Could not bind metadata {}: {e}", global.id);
+                                return;
+                            }
+                        };
+                        let sinks = sinks.clone();
+                        let output = output.clone();
                         let metadata_listener = metadata
                             .add_listener_local()
                             .property(move |_seq, key, _metadata_type, value| {
-                                if let Some(("default.audio.sink", value)) = key.zip(value.clone())
-                                {
-                                    let value = value.split_terminator("\"").nth(3);
-                                    if let Some(value) = value {
-                                        let value = value.to_string();
-                                        default_sink.replace(Some(value));
-                                    }
+                                if key != Some("default.audio.sink") {
+                                    return 0;
                                 }
+                                // The value is like {"name":"alsa_output.pci-0000_00_1f.3.analog-stereo"},
+                                // and goes away with the last sink
+                                let name = value.and_then(|value| value.split_terminator('"').nth(3));
+                                let mut sinks = sinks.borrow_mut();
+                                sinks.default_name = name.map(String::from);
+                                sinks.send_default(&output);
                                 0
                             })
                             .register();
@@ -228,23 +569,210 @@ fn audio_generator(output: Sender<Message>, _rt: Handle) -> Result<(), AudioErro
                 }
             }
         })
+        .global_remove(move |id| {
+            let mut sinks = remove_sinks.borrow_mut();
+            let was_default = sinks.is_default(id);
+            sinks.sinks.remove(&id);
+            if was_default {
+                sinks.send_default(&remove_output);
+            }
+            // Most globals are none of the state's business, and waiting on
+            // the channel would hold up the main loop
+            if !remove_devices.borrow_mut().remove(&id) {
+                return;
+            }
+            if let Err(e) = remove_output.try_send(Message::Audio(AudioMessage::GlobalRemoved(id))) {
+                log::error!("Could not remove audio device {id}: {e}");
+            };
+        })
         .register();
 
     mainloop.run();
     Ok(())
 }
 
+/// Pipewire sends a Props param for every step while a slider is dragged in
+/// another mixer, rendering each of them makes the bar flicker
+const VOLUME_FRAME: std::time::Duration = std::time::Duration::from_millis(33);
+/// Fraction of the remaining distance to the target covered every frame
+const VOLUME_EASING: f32 = 0.4;
+
+/// Volume updates are coalesced into at most one per VOLUME_FRAME and the
+/// displayed volume eases towards the latest one, everything else passes
+/// through untouched
+async fn smooth_volume(mut raw_receiver: Receiver<Message>, sender: Sender<Message>) {
+    let mut target: Option<Vec<f32>> = None;
+    let mut displayed: Vec<f32> = Vec::new();
+    let mut interval = tokio::time::interval(VOLUME_FRAME);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    loop {
+        tokio::select! {
+            message = raw_receiver.recv() => match message {
+                Some(Message::Audio(AudioMessage::SinkVolume(volume))) => target = Some(volume),
+                Some(message) => {
+                    if sender.send(message).await.is_err() {
+                        return;
+                    }
+                }
+                None => return,
+            },
+            _ = interval.tick(), if target.is_some() => {
+                let Some(target_volume) = target.as_ref() else {
+                    continue;
+                };
+                if displayed.len() == target_volume.len() {
+                    for (displayed, target) in displayed.iter_mut().zip(target_volume) {
+                        *displayed += (target - *displayed) * VOLUME_EASING;
+                    }
+                } else {
+                    // Channel layout changed, nothing to animate from
+                    displayed = target_volume.clone();
+                }
+                if displayed
+                    .iter()
+                    .zip(target_volume)
+                    .all(|(displayed, target)| (target - displayed).abs() < 0.005)
+                {
+                    displayed = target_volume.clone();
+                    target = None;
+                }
+                if sender
+                    .send(Message::Audio(AudioMessage::SinkVolume(displayed.clone())))
+                    .await
+                    .is_err()
+                {
+                    return;
+                }
+            }
+        }
+    }
+}
+
 pub fn audio_subscription(rt: Handle) -> tokio_stream::wrappers::ReceiverStream<Message> {
+    let (raw_sender, raw_receiver) = channel(16);
     let (sender, receiver) = channel(1);
+    rt.spawn(smooth_volume(raw_receiver, sender));
+    let generator_rt = rt.clone();
+    supervise_blocking(&rt, "audio", Backoff::DEFAULT, raw_sender, move |sender| {
+        audio_generator(sender, generator_rt.clone())
+    });
+    tokio_stream::wrappers::ReceiverStream::new(receiver)
+}
 
-    rt.clone().spawn_blocking(move || {
+/// The volume of the default sink, with its route
+pub const WIDGET: Widget = Widget {
+    name: "audio",
+    render,
+};
 
-        loop {
-            log::error!(
-                "Pipewire subscription event loop returned, this should never happen, trying to reconnect {:?}",
-                audio_generator(sender.clone(), rt.clone())
-            )
+fn render(state: &State) -> Vec<Renderable> {
+    let style = state.config.theme.style("audio");
+    let mut module = Vec::new();
+    let output_route = state
+        .audio_state
+        .default_sink_device
+        .and_then(|device| state.audio_state.output_routes.get(&device));
+    if let Some(output_route) = output_route {
+        if !state.audio_state.volume.is_empty() {
+            module.push(Renderable::Text {
+                text: output_route.clone().into(),
+                fg: style.fg,
+                bg: style.bg,
+            });
+            module.push(Renderable::Space(0.5));
         }
-    });
-    tokio_stream::wrappers::ReceiverStream::new(receiver)
+    }
+    let muted = state.audio_state.muted && !state.audio_state.volume.is_empty();
+    if muted {
+        // A crossed out speaker from the nerd fonts
+        module.push(Renderable::Text {
+            text: "\u{f075f}".into(),
+            fg: style.warning,
+            bg: style.bg,
+        });
+        module.push(Renderable::Space(0.5));
+    }
+    // The level stays visible while muted, for what unmuting brings back
+    let level = if muted { style.warning } else { style.accent };
+    for sink_volume in state.audio_state.volume.iter() {
+        module.push(Renderable::Box {
+            fg: style.dim,
+            bg: style.dim,
+            width: 1.,
+            height: 1.,
+            skip: 0.0,
+        });
+        module.push(Renderable::Box {
+            fg: level,
+            bg: level,
+            width: 1.,
+            height: sink_volume.cbrt(),
+            skip: 1.0,
+        });
+    }
+    match &state.audio_state.default_sink_name {
+        Some(name) if !module.is_empty() => vec![Renderable::Region {
+            tooltip: Some(if muted {
+                format!("{name}\nMuted")
+            } else {
+                name.clone()
+            }),
+            on_click: None,
+            on_scroll: None,
+            children: module,
+        }],
+        _ => module,
+    }
+}
+
+/// The active profile of each Bluetooth audio device, clicking switches it
+pub const BLUETOOTH: Widget = Widget {
+    name: "bluetooth",
+    render: render_bluetooth,
+};
+
+fn render_bluetooth(state: &State) -> Vec<Renderable> {
+    let style = state.config.theme.style("bluetooth");
+    let mut module = Vec::new();
+    for device in &state.audio_state.bluetooth_devices {
+        let active = match device.active_profile() {
+            Some(profile) => profile,
+            None => continue,
+        };
+        if !module.is_empty() {
+            module.push(Renderable::Space(1.));
+        }
+        module.push(Renderable::Region {
+            tooltip: Some(
+                std::iter::once(device.description.clone())
+                    .chain(device.profiles.iter().map(|profile| {
+                        let marker = if profile.index == active.index {
+                            "*"
+                        } else {
+                            " "
+                        };
+                        format!("{marker} {}", profile.description)
+                    }))
+                    .chain(["Right click for all profiles".to_string()])
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+            ),
+            on_click: device.toggled_profile().map(|profile| {
+                Action::Spawn(format!("wpctl set-profile {} {}", device.id, profile.index))
+            }),
+            on_scroll: None,
+            children: vec![Renderable::Text {
+                text: if active.name.starts_with("headset-head-unit") {
+                    "bt hsp".into()
+                } else if active.name.starts_with("a2dp-sink") {
+                    "bt a2dp".into()
+                } else {
+                    format!("bt {}", active.name).into()
+                },
+                fg: style.fg,
+                bg: style.bg,
+            }],
+        });
+    }
+    module
 }
@@ -1,9 +1,17 @@
-use std::io::{Read, Seek, SeekFrom};
+use std::io::{ErrorKind, Read, Seek, SeekFrom};
 use std::num::ParseIntError;
 use std::{
+    collections::BTreeMap,
     fs::{self, File},
     io::Error,
     os::fd::AsRawFd,
+    path::Path,
+    process::{Command, ExitStatus},
+    sync::{
+        OnceLock,
+        mpsc::{self, Receiver},
+    },
+    thread,
 };
 
 use mio::{Events, Interest, Token};
@@ -14,14 +22,34 @@ use tokio::{
 };
 use tokio_stream::wrappers::ReceiverStream;
 
-use crate::files::{ReadIntError, read_int_from_file};
-use crate::state::Message;
+use crate::action::{Action, ScrollActions};
+use crate::files::{ReadIntError, read_int_from_file, read_int_from_file_path};
+use crate::format::display_percent;
+use crate::renderer::Renderable;
+use crate::state::{Message, State};
+use crate::supervisor::{Backoff, supervise_blocking};
+use crate::widget::Widget;
 
 #[derive(Debug)]
-enum BacklightError {
+pub enum BacklightError {
     StdIoError(Error),
     ReadIntError(ReadIntError),
     SendError(SendError<Message>),
+    /// busctl failed setting the brightness through logind
+    LogindError(ExitStatus),
+}
+
+impl std::fmt::Display for BacklightError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BacklightError::StdIoError(e) => write!(f, "IO Error: {e}"),
+            BacklightError::ReadIntError(e) => write!(f, "{e}"),
+            BacklightError::SendError(e) => write!(f, "Channel Error: {e}"),
+            BacklightError::LogindError(status) => {
+                write!(f, "busctl failed setting the brightness: {status}")
+            }
+        }
+    }
 }
 
 impl From<Error> for BacklightError {
@@ -38,6 +66,8 @@ impl From<ReadIntError> for BacklightError {
 
 #[derive(Debug, Clone)]
 pub struct Backlight {
+    /// Name of the device under /sys/class/backlight
+    pub name: String,
     pub max_brightness: usize,
     pub brightness: usize,
 }
@@ -80,6 +110,7 @@ fn backlight_generator(sender: Sender<Message>) -> Result<(), BacklightError> {
         let max_brightness = read_int_from_file(&mut max_brightness_file)?;
         let brightness = read_int_from_file(&mut brightness_file)?;
         backlights.push(Backlight {
+                name: backlight_dir.file_name().to_string_lossy().into_owned(),
                 max_brightness,
                 brightness,
             });
@@ -100,12 +131,142 @@ fn backlight_generator(sender: Sender<Message>) -> Result<(), BacklightError> {
     }
 }
 
-pub fn backlight_subscription(rt: Handle) -> ReceiverStream<Message> {
-    let (sender, receiver) = channel(1);
-    rt.clone().spawn_blocking(move || {
-        loop {
-            log::error!("Backlight subscription event loop returned, this should never happen, trying to reconnect {:?}", backlight_generator(sender.clone()));
+/// Brightness writes need the video group or a udev rule, without them
+/// logind can still set it for the active session
+fn try_adjust_brightness(device: &str, percent: i32) -> Result<(), BacklightError> {
+    let dir = Path::new("/sys/class/backlight").join(device);
+    let max_brightness = read_int_from_file_path(dir.join("max_brightness"))? as i64;
+    if max_brightness == 0 {
+        return Ok(());
+    }
+    let brightness = read_int_from_file_path(dir.join("brightness"))? as i64;
+    // Devices with few levels would otherwise never move
+    let step = match percent as i64 * max_brightness / 100 {
+        0 => percent.signum() as i64,
+        step => step,
+    };
+    // 0 turns some panels off entirely
+    let target = (brightness + step).clamp(1.min(max_brightness), max_brightness);
+    match fs::write(dir.join("brightness"), target.to_string()) {
+        Err(e) if e.kind() == ErrorKind::PermissionDenied => {
+            let status = Command::new("busctl")
+                .args([
+                    "call",
+                    "org.freedesktop.login1",
+                    "/org/freedesktop/login1/session/auto",
+                    "org.freedesktop.login1.Session",
+                    "SetBrightness",
+                    "ssu",
+                    "backlight",
+                    device,
+                    &target.to_string(),
+                ])
+                .status()?;
+            if !status.success() {
+                return Err(BacklightError::LogindError(status));
+            }
+            Ok(())
         }
+        result => Ok(result?),
+    }
+}
+
+/// Applies the adjustments one after the other, adding up those that came in
+/// while the last one was written so fast scrolling doesn't lose steps
+fn adjust_worker(adjustments: Receiver<(String, i32)>) {
+    while let Ok((device, percent)) = adjustments.recv() {
+        let mut pending = BTreeMap::from([(device, percent)]);
+        for (device, percent) in adjustments.try_iter() {
+            *pending.entry(device).or_insert(0) += percent;
+        }
+        for (device, percent) in pending {
+            if percent == 0 {
+                continue;
+            }
+            if let Err(e) = try_adjust_brightness(&device, percent) {
+                log::error!("Could not adjust the brightness of {device}: {e:?}");
+            }
+        }
+    }
+}
+
+/// Queues the adjustment for the worker thread, as busctl may have to wait on
+/// logind
+pub fn adjust_brightness(device: &str, percent: i32) {
+    static WORKER: OnceLock<mpsc::Sender<(String, i32)>> = OnceLock::new();
+    let worker = WORKER.get_or_init(|| {
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || adjust_worker(receiver));
+        sender
     });
+    if worker.send((device.to_string(), percent)).is_err() {
+        log::error!("The brightness worker is gone, not adjusting {device}");
+    }
+}
+
+pub fn backlight_subscription(rt: Handle) -> ReceiverStream<Message> {
+    let (sender, receiver) = channel(1);
+    supervise_blocking(&rt, "backlight", Backoff::DEFAULT, sender, backlight_generator);
     ReceiverStream::new(receiver)
 }
+
+/// Percent of the maximum brightness one scroll step changes it by
+const BRIGHTNESS_STEP: i32 = 5;
+
+/// The brightness of each backlight, scrolling changes it
+pub const WIDGET: Widget = Widget {
+    name: "backlight",
+    render,
+};
+
+fn render(state: &State) -> Vec<Renderable> {
+    let style = state.config.theme.style("backlight");
+    let mut module = Vec::new();
+    for backlight in &state.backlights {
+        if !module.is_empty() {
+            module.push(Renderable::Space(1.));
+        }
+        // Some devices report no levels at all
+        let brightness = if backlight.max_brightness == 0 {
+            0.
+        } else {
+            backlight.brightness as f32 / backlight.max_brightness as f32
+        };
+        let adjust = |percent| Action::AdjustBrightness {
+            device: backlight.name.clone(),
+            percent,
+        };
+        module.push(Renderable::Region {
+            tooltip: Some(backlight.name.clone()),
+            on_click: None,
+            on_scroll: Some(ScrollActions {
+                up: adjust(BRIGHTNESS_STEP),
+                down: adjust(-BRIGHTNESS_STEP),
+                sensitivity: state.scroll_sensitivity("backlight"),
+            }),
+            children: vec![
+                Renderable::Box {
+                    fg: style.dim,
+                    bg: style.dim,
+                    width: 1.,
+                    height: 1.,
+                    skip: 0.0,
+                },
+                Renderable::Box {
+                    fg: style.fg,
+                    bg: style.fg,
+                    width: 1.,
+                    height: brightness,
+                    skip: 1.0,
+                },
+                Renderable::Space(0.25),
+                Renderable::Text {
+                    text: display_percent(brightness * 100.).into(),
+                    fg: style.fg,
+                    bg: style.bg,
+                },
+            ],
+        });
+    }
+    module
+}
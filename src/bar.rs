@@ -0,0 +1,480 @@
+use std::{
+    any::Any,
+    collections::BTreeMap,
+    fmt::Debug,
+    path::PathBuf,
+    sync::{Arc, Mutex, RwLock},
+    time::Duration,
+};
+
+use tokio::{
+    runtime::{Handle, Runtime},
+    sync::{
+        mpsc::{Sender, channel, error::SendError},
+        watch,
+    },
+    task::JoinHandle,
+};
+use tokio_stream::{StreamMap, wrappers::ReceiverStream};
+
+use crate::{
+    ambient::ambient_subscription,
+    audio::audio_subscription,
+    backlight::backlight_subscription,
+    battery::battery_subscription,
+    camera::camera_subscription,
+    clock::clock_subscription,
+    compositor::compositor,
+    config::Config,
+    cpu::cpu_subscription,
+    custom::custom_subscription,
+    docker::docker_subscription,
+    font::FontSystem,
+    hosts::hosts_subscription,
+    ipc::ipc_subscription,
+    kdeconnect::kdeconnect_subscription,
+    layer::{BAR_HEIGHT, Display, STARTUP_BACKOFF, StartupError},
+    libvirt::libvirt_subscription,
+    logind::logind_subscription,
+    memory::memory_subscription,
+    mic::mic_subscription,
+    mpd::mpd_subscription,
+    mpris::mpris_subscription,
+    mqtt::mqtt_subscription,
+    network::network_subscription,
+    nvme::nvme_subscription,
+    preview::preview_subscription,
+    privacy::privacy_subscription,
+    renderer::{Renderable, Renderer, SurfaceTarget, WaylandOutput, WaylandTarget},
+    state::{Message, State},
+    supervisor::retry,
+    systemd::systemd_subscription,
+    theme::Style,
+    thermal::thermal_subscription,
+    update::update_subscription,
+    watch::watch_subscription,
+    widget,
+};
+
+/// How long the bar keeps trying to connect to the compositor, unless told
+/// to wait for it
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// What a module sends from its subscription to its update
+pub type ModuleMessage = Box<dyn Any + Send>;
+
+/// Modules are called from the state loop, the lock is only contended
+/// while the subscription starts
+pub type SharedModule = Arc<Mutex<dyn Module>>;
+
+/// A module from another crate, shown where its name is in the layout like
+/// the modules of this one. The mouse bindings, max_width and theme of the
+/// config apply to it by that name as well
+pub trait Module: Debug + Send + 'static {
+    /// Its name in the layout, which can't be one of widget::WIDGETS
+    fn name(&self) -> &str;
+
+    /// Started once on the bar's runtime, everything sent through the sender
+    /// goes to update
+    fn subscription(&self, rt: Handle, sender: ModuleSender);
+
+    /// Takes a message from the subscription, a redraw follows
+    fn update(&mut self, message: ModuleMessage);
+
+    /// What the module shows, in the style of its name in the theme. Nothing
+    /// leaves it out of the bar
+    fn render(&self, style: &Style) -> Vec<Renderable>;
+
+    /// Whether the module takes left clicks, see on_click
+    fn clickable(&self) -> bool {
+        false
+    }
+
+    /// The module was left clicked, unless the config binds the button to
+    /// something else
+    fn on_click(&mut self) {}
+}
+
+/// Sends messages of a module to its update, in the "modules" subscription
+#[derive(Debug, Clone)]
+pub struct ModuleSender {
+    name: Arc<str>,
+    sender: Sender<Message>,
+}
+
+impl ModuleSender {
+    pub async fn send(&self, message: impl Any + Send) -> Result<(), SendError<Message>> {
+        self.sender.send(self.message(message)).await
+    }
+
+    /// For subscriptions running on a thread of their own
+    pub fn blocking_send(&self, message: impl Any + Send) -> Result<(), SendError<Message>> {
+        self.sender.blocking_send(self.message(message))
+    }
+
+    fn message(&self, message: impl Any + Send) -> Message {
+        Message::External {
+            name: self.name.clone(),
+            message: Box::new(message),
+        }
+    }
+}
+
+/// The whole bar, for binaries that bring modules of their own
+#[derive(Debug)]
+pub struct Bar {
+    config: Option<Config>,
+    modules: BTreeMap<String, SharedModule>,
+    wait_for_compositor: bool,
+    preview_theme: Option<PathBuf>,
+}
+
+#[derive(Debug, Default)]
+pub struct BarBuilder {
+    config: Option<Config>,
+    modules: Vec<SharedModule>,
+    wait_for_compositor: bool,
+    preview_theme: Option<PathBuf>,
+}
+
+impl BarBuilder {
+    pub fn with_module(mut self, module: impl Module) -> Self {
+        self.modules.push(Arc::new(Mutex::new(module)));
+        self
+    }
+
+    /// In place of the config file
+    pub fn config(mut self, config: Config) -> Self {
+        self.config = Some(config);
+        self
+    }
+
+    /// Keeps trying to connect to the compositor and sway for as long as it
+    /// takes, like when started before sway from a systemd unit
+    pub fn wait_for_compositor(mut self, wait: bool) -> Self {
+        self.wait_for_compositor = wait;
+        self
+    }
+
+    /// Shows made up values for every module and reloads the theme file on
+    /// changes, in place of the subscriptions watching the system
+    pub fn preview_theme(mut self, path: PathBuf) -> Self {
+        self.preview_theme = Some(path);
+        self
+    }
+
+    pub fn build(self) -> Bar {
+        let mut modules = BTreeMap::new();
+        for module in self.modules {
+            let name = module
+                .lock()
+                .expect("A new module to not be poisoned")
+                .name()
+                .to_string();
+            if widget::find(&name).is_some() {
+                log::warn!("The module {name:?} has the name of a built in one, it won't be shown");
+            } else if modules.insert(name.clone(), module).is_some() {
+                log::warn!("Another module is named {name:?}, only the last one is shown");
+            }
+        }
+        Bar {
+            config: self.config,
+            modules,
+            wait_for_compositor: self.wait_for_compositor,
+            preview_theme: self.preview_theme,
+        }
+    }
+}
+
+impl Bar {
+    pub fn builder() -> BarBuilder {
+        BarBuilder::default()
+    }
+
+    /// Runs the bar until it exits, which it only does when the compositor
+    /// can't be reached at startup
+    pub fn run(self) -> Result<(), StartupError> {
+        let give_up_after = (!self.wait_for_compositor).then_some(STARTUP_TIMEOUT);
+        let rt = Arc::new(Runtime::new().expect("To be able to initalize a tokio runtime"));
+
+        let mut streams = StreamMap::new();
+
+        let hit_regions = Arc::new(RwLock::new(Vec::new()));
+        let config = self.config.unwrap_or_else(Config::load);
+        let fonts = FontSystem::from_config(&config.font)?;
+        let state = State::new(config.clone(), hit_regions.clone(), self.modules.clone());
+        let (render_sender, render_receiver) = channel(1);
+        let (state_sender, state_receiver) = channel(1);
+        let state_stream = ReceiverStream::new(state_receiver);
+        let (occluded_sender, occluded_receiver) = watch::channel(false);
+        match self.preview_theme {
+            Some(theme) => {
+                streams.insert("preview", preview_subscription(rt.handle().clone(), theme));
+            }
+            None => {
+                // The subscriptions would keep failing until it is up anyway.
+                // Without a time limit this only returns once connected
+                let compositor = compositor();
+                if self.wait_for_compositor {
+                    let _ = retry(
+                        &format!("connect to {}", compositor.name()),
+                        &STARTUP_BACKOFF,
+                        None,
+                        || compositor.connect(),
+                    );
+                }
+                // Named after sway whichever compositor it is, for the throttle
+                streams.insert("sway", compositor.subscription(rt.handle().clone()));
+                streams.insert(
+                    "taskbar",
+                    compositor.taskbar_subscription(rt.handle().clone()),
+                );
+                streams.insert("mpd", mpd_subscription(rt.handle().clone()));
+                streams.insert("mpris", mpris_subscription(rt.handle().clone()));
+                streams.insert(
+                    "network",
+                    network_subscription(rt.handle().clone(), state.resumes.subscribe()),
+                );
+                streams.insert(
+                    "battery",
+                    battery_subscription(
+                        rt.handle().clone(),
+                        config.battery.backend,
+                        state.resumes.subscribe(),
+                    ),
+                );
+                streams.insert(
+                    "ambient",
+                    ambient_subscription(
+                        rt.handle().clone(),
+                        config.ambient.clone(),
+                        config.position,
+                        state.resumes.subscribe(),
+                    ),
+                );
+            }
+        }
+        streams.insert("audio", audio_subscription(rt.handle().clone()));
+        streams.insert(
+            "mic",
+            mic_subscription(rt.handle().clone(), state.mic_meter.subscribe()),
+        );
+        streams.insert("backlight", backlight_subscription(rt.handle().clone()));
+        streams.insert(
+            "clock",
+            clock_subscription(
+                rt.handle().clone(),
+                config.clock.clone(),
+                state.resumes.subscribe(),
+            ),
+        );
+        streams.insert(
+            "systemd",
+            systemd_subscription(rt.handle().clone(), state.resumes.subscribe()),
+        );
+        streams.insert(
+            "docker",
+            docker_subscription(rt.handle().clone(), state.resumes.subscribe()),
+        );
+        streams.insert(
+            "thermal",
+            thermal_subscription(rt.handle().clone(), state.resumes.subscribe()),
+        );
+        streams.insert(
+            "cpu",
+            cpu_subscription(rt.handle().clone(), state.resumes.subscribe()),
+        );
+        streams.insert(
+            "memory",
+            memory_subscription(rt.handle().clone(), state.resumes.subscribe()),
+        );
+        // smartctl is slow and needs root, so it only runs for the module
+        if config.layout.contains("nvme") {
+            streams.insert(
+                "nvme",
+                nvme_subscription(
+                    rt.handle().clone(),
+                    config.nvme.clone(),
+                    state.resumes.subscribe(),
+                ),
+            );
+        }
+        streams.insert(
+            "hosts",
+            hosts_subscription(
+                rt.handle().clone(),
+                config.hosts.clone(),
+                state.resumes.subscribe(),
+            ),
+        );
+        streams.insert(
+            "libvirt",
+            libvirt_subscription(
+                rt.handle().clone(),
+                config.libvirt.clone(),
+                state.resumes.subscribe(),
+            ),
+        );
+        streams.insert(
+            "kdeconnect",
+            kdeconnect_subscription(rt.handle().clone(), state.resumes.subscribe()),
+        );
+        streams.insert("logind", logind_subscription(rt.handle().clone()));
+        streams.insert("camera", camera_subscription(rt.handle().clone()));
+        streams.insert("privacy", privacy_subscription(rt.handle().clone()));
+        streams.insert(
+            "ipc",
+            ipc_subscription(
+                rt.handle().clone(),
+                hit_regions.clone(),
+                render_sender.clone(),
+            ),
+        );
+        streams.insert(
+            "custom",
+            custom_subscription(
+                rt.handle().clone(),
+                config.custom.clone(),
+                state.resumes.subscribe(),
+            ),
+        );
+        streams.insert(
+            "watch",
+            watch_subscription(rt.handle().clone(), config.watch.clone()),
+        );
+        streams.insert(
+            "mqtt",
+            mqtt_subscription(rt.handle().clone(), config.mqtt.clone()),
+        );
+        streams.insert(
+            "update",
+            update_subscription(
+                rt.handle().clone(),
+                config.update.clone(),
+                state.resumes.subscribe(),
+            ),
+        );
+        streams.insert(
+            "modules",
+            modules_subscription(rt.handle().clone(), &self.modules),
+        );
+        streams.insert("display", state_stream);
+        // Messages keep the name of their stream, which throttling goes by
+        let state_event_loop_handle =
+            rt.spawn(state.run_event_loop(streams, render_sender.clone(), occluded_receiver));
+
+        #[cfg(feature = "x11")]
+        if crate::x11::selected() {
+            log::info!("No Wayland compositor to run in, showing the bar on X11");
+            let window = Arc::new(crate::x11::X11Window::new(BAR_HEIGHT, config.position)?);
+            let target = SurfaceTarget::X11(window.clone());
+            let renderer_event_loop_handle = rt.spawn(async move {
+                let renderer = Renderer::new(
+                    target,
+                    100,
+                    BAR_HEIGHT,
+                    hit_regions,
+                    &config,
+                    fonts,
+                    None,
+                    occluded_sender,
+                )
+                .await;
+                renderer.run_event_loop(render_receiver).await;
+            });
+            let x11_event_loop_handle = rt.spawn_blocking(move || {
+                window
+                    .run_event_loop(render_sender, state_sender)
+                    .expect("To never exit the X11 event loop");
+            });
+            join_event_loops(
+                &rt,
+                [
+                    ("state", state_event_loop_handle),
+                    ("renderer", renderer_event_loop_handle),
+                    ("X11", x11_event_loop_handle),
+                ],
+            );
+            return Ok(());
+        }
+
+        let (display, event_queue) = rt.block_on(Display::new(
+            BAR_HEIGHT,
+            config.position,
+            config.autohide.enabled,
+            give_up_after,
+            render_sender,
+            state_sender,
+        ))?;
+        let target = SurfaceTarget::Wayland(WaylandTarget {
+            wayland_conn: display.wayland_conn.clone(),
+            layer: display.layer.clone(),
+        });
+
+        let queue_handle = event_queue.handle();
+        let wayland = WaylandOutput {
+            tooltip_layers: display.tooltip_layers(&queue_handle),
+            queue_handle,
+        };
+        let renderer_event_loop_handle = rt.spawn(async move {
+            let renderer = Renderer::new(
+                target,
+                100,
+                BAR_HEIGHT,
+                hit_regions,
+                &config,
+                fonts,
+                Some(wayland),
+                occluded_sender,
+            )
+            .await;
+            renderer.run_event_loop(render_receiver).await;
+        });
+
+        let display_event_loop_handle = rt.spawn_blocking(|| {
+            display
+                .run_event_loop(event_queue)
+                .expect("To never exit the event loop");
+        });
+
+        join_event_loops(
+            &rt,
+            [
+                ("state", state_event_loop_handle),
+                ("renderer", renderer_event_loop_handle),
+                ("display", display_event_loop_handle),
+            ],
+        );
+        Ok(())
+    }
+}
+
+/// Blocks on the event loops of the bar, none of which return
+fn join_event_loops(rt: &Runtime, event_loops: [(&str, JoinHandle<()>); 3]) {
+    rt.block_on(async {
+        for (name, handle) in event_loops {
+            if let Err(e) = handle.await {
+                panic!("Never erroring out in the {name} event loop: {e}");
+            }
+        }
+    });
+}
+
+/// Starts the subscription of every module, all sending into the one stream
+fn modules_subscription(
+    rt: Handle,
+    modules: &BTreeMap<String, SharedModule>,
+) -> ReceiverStream<Message> {
+    let (sender, receiver) = channel(1);
+    for (name, module) in modules {
+        let sender = ModuleSender {
+            name: name.as_str().into(),
+            sender: sender.clone(),
+        };
+        module
+            .lock()
+            .expect("A module to not be poisoned before it started")
+            .subscription(rt.clone(), sender);
+    }
+    ReceiverStream::new(receiver)
+}
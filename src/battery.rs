@@ -1,25 +1,52 @@
-use std::{fs, str::FromStr, thread, time::Duration};
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
 
 use mio::{Events, Interest, Poll, Token};
 use tokio::{
     runtime::Handle,
-    sync::mpsc::{Sender, channel, error::SendError},
+    sync::{
+        mpsc::{Sender, channel, error::SendError},
+        watch,
+    },
 };
 use tokio_stream::wrappers::ReceiverStream;
 
 use crate::{
+    config::BatteryBackend,
     files::{ReadIntError, read_int_from_file_path, read_string_from_file_path},
-    state::Message,
+    format::display_percent,
+    renderer::Renderable,
+    state::{BatteryLevel, Message, State},
+    supervisor::{Backoff, sleep_unless_resumed, supervise, supervise_blocking},
+    upower::upower_generator,
+    widget::Widget,
 };
 
 #[derive(Debug)]
-enum BatteryError {
+pub enum BatteryError {
     StdIoError(std::io::Error),
     ReadIntError(ReadIntError),
-
+    /// From the UPower backend
+    DbusError(zbus::Error),
     SendError(SendError<Message>),
 }
 
+impl std::fmt::Display for BatteryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BatteryError::StdIoError(e) => write!(f, "IO Error: {e}"),
+            BatteryError::ReadIntError(e) => write!(f, "{e}"),
+            BatteryError::DbusError(e) => write!(f, "D-Bus Error: {e}"),
+            BatteryError::SendError(e) => write!(f, "Channel Error: {e}"),
+        }
+    }
+}
+
 impl From<std::io::Error> for BatteryError {
     fn from(value: std::io::Error) -> Self {
         Self::StdIoError(value)
@@ -32,6 +59,12 @@ impl From<ReadIntError> for BatteryError {
     }
 }
 
+impl From<zbus::Error> for BatteryError {
+    fn from(value: zbus::Error) -> Self {
+        Self::DbusError(value)
+    }
+}
+
 impl From<SendError<Message>> for BatteryError {
     fn from(value: SendError<Message>) -> Self {
         Self::SendError(value)
@@ -48,10 +81,23 @@ pub enum PowerSupply {
     Battery {
         status: PowerSupplyStatus,
         capacity: usize,
+        /// Until empty while discharging and until full while charging, if
+        /// the driver reports the rate
+        time_remaining: Option<Duration>,
+        /// Watts going in or out, if the driver reports them
+        power: Option<f32>,
     },
     Mains {
         online: bool,
     },
+    /// A device with a battery of its own, like a mouse or a headset, only
+    /// known through UPower
+    Peripheral {
+        /// Like mouse or headphones
+        kind: String,
+        model: Option<String>,
+        capacity: usize,
+    },
 }
 
 #[derive(Debug)]
@@ -102,6 +148,26 @@ pub enum PowerSupplyStatus {
     Full,
 }
 
+impl PowerSupplyStatus {
+    /// Material design battery glyph from the nerd fonts for the capacity
+    /// in steps of 10%, with a bolt while charging
+    pub fn icon(&self, capacity: usize) -> char {
+        const DISCHARGING: [char; 11] = [
+            '\u{f008e}', '\u{f007a}', '\u{f007b}', '\u{f007c}', '\u{f007d}', '\u{f007e}',
+            '\u{f007f}', '\u{f0080}', '\u{f0081}', '\u{f0082}', '\u{f0079}',
+        ];
+        const CHARGING: [char; 11] = [
+            '\u{f089f}', '\u{f089c}', '\u{f0086}', '\u{f0087}', '\u{f0088}', '\u{f089d}',
+            '\u{f0089}', '\u{f089e}', '\u{f008a}', '\u{f008b}', '\u{f0085}',
+        ];
+        let step = (capacity.min(100) + 5) / 10;
+        match self {
+            Self::Charging => CHARGING[step],
+            _ => DISCHARGING[step],
+        }
+    }
+}
+
 impl FromStr for PowerSupplyStatus {
     type Err = ();
 
@@ -116,7 +182,78 @@ impl FromStr for PowerSupplyStatus {
     }
 }
 
-fn battery_generator(sender: Sender<Message>) -> Result<(), BatteryError> {
+/// Magnitude of a micro unit attribute, some drivers report the current and
+/// power as negative while discharging
+fn read_micro(dir: &Path, name: &str) -> Option<u64> {
+    read_string_from_file_path(dir.join(name))
+        .ok()?
+        .trim()
+        .parse::<i64>()
+        .ok()
+        .map(i64::unsigned_abs)
+}
+
+/// Weight of a new rate reading against the smoothed rate. The rate swings
+/// with the load from one reading to the next, with a reading every 10
+/// seconds this averages it over about the last minute
+const RATE_SMOOTHING: f64 = 0.2;
+
+/// The rate of a battery averaged over its readings, and whether it was
+/// charging at the time
+type SmoothedRate = Option<(bool, f64)>;
+
+/// From whichever of the energy and charge counters the driver has, at the
+/// smoothed rate. The average starts over when the battery starts or stops
+/// charging, readings of 0 leave it as it is
+fn time_remaining(
+    dir: &Path,
+    status: &PowerSupplyStatus,
+    smoothed: &mut SmoothedRate,
+) -> Option<Duration> {
+    let read = |name| read_micro(dir, name);
+    let (now, full, rate) = [
+        ("energy_now", "energy_full", "power_now"),
+        ("charge_now", "charge_full", "current_now"),
+    ]
+    .into_iter()
+    .find_map(|(now, full, rate)| Some((read(now)?, read(full)?, read(rate)?)))?;
+    let (left, charging) = match status {
+        PowerSupplyStatus::Charging => (full.saturating_sub(now), true),
+        PowerSupplyStatus::Discharging => (now, false),
+        _ => {
+            *smoothed = None;
+            return None;
+        }
+    };
+    let rate = match *smoothed {
+        Some((was_charging, average)) if was_charging == charging && rate == 0 => average,
+        Some((was_charging, average)) if was_charging == charging => {
+            average + RATE_SMOOTHING * (rate as f64 - average)
+        }
+        _ if rate == 0 => return None,
+        _ => rate as f64,
+    };
+    *smoothed = Some((charging, rate));
+    Some(Duration::from_secs_f64(left as f64 / rate * 3600.))
+}
+
+/// power_now where the driver has it, the current times the voltage
+/// otherwise
+fn power(dir: &Path) -> Option<f32> {
+    let microwatts = match read_micro(dir, "power_now") {
+        Some(power) => power as f64,
+        None => {
+            read_micro(dir, "current_now")? as f64 * read_micro(dir, "voltage_now")? as f64 / 1e6
+        }
+    };
+    Some((microwatts / 1e6) as f32)
+}
+
+fn battery_generator(
+    sender: Sender<Message>,
+    resumes: &mut watch::Receiver<()>,
+) -> Result<(), BatteryError> {
+    let mut rates: HashMap<PathBuf, SmoothedRate> = HashMap::new();
     loop {
         let mut power_supplies = Vec::new();
         for power_supply_dir in fs::read_dir("/sys/class/power_supply")? {
@@ -135,7 +272,15 @@ fn battery_generator(sender: Sender<Message>) -> Result<(), BatteryError> {
                             .expect("All paths are handled");
                     let capacity =
                         read_int_from_file_path(power_supply_dir.path().join("capacity"))?;
-                    power_supplies.push(PowerSupply::Battery { status, capacity });
+                    let path = power_supply_dir.path();
+                    let time_remaining =
+                        time_remaining(&path, &status, rates.entry(path.clone()).or_default());
+                    power_supplies.push(PowerSupply::Battery {
+                        status,
+                        capacity,
+                        time_remaining,
+                        power: power(&power_supply_dir.path()),
+                    });
                 }
                 PowerSupplyType::Mains => {
                     let online = read_int_from_file_path(power_supply_dir.path().join("online"))?;
@@ -149,16 +294,153 @@ fn battery_generator(sender: Sender<Message>) -> Result<(), BatteryError> {
         sender.blocking_send(Message::Battery(BatteryMessage::UpdatePowerSupplies(
             power_supplies,
         )))?;
-        thread::sleep(Duration::from_secs(10));
+        sleep_unless_resumed(resumes, Duration::from_secs(10));
     }
 }
 
-pub fn battery_subscription(rt: Handle) -> ReceiverStream<Message> {
+pub fn battery_subscription(
+    rt: Handle,
+    backend: BatteryBackend,
+    mut resumes: watch::Receiver<()>,
+) -> ReceiverStream<Message> {
     let (sender, receiver) = channel(1);
-    rt.clone().spawn_blocking(move || {
-        loop {
-            log::error!("Battery subscription event loop returned, this should never happen, trying to reconnect: {:?}", battery_generator(sender.clone()));
+    match backend {
+        BatteryBackend::Sysfs => {
+            supervise_blocking(&rt, "battery", Backoff::DEFAULT, sender, move |sender| {
+                battery_generator(sender, &mut resumes)
+            })
+        }
+        BatteryBackend::Upower => {
+            supervise(&rt, "battery", Backoff::DEFAULT, sender, upower_generator)
         }
-    });
+    }
     ReceiverStream::new(receiver)
 }
+
+/// The charge of the batteries and peripherals, with the time left
+pub const WIDGET: Widget = Widget {
+    name: "battery",
+    render,
+};
+
+fn render(state: &State) -> Vec<Renderable> {
+    let style = state.config.theme.style("battery");
+    let mut module = Vec::new();
+    for (i, power_supply) in state.power_supply.iter().enumerate() {
+        if matches!(power_supply, PowerSupply::Mains { online: false }) {
+            continue;
+        }
+        if !module.is_empty() {
+            module.push(Renderable::Space(1.));
+        }
+        module.push(match power_supply {
+            PowerSupply::Battery {
+                status,
+                capacity,
+                time_remaining,
+                power,
+            } => {
+                let level = state.battery_levels.get(i).copied().unwrap_or_default();
+                let critical = level == BatteryLevel::Critical;
+                let text = Renderable::Text {
+                    text: format!(
+                        "{} {}",
+                        status.icon(*capacity),
+                        display_percent(*capacity as f32)
+                    )
+                    .into(),
+                    fg: match status {
+                        PowerSupplyStatus::Charging => style.good,
+                        PowerSupplyStatus::Full => style.good,
+                        _ if critical => style.urgent,
+                        _ if level == BatteryLevel::Low => style.warning,
+                        _ => style.fg,
+                    },
+                    bg: style.bg,
+                };
+                let mut tooltip = match (status, time_remaining) {
+                    (PowerSupplyStatus::Charging, Some(time)) => {
+                        format!("{} until full", display_duration(*time))
+                    }
+                    (PowerSupplyStatus::Discharging, Some(time)) => {
+                        format!("{} until empty", display_duration(*time))
+                    }
+                    (PowerSupplyStatus::Full, _) => "Full".to_string(),
+                    (PowerSupplyStatus::NotCharging, _) => "Not charging".to_string(),
+                    _ => "Time remaining unknown".to_string(),
+                };
+                // Shows whether a charger delivers as much as it should
+                match (status, power) {
+                    (PowerSupplyStatus::Charging, Some(power)) => {
+                        tooltip += &format!("\nCharging at {power:.1} W")
+                    }
+                    (PowerSupplyStatus::Discharging, Some(power)) => {
+                        tooltip += &format!("\nDrawing {power:.1} W")
+                    }
+                    _ => {}
+                }
+                Renderable::Region {
+                    tooltip: Some(tooltip),
+                    on_click: None,
+                    on_scroll: None,
+                    children: vec![if critical {
+                        Renderable::Pulse(vec![text])
+                    } else {
+                        text
+                    }],
+                }
+            }
+            PowerSupply::Mains { .. } => Renderable::Text {
+                text: "Plugged".into(),
+                fg: style.fg,
+                bg: style.bg,
+            },
+            PowerSupply::Peripheral {
+                kind,
+                model,
+                capacity,
+            } => Renderable::Region {
+                tooltip: Some(match model {
+                    Some(model) => format!("{model} ({kind})"),
+                    None => kind.clone(),
+                }),
+                on_click: None,
+                on_scroll: None,
+                children: vec![Renderable::Text {
+                    text: format!(
+                        "{} {}",
+                        peripheral_icon(kind),
+                        display_percent(*capacity as f32)
+                    )
+                    .into(),
+                    fg: if *capacity <= state.config.battery.low {
+                        style.warning
+                    } else {
+                        style.dim
+                    },
+                    bg: style.bg,
+                }],
+            },
+        })
+    }
+    module
+}
+
+/// Material design glyph from the nerd fonts for the kind of a peripheral
+/// battery, a plain battery for kinds without one
+fn peripheral_icon(kind: &str) -> &'static str {
+    match kind {
+        "mouse" => "\u{f037d}",
+        "keyboard" => "\u{f030c}",
+        "headset" => "\u{f02ce}",
+        "headphones" => "\u{f02cb}",
+        "controller" => "\u{f0297}",
+        "phone" => "\u{f011c}",
+        _ => "\u{f0079}",
+    }
+}
+
+fn display_duration(duration: Duration) -> String {
+    let minutes = duration.as_secs() / 60;
+    format!("{}h {:02}m", minutes / 60, minutes % 60)
+}
@@ -0,0 +1,176 @@
+use std::{fs, time::Duration};
+
+use inotify::{Inotify, WatchMask};
+use tokio::{
+    runtime::Handle,
+    sync::mpsc::{Sender, channel, error::SendError},
+};
+use tokio_stream::wrappers::ReceiverStream;
+
+use crate::{
+    files::read_string_from_file_path,
+    renderer::Renderable,
+    state::{Message, State},
+    supervisor::{Backoff, supervise_blocking},
+    widget::Widget,
+};
+
+#[derive(Debug)]
+pub enum CameraError {
+    StdIoError(std::io::Error),
+    SendError(SendError<Message>),
+}
+
+impl std::fmt::Display for CameraError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CameraError::StdIoError(e) => write!(f, "IO Error: {e}"),
+            CameraError::SendError(e) => write!(f, "Channel Error: {e}"),
+        }
+    }
+}
+
+impl From<std::io::Error> for CameraError {
+    fn from(value: std::io::Error) -> Self {
+        Self::StdIoError(value)
+    }
+}
+
+impl From<SendError<Message>> for CameraError {
+    fn from(value: SendError<Message>) -> Self {
+        Self::SendError(value)
+    }
+}
+
+#[derive(Debug)]
+pub enum CameraMessage {
+    /// Names of the processes holding a video device open, empty when the
+    /// camera is not in use
+    UsersUpdate(Vec<String>),
+}
+
+/// Whether a file in /dev is a video device
+fn is_video(name: &str) -> bool {
+    name.starts_with("video")
+}
+
+/// Watches the video devices there are for being opened and closed, adding
+/// one that is already watched does nothing. Devices the bar can't read
+/// can't be watched, nor opened by the user
+fn watch_devices(inotify: &Inotify) -> Result<(), CameraError> {
+    for device in fs::read_dir("/dev")? {
+        let device = device?;
+        if !is_video(&device.file_name().to_string_lossy()) {
+            continue;
+        }
+        let watched = inotify.watches().add(
+            device.path(),
+            WatchMask::OPEN | WatchMask::CLOSE_WRITE | WatchMask::CLOSE_NOWRITE,
+        );
+        if let Err(e) = watched {
+            log::debug!("Could not watch {:?}: {e}", device.path());
+        }
+    }
+    Ok(())
+}
+
+/// inotify only says that a device was opened, not by whom, so every
+/// process' fd table is scanned for a /dev/video* link then. Processes of
+/// other users can only be inspected when the bar runs as root, a camera
+/// they hold open doesn't show up otherwise
+fn camera_users() -> Result<Vec<String>, CameraError> {
+    let mut users = Vec::new();
+    for process_dir in fs::read_dir("/proc")? {
+        let process_dir = process_dir?.path();
+        let fds = match fs::read_dir(process_dir.join("fd")) {
+            Ok(fds) => fds,
+            Err(_) => continue,
+        };
+        let uses_camera = fds.filter_map(|fd| fd.ok()).any(|fd| {
+            fs::read_link(fd.path())
+                .is_ok_and(|target| target.to_string_lossy().starts_with("/dev/video"))
+        });
+        if !uses_camera {
+            continue;
+        }
+        if let Ok(name) = read_string_from_file_path(process_dir.join("comm")) {
+            users.push(name.trim().to_string());
+        }
+    }
+    users.sort();
+    users.dedup();
+    Ok(users)
+}
+
+fn camera_generator(sender: Sender<Message>) -> Result<(), CameraError> {
+    let mut inotify = Inotify::init()?;
+    // Cameras plugged in later show up in /dev
+    inotify.watches().add("/dev", WatchMask::CREATE)?;
+    watch_devices(&inotify)?;
+    let mut buffer = [0; 4096];
+    let mut last_users = None;
+    loop {
+        let users = camera_users()?;
+        if last_users.as_ref() != Some(&users) {
+            last_users = Some(users.clone());
+            sender.blocking_send(Message::Camera(CameraMessage::UsersUpdate(users)))?;
+        }
+        loop {
+            let mut plugged = false;
+            let mut used = false;
+            for event in inotify.read_events_blocking(&mut buffer)? {
+                match event.name {
+                    // Created in /dev
+                    Some(name) => plugged |= is_video(&name.to_string_lossy()),
+                    // Opened or closed
+                    None => used = true,
+                }
+            }
+            if plugged {
+                watch_devices(&inotify)?;
+            }
+            if used {
+                break;
+            }
+        }
+    }
+}
+
+pub fn camera_subscription(rt: Handle) -> ReceiverStream<Message> {
+    let (sender, receiver) = channel(1);
+    supervise_blocking(
+        &rt,
+        "camera",
+        Backoff::starting_at(Duration::from_secs(2)),
+        sender,
+        camera_generator,
+    );
+    ReceiverStream::new(receiver)
+}
+
+/// Names the processes using a camera
+pub const WIDGET: Widget = Widget {
+    name: "camera",
+    render,
+};
+
+fn render(state: &State) -> Vec<Renderable> {
+    let style = state.config.theme.style("camera");
+    let mut module = Vec::new();
+    if !state.camera_users.is_empty() {
+        module.push(Renderable::Region {
+            tooltip: Some(format!(
+                "Camera in use by {}",
+                state.camera_users.join(", ")
+            )),
+            on_click: None,
+            on_scroll: None,
+            children: vec![Renderable::Text {
+                text: "cam".into(),
+                fg: style.urgent,
+                bg: style.bg,
+            }],
+        });
+    }
+    module
+}
@@ -1,34 +1,147 @@
+use std::fmt::Write;
 use std::time::Duration;
 use tokio::sync::mpsc::Sender;
-use std::thread;
 
-use tokio::{runtime::Handle, sync::mpsc::channel};
+use chrono::{DateTime, TimeZone, Timelike};
+
+use tokio::{
+    runtime::Handle,
+    sync::{mpsc::channel, watch},
+};
 use tokio_stream::wrappers::ReceiverStream;
 
-use crate::state::Message;
+use crate::{
+    config::ClockConfig,
+    renderer::Renderable,
+    state::{Message, State},
+    supervisor::{Backoff, sleep_unless_resumed, supervise_blocking},
+    widget::Widget,
+};
 
 #[derive(Debug)]
 pub enum ClockMessage {
     TimeUpdate(chrono::DateTime<chrono::Local>),
 }
 
+/// strftime specifiers which show seconds, after the %
+const SECOND_SPECIFIERS: [char; 7] = ['S', 'T', 's', 'X', 'r', 'c', '+'];
+
+/// Whether the format shows seconds. Padding flags like the - of %-S are
+/// skipped, which not every strftime knows, and %% is a literal percent
+fn shows_seconds(format: &str) -> bool {
+    let mut chars = format.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            continue;
+        }
+        let specifier = match chars.next() {
+            Some('-' | '_' | '0') => chars.next(),
+            specifier => specifier,
+        };
+        if specifier.is_some_and(|specifier| SECOND_SPECIFIERS.contains(&specifier)) {
+            return true;
+        }
+    }
+    false
+}
+
+/// Formats the time, invalid formats are shown as such instead of panicking
+/// like chrono's to_string does
+pub fn format_time<Tz: TimeZone>(time: &DateTime<Tz>, format: &str) -> String
+where
+    Tz::Offset: std::fmt::Display,
+{
+    let mut text = String::new();
+    match write!(text, "{}", time.format(format)) {
+        Ok(()) => text,
+        Err(_) => format!("invalid format {format:?}"),
+    }
+}
+
+impl ClockConfig {
+    /// Seconds between ticks, enough to keep every shown format current
+    fn interval(&self) -> u32 {
+        let shows_seconds = std::iter::once(&self.format)
+            .chain(self.secondary.iter().map(|secondary| &secondary.format))
+            .any(|format| shows_seconds(format));
+        if shows_seconds { 1 } else { 60 }
+    }
+}
+
 fn clock_generator(
     sender: Sender<Message>,
+    interval: u32,
+    resumes: &mut watch::Receiver<()>,
 ) -> Result<(), tokio::sync::mpsc::error::SendError<Message>> {
     loop {
-        sender.blocking_send(Message::ClockMessage(ClockMessage::TimeUpdate(
-            chrono::Local::now(),
-        )))?;
-        thread::sleep(Duration::from_mins(1));
+        let now = chrono::Local::now();
+        sender.blocking_send(Message::ClockMessage(ClockMessage::TimeUpdate(now)))?;
+        // Lined up with the turn of the second or minute, so the clock
+        // doesn't lag behind by up to a whole interval
+        let into_interval = Duration::new(
+            (now.num_seconds_from_midnight() % interval) as u64,
+            now.nanosecond().min(999_999_999),
+        );
+        sleep_unless_resumed(
+            resumes,
+            Duration::from_secs(interval as u64).saturating_sub(into_interval),
+        );
     }
 }
 
-pub fn clock_subscription(rt: Handle) -> ReceiverStream<Message> {
+pub fn clock_subscription(
+    rt: Handle,
+    config: ClockConfig,
+    mut resumes: watch::Receiver<()>,
+) -> ReceiverStream<Message> {
     let (sender, receiver) = channel(1);
-    rt.clone().spawn_blocking(move || {
-        loop {
-            log::error!("Clock subscription event loop returned, this should never happen, trying to reconnect {:?}", clock_generator(sender.clone()));
-        }
+    let interval = config.interval();
+    supervise_blocking(&rt, "clock", Backoff::DEFAULT, sender, move |sender| {
+        clock_generator(sender, interval, &mut resumes)
     });
     ReceiverStream::new(receiver)
 }
+
+/// The time, and the time in the secondary timezone
+pub const WIDGET: Widget = Widget {
+    name: "clock",
+    render,
+};
+
+fn render(state: &State) -> Vec<Renderable> {
+    let style = state.config.theme.style("clock");
+    let clock_config = &state.config.clock;
+    let mut module = vec![Renderable::Text {
+        text: format_time(&state.clock, &clock_config.format).into(),
+        fg: style.fg,
+        bg: style.bg,
+    }];
+    if let Some(secondary) = &clock_config.secondary {
+        module.push(Renderable::Space(1.));
+        module.push(Renderable::Text {
+            text: format_time(
+                &state.clock.with_timezone(&secondary.timezone),
+                &secondary.format,
+            )
+            .into(),
+            fg: style.dim,
+            bg: style.bg,
+        });
+    }
+    module
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_seconds_through_padding_flags() {
+        assert!(shows_seconds("%H:%M:%S"));
+        assert!(shows_seconds("%H:%M:%-S"));
+        assert!(shows_seconds("%_S"));
+        assert!(shows_seconds("%T"));
+        assert!(!shows_seconds("%a %d %b %H:%M"));
+        assert!(!shows_seconds("100%%S"));
+    }
+}
@@ -0,0 +1,499 @@
+use std::{collections::BTreeMap, fmt::Debug, sync::OnceLock};
+
+use tokio::{
+    runtime::Handle,
+    sync::mpsc::{Sender, error::SendError},
+};
+use tokio_stream::wrappers::ReceiverStream;
+
+use crate::{
+    action::{Action, ScrollActions},
+    config::WorkspaceSort,
+    error::ShellError,
+    format::superscript,
+    hyprland::{self, Hyprland},
+    renderer::Renderable,
+    state::{Message, State},
+    sway::{self, Sway, SwayMessage, Workspace},
+    theme::Color,
+    widget::Widget,
+    wlr::{self, Wlr},
+};
+
+static COMPOSITOR: OnceLock<Box<dyn Compositor>> = OnceLock::new();
+
+/// What the bar needs from the compositor it runs on. The workspaces and
+/// windows come as SwayMessages whichever it is
+pub trait Compositor: Debug + Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Fails while the compositor can't be reached, like before it started
+    fn connect(&self) -> Result<(), ShellError>;
+
+    /// The workspaces, the focused window, the binding mode and the
+    /// sway-shell commands from bindings
+    fn subscription(&self, rt: Handle) -> ReceiverStream<Message>;
+
+    /// The windows on the focused workspace
+    fn taskbar_subscription(&self, rt: Handle) -> ReceiverStream<Message>;
+
+    /// Runs a command without waiting for it. Commands are in the syntax of
+    /// sway, which the bar and the config build them in, other compositors
+    /// translate those they know and take the rest as their own
+    fn run_command(&self, command: String);
+}
+
+/// The compositor the bar runs on, Hyprland when started in one, any other
+/// without the i3 IPC like River or niri over the Wayland protocols, and
+/// sway otherwise
+pub fn compositor() -> &'static dyn Compositor {
+    COMPOSITOR
+        .get_or_init(|| {
+            if hyprland::running() {
+                Box::new(Hyprland)
+            } else if wlr::selected() {
+                Box::new(Wlr)
+            } else {
+                Box::new(Sway)
+            }
+        })
+        .as_ref()
+}
+
+/// Runs a command on the compositor, see Compositor::run_command
+pub fn run_command(command: String) {
+    compositor().run_command(command)
+}
+
+/// The number a workspace name starts with, -1 without one like sway
+pub(crate) fn workspace_num(name: &str) -> i32 {
+    let digits = name.len() - name.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    name[..digits].parse().unwrap_or(-1)
+}
+
+/// The argument of a sway command like `workspace "name"`, unquoted
+pub(crate) fn unquote(argument: &str) -> String {
+    let argument = argument.trim();
+    match argument.strip_prefix('"').and_then(|a| a.strip_suffix('"')) {
+        Some(quoted) => quoted.replace("\\\"", "\"").replace("\\\\", "\\"),
+        None => argument.to_string(),
+    }
+}
+
+/// What the state was last told about the workspaces, for compositors that
+/// only say that something changed. Each sync sends the difference to what
+/// they are now as the SwayMessages sway would have sent
+#[derive(Debug, Default)]
+pub(crate) struct SentWorkspaces {
+    workspaces: BTreeMap<i64, Workspace>,
+    window_name: Option<Option<String>>,
+    apps: Option<BTreeMap<String, Vec<String>>>,
+}
+
+impl SentWorkspaces {
+    pub(crate) fn get_mut(&mut self, id: i64) -> Option<&mut Workspace> {
+        self.workspaces.get_mut(&id)
+    }
+
+    /// Apps are None where the compositor doesn't say which windows are on
+    /// which workspace, they are never sent then
+    pub(crate) fn sync(
+        &mut self,
+        workspaces: BTreeMap<i64, Workspace>,
+        window_name: Option<String>,
+        apps: Option<BTreeMap<String, Vec<String>>>,
+        sender: &Sender<Message>,
+    ) -> Result<(), SendError<Message>> {
+        let send = |message| sender.blocking_send(Message::Sway(message));
+        for id in self.workspaces.keys() {
+            if !workspaces.contains_key(id) {
+                send(SwayMessage::WorkspaceDel(*id))?;
+            }
+        }
+        for (id, workspace) in &workspaces {
+            let Some(old) = self.workspaces.get(id) else {
+                send(SwayMessage::WorkspaceAdd(workspace.clone()))?;
+                continue;
+            };
+            // Moved to another output
+            if old.output != workspace.output {
+                send(SwayMessage::WorkspaceDel(*id))?;
+                send(SwayMessage::WorkspaceAdd(workspace.clone()))?;
+                continue;
+            }
+            if old.name != workspace.name {
+                send(SwayMessage::WorkspaceRename {
+                    id: *id,
+                    name: workspace.name.clone(),
+                })?;
+            }
+            if old.visible != workspace.visible {
+                send(SwayMessage::WorkspaceChangeVisiblity {
+                    id: *id,
+                    visible: workspace.visible,
+                })?;
+            }
+            if old.focused != workspace.focused || old.focus != workspace.focus {
+                send(SwayMessage::WorkspaceChangeFocus {
+                    id: *id,
+                    focus: workspace.focus.clone(),
+                    focused: workspace.focused,
+                })?;
+            }
+            if old.urgent != workspace.urgent {
+                send(SwayMessage::WorkspaceChangeUrgency {
+                    id: *id,
+                    urgent: workspace.urgent,
+                })?;
+            }
+        }
+        if self.window_name.as_ref() != Some(&window_name) {
+            send(SwayMessage::WindowFocusedChange {
+                window_name: window_name.clone(),
+            })?;
+            self.window_name = Some(window_name);
+        }
+        if let Some(apps) = apps
+            && self.apps.as_ref() != Some(&apps)
+        {
+            send(SwayMessage::WorkspaceApps(apps.clone()))?;
+            self.apps = Some(apps);
+        }
+        self.workspaces = workspaces;
+        Ok(())
+    }
+}
+
+impl Compositor for Sway {
+    fn name(&self) -> &'static str {
+        "sway"
+    }
+
+    fn connect(&self) -> Result<(), ShellError> {
+        swayipc::Connection::new()
+            .map(|_| ())
+            .map_err(|e| ShellError::Sway(e.into()))
+    }
+
+    fn subscription(&self, rt: Handle) -> ReceiverStream<Message> {
+        sway::sway_subscription(rt)
+    }
+
+    fn taskbar_subscription(&self, rt: Handle) -> ReceiverStream<Message> {
+        crate::taskbar::taskbar_subscription(rt)
+    }
+
+    fn run_command(&self, command: String) {
+        sway::run_command(command)
+    }
+}
+
+/// The workspaces in the configured order, with the glyphs of their windows
+pub const WORKSPACES: Widget = Widget {
+    name: "workspaces",
+    render: render_workspaces,
+};
+
+fn render_workspaces(state: &State) -> Vec<Renderable> {
+    let style = state.config.theme.style("workspaces");
+    // Only while both ends of the slide are still around
+    let slide = state.workspace_slide.filter(|(from, _)| {
+        state
+            .workspaces
+            .iter()
+            .any(|workspace| workspace.id == *from)
+            && state.workspaces.iter().any(|workspace| workspace.focused)
+    });
+    let mut module = Vec::new();
+    let (mut from, mut to) = (0, 0);
+    let grouped = state.config.workspaces.sort == WorkspaceSort::Output;
+    let mut output = None;
+    for workspace in state.workspaces.iter() {
+        if !module.is_empty() {
+            module.push(Renderable::Space(1.));
+        }
+        if grouped && output.is_some_and(|output| output != &workspace.output) {
+            module.push(Renderable::Text {
+                text: "\u{2502}".into(),
+                fg: style.dim,
+                bg: style.bg,
+            });
+            module.push(Renderable::Space(1.));
+        }
+        output = Some(&workspace.output);
+        if slide.is_some_and(|(id, _)| id == workspace.id) {
+            from = module.len();
+        }
+        if workspace.focused {
+            to = module.len();
+        }
+        let mut text = match &workspace.name {
+            Some(name) => name.to_string(),
+            None => workspace.num.to_string(),
+        };
+        let apps = workspace.name.as_deref().unwrap_or_default();
+        if state.config.workspaces.window_count {
+            let count = state.workspace_apps.get(apps).map_or(0, Vec::len);
+            if count > 0 {
+                text.push_str(&superscript(count));
+            }
+        }
+        let icons = workspace_icons(state, apps);
+        if !icons.is_empty() {
+            text = format!("{text} {}", icons.join(" "));
+        }
+        // The slide draws the highlight behind the focused workspace
+        let bg = if slide.is_some() && workspace.focused {
+            Color::TRANSPARENT.into()
+        } else if workspace.visible {
+            style.dim
+        } else {
+            style.bg
+        };
+        if workspace.urgent {
+            module.push(Renderable::Pulse(vec![Renderable::Text {
+                text: text.into(),
+                fg: style.urgent,
+                bg,
+            }]));
+        } else {
+            module.push(Renderable::Text {
+                text: text.into(),
+                fg: if workspace.visible {
+                    style.fg
+                } else {
+                    style.dim
+                },
+                bg,
+            });
+        }
+    }
+    if module.is_empty() {
+        return module;
+    }
+    if let Some((_, since)) = slide {
+        module = vec![Renderable::Slide {
+            bg: style.dim,
+            from,
+            to,
+            since,
+            children: module,
+        }];
+    }
+    vec![Renderable::Region {
+        tooltip: None,
+        on_click: None,
+        on_scroll: Some(ScrollActions {
+            up: Action::CycleWorkspace(-1),
+            down: Action::CycleWorkspace(1),
+            sensitivity: state.scroll_sensitivity("workspaces"),
+        }),
+        children: module,
+    }]
+}
+
+/// Title of the focused window, or what was plugged into the audio jack
+pub const WINDOW: Widget = Widget {
+    name: "window",
+    render: render_window,
+};
+
+fn render_window(state: &State) -> Vec<Renderable> {
+    let style = state.config.theme.style("window");
+    let mut module = Vec::new();
+    if let Some((jack_osd, _)) = &state.audio_state.jack_osd {
+        module.push(Renderable::Text {
+            text: jack_osd.clone().into(),
+            fg: state.config.theme.background.into(),
+            bg: style.fg,
+        })
+    } else if let Some(window_name) = &state.focused_window_name {
+        module.push(Renderable::Text {
+            text: window_name.clone().into(),
+            fg: style.fg,
+            bg: style.bg,
+        })
+    }
+    module
+}
+
+/// The binding mode while it isn't the default one
+pub const MODE: Widget = Widget {
+    name: "mode",
+    render: render_mode,
+};
+
+fn render_mode(state: &State) -> Vec<Renderable> {
+    let style = state.config.theme.style("mode");
+    match &state.binding_mode {
+        Some(mode) => vec![Renderable::Text {
+            text: mode.clone().into(),
+            fg: state.config.theme.background.into(),
+            bg: style.accent,
+        }],
+        None => vec![],
+    }
+}
+
+/// Glyph of the app in the config, the default one for the others
+pub(crate) fn app_icon<'a>(state: &'a State, app_id: &str) -> Option<&'a str> {
+    let config = &state.config.workspaces;
+    config
+        .app_icons
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(app_id))
+        .map(|(_, icon)| icon)
+        .or(config.default_icon.as_ref())
+        .map(String::as_str)
+}
+
+/// Glyphs of the windows on the workspace going by the config, each once
+pub(crate) fn workspace_icons<'a>(state: &'a State, workspace: &str) -> Vec<&'a str> {
+    let mut icons = Vec::new();
+    for app_id in state.workspace_apps.get(workspace).into_iter().flatten() {
+        // Windows without an app_id are only counted
+        if app_id.is_empty() {
+            continue;
+        }
+        if let Some(icon) = app_icon(state, app_id)
+            && !icons.contains(&icon)
+        {
+            icons.push(icon);
+        }
+    }
+    icons
+}
+
+#[cfg(test)]
+mod tests {
+    use swayipc::Rect;
+    use tokio::sync::mpsc::channel;
+
+    use super::*;
+
+    fn workspace(id: i64, name: &str, output: &str) -> Workspace {
+        Workspace {
+            id,
+            num: workspace_num(name),
+            name: Some(name.to_string()),
+            layout: "none".to_string(),
+            visible: false,
+            focused: false,
+            urgent: false,
+            representation: None,
+            orientation: "none".to_string(),
+            rect: Rect {
+                x: 0,
+                y: 0,
+                width: 0,
+                height: 0,
+            },
+            output: output.to_string(),
+            focus: Vec::new(),
+        }
+    }
+
+    /// What a sync sent, in order
+    fn sync(
+        sent: &mut SentWorkspaces,
+        workspaces: impl IntoIterator<Item = Workspace>,
+        window_name: Option<&str>,
+        apps: Option<BTreeMap<String, Vec<String>>>,
+    ) -> Vec<SwayMessage> {
+        let (sender, mut receiver) = channel(16);
+        let workspaces = workspaces
+            .into_iter()
+            .map(|workspace| (workspace.id, workspace))
+            .collect();
+        sent.sync(workspaces, window_name.map(str::to_string), apps, &sender)
+            .unwrap();
+        let mut messages = Vec::new();
+        while let Ok(Message::Sway(message)) = receiver.try_recv() {
+            messages.push(message);
+        }
+        messages
+    }
+
+    #[test]
+    fn sends_what_changed_since_the_last_sync() {
+        let mut sent = SentWorkspaces::default();
+        let mut web = workspace(1, "1: web", "eDP-1");
+        web.visible = true;
+        web.focused = true;
+        let messages = sync(
+            &mut sent,
+            [web.clone(), workspace(2, "2", "eDP-1")],
+            Some("a"),
+            None,
+        );
+        assert!(matches!(
+            messages.as_slice(),
+            [
+                SwayMessage::WorkspaceAdd(Workspace { id: 1, .. }),
+                SwayMessage::WorkspaceAdd(Workspace { id: 2, .. }),
+                SwayMessage::WindowFocusedChange {
+                    window_name: Some(_)
+                },
+            ]
+        ));
+
+        // Nothing changed, nothing is sent
+        assert!(
+            sync(
+                &mut sent,
+                [web.clone(), workspace(2, "2", "eDP-1")],
+                Some("a"),
+                None
+            )
+            .is_empty()
+        );
+
+        let mut code = workspace(2, "2: code", "eDP-1");
+        code.urgent = true;
+        web.focused = false;
+        let messages = sync(&mut sent, [web.clone(), code], Some("a"), None);
+        assert!(matches!(
+            messages.as_slice(),
+            [
+                SwayMessage::WorkspaceChangeFocus {
+                    id: 1,
+                    focused: false,
+                    ..
+                },
+                SwayMessage::WorkspaceRename { id: 2, .. },
+                SwayMessage::WorkspaceChangeUrgency {
+                    id: 2,
+                    urgent: true
+                },
+            ]
+        ));
+
+        // Moving to another output takes it off the old one first
+        let messages = sync(&mut sent, [workspace(1, "1: web", "DP-1")], None, None);
+        assert!(matches!(
+            messages.as_slice(),
+            [
+                SwayMessage::WorkspaceDel(2),
+                SwayMessage::WorkspaceDel(1),
+                SwayMessage::WorkspaceAdd(Workspace { id: 1, .. }),
+                SwayMessage::WindowFocusedChange { window_name: None },
+            ]
+        ));
+    }
+
+    #[test]
+    fn sends_apps_only_when_known() {
+        let mut sent = SentWorkspaces::default();
+        let apps = BTreeMap::from([("1".to_string(), vec!["firefox".to_string()])]);
+        let messages = sync(&mut sent, [], None, Some(apps.clone()));
+        assert!(matches!(
+            messages.as_slice(),
+            [
+                SwayMessage::WindowFocusedChange { .. },
+                SwayMessage::WorkspaceApps(_)
+            ]
+        ));
+        assert!(sync(&mut sent, [], None, Some(apps)).is_empty());
+        assert!(sync(&mut sent, [], None, None).is_empty());
+    }
+}
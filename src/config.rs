@@ -0,0 +1,846 @@
+use std::{collections::BTreeMap, env::VarError, path::PathBuf, sync::Arc};
+
+use serde::Deserialize;
+
+use crate::{action::MouseBindings, theme::Theme};
+
+#[derive(Debug)]
+pub enum ConfigError {
+    VarError(VarError),
+    StdIoError(std::io::Error),
+    TomlError(toml::de::Error),
+}
+
+impl From<VarError> for ConfigError {
+    fn from(value: VarError) -> Self {
+        Self::VarError(value)
+    }
+}
+
+impl From<std::io::Error> for ConfigError {
+    fn from(value: std::io::Error) -> Self {
+        Self::StdIoError(value)
+    }
+}
+
+impl From<toml::de::Error> for ConfigError {
+    fn from(value: toml::de::Error) -> Self {
+        Self::TomlError(value)
+    }
+}
+
+/// Read from $XDG_CONFIG_HOME/sway-shell/config.toml, every field is optional
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub workspaces: WorkspacesConfig,
+    pub thermal: ThermalConfig,
+    pub cpu: CpuConfig,
+    pub battery: BatteryConfig,
+    pub clock: ClockConfig,
+    pub libvirt: LibvirtConfig,
+    pub font: FontConfig,
+    pub palette: PaletteConfig,
+    pub power: PowerConfig,
+    pub gpu: GpuConfig,
+    pub layout: LayoutConfig,
+    pub theme: Theme,
+    pub cache: CacheConfig,
+    pub autohide: AutohideConfig,
+    pub ambient: AmbientConfig,
+    pub media: MediaConfig,
+    pub nvme: NvmeConfig,
+    pub mqtt: MqttConfig,
+    pub hosts: HostsConfig,
+    pub mic: MicConfig,
+    pub update: UpdateConfig,
+    pub timer: TimerConfig,
+    pub night_light: NightLightConfig,
+    /// The edge of the output the bar runs along. Needs a restart to change
+    pub position: BarPosition,
+    /// Least milliseconds between redraws for changes from a subscription,
+    /// by its name in main.rs like "network" or "mpd". Changes in between
+    /// are still applied, they just show up together once the time is up
+    pub throttle: BTreeMap<String, u64>,
+    /// Touchpad scroll sensitivity by module name, like "workspaces" or
+    /// "backlight". At 1 a step takes as much scrolling as a wheel notch
+    /// usually does, at 2 half of that. Mouse wheels take a step per notch
+    /// either way
+    pub scroll_sensitivity: BTreeMap<String, f32>,
+    /// Widest a module may get in pixels, by its name like "window" or "mpd".
+    /// Text past it is cut off with an ellipsis. The window and mpd modules
+    /// are kept to 240 and 400 unless set here
+    pub max_width: BTreeMap<String, u32>,
+    /// What the mouse buttons do on a module, by its name like "workspaces",
+    /// taking the place of what it does on its own. Like
+    /// [mouse.clock] right = { spawn = "gnome-calendar" }, or
+    /// middle = { sway_command = "workspace back_and_forth" }
+    pub mouse: BTreeMap<String, MouseBindings>,
+    /// Modules running a command, put in the layout as custom/<name>
+    pub custom: BTreeMap<String, CustomConfig>,
+    /// Modules showing what is in a file, put in the layout as watch/<name>
+    pub watch: BTreeMap<String, WatchConfig>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct WorkspacesConfig {
+    /// Scrolling past the last workspace goes on with the first
+    pub scroll_wrap: bool,
+    /// Scrolling passes over workspaces without windows, like the ones
+    /// shown on other outputs
+    pub scroll_skip_empty: bool,
+    /// Glyphs shown after the workspace name for the windows on it, by
+    /// app_id or X11 class ignoring case, like { firefox = "\uf269" }.
+    /// Windows with the same glyph show it once
+    pub app_icons: BTreeMap<String, String>,
+    /// Glyph for windows missing from app_icons, without it they are left out
+    pub default_icon: Option<String>,
+    pub sort: WorkspaceSort,
+    /// Shows how many windows are on each workspace as a superscript after
+    /// its name, none on empty ones
+    pub window_count: bool,
+}
+
+/// The order of the workspaces module
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum WorkspaceSort {
+    /// By number, with the workspaces that have none first
+    #[default]
+    Numeric,
+    /// By name ignoring case, so 10 comes before 2
+    Alphabetical,
+    /// Grouped by output in name order with a separator between the groups,
+    /// by number within them
+    Output,
+    /// The most recently focused first, the ones not focused yet after them
+    /// by number. Scrolling still goes by number
+    Recent,
+}
+
+impl Default for WorkspacesConfig {
+    fn default() -> Self {
+        Self {
+            scroll_wrap: true,
+            scroll_skip_empty: false,
+            app_icons: BTreeMap::new(),
+            default_icon: None,
+            sort: WorkspaceSort::default(),
+            window_count: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ThermalConfig {
+    /// Temperatures in celsius at which the text takes the theme's warning
+    /// and urgent colors
+    pub warning: f32,
+    pub critical: f32,
+    /// What the CPU entry can show, clicking it goes to the next one
+    pub cpu_views: Vec<CpuView>,
+}
+
+impl Default for ThermalConfig {
+    fn default() -> Self {
+        Self {
+            warning: 75.,
+            critical: 90.,
+            cpu_views: vec![CpuView::Temperature, CpuView::Usage],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct CpuConfig {
+    /// Switches the cpufreq governor, run through `sh -c` with {governor}
+    /// replaced. Needs to be privileged, polkit asks for the password
+    pub governor_command: String,
+}
+
+impl Default for CpuConfig {
+    fn default() -> Self {
+        Self {
+            governor_command: "pkexec cpupower frequency-set -g {governor}".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CpuView {
+    Temperature,
+    Usage,
+    /// The highest and the average frequency over the cores
+    Frequency,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct BatteryConfig {
+    /// Percentages at and below which a discharging battery takes the
+    /// theme's warning and urgent colors
+    pub low: usize,
+    /// The battery module also flashes at and below this
+    pub critical: usize,
+    /// Percentage at which a notification is sent, once per discharge
+    pub notify: usize,
+    /// Percentage at which the system suspends, off unless set
+    pub suspend: Option<usize>,
+    /// Seconds the suspend countdown runs for, during which clicking it
+    /// cancels the suspend
+    pub suspend_countdown: u64,
+    /// Percentage points a battery has to get back above low, critical or
+    /// notify before it counts as above them again, so a reading going back
+    /// and forth over one doesn't make the colors flap
+    pub hysteresis: usize,
+    /// Where the batteries are read from
+    pub backend: BatteryBackend,
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BatteryBackend {
+    /// Polls /sys/class/power_supply
+    #[default]
+    Sysfs,
+    /// Follows UPower over D-Bus, which also knows the batteries of mice,
+    /// keyboards and headsets
+    Upower,
+}
+
+impl Default for BatteryConfig {
+    fn default() -> Self {
+        Self {
+            low: 20,
+            critical: 10,
+            notify: 15,
+            suspend: None,
+            suspend_countdown: 60,
+            hysteresis: 2,
+            backend: BatteryBackend::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ClockConfig {
+    /// chrono strftime format, the clock ticks every second if it shows
+    /// seconds and every minute otherwise. Padding flags like the - of %-S
+    /// are chrono's own, %S and the like read the same everywhere
+    pub format: String,
+    /// Another timezone shown after the local time
+    pub secondary: Option<SecondaryClockConfig>,
+}
+
+impl Default for ClockConfig {
+    fn default() -> Self {
+        Self {
+            format: "%a %d %b %H:%M".to_string(),
+            secondary: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SecondaryClockConfig {
+    /// IANA name like "America/New_York"
+    #[serde(deserialize_with = "deserialize_timezone")]
+    pub timezone: chrono_tz::Tz,
+    #[serde(default = "SecondaryClockConfig::default_format")]
+    pub format: String,
+}
+
+impl SecondaryClockConfig {
+    fn default_format() -> String {
+        "%H:%M %Z".to_string()
+    }
+}
+
+fn deserialize_timezone<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<chrono_tz::Tz, D::Error> {
+    let name = String::deserialize(deserializer)?;
+    name.parse()
+        .map_err(|_| serde::de::Error::custom(format!("{name:?} is not a known timezone")))
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct CustomConfig {
+    /// Run through `sh -c`, see CustomOutput::parse for what it can print
+    pub exec: String,
+    /// Seconds between runs, at least one. Without one the command is run
+    /// once and every line it prints is an update
+    pub interval: Option<u64>,
+    /// Run through `sh -c` when the module is clicked
+    pub on_click: Option<String>,
+    /// Whether the text is markup like `<span fg="#ff0000">red</span>`, see
+    /// markup::parse
+    #[serde(default)]
+    pub markup: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct WatchConfig {
+    /// Absolute path of the file, read again whenever it is written
+    pub path: String,
+    /// What the module shows, with {contents} for the whole file and {1},
+    /// {2} and so on for its lines
+    #[serde(default = "default_watch_format")]
+    pub format: String,
+    /// Same placeholders as format
+    pub tooltip: Option<String>,
+    /// Run through `sh -c` when the module is clicked
+    pub on_click: Option<String>,
+}
+
+fn default_watch_format() -> String {
+    "{1}".to_string()
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct MqttConfig {
+    /// Broker to connect to, nothing connects without one
+    pub host: Option<String>,
+    pub port: u16,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub client_id: String,
+    /// Modules showing the payloads of a topic, put in the layout as
+    /// mqtt/<name>
+    pub modules: BTreeMap<String, MqttModuleConfig>,
+}
+
+impl Default for MqttConfig {
+    fn default() -> Self {
+        Self {
+            host: None,
+            port: 1883,
+            username: None,
+            password: None,
+            client_id: "sway-shell".to_string(),
+            modules: BTreeMap::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct MqttModuleConfig {
+    /// Can have + and # wildcards, the latest payload on any match is shown
+    pub topic: String,
+    /// What the module shows, with {payload} for the whole payload and
+    /// {key} or {key.inner} for fields of a JSON one
+    #[serde(default = "default_mqtt_format")]
+    pub format: String,
+    /// Same placeholders as format
+    pub tooltip: Option<String>,
+    /// Run through `sh -c` when the module is clicked
+    pub on_click: Option<String>,
+}
+
+fn default_mqtt_format() -> String {
+    "{payload}".to_string()
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct LibvirtConfig {
+    /// Connection URI handed to virsh -c
+    pub uri: String,
+    /// Seconds between polls
+    pub interval: u64,
+}
+
+impl Default for LibvirtConfig {
+    fn default() -> Self {
+        Self {
+            uri: "qemu:///system".to_string(),
+            interval: 10,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct FontConfig {
+    /// Tried in order for chars the embedded font has no glyph for, either
+    /// paths to font files or family names resolved through fontconfig
+    pub fallback: Vec<String>,
+}
+
+impl Default for FontConfig {
+    fn default() -> Self {
+        Self {
+            fallback: vec![
+                "Symbols Nerd Font".to_string(),
+                "Noto Sans CJK JP".to_string(),
+            ],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct PaletteConfig {
+    /// Extra palette entries, from the label typed for to the command run
+    /// through `sh -c`
+    pub commands: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct PowerConfig {
+    /// Entries of the power menu, from the label to the command run through
+    /// `sh -c` once confirmed
+    pub commands: BTreeMap<String, String>,
+}
+
+impl Default for PowerConfig {
+    fn default() -> Self {
+        Self {
+            commands: BTreeMap::from([
+                ("log out".to_string(), "swaymsg exit".to_string()),
+                ("reboot".to_string(), "systemctl reboot".to_string()),
+                ("shut down".to_string(), "systemctl poweroff".to_string()),
+                ("suspend".to_string(), "systemctl suspend".to_string()),
+            ]),
+        }
+    }
+}
+
+/// Mirrors wgpu::PowerPreference, which is only deserializable with its
+/// serde feature
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PowerPreference {
+    None,
+    /// Usually the integrated GPU, plenty for a bar
+    #[default]
+    LowPower,
+    HighPerformance,
+}
+
+impl From<PowerPreference> for wgpu::PowerPreference {
+    fn from(value: PowerPreference) -> Self {
+        match value {
+            PowerPreference::None => Self::None,
+            PowerPreference::LowPower => Self::LowPower,
+            PowerPreference::HighPerformance => Self::HighPerformance,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct GpuConfig {
+    pub power_preference: PowerPreference,
+    /// Picks the first adapter whose name contains this, ignoring case,
+    /// over the power preference
+    pub adapter: Option<String>,
+    /// Seconds without changes or input after which animations, like
+    /// sliding text or pulses, are only drawn at idle_fps. 0 never slows
+    /// them down
+    pub idle_after: u64,
+    pub idle_fps: u32,
+}
+
+impl Default for GpuConfig {
+    fn default() -> Self {
+        Self {
+            power_preference: PowerPreference::default(),
+            adapter: None,
+            idle_after: 10,
+            idle_fps: 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct CacheConfig {
+    /// MiB the caches may take up together, the glyphs, the shaped text and
+    /// the image atlas. Shaped text gets up to a quarter of it, the atlas
+    /// keeps a fixed size and the glyphs get the rest. The least recently
+    /// used glyphs and text are dropped once over
+    pub budget: usize,
+    /// Shows the cache usage, the watchdog restarts and whether memory
+    /// outside the caches keeps growing at the end of the left section
+    pub overlay: bool,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            budget: 16,
+            overlay: false,
+        }
+    }
+}
+
+impl CacheConfig {
+    pub fn budget_bytes(&self) -> usize {
+        self.budget.saturating_mul(1024 * 1024)
+    }
+}
+
+/// Layout goes along the bar either way, side bars draw it turned a quarter
+/// clockwise so text reads top to bottom
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum BarPosition {
+    #[default]
+    Top,
+    Left,
+    Right,
+}
+
+impl BarPosition {
+    pub fn vertical(self) -> bool {
+        matches!(self, Self::Left | Self::Right)
+    }
+
+    /// Swaps the x and y of a size or a point on a side bar, going between
+    /// surface pixels and the length along the bar and across it
+    pub fn along<T>(self, (x, y): (T, T)) -> (T, T) {
+        if self.vertical() { (y, x) } else { (x, y) }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct AutohideConfig {
+    /// Gives the space of the bar to windows and slides it off the screen
+    /// until the pointer touches its edge. Needs a restart to change
+    pub enabled: bool,
+    /// Milliseconds the bar stays out after the pointer leaves it
+    pub delay: u64,
+    /// Milliseconds sliding in or out takes
+    pub duration: u64,
+}
+
+impl Default for AutohideConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            delay: 1000,
+            duration: 200,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct AmbientConfig {
+    /// Tints the background of the bar towards the color of what is next to
+    /// it, sampled over wlr-screencopy. Needs a restart to change
+    pub enabled: bool,
+    /// Seconds between samples
+    pub interval: u64,
+    /// How far the background goes towards the sampled color, from 0 for
+    /// not at all to 1 for all the way
+    pub strength: f32,
+    /// How much of the color carries over from the samples before, from 0
+    /// for following each sample right away to just below 1 for barely
+    /// changing
+    pub smoothing: f32,
+    /// The output sampled, by name like "DP-1". The first one otherwise
+    pub output: Option<String>,
+}
+
+impl Default for AmbientConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            interval: 5,
+            strength: 0.3,
+            smoothing: 0.7,
+            output: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct NvmeConfig {
+    /// Prints the SMART data of {device} as JSON like smartctl does. Opening
+    /// the device needs root, so this may have to go through sudo
+    pub command: String,
+    /// Seconds between polls, wear doesn't change quickly
+    pub interval: u64,
+    /// Percent of the rated endurance used at which the warning icon shows
+    pub wear_warning: u32,
+}
+
+impl Default for NvmeConfig {
+    fn default() -> Self {
+        Self {
+            command: "smartctl -a -j {device}".to_string(),
+            interval: 3600,
+            wear_warning: 80,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct MicConfig {
+    /// Whether the level meter captures from the start, rather than after
+    /// clicking the mic module
+    pub enabled: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct UpdateConfig {
+    /// Whether to ask GitHub for new releases at all, off unless set
+    pub enabled: bool,
+    /// The owner/name of the repository whose releases are checked
+    pub repository: String,
+    /// Seconds between checks
+    pub interval: u64,
+}
+
+impl Default for UpdateConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            repository: "eternalfrustation/sway-shell".to_string(),
+            interval: 7 * 24 * 3600,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct TimerConfig {
+    /// Seconds the timer runs for after a reset, a day at most
+    pub duration: u64,
+    /// Seconds a scroll step over the module adds or takes away
+    pub step: u64,
+    /// Seconds the module flashes for once the timer runs out, 0 for not
+    /// at all
+    pub flash: u64,
+    /// Whether running out also sends a notification
+    pub notify: bool,
+}
+
+impl Default for TimerConfig {
+    fn default() -> Self {
+        Self {
+            duration: 25 * 60,
+            step: 60,
+            flash: 10,
+            notify: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct NightLightConfig {
+    /// How the night light is turned on and off. Needs a restart to change
+    pub backend: NightLightBackend,
+    /// Run while the night light is on and killed to turn it off, with
+    /// {temperature} replaced by the temperature in kelvin
+    pub command: String,
+    /// The name of the running gammastep or wlsunset the signal backend
+    /// toggles
+    pub process: String,
+    /// Kelvin the command starts with
+    pub temperature: u32,
+    /// Kelvin a scroll step over the module adds or takes away
+    pub step: u32,
+    /// The warmest and coldest scrolling gets to, in kelvin
+    pub min_temperature: u32,
+    pub max_temperature: u32,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NightLightBackend {
+    /// Runs the command, and again at the new temperature after scrolling
+    #[default]
+    Command,
+    /// Sends SIGUSR1 to an already running gammastep or wlsunset, which
+    /// they take as a toggle. Their temperature stays their own, scrolling
+    /// over the module does nothing
+    Signal,
+}
+
+impl Default for NightLightConfig {
+    fn default() -> Self {
+        Self {
+            backend: NightLightBackend::default(),
+            command: "gammastep -O {temperature}".to_string(),
+            process: "gammastep".to_string(),
+            temperature: 4500,
+            step: 250,
+            min_temperature: 2500,
+            max_temperature: 6500,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct HostsConfig {
+    /// Seconds between probes
+    pub interval: u64,
+    /// Milliseconds a connection may take before its host counts as down
+    pub timeout: u64,
+    /// Shown as a dot each, in this order
+    pub hosts: Vec<HostConfig>,
+}
+
+impl Default for HostsConfig {
+    fn default() -> Self {
+        Self {
+            interval: 30,
+            timeout: 2000,
+            hosts: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct HostConfig {
+    pub name: String,
+    /// host:port to connect to over TCP, like nas.lan:22
+    pub address: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct MediaConfig {
+    /// Players the media module prefers when more than one is playing, or
+    /// none is and more than one is paused. MPRIS players go by their bus
+    /// name like "spotify" or "firefox", MPD by "mpd". Players not listed
+    /// come after the listed ones
+    pub priority: Vec<String>,
+    /// What the module shows, with {title} and {artist}. It is markup, so
+    /// parts can be colored like `{title} <span fg="#888888">{artist}</span>`
+    pub format: String,
+}
+
+impl Default for MediaConfig {
+    fn default() -> Self {
+        Self {
+            priority: vec!["mpd".to_string()],
+            format: "{title}".to_string(),
+        }
+    }
+}
+
+/// Which modules go in which section of the bar, in order. See
+/// widget::WIDGETS for the names, plus custom/<name> for custom modules
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct LayoutConfig {
+    pub left: Vec<Arc<str>>,
+    pub center: Vec<Arc<str>>,
+    pub right: Vec<Arc<str>>,
+    /// What the center section does when the left or right one grows into it
+    pub center_collision: CenterCollision,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CenterCollision {
+    /// Moves aside as far as there is room, and is cut off where there is
+    /// none left
+    #[default]
+    Push,
+    /// Stays centered and is cut off where it overlaps
+    Clip,
+    /// Is hidden while it would overlap
+    Yield,
+}
+
+impl Default for LayoutConfig {
+    fn default() -> Self {
+        let names = |names: &[&str]| names.iter().map(|&name| Arc::from(name)).collect();
+        Self {
+            left: names(&["workspaces", "mpd"]),
+            center: names(&["window"]),
+            right: names(&[
+                "camera",
+                "network",
+                "audio",
+                "bluetooth",
+                "backlight",
+                "battery",
+                "thermal",
+                "governor",
+                "memory",
+                "docker",
+                "kdeconnect",
+                "libvirt",
+                "systemd",
+                "clock",
+            ]),
+            center_collision: CenterCollision::default(),
+        }
+    }
+}
+
+impl LayoutConfig {
+    /// Whether any section shows the module, feeds only it needs aren't
+    /// started otherwise
+    pub fn contains(&self, module: &str) -> bool {
+        [&self.left, &self.center, &self.right]
+            .into_iter()
+            .flatten()
+            .any(|name| &**name == module)
+    }
+}
+
+impl Config {
+    pub fn path() -> Result<PathBuf, ConfigError> {
+        let config_dir = match std::env::var("XDG_CONFIG_HOME") {
+            Ok(dir) => PathBuf::from(dir),
+            Err(_) => PathBuf::from(std::env::var("HOME")?).join(".config"),
+        };
+        Ok(config_dir.join("sway-shell/config.toml"))
+    }
+
+    pub fn parse(config: &str) -> Result<Self, ConfigError> {
+        Ok(toml::from_str(config)?)
+    }
+
+    pub fn try_load() -> Result<Self, ConfigError> {
+        let path = Self::path()?;
+        if !path.exists() {
+            log::info!("No config found at {path:?}, using the defaults");
+            return Ok(Self::default());
+        }
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+
+    /// A path, or the name of a file in the themes directory next to the
+    /// config, written like the [theme] table
+    pub fn load_theme(name: &str) -> Result<Theme, ConfigError> {
+        let path = if name.contains('/') {
+            PathBuf::from(name)
+        } else {
+            Self::path()?.with_file_name("themes").join(format!("{name}.toml"))
+        };
+        Ok(toml::from_str(&std::fs::read_to_string(path)?)?)
+    }
+
+    /// A broken config shouldn't take the bar down with it, so errors are
+    /// logged and the defaults are used instead
+    pub fn load() -> Self {
+        match Self::try_load() {
+            Ok(config) => config,
+            Err(e) => {
+                log::error!("Could not load the config, using the defaults: {e:?}");
+                Self::default()
+            }
+        }
+    }
+}
@@ -0,0 +1,213 @@
+use std::{fs, num::ParseIntError, path::Path, time::Duration};
+
+use tokio::{
+    runtime::Handle,
+    sync::{
+        mpsc::{Sender, channel, error::SendError},
+        watch,
+    },
+};
+use tokio_stream::wrappers::ReceiverStream;
+
+use crate::{
+    action::Action,
+    files::{read_int_from_file_path, read_string_from_file_path},
+    palette::PaletteMode,
+    renderer::Renderable,
+    state::{Message, State},
+    supervisor::{Backoff, sleep_unless_resumed, supervise_blocking},
+    widget::Widget,
+};
+
+#[derive(Debug)]
+pub enum CpuError {
+    StdIoError(std::io::Error),
+    ParseIntError(ParseIntError),
+    /// /proc/stat has no cpu line, or too few fields on it
+    MissingTimes,
+    SendError(SendError<Message>),
+}
+
+impl std::fmt::Display for CpuError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CpuError::StdIoError(e) => write!(f, "IO Error: {e}"),
+            CpuError::ParseIntError(e) => write!(f, "Invalid number in /proc/stat: {e}"),
+            CpuError::MissingTimes => write!(f, "/proc/stat has no CPU times"),
+            CpuError::SendError(e) => write!(f, "Channel Error: {e}"),
+        }
+    }
+}
+
+impl From<std::io::Error> for CpuError {
+    fn from(value: std::io::Error) -> Self {
+        Self::StdIoError(value)
+    }
+}
+
+impl From<ParseIntError> for CpuError {
+    fn from(value: ParseIntError) -> Self {
+        Self::ParseIntError(value)
+    }
+}
+
+impl From<SendError<Message>> for CpuError {
+    fn from(value: SendError<Message>) -> Self {
+        Self::SendError(value)
+    }
+}
+
+#[derive(Debug)]
+pub enum CpuMessage {
+    Update(CpuStats),
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CpuStats {
+    /// Percent of the time since the last poll spent not idling, over all
+    /// cores
+    pub usage: f32,
+    /// Current frequency of every core with cpufreq, in kHz
+    pub frequencies: Vec<usize>,
+    /// cpufreq governor of the first core, cpupower sets them all alike
+    pub governor: Option<String>,
+    pub available_governors: Vec<String>,
+}
+
+impl CpuStats {
+    pub fn max_frequency(&self) -> Option<usize> {
+        self.frequencies.iter().copied().max()
+    }
+
+    pub fn average_frequency(&self) -> Option<usize> {
+        if self.frequencies.is_empty() {
+            return None;
+        }
+        Some(self.frequencies.iter().sum::<usize>() / self.frequencies.len())
+    }
+}
+
+/// Jiffies counted on the summed up cpu line of /proc/stat
+#[derive(Debug, Clone, Copy)]
+struct CpuTimes {
+    idle: u64,
+    total: u64,
+}
+
+fn cpu_times() -> Result<CpuTimes, CpuError> {
+    let stat = fs::read_to_string("/proc/stat")?;
+    let line = stat
+        .lines()
+        .find(|line| line.starts_with("cpu "))
+        .ok_or(CpuError::MissingTimes)?;
+    // user nice system idle iowait irq softirq steal, guest time is already
+    // counted in user
+    let times = line
+        .split_whitespace()
+        .skip(1)
+        .take(8)
+        .map(str::parse)
+        .collect::<Result<Vec<u64>, _>>()?;
+    if times.len() < 5 {
+        return Err(CpuError::MissingTimes);
+    }
+    Ok(CpuTimes {
+        idle: times[3] + times[4],
+        total: times.iter().sum(),
+    })
+}
+
+/// Cores without cpufreq, like in most VMs, are left out
+fn frequencies() -> Result<Vec<usize>, CpuError> {
+    let mut frequencies = Vec::new();
+    for cpu_dir in fs::read_dir("/sys/devices/system/cpu")? {
+        let cpu_dir = cpu_dir?.path();
+        if !cpu_dir
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.strip_prefix("cpu"))
+            .is_some_and(|id| id.parse::<usize>().is_ok())
+        {
+            continue;
+        }
+        if let Ok(frequency) = read_int_from_file_path(cpu_dir.join("cpufreq/scaling_cur_freq")) {
+            frequencies.push(frequency);
+        }
+    }
+    Ok(frequencies)
+}
+
+/// The governor and the ones it can be switched to, None without cpufreq
+fn governors() -> Option<(String, Vec<String>)> {
+    let cpufreq_dir = Path::new("/sys/devices/system/cpu/cpu0/cpufreq");
+    let governor = read_string_from_file_path(cpufreq_dir.join("scaling_governor")).ok()?;
+    let available = read_string_from_file_path(cpufreq_dir.join("scaling_available_governors"))
+        .map(|governors| governors.split_whitespace().map(str::to_string).collect())
+        .unwrap_or_default();
+    Some((governor.trim().to_string(), available))
+}
+
+fn cpu_generator(
+    sender: Sender<Message>,
+    resumes: &mut watch::Receiver<()>,
+) -> Result<(), CpuError> {
+    let mut previous = cpu_times()?;
+    loop {
+        sleep_unless_resumed(resumes, Duration::from_secs(2));
+        let times = cpu_times()?;
+        let total = times.total.saturating_sub(previous.total);
+        let idle = times.idle.saturating_sub(previous.idle);
+        previous = times;
+        let usage = if total == 0 {
+            0.
+        } else {
+            100. * (total - idle.min(total)) as f32 / total as f32
+        };
+        let (governor, available_governors) = governors().unzip();
+        sender.blocking_send(Message::Cpu(CpuMessage::Update(CpuStats {
+            usage,
+            frequencies: frequencies()?,
+            governor,
+            available_governors: available_governors.unwrap_or_default(),
+        })))?;
+    }
+}
+
+pub fn cpu_subscription(rt: Handle, mut resumes: watch::Receiver<()>) -> ReceiverStream<Message> {
+    let (sender, receiver) = channel(1);
+    supervise_blocking(
+        &rt,
+        "cpu",
+        Backoff::starting_at(Duration::from_secs(5)),
+        sender,
+        move |sender| cpu_generator(sender, &mut resumes),
+    );
+    ReceiverStream::new(receiver)
+}
+
+/// The CPU frequency governor, clicking switches it
+pub const GOVERNOR: Widget = Widget {
+    name: "governor",
+    render: render_governor,
+};
+
+fn render_governor(state: &State) -> Vec<Renderable> {
+    let style = state.config.theme.style("governor");
+    let Some(governor) = state.cpu.as_ref().and_then(|cpu| cpu.governor.as_ref()) else {
+        return vec![];
+    };
+    vec![Renderable::Region {
+        tooltip: Some("CPU frequency governor, click to switch".to_string()),
+        on_click: Some(Action::TogglePalette(PaletteMode::Governors)),
+        on_scroll: None,
+        children: vec![Renderable::Text {
+            text: governor.clone().into(),
+            fg: match governor.as_str() {
+                "performance" => style.warning,
+                "powersave" => style.good,
+                _ => style.fg,
+            },
+            bg: style.bg,
+        }],
+    }]
+}
@@ -0,0 +1,140 @@
+use std::{
+    any::Any,
+    backtrace::Backtrace,
+    collections::VecDeque,
+    fmt::{self, Debug, Write as _},
+    fs, io,
+    path::PathBuf,
+    sync::{Mutex, PoisonError},
+};
+
+use crate::state::Message;
+
+/// Messages the state handled last, kept for the next crash report
+const RECENT_MESSAGES: usize = 64;
+
+/// Longest a message gets in the report, covers come with whole images
+const MESSAGE_LENGTH: usize = 512;
+
+static RECENT: Mutex<VecDeque<String>> = Mutex::new(VecDeque::new());
+
+/// What the panic hook saw of the last panic, with its backtrace
+static LAST_PANIC: Mutex<Option<String>> = Mutex::new(None);
+
+/// Stops the formatting once full, so a huge message costs no more than
+/// its start
+struct Truncated {
+    text: String,
+    max: usize,
+}
+
+impl fmt::Write for Truncated {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let room = self.max.saturating_sub(self.text.len());
+        if s.len() <= room {
+            self.text.push_str(s);
+            return Ok(());
+        }
+        let end = (0..=room)
+            .rev()
+            .find(|&end| s.is_char_boundary(end))
+            .unwrap_or(0);
+        self.text.push_str(&s[..end]);
+        self.text.push('…');
+        Err(fmt::Error)
+    }
+}
+
+/// Keeps the panic and a backtrace for the report, then panics as before
+pub fn install_panic_hook() {
+    let default = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        let thread = std::thread::current();
+        let panic = format!(
+            "thread '{}' {info}\n\n{}",
+            thread.name().unwrap_or("<unnamed>"),
+            Backtrace::force_capture()
+        );
+        *LAST_PANIC.lock().unwrap_or_else(PoisonError::into_inner) = Some(panic);
+        default(info);
+    }));
+}
+
+/// Remembers the message from this subscription for the crash reports
+pub fn record(source: &str, message: &Message) {
+    let mut text = Truncated {
+        text: format!("{source}: "),
+        max: MESSAGE_LENGTH,
+    };
+    // Only fails when cut short
+    let _ = write!(text, "{message:?}");
+    let mut recent = RECENT.lock().unwrap_or_else(PoisonError::into_inner);
+    if recent.len() == RECENT_MESSAGES {
+        recent.pop_front();
+    }
+    recent.push_back(text.text);
+}
+
+/// What was passed to panic!, for the ModuleStatus of the task
+pub fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "Panicked without a message".to_string()
+    }
+}
+
+/// $XDG_STATE_HOME/sway-shell/crashes
+fn crash_dir() -> io::Result<PathBuf> {
+    let state_dir = match std::env::var_os("XDG_STATE_HOME") {
+        Some(dir) => PathBuf::from(dir),
+        None => std::env::var_os("HOME")
+            .map(|home| PathBuf::from(home).join(".local/state"))
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "HOME is not set"))?,
+    };
+    Ok(state_dir.join("sway-shell/crashes"))
+}
+
+fn write_report(task: &str, state: Option<&dyn Debug>) -> io::Result<PathBuf> {
+    let dir = crash_dir()?;
+    fs::create_dir_all(&dir)?;
+    let now = chrono::Local::now();
+    let path = dir.join(format!(
+        "{}-{task}.txt",
+        now.format("%Y-%m-%dT%H-%M-%S%.3f")
+    ));
+    let mut report = format!(
+        "sway-shell {} crashed in {task} at {}\n\n",
+        env!("CARGO_PKG_VERSION"),
+        now.to_rfc3339()
+    );
+    match LAST_PANIC
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .take()
+    {
+        Some(panic) => report.push_str(&panic),
+        None => report.push_str("The panic hook saw no panic"),
+    }
+    report.push_str("\n\nLast messages, oldest first:\n");
+    for message in RECENT.lock().unwrap_or_else(PoisonError::into_inner).iter() {
+        report.push_str(message);
+        report.push('\n');
+    }
+    if let Some(state) = state {
+        let _ = write!(report, "\nState:\n{state:#?}\n");
+    }
+    fs::write(&path, report)?;
+    Ok(path)
+}
+
+/// Writes what led up to the panic the task just caught to a file of its
+/// own in the crash directory, with the state if the panic happened there
+pub fn report(task: &str, state: Option<&dyn Debug>) {
+    match write_report(task, state) {
+        Ok(path) => log::error!("{task} panicked, wrote a crash report to {path:?}"),
+        Err(e) => log::error!("{task} panicked, could not write a crash report: {e}"),
+    }
+}
@@ -0,0 +1,251 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::{BufRead, BufReader},
+    process::{Command, ExitStatus, Stdio},
+    string::FromUtf8Error,
+    time::Duration,
+};
+
+use tinyjson::JsonValue;
+use tokio::{
+    runtime::Handle,
+    sync::{
+        mpsc::{Sender, channel, error::SendError},
+        watch,
+    },
+};
+use tokio_stream::wrappers::ReceiverStream;
+
+use crate::{
+    config::CustomConfig,
+    state::Message,
+    supervisor::{Backoff, sleep_unless_resumed, supervise_blocking},
+};
+
+#[derive(Debug)]
+pub enum CustomError {
+    StdIoError(std::io::Error),
+    Utf8Error(FromUtf8Error),
+    /// The command failed, with what it printed to stderr
+    CommandError(String),
+    /// A command that keeps printing exited
+    Exited(ExitStatus),
+    SendError(SendError<Message>),
+}
+
+impl std::fmt::Display for CustomError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CustomError::StdIoError(e) => write!(f, "IO Error: {e}"),
+            CustomError::Utf8Error(e) => write!(f, "The output is not UTF-8: {e}"),
+            CustomError::CommandError(stderr) => write!(f, "The command failed: {stderr}"),
+            CustomError::Exited(status) => write!(f, "The command exited: {status}"),
+            CustomError::SendError(e) => write!(f, "Channel Error: {e}"),
+        }
+    }
+}
+
+impl From<std::io::Error> for CustomError {
+    fn from(value: std::io::Error) -> Self {
+        Self::StdIoError(value)
+    }
+}
+
+impl From<FromUtf8Error> for CustomError {
+    fn from(value: FromUtf8Error) -> Self {
+        Self::Utf8Error(value)
+    }
+}
+
+impl From<SendError<Message>> for CustomError {
+    fn from(value: SendError<Message>) -> Self {
+        Self::SendError(value)
+    }
+}
+
+#[derive(Debug)]
+pub enum CustomMessage {
+    /// New output of the module with this name in the config
+    Output { name: String, output: CustomOutput },
+}
+
+/// What a custom command printed last
+#[derive(Debug, Clone, Default)]
+pub struct CustomOutput {
+    pub text: String,
+    pub tooltip: Option<String>,
+    /// Picks the color of the text from the theme, one of good, warning,
+    /// urgent, dim or accent
+    pub class: Option<String>,
+}
+
+impl CustomOutput {
+    /// Either JSON like {"text": "...", "tooltip": "...", "class": "..."}, or
+    /// the text, tooltip and class on lines of their own, like waybar takes
+    pub fn parse(output: &str) -> Self {
+        let output = output.trim_end();
+        if output.trim_start().starts_with('{') {
+            if let Some(output) = Self::parse_json(output) {
+                return output;
+            }
+        }
+        let mut lines = output.lines();
+        let mut next = || lines.next().filter(|line| !line.is_empty()).map(str::to_string);
+        Self {
+            text: next().unwrap_or_default(),
+            tooltip: next(),
+            class: next(),
+        }
+    }
+
+    fn parse_json(output: &str) -> Option<Self> {
+        let output: JsonValue = output.parse().ok()?;
+        let output: &HashMap<String, JsonValue> = output.get()?;
+        let field = |name: &str| output.get(name).and_then(|value| value.get::<String>()).cloned();
+        Some(Self {
+            text: field("text").unwrap_or_default(),
+            tooltip: field("tooltip"),
+            class: field("class"),
+        })
+    }
+}
+
+/// Replaces every {key} in the template with what value gives for the key.
+/// Braces value gives None for are left as they are, like those of JSON, as
+/// is an unclosed brace and the rest after it
+pub fn fill_template(template: &str, mut value: impl FnMut(&str) -> Option<String>) -> String {
+    let mut filled = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        filled.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let Some(end) = rest.find('}') else {
+            break;
+        };
+        match value(&rest[1..end]) {
+            Some(value) => filled.push_str(&value),
+            None => filled.push_str(&rest[..=end]),
+        }
+        rest = &rest[end + 1..];
+    }
+    filled.push_str(rest);
+    filled
+}
+
+/// Failing commands are run again after this, or their interval if longer
+const RESTART_DELAY: Duration = Duration::from_secs(5);
+
+/// Shorter intervals are taken as this, so interval = 0 doesn't keep a core
+/// busy starting sh
+const MIN_INTERVAL: Duration = Duration::from_secs(1);
+
+fn send(sender: &Sender<Message>, name: &str, output: CustomOutput) -> Result<(), CustomError> {
+    sender.blocking_send(Message::Custom(CustomMessage::Output {
+        name: name.to_string(),
+        output,
+    }))?;
+    Ok(())
+}
+
+fn custom_generator(
+    sender: &Sender<Message>,
+    name: &str,
+    config: &CustomConfig,
+    resumes: &mut watch::Receiver<()>,
+) -> Result<(), CustomError> {
+    let Some(interval) = config
+        .interval
+        .map(|interval| Duration::from_secs(interval).max(MIN_INTERVAL))
+    else {
+        // Every line printed is an update
+        let mut child = Command::new("sh")
+            .arg("-c")
+            .arg(&config.exec)
+            .stdout(Stdio::piped())
+            .spawn()?;
+        let stdout = child.stdout.take().expect("The stdout of the command to be piped");
+        for line in BufReader::new(stdout).lines() {
+            if let Err(e) = send(sender, name, CustomOutput::parse(&line?)) {
+                let _ = child.kill();
+                return Err(e);
+            }
+        }
+        return Err(CustomError::Exited(child.wait()?));
+    };
+    loop {
+        let output = Command::new("sh").arg("-c").arg(&config.exec).output()?;
+        if !output.status.success() {
+            return Err(CustomError::CommandError(String::from_utf8(output.stderr)?));
+        }
+        send(sender, name, CustomOutput::parse(&String::from_utf8(output.stdout)?))?;
+        sleep_unless_resumed(resumes, interval);
+    }
+}
+
+/// Runs the command of every custom module on a thread of its own
+pub fn custom_subscription(
+    rt: Handle,
+    config: BTreeMap<String, CustomConfig>,
+    resumes: watch::Receiver<()>,
+) -> ReceiverStream<Message> {
+    let (sender, receiver) = channel(1);
+    for (name, config) in config {
+        let backoff = Backoff::starting_at(RESTART_DELAY.max(Duration::from_secs(config.interval.unwrap_or(0))));
+        let mut resumes = resumes.clone();
+        supervise_blocking(
+            &rt,
+            format!("custom/{name}"),
+            backoff,
+            sender.clone(),
+            move |sender| custom_generator(&sender, &name, &config, &mut resumes),
+        );
+    }
+    ReceiverStream::new(receiver)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_lines() {
+        let output = CustomOutput::parse("42%\nCPU load\nwarning\n");
+        assert_eq!(output.text, "42%");
+        assert_eq!(output.tooltip.as_deref(), Some("CPU load"));
+        assert_eq!(output.class.as_deref(), Some("warning"));
+
+        let output = CustomOutput::parse("42%\n\nwarning");
+        assert_eq!(output.tooltip, None);
+        assert_eq!(output.class.as_deref(), Some("warning"));
+
+        let output = CustomOutput::parse("");
+        assert_eq!(output.text, "");
+        assert_eq!(output.tooltip, None);
+    }
+
+    #[test]
+    fn parses_json() {
+        let output = CustomOutput::parse(r#"{"text": "on", "class": "good"}"#);
+        assert_eq!(output.text, "on");
+        assert_eq!(output.tooltip, None);
+        assert_eq!(output.class.as_deref(), Some("good"));
+    }
+
+    #[test]
+    fn fills_templates() {
+        let value = |key: &str| (key == "a").then(|| "1".to_string());
+        assert_eq!(fill_template("{a} and {a}", value), "1 and 1");
+        assert_eq!(fill_template("{b} {a}", value), "{b} 1");
+        assert_eq!(fill_template("{\"json\": {a}}", value), "{\"json\": {a}}");
+        assert_eq!(fill_template("x {a", value), "x {a");
+        assert_eq!(fill_template("no placeholders", value), "no placeholders");
+        assert_eq!(fill_template("", value), "");
+    }
+
+    #[test]
+    fn takes_invalid_json_as_lines() {
+        let output = CustomOutput::parse("{not json\ntooltip");
+        assert_eq!(output.text, "{not json");
+        assert_eq!(output.tooltip.as_deref(), Some("tooltip"));
+    }
+}
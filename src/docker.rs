@@ -0,0 +1,230 @@
+use std::{
+    collections::HashMap,
+    env::VarError,
+    io::{Read, Write},
+    os::unix::net::UnixStream,
+    path::PathBuf,
+    time::Duration,
+};
+
+use tinyjson::JsonValue;
+use tokio::{
+    runtime::Handle,
+    sync::{
+        mpsc::{Sender, channel, error::SendError},
+        watch,
+    },
+};
+use tokio_stream::wrappers::ReceiverStream;
+
+use crate::action::Action;
+use crate::renderer::Renderable;
+use crate::state::{Message, State};
+use crate::supervisor::{Backoff, sleep_unless_resumed, supervise_blocking};
+use crate::widget::Widget;
+
+/// Longest the daemon may take to take the request or answer it, past which
+/// the poll fails and the supervisor tries again
+const SOCKET_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug)]
+pub enum DockerError {
+    StdIoError(std::io::Error),
+    VarError(VarError),
+    JsonError(tinyjson::JsonParseError),
+    InvalidResponse(String),
+    SendError(SendError<Message>),
+}
+
+impl std::fmt::Display for DockerError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DockerError::StdIoError(e) => write!(f, "IO Error: {e}"),
+            DockerError::VarError(e) => write!(f, "Environment Variable Error: {e}"),
+            DockerError::JsonError(e) => write!(f, "JSON Error: {e}"),
+            DockerError::InvalidResponse(response) => {
+                write!(f, "Unexpected Docker reply: {response}")
+            }
+            DockerError::SendError(e) => write!(f, "Channel Error: {e}"),
+        }
+    }
+}
+
+impl From<std::io::Error> for DockerError {
+    fn from(value: std::io::Error) -> Self {
+        Self::StdIoError(value)
+    }
+}
+
+impl From<VarError> for DockerError {
+    fn from(value: VarError) -> Self {
+        Self::VarError(value)
+    }
+}
+
+impl From<tinyjson::JsonParseError> for DockerError {
+    fn from(value: tinyjson::JsonParseError) -> Self {
+        Self::JsonError(value)
+    }
+}
+
+impl From<SendError<Message>> for DockerError {
+    fn from(value: SendError<Message>) -> Self {
+        Self::SendError(value)
+    }
+}
+
+#[derive(Debug)]
+pub enum DockerMessage {
+    ContainersUpdate(Vec<Container>),
+}
+
+#[derive(Debug, Clone)]
+pub struct Container {
+    pub name: String,
+    /// Short state, e.g. running, exited
+    pub state: String,
+    /// Human readable status, e.g. "Up 2 hours (unhealthy)"
+    pub status: String,
+}
+
+impl Container {
+    pub fn running(&self) -> bool {
+        self.state == "running"
+    }
+
+    pub fn unhealthy(&self) -> bool {
+        self.status.contains("(unhealthy)")
+    }
+}
+
+/// Podman exposes a docker compatible API, so whichever socket is found first
+/// is used, preferring an explicitly configured DOCKER_HOST
+fn docker_socket_path() -> Result<PathBuf, DockerError> {
+    if let Ok(host) = std::env::var("DOCKER_HOST") {
+        if let Some(path) = host.strip_prefix("unix://") {
+            return Ok(PathBuf::from(path));
+        }
+    }
+    let runtime_dir = PathBuf::from(std::env::var("XDG_RUNTIME_DIR")?);
+    Ok([
+        runtime_dir.join("podman/podman.sock"),
+        runtime_dir.join("docker.sock"),
+        PathBuf::from("/run/podman/podman.sock"),
+    ]
+    .into_iter()
+    .find(|path| path.exists())
+    .unwrap_or(PathBuf::from("/var/run/docker.sock")))
+}
+
+fn list_containers(socket_path: &PathBuf) -> Result<Vec<Container>, DockerError> {
+    let mut stream = UnixStream::connect(socket_path)?;
+    stream.set_read_timeout(Some(SOCKET_TIMEOUT))?;
+    stream.set_write_timeout(Some(SOCKET_TIMEOUT))?;
+    // HTTP/1.0 so the daemon closes the connection and doesn't use chunked encoding
+    stream.write_all(b"GET /containers/json?all=true HTTP/1.0\r\nHost: localhost\r\n\r\n")?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response)?;
+    let body = match response.split_once("\r\n\r\n") {
+        Some((_, body)) => body,
+        None => return Err(DockerError::InvalidResponse(response)),
+    };
+    let containers: JsonValue = body.parse()?;
+    let containers: &Vec<JsonValue> = match containers.get() {
+        Some(x) => x,
+        None => return Err(DockerError::InvalidResponse(body.to_string())),
+    };
+    Ok(containers
+        .iter()
+        .filter_map(|container| container.get::<HashMap<String, JsonValue>>())
+        .map(|container| {
+            let field = |key: &str| {
+                container
+                    .get(key)
+                    .and_then(|v| v.get::<String>())
+                    .cloned()
+                    .unwrap_or_default()
+            };
+            Container {
+                name: container
+                    .get("Names")
+                    .and_then(|names| names.get::<Vec<JsonValue>>())
+                    .and_then(|names| names.first())
+                    .and_then(|name| name.get::<String>())
+                    .map(|name| name.trim_start_matches('/').to_string())
+                    .unwrap_or_default(),
+                state: field("State"),
+                status: field("Status"),
+            }
+        })
+        .collect())
+}
+
+fn docker_generator(
+    sender: Sender<Message>,
+    resumes: &mut watch::Receiver<()>,
+) -> Result<(), DockerError> {
+    let socket_path = docker_socket_path()?;
+    loop {
+        sender.blocking_send(Message::Docker(DockerMessage::ContainersUpdate(
+            list_containers(&socket_path)?,
+        )))?;
+        sleep_unless_resumed(resumes, Duration::from_secs(10));
+    }
+}
+
+pub fn docker_subscription(
+    rt: Handle,
+    mut resumes: watch::Receiver<()>,
+) -> ReceiverStream<Message> {
+    let (sender, receiver) = channel(1);
+    supervise_blocking(
+        &rt,
+        "docker",
+        Backoff::starting_at(Duration::from_secs(10)),
+        sender,
+        move |sender| docker_generator(sender, &mut resumes),
+    );
+    ReceiverStream::new(receiver)
+}
+
+/// The running containers, counting the unhealthy ones
+pub const WIDGET: Widget = Widget {
+    name: "docker",
+    render,
+};
+
+fn render(state: &State) -> Vec<Renderable> {
+    let style = state.config.theme.style("docker");
+    let mut module = Vec::new();
+    let running_containers = state.containers.iter().filter(|v| v.running()).count();
+    if running_containers > 0 {
+        let unhealthy_containers = state.containers.iter().filter(|v| v.unhealthy()).count();
+        let mut children = vec![Renderable::Text {
+            text: format!("ctr {running_containers}").into(),
+            fg: style.fg,
+            bg: style.bg,
+        }];
+        if unhealthy_containers > 0 {
+            children.push(Renderable::Text {
+                text: format!(" {unhealthy_containers}!").into(),
+                fg: style.urgent,
+                bg: style.bg,
+            });
+        }
+        module.push(Renderable::Region {
+            tooltip: Some(
+                state
+                    .containers
+                    .iter()
+                    .map(|container| format!("{}: {}", container.name, container.status))
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+            ),
+            on_click: Some(Action::Spawn("${TERMINAL:-foot} lazydocker".to_string())),
+            on_scroll: None,
+            children,
+        });
+    }
+    module
+}
@@ -0,0 +1,290 @@
+use std::{fmt::Display, time::Duration};
+
+use tokio::sync::mpsc::error::SendError;
+
+use crate::{
+    ambient::AmbientError, audio::AudioError, backlight::BacklightError, battery::BatteryError,
+    camera::CameraError, cpu::CpuError, custom::CustomError, docker::DockerError, font::FontError,
+    hosts::HostsError, hyprland::HyprlandError, ipc::IpcError, kdeconnect::KdeConnectError,
+    libvirt::LibvirtError, logind::LogindError, memory::MemoryError, mic::MicError, mpd::MpdError,
+    mpris::MprisError, mqtt::MqttError, network::NetworkError, nvme::NvmeError,
+    preview::PreviewError, privacy::PrivacyError, state::Message, sway::SwayError,
+    systemd::SystemdError, taskbar::TaskbarError, thermal::ThermalError, update::UpdateError,
+    watch::WatchError, wlr::WlrError,
+};
+
+/// What stopped a subscription, whichever module it came from. The
+/// supervisor reports it and runs the subscription again
+#[derive(Debug)]
+pub enum ShellError {
+    Ambient(AmbientError),
+    Audio(AudioError),
+    Backlight(BacklightError),
+    Battery(BatteryError),
+    Camera(CameraError),
+    Cpu(CpuError),
+    Custom(CustomError),
+    Docker(DockerError),
+    Font(FontError),
+    Hosts(HostsError),
+    Hyprland(HyprlandError),
+    Ipc(IpcError),
+    KdeConnect(KdeConnectError),
+    Libvirt(LibvirtError),
+    Logind(LogindError),
+    Memory(MemoryError),
+    Mic(MicError),
+    Mpd(MpdError),
+    Mpris(MprisError),
+    Mqtt(MqttError),
+    Network(NetworkError),
+    Nvme(NvmeError),
+    Preview(PreviewError),
+    Privacy(PrivacyError),
+    Sway(SwayError),
+    Systemd(SystemdError),
+    Taskbar(TaskbarError),
+    Thermal(ThermalError),
+    Update(UpdateError),
+    Watch(WatchError),
+    Wlr(WlrError),
+    /// For subscriptions with nothing to fail but sending, the state is gone
+    ChannelError(SendError<Message>),
+    /// The generator panicked, with what it panicked with
+    Panicked(String),
+    /// The watchdog aborted the generator after it sent nothing this long
+    Unresponsive(Duration),
+}
+
+impl Display for ShellError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ShellError::Ambient(e) => write!(f, "{e}"),
+            ShellError::Audio(e) => write!(f, "{e}"),
+            ShellError::Backlight(e) => write!(f, "{e}"),
+            ShellError::Battery(e) => write!(f, "{e}"),
+            ShellError::Camera(e) => write!(f, "{e}"),
+            ShellError::Cpu(e) => write!(f, "{e}"),
+            ShellError::Custom(e) => write!(f, "{e}"),
+            ShellError::Docker(e) => write!(f, "{e}"),
+            ShellError::Font(e) => write!(f, "{e}"),
+            ShellError::Hosts(e) => write!(f, "{e}"),
+            ShellError::Hyprland(e) => write!(f, "{e}"),
+            ShellError::Ipc(e) => write!(f, "{e}"),
+            ShellError::KdeConnect(e) => write!(f, "{e}"),
+            ShellError::Libvirt(e) => write!(f, "{e}"),
+            ShellError::Logind(e) => write!(f, "{e}"),
+            ShellError::Memory(e) => write!(f, "{e}"),
+            ShellError::Mic(e) => write!(f, "{e}"),
+            ShellError::Mpd(e) => write!(f, "{e}"),
+            ShellError::Mpris(e) => write!(f, "{e}"),
+            ShellError::Mqtt(e) => write!(f, "{e}"),
+            ShellError::Network(e) => write!(f, "{e}"),
+            ShellError::Nvme(e) => write!(f, "{e}"),
+            ShellError::Preview(e) => write!(f, "{e}"),
+            ShellError::Privacy(e) => write!(f, "{e}"),
+            ShellError::Sway(e) => write!(f, "{e}"),
+            ShellError::Systemd(e) => write!(f, "{e}"),
+            ShellError::Taskbar(e) => write!(f, "{e}"),
+            ShellError::Thermal(e) => write!(f, "{e}"),
+            ShellError::Update(e) => write!(f, "{e}"),
+            ShellError::Watch(e) => write!(f, "{e}"),
+            ShellError::Wlr(e) => write!(f, "{e}"),
+            ShellError::ChannelError(e) => write!(f, "Channel Error: {e}"),
+            ShellError::Panicked(e) => write!(f, "Panicked: {e}"),
+            ShellError::Unresponsive(deadline) => write!(f, "Sent nothing for {deadline:?}"),
+        }
+    }
+}
+
+impl From<AmbientError> for ShellError {
+    fn from(value: AmbientError) -> Self {
+        Self::Ambient(value)
+    }
+}
+
+impl From<AudioError> for ShellError {
+    fn from(value: AudioError) -> Self {
+        Self::Audio(value)
+    }
+}
+
+impl From<BacklightError> for ShellError {
+    fn from(value: BacklightError) -> Self {
+        Self::Backlight(value)
+    }
+}
+
+impl From<BatteryError> for ShellError {
+    fn from(value: BatteryError) -> Self {
+        Self::Battery(value)
+    }
+}
+
+impl From<CameraError> for ShellError {
+    fn from(value: CameraError) -> Self {
+        Self::Camera(value)
+    }
+}
+
+impl From<CpuError> for ShellError {
+    fn from(value: CpuError) -> Self {
+        Self::Cpu(value)
+    }
+}
+
+impl From<CustomError> for ShellError {
+    fn from(value: CustomError) -> Self {
+        Self::Custom(value)
+    }
+}
+
+impl From<DockerError> for ShellError {
+    fn from(value: DockerError) -> Self {
+        Self::Docker(value)
+    }
+}
+
+impl From<FontError> for ShellError {
+    fn from(value: FontError) -> Self {
+        Self::Font(value)
+    }
+}
+
+impl From<HostsError> for ShellError {
+    fn from(value: HostsError) -> Self {
+        Self::Hosts(value)
+    }
+}
+
+impl From<HyprlandError> for ShellError {
+    fn from(value: HyprlandError) -> Self {
+        Self::Hyprland(value)
+    }
+}
+
+impl From<IpcError> for ShellError {
+    fn from(value: IpcError) -> Self {
+        Self::Ipc(value)
+    }
+}
+
+impl From<KdeConnectError> for ShellError {
+    fn from(value: KdeConnectError) -> Self {
+        Self::KdeConnect(value)
+    }
+}
+
+impl From<LibvirtError> for ShellError {
+    fn from(value: LibvirtError) -> Self {
+        Self::Libvirt(value)
+    }
+}
+
+impl From<LogindError> for ShellError {
+    fn from(value: LogindError) -> Self {
+        Self::Logind(value)
+    }
+}
+
+impl From<MemoryError> for ShellError {
+    fn from(value: MemoryError) -> Self {
+        Self::Memory(value)
+    }
+}
+
+impl From<MicError> for ShellError {
+    fn from(value: MicError) -> Self {
+        Self::Mic(value)
+    }
+}
+
+impl From<MpdError> for ShellError {
+    fn from(value: MpdError) -> Self {
+        Self::Mpd(value)
+    }
+}
+
+impl From<MprisError> for ShellError {
+    fn from(value: MprisError) -> Self {
+        Self::Mpris(value)
+    }
+}
+
+impl From<MqttError> for ShellError {
+    fn from(value: MqttError) -> Self {
+        Self::Mqtt(value)
+    }
+}
+
+impl From<NetworkError> for ShellError {
+    fn from(value: NetworkError) -> Self {
+        Self::Network(value)
+    }
+}
+
+impl From<NvmeError> for ShellError {
+    fn from(value: NvmeError) -> Self {
+        Self::Nvme(value)
+    }
+}
+
+impl From<PreviewError> for ShellError {
+    fn from(value: PreviewError) -> Self {
+        Self::Preview(value)
+    }
+}
+
+impl From<PrivacyError> for ShellError {
+    fn from(value: PrivacyError) -> Self {
+        Self::Privacy(value)
+    }
+}
+
+impl From<SwayError> for ShellError {
+    fn from(value: SwayError) -> Self {
+        Self::Sway(value)
+    }
+}
+
+impl From<SystemdError> for ShellError {
+    fn from(value: SystemdError) -> Self {
+        Self::Systemd(value)
+    }
+}
+
+impl From<TaskbarError> for ShellError {
+    fn from(value: TaskbarError) -> Self {
+        Self::Taskbar(value)
+    }
+}
+
+impl From<ThermalError> for ShellError {
+    fn from(value: ThermalError) -> Self {
+        Self::Thermal(value)
+    }
+}
+
+impl From<UpdateError> for ShellError {
+    fn from(value: UpdateError) -> Self {
+        Self::Update(value)
+    }
+}
+
+impl From<WatchError> for ShellError {
+    fn from(value: WatchError) -> Self {
+        Self::Watch(value)
+    }
+}
+
+impl From<WlrError> for ShellError {
+    fn from(value: WlrError) -> Self {
+        Self::Wlr(value)
+    }
+}
+
+impl From<SendError<Message>> for ShellError {
+    fn from(value: SendError<Message>) -> Self {
+        Self::ChannelError(value)
+    }
+}
@@ -11,6 +11,15 @@ pub enum ReadIntError {
     StdIoError(std::io::Error),
 }
 
+impl std::fmt::Display for ReadIntError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ReadIntError::Parsing(e) => write!(f, "Invalid number: {e}"),
+            ReadIntError::StdIoError(e) => write!(f, "IO Error: {e}"),
+        }
+    }
+}
+
 impl From<ParseIntError> for ReadIntError {
     fn from(value: ParseIntError) -> Self {
         Self::Parsing(value)
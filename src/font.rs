@@ -1,12 +1,151 @@
 use std::{
     collections::HashMap,
+    fmt, mem,
     ops::{Add, Div, Mul, Sub},
+    process::Command,
+    sync::{Arc, Mutex},
 };
 
 use ab_glyph::{Font, FontArc, GlyphId, OutlineCurve, Point};
 
+use crate::{config::FontConfig, shaper::ShapeCache};
+
 pub const FONT_DATA: &[u8] = include_bytes!("test_font.ttf");
 
+/// Why the fonts could not be loaded
+#[derive(Debug)]
+pub enum FontError {
+    /// The font built into the bar can't be parsed, there is nothing to fall
+    /// back on
+    InvalidEmbeddedFont,
+}
+
+impl fmt::Display for FontError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FontError::InvalidEmbeddedFont => write!(f, "The embedded font can't be parsed"),
+        }
+    }
+}
+
+/// One font in the fallback chain
+#[derive(Clone)]
+pub struct FontFace {
+    /// The raw file, which both faces below borrow
+    pub data: &'static [u8],
+    /// For the outlines and metrics
+    pub font_arc: FontArc,
+    /// For shaping
+    pub shaping: rustybuzz::Face<'static>,
+    pub units_per_em: f32,
+}
+
+impl fmt::Debug for FontFace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FontFace")
+            .field("bytes", &self.data.len())
+            .field("units_per_em", &self.units_per_em)
+            .finish_non_exhaustive()
+    }
+}
+
+impl FontFace {
+    pub fn new(data: &'static [u8]) -> Option<Self> {
+        let font_arc = FontArc::try_from_slice(data).ok()?;
+        Some(Self {
+            data,
+            units_per_em: font_arc.units_per_em().unwrap_or(16384.0),
+            font_arc,
+            shaping: rustybuzz::Face::from_slice(data, 0)?,
+        })
+    }
+
+    pub fn has_glyph(&self, c: char) -> bool {
+        self.font_arc.glyph_id(c).0 != 0
+    }
+
+    /// Entries containing a / are taken as paths, anything else is a family
+    /// name handed to fc-match
+    fn resolve(name: &str) -> Option<String> {
+        if name.contains('/') {
+            return Some(name.to_string());
+        }
+        let output = Command::new("fc-match")
+            .args(["--format=%{file}", name])
+            .output()
+            .map_err(|e| log::error!("Could not run fc-match for {name}: {e}"))
+            .ok()?;
+        String::from_utf8(output.stdout).ok().filter(|path| !path.is_empty())
+    }
+
+    /// The embedded font followed by the configured fallbacks, fallbacks
+    /// which can't be found or parsed are skipped
+    pub fn chain(config: &FontConfig) -> Result<Vec<Self>, FontError> {
+        let mut paths: Vec<String> = Vec::new();
+        for path in config.fallback.iter().filter_map(|name| Self::resolve(name)) {
+            // fc-match falls back to the default font for unknown families,
+            // which would otherwise show up once per missing family
+            if !paths.contains(&path) {
+                paths.push(path);
+            }
+        }
+        let embedded = Self::new(FONT_DATA).ok_or(FontError::InvalidEmbeddedFont)?;
+        let fallbacks = paths.into_iter().filter_map(|path| match std::fs::read(&path) {
+            // Faces live as long as the bar does, leaking them gives the
+            // shaper the 'static data it needs
+            Ok(data) => Self::new(Box::leak(data.into_boxed_slice())).or_else(|| {
+                log::error!("Could not parse the font at {path}");
+                None
+            }),
+            Err(e) => {
+                log::error!("Could not read the font at {path}: {e}");
+                None
+            }
+        });
+        Ok(std::iter::once(embedded).chain(fallbacks).collect())
+    }
+}
+
+/// The fallback chain, read once and shared through an Arc by the shaping
+/// in layout and the glyph container uploading outlines to the GPU
+#[derive(Debug)]
+pub struct FontSystem {
+    pub faces: Vec<FontFace>,
+    /// Behind a lock as the system is shared, though only layout shapes
+    pub shaped: Mutex<ShapeCache>,
+}
+
+impl FontSystem {
+    pub fn new(faces: Vec<FontFace>) -> Arc<Self> {
+        Arc::new(Self {
+            faces,
+            shaped: Mutex::new(ShapeCache::default()),
+        })
+    }
+
+    /// The embedded font followed by the configured fallbacks
+    pub fn from_config(config: &FontConfig) -> Result<Arc<Self>, FontError> {
+        Ok(Self::new(FontFace::chain(config)?))
+    }
+
+    /// Index of the first face in the chain which can render the char, the
+    /// primary face if none can
+    pub fn face_for_char(&self, c: char) -> usize {
+        self.faces.iter().position(|face| face.has_glyph(c)).unwrap_or(0)
+    }
+
+    /// Advance of the glyph, in em
+    pub fn advance(&self, face: usize, glyph_id: GlyphId) -> Option<f32> {
+        let face = self.faces.get(face)?;
+        Some(face.font_arc.h_advance_unscaled(glyph_id) / face.units_per_em)
+    }
+
+    /// Outline of the glyph, scaled down to em
+    fn shape(&self, face: usize, glyph_id: GlyphId) -> Option<Shape> {
+        Shape::from_glyph(self.faces.get(face)?.font_arc.clone(), glyph_id)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct FontContainer {
     /// This texture holds the points for lines
@@ -26,12 +165,18 @@ pub struct FontContainer {
     /// Locations of characters in the curve_offsets, defined in curve_offsets
     pub locations: HashMap<char, GlyphInfo>,
 
-    /// The original font parsed into a struct
-    pub font_arc: FontArc,
+    /// Same as locations, but keyed by face and glyph, shaped text can contain
+    /// glyphs which no single char maps to, like ligatures
+    pub glyph_locations: HashMap<(usize, GlyphId), GlyphInfo>,
 
-    pub char_map: HashMap<GlyphId, char>,
+    /// Fallback chain, the first face which has a glyph for a char is used.
+    /// Glyphs of every face share the curve buffers above
+    pub fonts: Arc<FontSystem>,
 
-    pub units_per_em: f32,
+    /// Bumped by begin_frame, glyphs remember the frame they were last used
+    /// in so trim can drop the least recently used ones
+    frame: u64,
+    last_used: HashMap<(usize, GlyphId), u64>,
 }
 
 #[repr(C)]
@@ -60,136 +205,67 @@ pub struct GlyphInfo {
     /// Normalized offset in 0..1 range
     pub offset: Vec2,
 
+    /// Index of the face in the fallback chain
+    pub face: usize,
+
     /// GlyphId corresponding to the font
     pub glyph_id: GlyphId,
 
     pub advance: f32,
 }
 
-impl FontContainer {
-    pub fn new(available_chars: &str) -> Self {
-        let font_arc = FontArc::try_from_slice(FONT_DATA).expect("The font to be a valid file");
-        let units_per_em = font_arc.units_per_em().unwrap_or(16384.0);
-        let char_map = HashMap::from_iter(font_arc.codepoint_ids());
-        let (
-            (line_points, quadratic_points, cubic_points),
-            (line_curve_offsets, quadratic_curve_offsets, cubic_curve_offsets),
-            locations,
-        ) = available_chars
-            .chars()
-            .map(|c| (c, font_arc.glyph_id(c)))
-            .flat_map(|(c, id)| Shape::from_glyph(font_arc.clone(), id).map(|shape| (c, shape, id)))
-            .fold(
-                (
-                    (Vec::<Line>::new(), Vec::<Bez2>::new(), Vec::<Bez3>::new()),
-                    (Vec::<u32>::new(), Vec::<u32>::new(), Vec::<u32>::new()),
-                    HashMap::new(),
-                ),
-                |(mut segments, mut offsets, mut locations), (c, shape, glyph_id)| {
-                    let (lines_offset, bez2_offset, bez3_offset) = (
-                        offsets.0.len() as u32,
-                        offsets.1.len() as u32,
-                        offsets.2.len() as u32,
-                    );
-                    for segment in shape.segments.into_iter() {
-                        match segment {
-                            Segment::LINE(line) => {
-                                offsets.0.push(segments.0.len() as u32);
-                                segments.0.push(line)
-                            }
-                            Segment::BEZ2(bez2) => {
-                                offsets.1.push(segments.1.len() as u32);
-                                segments.1.push(bez2)
-                            }
-                            Segment::BEZ3(bez3) => {
-                                offsets.2.push(segments.2.len() as u32);
-                                segments.2.push(bez3)
-                            }
-                        }
-                    }
-                    locations.insert(
-                        c,
-                        GlyphInfo {
-                            glyph_id,
-                            advance: font_arc.h_advance_unscaled(glyph_id) / units_per_em,
-                            line_off: GlyphOffLen {
-                                position: lines_offset,
-                                len: segments.0.len() as u32 - lines_offset,
-                            },
-                            bez2_off: GlyphOffLen {
-                                position: bez2_offset,
-                                len: segments.1.len() as u32 - bez2_offset,
-                            },
-                            bez3_off: GlyphOffLen {
-                                position: bez3_offset,
-                                len: segments.2.len() as u32 - bez3_offset,
-                            },
-                            dimensions: shape.dimensions,
-                            offset: shape.offset,
-                        },
-                    );
-                    (segments, offsets, locations)
-                },
-            );
-        /*test_svg_from_locations(
-                    &locations,
-                    line_points
-                        .clone()
-                        .into_iter()
-                        .flat_map(|v| v.to_f32_arr())
-                        .collect(),
-                    quadratic_points
-                        .clone()
-                        .into_iter()
-                        .flat_map(|v| v.to_f32_arr())
-                        .collect(),
-                    '1',
-                );
-                dbg!(locations[&'1']);
-        */
-        Self {
-            units_per_em,
-            char_map,
-            linear_points_buffer: line_points
-                .clone()
-                .into_iter()
-                .flat_map(|v| v.to_f32_arr())
-                .collect(),
-            quadratic_points_buffer: quadratic_points
-                .clone()
-                .into_iter()
-                .flat_map(|v| v.to_f32_arr())
-                .collect(),
-            cubic_points_buffer: cubic_points
-                .clone()
-                .into_iter()
-                .flat_map(|v| v.to_f32_arr())
-                .collect(),
-            line_curve_offsets,
-            quadratic_curve_offsets,
-            cubic_curve_offsets,
-            locations,
-            font_arc: font_arc.into(),
-        }
+impl GlyphInfo {
+    /// Bytes the glyph takes up in the container, its curves and offsets
+    /// plus its map entries
+    pub fn memory_usage(&self) -> usize {
+        let f32_size = mem::size_of::<f32>();
+        let u32_size = mem::size_of::<u32>();
+        self.line_off.len as usize * (4 * f32_size + u32_size)
+            + self.bez2_off.len as usize * (6 * f32_size + u32_size)
+            + self.bez3_off.len as usize * (8 * f32_size + u32_size)
+            + 2 * mem::size_of::<(usize, GlyphId, GlyphInfo)>()
     }
+}
 
-    pub fn load_char_with_id(&mut self, id: GlyphId) -> Option<GlyphInfo> {
-            match self.char_map.get(&id) {
-                Some(x) => return self.load_char(*x),
-                None => None,
-            }
+impl FontContainer {
+    pub fn new(available_chars: &str, fonts: Arc<FontSystem>) -> Self {
+        let mut font_container = Self {
+            linear_points_buffer: Vec::new(),
+            quadratic_points_buffer: Vec::new(),
+            cubic_points_buffer: Vec::new(),
+            line_curve_offsets: Vec::new(),
+            quadratic_curve_offsets: Vec::new(),
+            cubic_curve_offsets: Vec::new(),
+            locations: HashMap::new(),
+            glyph_locations: HashMap::new(),
+            fonts,
+            frame: 0,
+            last_used: HashMap::new(),
+        };
+        for c in available_chars.chars() {
+            font_container.load_char(c);
+        }
+        font_container
     }
 
     pub fn load_char(&mut self, c: char) -> Option<GlyphInfo> {
-        let units_per_em = self.units_per_em;
         if let Some(x) = self.locations.get(&c) {
+            self.last_used.insert((x.face, x.glyph_id), self.frame);
             return Some(*x);
         }
-        let glyph_id = self.font_arc.glyph_id(c);
-        let shape = match Shape::from_glyph(self.font_arc.clone(), glyph_id) {
-            Some(x) => x,
-            None => return None,
-        };
+        let face = self.fonts.face_for_char(c);
+        let glyph_info = self.load_glyph(face, self.fonts.faces[face].font_arc.glyph_id(c))?;
+        self.locations.insert(c, glyph_info);
+        Some(glyph_info)
+    }
+
+    pub fn load_glyph(&mut self, face: usize, glyph_id: GlyphId) -> Option<GlyphInfo> {
+        if let Some(x) = self.glyph_locations.get(&(face, glyph_id)) {
+            self.last_used.insert((face, glyph_id), self.frame);
+            return Some(*x);
+        }
+        let advance = self.fonts.advance(face, glyph_id)?;
+        let shape = self.fonts.shape(face, glyph_id)?;
 
         let (lines_offset, bez2_offset, bez3_offset) = (
             self.linear_points_buffer.len() as u32 / 4,
@@ -217,8 +293,9 @@ impl FontContainer {
             }
         }
         let glyph_info = GlyphInfo {
+            face,
             glyph_id,
-            advance: self.font_arc.h_advance_unscaled(glyph_id) / units_per_em,
+            advance,
             line_off: GlyphOffLen {
                 position: lines_offset,
                 len: self.linear_points_buffer.len() as u32 / 4 - lines_offset,
@@ -234,10 +311,81 @@ impl FontContainer {
             offset: shape.offset,
             dimensions: shape.dimensions,
         };
-        self.locations.insert(c, glyph_info);
+        self.glyph_locations.insert((face, glyph_id), glyph_info);
+        self.last_used.insert((face, glyph_id), self.frame);
 
         Some(glyph_info)
     }
+
+    /// Marks the start of a frame, glyphs loaded from here on count as used
+    /// in it
+    pub fn begin_frame(&mut self) {
+        self.frame += 1;
+    }
+
+    /// Bytes taken up by the loaded glyphs
+    pub fn memory_usage(&self) -> usize {
+        self.glyph_locations
+            .values()
+            .map(GlyphInfo::memory_usage)
+            .sum::<usize>()
+            + self.locations.len() * mem::size_of::<(char, GlyphInfo)>()
+    }
+
+    /// Drops the least recently used glyphs once the container is over the
+    /// budget in bytes, down to half of it so this doesn't run every frame.
+    /// The curve buffers are rebuilt, so every offset handed out before is
+    /// invalid afterwards. Returns whether anything was dropped
+    pub fn trim(&mut self, budget: usize) -> bool {
+        let usage = self.memory_usage();
+        if usage <= budget {
+            return false;
+        }
+        let mut glyphs = self.glyph_locations.iter().collect::<Vec<_>>();
+        glyphs.sort_by_key(|(key, _)| std::cmp::Reverse(self.last_used.get(key).copied()));
+        let mut kept_usage = 0;
+        let kept = glyphs
+            .into_iter()
+            .take_while(|(_, info)| {
+                kept_usage += info.memory_usage();
+                kept_usage <= budget / 2
+            })
+            .map(|(key, _)| *key)
+            .collect::<Vec<_>>();
+        let chars = self
+            .locations
+            .iter()
+            .map(|(c, info)| (*c, (info.face, info.glyph_id)))
+            .collect::<Vec<_>>();
+        let last_used = mem::take(&mut self.last_used);
+
+        self.linear_points_buffer.clear();
+        self.quadratic_points_buffer.clear();
+        self.cubic_points_buffer.clear();
+        self.line_curve_offsets.clear();
+        self.quadratic_curve_offsets.clear();
+        self.cubic_curve_offsets.clear();
+        self.locations.clear();
+        self.glyph_locations.clear();
+        for (face, glyph_id) in kept {
+            self.load_glyph(face, glyph_id);
+        }
+        for (c, key) in chars {
+            if let Some(info) = self.glyph_locations.get(&key) {
+                self.locations.insert(c, *info);
+            }
+        }
+        self.last_used = last_used;
+        self.last_used
+            .retain(|key, _| self.glyph_locations.contains_key(key));
+
+        log::info!(
+            "Glyph cache went over its budget of {budget} bytes with {usage}, kept {} glyphs in {} bytes",
+            self.glyph_locations.len(),
+            self.memory_usage()
+        );
+        true
+    }
 }
 
 /*
@@ -0,0 +1,158 @@
+use unicode_segmentation::UnicodeSegmentation;
+
+const UNITS: [&str; 7] = ["B", "KiB", "MiB", "GiB", "TiB", "PiB", "EiB"];
+
+/// Binary prefixed size, rounded down to a whole unit and right aligned to a
+/// fixed width so rates don't make the rest of the bar jump around
+pub fn display_bytes(x: u64) -> String {
+    let mut scaled_size = x;
+    let mut current_unit_idx = 0;
+    while scaled_size >= 1024 && current_unit_idx + 1 < UNITS.len() {
+        scaled_size /= 1024;
+        current_unit_idx += 1;
+    }
+    let display_str = format!("{scaled_size} {}", UNITS[current_unit_idx]);
+    format!("{display_str:>8}")
+}
+
+/// Cuts the text down to max_graphemes user perceived chars, marking the
+/// cut with an ellipsis. Cutting between grapheme clusters keeps emoji
+/// sequences and combining marks whole
+pub fn truncate(text: &str, max_graphemes: usize) -> String {
+    match text.grapheme_indices(true).nth(max_graphemes) {
+        Some((cut, _)) => format!("{}…", text[..cut].trim_end()),
+        None => text.to_string(),
+    }
+}
+
+/// Whole percent, clamped as some drivers report slightly over 100
+pub fn display_percent(percent: f32) -> String {
+    if percent.is_nan() {
+        return "n/a".to_string();
+    }
+    format!("{}%", percent.clamp(0., 100.).round() as u8)
+}
+
+/// A frequency in kHz, as cpufreq reports them, in GHz
+pub fn display_frequency(khz: usize) -> String {
+    format!("{:.1}GHz", khz as f32 / 1_000_000.)
+}
+
+/// The number in superscript digits, for counts set after a label
+pub fn superscript(number: usize) -> String {
+    const DIGITS: [char; 10] = ['⁰', '¹', '²', '³', '⁴', '⁵', '⁶', '⁷', '⁸', '⁹'];
+    number
+        .to_string()
+        .chars()
+        .filter_map(|digit| digit.to_digit(10))
+        .map(|digit| DIGITS[digit as usize])
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display_bytes_zero() {
+        assert_eq!(display_bytes(0), "     0 B");
+    }
+
+    #[test]
+    fn display_bytes_unit_boundaries() {
+        assert_eq!(display_bytes(1023).trim(), "1023 B");
+        assert_eq!(display_bytes(1024).trim(), "1 KiB");
+        assert_eq!(display_bytes(1024 * 1024 - 1).trim(), "1023 KiB");
+        assert_eq!(display_bytes(1024 * 1024).trim(), "1 MiB");
+        assert_eq!(display_bytes(1 << 30).trim(), "1 GiB");
+        assert_eq!(display_bytes(1 << 40).trim(), "1 TiB");
+        assert_eq!(display_bytes(1 << 50).trim(), "1 PiB");
+        assert_eq!(display_bytes(1 << 60).trim(), "1 EiB");
+    }
+
+    #[test]
+    fn display_bytes_max() {
+        assert_eq!(display_bytes(u64::MAX).trim(), "15 EiB");
+    }
+
+    #[test]
+    fn display_bytes_fixed_width() {
+        for x in [0, 1, 1023, 1024, 1 << 20, 1 << 40, u64::MAX] {
+            assert_eq!(display_bytes(x).len(), 8, "{x}");
+        }
+    }
+
+    #[test]
+    fn truncate_short_text_untouched() {
+        assert_eq!(truncate("", 5), "");
+        assert_eq!(truncate("hello", 5), "hello");
+    }
+
+    #[test]
+    fn truncate_long_text() {
+        assert_eq!(truncate("hello world", 5), "hello…");
+        assert_eq!(truncate("hello", 0), "…");
+    }
+
+    #[test]
+    fn truncate_drops_trailing_whitespace() {
+        assert_eq!(truncate("hello world", 6), "hello…");
+    }
+
+    #[test]
+    fn truncate_multi_byte_chars() {
+        assert_eq!(truncate("héllo wörld", 7), "héllo w…");
+        assert_eq!(truncate("日本語のタイトル", 3), "日本語…");
+        assert_eq!(truncate("日本語", 3), "日本語");
+    }
+
+    #[test]
+    fn truncate_combining_marks() {
+        // e followed by a combining acute accent is one grapheme
+        assert_eq!(truncate("cafe\u{301} noir", 4), "cafe\u{301}…");
+        assert_eq!(truncate("cafe\u{301}", 4), "cafe\u{301}");
+    }
+
+    #[test]
+    fn truncate_emoji_sequences() {
+        let family = "👨\u{200d}👩\u{200d}👧";
+        assert_eq!(truncate(&format!("{family} at the beach"), 1), format!("{family}…"));
+        assert_eq!(truncate(&format!("{family}{family}"), 1), format!("{family}…"));
+        // Flags are pairs of regional indicators
+        assert_eq!(truncate("🇯🇵🇫🇷🇩🇪", 2), "🇯🇵🇫🇷…");
+        // Skin tone modifiers stay on their emoji
+        assert_eq!(truncate("👍🏽👍🏽", 1), "👍🏽…");
+    }
+
+    #[test]
+    fn truncate_hangul_jamo() {
+        // Conjoining jamo, each syllable is three chars
+        let han = "\u{1112}\u{1161}\u{11ab}";
+        let geul = "\u{1100}\u{1173}\u{11af}";
+        assert_eq!(truncate(&format!("{han}{geul}"), 1), format!("{han}…"));
+    }
+
+    #[test]
+    fn display_percent_rounds_and_clamps() {
+        assert_eq!(display_percent(0.), "0%");
+        assert_eq!(display_percent(49.5), "50%");
+        assert_eq!(display_percent(100.), "100%");
+        assert_eq!(display_percent(101.), "100%");
+        assert_eq!(display_percent(-3.), "0%");
+        assert_eq!(display_percent(f32::NAN), "n/a");
+    }
+
+    #[test]
+    fn display_frequency_in_ghz() {
+        assert_eq!(display_frequency(800_000), "0.8GHz");
+        assert_eq!(display_frequency(4_249_999), "4.2GHz");
+        assert_eq!(display_frequency(0), "0.0GHz");
+    }
+
+    #[test]
+    fn superscript_digits() {
+        assert_eq!(superscript(0), "⁰");
+        assert_eq!(superscript(7), "⁷");
+        assert_eq!(superscript(1024), "¹⁰²⁴");
+    }
+}
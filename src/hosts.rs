@@ -0,0 +1,160 @@
+use std::time::{Duration, Instant};
+
+use tokio::{
+    net::TcpStream,
+    runtime::Handle,
+    sync::{
+        mpsc::{Sender, channel, error::SendError},
+        watch,
+    },
+};
+use tokio_stream::wrappers::ReceiverStream;
+
+use crate::{
+    config::{HostConfig, HostsConfig},
+    renderer::Renderable,
+    state::{Message, State},
+    supervisor::{Backoff, supervise},
+    widget::Widget,
+};
+
+#[derive(Debug)]
+pub enum HostsError {
+    SendError(SendError<Message>),
+}
+
+impl std::fmt::Display for HostsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HostsError::SendError(e) => write!(f, "Channel Error: {e}"),
+        }
+    }
+}
+
+impl From<SendError<Message>> for HostsError {
+    fn from(value: SendError<Message>) -> Self {
+        Self::SendError(value)
+    }
+}
+
+#[derive(Debug)]
+pub enum HostsMessage {
+    Update(Vec<Host>),
+}
+
+#[derive(Debug, Clone)]
+pub struct Host {
+    pub name: String,
+    /// Like nas.lan:22
+    pub address: String,
+    /// How long connecting took, None when it failed or timed out
+    pub latency: Option<Duration>,
+}
+
+/// Only the TCP handshake is done, the connection is closed right after
+async fn probe(host: &HostConfig, timeout: Duration) -> Host {
+    let start = Instant::now();
+    let latency = match tokio::time::timeout(timeout, TcpStream::connect(&host.address)).await {
+        Ok(Ok(_)) => Some(start.elapsed()),
+        Ok(Err(e)) => {
+            log::debug!("Could not connect to {}: {e}", host.address);
+            None
+        }
+        Err(_) => None,
+    };
+    Host {
+        name: host.name.clone(),
+        address: host.address.clone(),
+        latency,
+    }
+}
+
+async fn hosts_generator(
+    sender: Sender<Message>,
+    config: &HostsConfig,
+    mut resumes: watch::Receiver<()>,
+) -> Result<(), HostsError> {
+    let timeout = Duration::from_millis(config.timeout);
+    resumes.mark_unchanged();
+    loop {
+        // Probed all at once, so a few hosts that are down don't add up
+        let probes = config
+            .hosts
+            .iter()
+            .cloned()
+            .map(|host| tokio::spawn(async move { probe(&host, timeout).await }))
+            .collect::<Vec<_>>();
+        let mut hosts = Vec::new();
+        for probe in probes {
+            match probe.await {
+                Ok(host) => hosts.push(host),
+                Err(e) => log::error!("Host probe panicked: {e:?}"),
+            }
+        }
+        sender.send(Message::Hosts(HostsMessage::Update(hosts))).await?;
+        // Hosts came and went while suspended, probed again right away
+        tokio::select! {
+            _ = tokio::time::sleep(Duration::from_secs(config.interval)) => {}
+            Ok(()) = resumes.changed() => {}
+        }
+    }
+}
+
+/// Probes nothing without hosts in the config
+pub fn hosts_subscription(
+    rt: Handle,
+    config: HostsConfig,
+    resumes: watch::Receiver<()>,
+) -> ReceiverStream<Message> {
+    let (sender, receiver) = channel(1);
+    if config.hosts.is_empty() {
+        return ReceiverStream::new(receiver);
+    }
+    supervise(&rt, "hosts", Backoff::starting_at(Duration::from_secs(5)), sender, move |sender| {
+        let config = config.clone();
+        let resumes = resumes.clone();
+        async move { hosts_generator(sender, &config, resumes).await }
+    });
+    ReceiverStream::new(receiver)
+}
+
+/// A dot per host, whether it is up
+pub const WIDGET: Widget = Widget {
+    name: "hosts",
+    render,
+};
+
+fn render(state: &State) -> Vec<Renderable> {
+    let style = state.config.theme.style("hosts");
+    if state.hosts.is_empty() {
+        return vec![];
+    }
+    let mut children = Vec::new();
+    let mut tooltip = Vec::new();
+    for host in &state.hosts {
+        children.push(Renderable::Text {
+            text: "●".into(),
+            fg: if host.latency.is_some() {
+                style.good
+            } else {
+                style.urgent
+            },
+            bg: style.bg,
+        });
+        tooltip.push(match host.latency {
+            Some(latency) => format!(
+                "{} ({}) up, {} ms",
+                host.name,
+                host.address,
+                latency.as_millis()
+            ),
+            None => format!("{} ({}) down", host.name, host.address),
+        });
+    }
+    vec![Renderable::Region {
+        tooltip: Some(tooltip.join("\n")),
+        on_click: None,
+        on_scroll: None,
+        children,
+    }]
+}
@@ -0,0 +1,622 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    env::VarError,
+    io::{BufRead, BufReader, Read, Write},
+    os::unix::net::UnixStream,
+    path::PathBuf,
+    sync::{Arc, LazyLock},
+    time::Duration,
+};
+
+use swayipc::Rect;
+use tinyjson::JsonValue;
+use tokio::{
+    runtime::Handle,
+    sync::{
+        mpsc::{Sender, channel, error::SendError},
+        watch,
+    },
+};
+use tokio_stream::wrappers::ReceiverStream;
+
+use crate::{
+    compositor::{Compositor, SentWorkspaces, unquote, workspace_num},
+    error::ShellError,
+    state::Message,
+    supervisor::{Backoff, supervise_blocking},
+    sway::{SwayMessage, Window, Workspace},
+    taskbar::{TaskbarMessage, TaskbarWindow},
+};
+
+/// Prefix of the custom events meant for the bar, sent by binding the
+/// event dispatcher like `bind = SUPER, P, event, sway-shell palette`
+const COMMAND_PREFIX: &str = "sway-shell ";
+
+/// How long a request may take to be answered, Hyprland answers right
+/// away unless it hangs
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Events after which the workspaces or windows may have changed, the v2
+/// events come along with these
+const CHANGES: [&str; 13] = [
+    "workspace",
+    "createworkspace",
+    "destroyworkspace",
+    "moveworkspace",
+    "renameworkspace",
+    "focusedmon",
+    "monitoradded",
+    "monitorremoved",
+    "activewindow",
+    "openwindow",
+    "closewindow",
+    "movewindow",
+    "windowtitle",
+];
+
+#[derive(Debug)]
+pub enum HyprlandError {
+    StdIoError(std::io::Error),
+    VarError(VarError),
+    JsonError(tinyjson::JsonParseError),
+    InvalidResponse(String),
+    SendError(SendError<Message>),
+}
+
+impl std::fmt::Display for HyprlandError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HyprlandError::StdIoError(e) => write!(f, "IO Error: {e}"),
+            HyprlandError::VarError(e) => write!(f, "Environment Variable Error: {e}"),
+            HyprlandError::JsonError(e) => write!(f, "JSON Error: {e}"),
+            HyprlandError::InvalidResponse(response) => {
+                write!(f, "Unexpected Hyprland reply: {response}")
+            }
+            HyprlandError::SendError(e) => write!(f, "Channel Error: {e}"),
+        }
+    }
+}
+
+impl From<std::io::Error> for HyprlandError {
+    fn from(value: std::io::Error) -> Self {
+        Self::StdIoError(value)
+    }
+}
+
+impl From<VarError> for HyprlandError {
+    fn from(value: VarError) -> Self {
+        Self::VarError(value)
+    }
+}
+
+impl From<tinyjson::JsonParseError> for HyprlandError {
+    fn from(value: tinyjson::JsonParseError) -> Self {
+        Self::JsonError(value)
+    }
+}
+
+impl From<SendError<Message>> for HyprlandError {
+    fn from(value: SendError<Message>) -> Self {
+        Self::SendError(value)
+    }
+}
+
+/// Talks to Hyprland over the sockets of the instance the bar runs in
+#[derive(Debug)]
+pub struct Hyprland;
+
+/// Whether the bar was started in Hyprland
+pub fn running() -> bool {
+    std::env::var_os("HYPRLAND_INSTANCE_SIGNATURE").is_some()
+}
+
+/// $XDG_RUNTIME_DIR/hypr/<signature>, or /tmp/hypr/<signature> before
+/// Hyprland 0.40
+fn socket_dir() -> Result<PathBuf, HyprlandError> {
+    let signature = std::env::var("HYPRLAND_INSTANCE_SIGNATURE")?;
+    let dir = PathBuf::from(std::env::var("XDG_RUNTIME_DIR")?)
+        .join("hypr")
+        .join(&signature);
+    if dir.exists() {
+        Ok(dir)
+    } else {
+        Ok(PathBuf::from("/tmp/hypr").join(signature))
+    }
+}
+
+/// Sends a request on the command socket, which answers and hangs up
+fn request(request: &str) -> Result<String, HyprlandError> {
+    let mut stream = UnixStream::connect(socket_dir()?.join(".socket.sock"))?;
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    stream.set_write_timeout(Some(REQUEST_TIMEOUT))?;
+    stream.write_all(request.as_bytes())?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response)?;
+    Ok(response)
+}
+
+/// Objects of the JSON array answering the query, like clients
+fn query(query: &str) -> Result<Vec<HashMap<String, JsonValue>>, HyprlandError> {
+    let response = request(&format!("j/{query}"))?;
+    let json: JsonValue = response.parse()?;
+    match json {
+        JsonValue::Array(values) => Ok(values
+            .into_iter()
+            .filter_map(|value| match value {
+                JsonValue::Object(object) => Some(object),
+                _ => None,
+            })
+            .collect()),
+        // Single objects like the active window
+        JsonValue::Object(object) => Ok(vec![object]),
+        _ => Err(HyprlandError::InvalidResponse(response)),
+    }
+}
+
+fn string(object: &HashMap<String, JsonValue>, key: &str) -> String {
+    object
+        .get(key)
+        .and_then(|value| value.get::<String>())
+        .cloned()
+        .unwrap_or_default()
+}
+
+fn number(object: &HashMap<String, JsonValue>, key: &str) -> i64 {
+    object
+        .get(key)
+        .and_then(|value| value.get::<f64>())
+        .map_or(0, |number| *number as i64)
+}
+
+fn boolean(object: &HashMap<String, JsonValue>, key: &str) -> bool {
+    object
+        .get(key)
+        .and_then(|value| value.get::<bool>())
+        .copied()
+        .unwrap_or(false)
+}
+
+/// The workspace object nested in clients and monitors
+fn workspace_of(object: &HashMap<String, JsonValue>, key: &str) -> (i64, String) {
+    match object
+        .get(key)
+        .and_then(|value| value.get::<HashMap<String, JsonValue>>())
+    {
+        Some(workspace) => (number(workspace, "id"), string(workspace, "name")),
+        None => (0, String::new()),
+    }
+}
+
+/// Window addresses are hex pointers, which fit the con_id of sway
+fn address(address: &str) -> i64 {
+    i64::from_str_radix(address.trim_start_matches("0x"), 16).unwrap_or_default()
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Client {
+    address: i64,
+    title: String,
+    class: String,
+    workspace_id: i64,
+    workspace: String,
+}
+
+#[derive(Debug)]
+struct Snapshot {
+    workspaces: BTreeMap<i64, Workspace>,
+    clients: Vec<Client>,
+    /// Address of the focused window
+    active: Option<i64>,
+    /// Of the focused monitor
+    active_workspace: Option<i64>,
+}
+
+impl Snapshot {
+    fn take() -> Result<Self, HyprlandError> {
+        let clients = query("clients")?
+            .iter()
+            .map(|client| {
+                let (workspace_id, workspace) = workspace_of(client, "workspace");
+                Client {
+                    address: address(&string(client, "address")),
+                    title: string(client, "title"),
+                    class: string(client, "class"),
+                    workspace_id,
+                    workspace,
+                }
+            })
+            // Unmapped windows sit on no workspace
+            .filter(|client| client.workspace_id != 0)
+            .collect::<Vec<_>>();
+        let monitors = query("monitors")?;
+        let mut active_workspace = None;
+        let mut shown = HashMap::new();
+        for monitor in &monitors {
+            let (id, _) = workspace_of(monitor, "activeWorkspace");
+            let focused = boolean(monitor, "focused");
+            if focused {
+                active_workspace = Some(id);
+            }
+            shown.insert(id, focused);
+        }
+        let mut workspaces = BTreeMap::new();
+        for workspace in query("workspaces")? {
+            let id = number(&workspace, "id");
+            let name = string(&workspace, "name");
+            // Special workspaces are shown over the others, never next to them
+            if name.starts_with("special") {
+                continue;
+            }
+            let output = string(&workspace, "monitor");
+            let rect = monitors
+                .iter()
+                .find(|monitor| string(monitor, "name") == output)
+                .map_or(
+                    Rect {
+                        x: 0,
+                        y: 0,
+                        width: 0,
+                        height: 0,
+                    },
+                    |monitor| Rect {
+                        x: number(monitor, "x") as i32,
+                        y: number(monitor, "y") as i32,
+                        width: number(monitor, "width") as i32,
+                        height: number(monitor, "height") as i32,
+                    },
+                );
+            workspaces.insert(
+                id,
+                Workspace {
+                    id,
+                    num: workspace_num(&name),
+                    name: Some(name),
+                    layout: "none".to_string(),
+                    visible: shown.contains_key(&id),
+                    focused: shown.get(&id).copied().unwrap_or(false),
+                    urgent: false,
+                    representation: None,
+                    orientation: "none".to_string(),
+                    rect,
+                    output,
+                    focus: clients
+                        .iter()
+                        .filter(|client| client.workspace_id == id)
+                        .map(|client| client.address)
+                        .collect(),
+                },
+            );
+        }
+        let active = query("activewindow")?
+            .first()
+            .map(|window| string(window, "address"))
+            .filter(|window| !window.is_empty())
+            .map(|window| address(&window));
+        Ok(Self {
+            workspaces,
+            clients,
+            active,
+            active_workspace,
+        })
+    }
+
+    fn window_name(&self) -> Option<String> {
+        self.clients
+            .iter()
+            .find(|client| Some(client.address) == self.active)
+            .map(|client| client.title.clone())
+    }
+
+    fn workspace_apps(&self) -> BTreeMap<String, Vec<String>> {
+        let mut apps: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for client in &self.clients {
+            apps.entry(client.workspace.clone())
+                .or_default()
+                .push(client.class.clone());
+        }
+        apps
+    }
+
+    fn windows(&self) -> Vec<Window> {
+        self.clients
+            .iter()
+            .map(|client| Window {
+                id: client.address,
+                name: Some(client.title.clone()),
+                app_id: Some(client.class.clone()),
+                workspace: client.workspace.clone(),
+            })
+            .collect()
+    }
+
+    fn taskbar(&self) -> Vec<TaskbarWindow> {
+        self.clients
+            .iter()
+            .filter(|client| Some(client.workspace_id) == self.active_workspace)
+            .map(|client| TaskbarWindow {
+                id: client.address,
+                title: client.title.clone(),
+                app_id: Some(client.class.clone()),
+                focused: Some(client.address) == self.active,
+                urgent: false,
+            })
+            .collect()
+    }
+}
+
+/// The snapshot the workspace feed took after the last change, which the
+/// taskbar feed follows instead of querying Hyprland for every event again
+static SNAPSHOTS: LazyLock<watch::Sender<Option<Arc<Snapshot>>>> =
+    LazyLock::new(|| watch::Sender::new(None));
+
+/// Takes a snapshot and hands it to the taskbar feed
+fn publish() -> Result<Arc<Snapshot>, HyprlandError> {
+    let snapshot = Arc::new(Snapshot::take()?);
+    SNAPSHOTS.send_replace(Some(snapshot.clone()));
+    Ok(snapshot)
+}
+
+/// What the state was last told, Hyprland only says that something changed
+#[derive(Debug, Default)]
+struct Sent {
+    workspaces: SentWorkspaces,
+    /// Hyprland has no event for the urgency going away, it does once the
+    /// workspace is focused
+    urgent: BTreeSet<i64>,
+}
+
+impl Sent {
+    fn sync(&mut self, snapshot: &Snapshot, sender: &Sender<Message>) -> Result<(), HyprlandError> {
+        let mut workspaces = snapshot.workspaces.clone();
+        self.urgent.retain(|id| {
+            workspaces
+                .get(id)
+                .is_some_and(|workspace| !workspace.focused)
+        });
+        for id in &self.urgent {
+            if let Some(workspace) = workspaces.get_mut(id) {
+                workspace.urgent = true;
+            }
+        }
+        let window_name = snapshot.window_name();
+        let apps = snapshot.workspace_apps();
+        self.workspaces
+            .sync(workspaces, window_name, Some(apps), sender)?;
+        Ok(())
+    }
+
+    /// The window at the address asked for attention
+    fn urgent(&mut self, window: i64, sender: &Sender<Message>) -> Result<(), HyprlandError> {
+        let Some(client) = Snapshot::take()?
+            .clients
+            .into_iter()
+            .find(|client| client.address == window)
+        else {
+            return Ok(());
+        };
+        let id = client.workspace_id;
+        if let Some(workspace) = self.workspaces.get_mut(id)
+            && !workspace.focused
+            && self.urgent.insert(id)
+        {
+            workspace.urgent = true;
+            sender.blocking_send(Message::Sway(SwayMessage::WorkspaceChangeUrgency {
+                id,
+                urgent: true,
+            }))?;
+        }
+        Ok(())
+    }
+}
+
+/// Lines of the event socket, split into the event and its data
+fn events() -> Result<impl Iterator<Item = Result<(String, String), HyprlandError>>, HyprlandError>
+{
+    let stream = UnixStream::connect(socket_dir()?.join(".socket2.sock"))?;
+    Ok(BufReader::new(stream)
+        .lines()
+        .filter_map(|line| match line {
+            Ok(line) => line
+                .split_once(">>")
+                .map(|(event, data)| Ok((event.to_string(), data.to_string()))),
+            Err(e) => Some(Err(e.into())),
+        }))
+}
+
+fn hyprland_generator(sender: Sender<Message>) -> Result<(), HyprlandError> {
+    let events = events()?;
+    let mut sent = Sent::default();
+    sent.sync(&publish()?, &sender)?;
+    for event in events {
+        let (event, data) = event?;
+        match event.as_str() {
+            "submap" => {
+                let mode = (!data.is_empty()).then_some(data);
+                sender.blocking_send(Message::Sway(SwayMessage::Mode(mode)))?;
+            }
+            "urgent" => sent.urgent(address(&data), &sender)?,
+            "custom" => {
+                if let Some(command) = data.strip_prefix(COMMAND_PREFIX) {
+                    if command.trim() == "windows" {
+                        let windows = SNAPSHOTS
+                            .borrow()
+                            .as_ref()
+                            .map(|snapshot| snapshot.windows())
+                            .unwrap_or_default();
+                        sender.blocking_send(Message::Sway(SwayMessage::WindowList(windows)))?;
+                    }
+                    sender.blocking_send(Message::Sway(SwayMessage::Command(
+                        command.trim().to_string(),
+                    )))?;
+                }
+            }
+            event if CHANGES.contains(&event) => sent.sync(&publish()?, &sender)?,
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Follows the snapshots of the workspace feed, which runs alongside
+fn hyprland_taskbar_generator(sender: Sender<Message>) -> Result<(), HyprlandError> {
+    let mut snapshots = SNAPSHOTS.subscribe();
+    let mut windows = None;
+    loop {
+        let current = snapshots
+            .borrow_and_update()
+            .as_ref()
+            .map(|snapshot| snapshot.taskbar());
+        if let Some(current) = current
+            && windows.as_ref() != Some(&current)
+        {
+            sender.blocking_send(Message::Taskbar(TaskbarMessage::Windows(current.clone())))?;
+            windows = Some(current);
+        }
+        // The sender is static, so this only returns once there is more
+        let _ = Handle::current().block_on(snapshots.changed());
+    }
+}
+
+/// The Hyprland request for the sway commands the bar builds, None for
+/// the others which are sent as they are
+fn translate(command: &str) -> Option<String> {
+    if command == "workspace back_and_forth" {
+        return Some("dispatch workspace previous".to_string());
+    }
+    if let Some(num) = command.strip_prefix("workspace number ") {
+        return Some(format!("dispatch workspace {}", num.trim()));
+    }
+    if let Some(name) = command.strip_prefix("workspace ") {
+        return Some(format!("dispatch workspace name:{}", unquote(name)));
+    }
+    if let Some(name) = command.strip_prefix("rename workspace to ") {
+        let id = number(query("activeworkspace").ok()?.first()?, "id");
+        return Some(format!("dispatch renameworkspace {id} {}", unquote(name)));
+    }
+    if let Some(output) = command.strip_prefix("move workspace to output ") {
+        return Some(format!(
+            "dispatch movecurrentworkspacetomonitor {}",
+            unquote(output)
+        ));
+    }
+    let (criteria, command) = command.strip_prefix("[con_id=")?.split_once(']')?;
+    let window = format!("address:0x{:x}", criteria.parse::<i64>().ok()?);
+    match command.trim() {
+        "focus" => Some(format!("dispatch focuswindow {window}")),
+        "kill" => Some(format!("dispatch closewindow {window}")),
+        _ => None,
+    }
+}
+
+impl Compositor for Hyprland {
+    fn name(&self) -> &'static str {
+        "Hyprland"
+    }
+
+    fn connect(&self) -> Result<(), ShellError> {
+        request("j/version").map(|_| ()).map_err(ShellError::from)
+    }
+
+    fn subscription(&self, rt: Handle) -> ReceiverStream<Message> {
+        let (sender, receiver) = channel(1);
+        supervise_blocking(
+            &rt,
+            "hyprland",
+            Backoff::DEFAULT,
+            sender,
+            hyprland_generator,
+        );
+        ReceiverStream::new(receiver)
+    }
+
+    fn taskbar_subscription(&self, rt: Handle) -> ReceiverStream<Message> {
+        let (sender, receiver) = channel(1);
+        supervise_blocking(
+            &rt,
+            "taskbar",
+            Backoff::DEFAULT,
+            sender,
+            hyprland_taskbar_generator,
+        );
+        ReceiverStream::new(receiver)
+    }
+
+    fn run_command(&self, command: String) {
+        std::thread::spawn(move || {
+            let command = translate(&command).unwrap_or(command);
+            match request(&command) {
+                Ok(response) if response.trim() == "ok" => {}
+                Ok(response) => log::error!("Hyprland could not run {command:?}: {response}"),
+                Err(e) => log::error!("Could not send {command:?} to Hyprland: {e:?}"),
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn translates_the_commands_of_the_bar() {
+        assert_eq!(
+            translate("workspace number 3").as_deref(),
+            Some("dispatch workspace 3")
+        );
+        assert_eq!(
+            translate("workspace \"web \\\"dev\\\"\"").as_deref(),
+            Some("dispatch workspace name:web \"dev\"")
+        );
+        assert_eq!(
+            translate("workspace back_and_forth").as_deref(),
+            Some("dispatch workspace previous")
+        );
+        assert_eq!(
+            translate("move workspace to output \"DP-1\"").as_deref(),
+            Some("dispatch movecurrentworkspacetomonitor DP-1")
+        );
+        assert_eq!(
+            translate("[con_id=255] focus").as_deref(),
+            Some("dispatch focuswindow address:0xff")
+        );
+        assert_eq!(
+            translate("[con_id=255] kill").as_deref(),
+            Some("dispatch closewindow address:0xff")
+        );
+        // Hyprland's own are sent as they are
+        assert_eq!(translate("dispatch exec kitty"), None);
+        assert_eq!(translate("[con_id=255] floating toggle"), None);
+        assert_eq!(translate("[con_id=web] focus"), None);
+    }
+
+    #[test]
+    fn unquotes_arguments() {
+        assert_eq!(unquote(" web "), "web");
+        assert_eq!(unquote("\"1: web\""), "1: web");
+        assert_eq!(unquote("\"a \\\"b\\\" \\\\c\""), "a \"b\" \\c");
+        // Only a quote on both ends is taken off
+        assert_eq!(unquote("\"web"), "\"web");
+    }
+
+    #[test]
+    fn numbers_workspaces_by_their_name() {
+        assert_eq!(workspace_num("1"), 1);
+        assert_eq!(workspace_num("10: web"), 10);
+        assert_eq!(workspace_num("web"), -1);
+        assert_eq!(workspace_num("special:scratch"), -1);
+        assert_eq!(workspace_num(""), -1);
+    }
+
+    #[test]
+    fn parses_addresses() {
+        assert_eq!(address("0x5581c4a0f2d0"), 0x5581c4a0f2d0);
+        assert_eq!(address("5581c4a0f2d0"), 0x5581c4a0f2d0);
+        assert_eq!(address(""), 0);
+        assert_eq!(address("0xnope"), 0);
+        // What translate turns a con_id back into
+        assert_eq!(
+            translate(&format!("[con_id={}] focus", address("0x5581c4a0f2d0"))).as_deref(),
+            Some("dispatch focuswindow address:0x5581c4a0f2d0")
+        );
+    }
+}
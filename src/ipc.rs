@@ -0,0 +1,322 @@
+use std::{
+    collections::HashMap,
+    env::VarError,
+    fs,
+    io::{BufRead, BufReader, Write},
+    os::unix::net::{UnixListener, UnixStream},
+    path::PathBuf,
+    thread,
+    time::Duration,
+};
+
+use tinyjson::JsonValue;
+use tokio::{
+    runtime::Handle,
+    sync::{
+        mpsc::{Sender, channel},
+        oneshot,
+    },
+};
+use tokio_stream::wrappers::ReceiverStream;
+
+use crate::{
+    config::Config,
+    layer::BAR_HEIGHT,
+    renderer::{HitRegions, RenderCommand, Screenshot},
+    state::Message,
+    supervisor::{Backoff, supervise_blocking},
+    theme::Theme,
+    timer::TimerAction,
+};
+
+#[derive(Debug)]
+pub enum IpcError {
+    StdIoError(std::io::Error),
+    VarError(VarError),
+    /// Another bar answers on the socket
+    AlreadyRunning(PathBuf),
+}
+
+impl std::fmt::Display for IpcError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            IpcError::StdIoError(e) => write!(f, "IO Error: {e}"),
+            IpcError::VarError(e) => write!(f, "Environment Variable Error: {e}"),
+            IpcError::AlreadyRunning(path) => {
+                write!(f, "Another bar answers on {}", path.display())
+            }
+        }
+    }
+}
+
+impl From<std::io::Error> for IpcError {
+    fn from(value: std::io::Error) -> Self {
+        Self::StdIoError(value)
+    }
+}
+
+impl From<VarError> for IpcError {
+    fn from(value: VarError) -> Self {
+        Self::VarError(value)
+    }
+}
+
+/// Commands from the control socket. Files are read before the command is
+/// sent, so the client hears about a broken theme or config
+#[derive(Debug)]
+pub enum IpcMessage {
+    /// An autohiding bar goes away again after its delay, unless the
+    /// pointer gets onto it
+    Show,
+    /// Slides the bar off the screen, giving its space to windows
+    Hide,
+    ToggleModule(String),
+    /// Ignores clicks, scrolling and hovering until unlocked, for touch
+    /// screens and presentations
+    Lock,
+    Unlock,
+    ToggleLock,
+    SetTheme(Theme),
+    Timer(TimerAction),
+    /// The config read again. Only what the state uses takes effect, the
+    /// font, GPU and subscription settings need a restart
+    Reload(Box<Config>),
+}
+
+/// What a line from a client asks for, either something for the state or a
+/// question the socket answers on its own
+#[derive(Debug)]
+pub enum Command {
+    Message(IpcMessage),
+    /// The boxes of the modules, in surface pixels, for overlays and tests
+    GetLayout,
+    /// The bar as it is drawn right now, written to a PNG at the path
+    Screenshot(PathBuf),
+}
+
+pub fn socket_path() -> Result<PathBuf, VarError> {
+    Ok(PathBuf::from(std::env::var("XDG_RUNTIME_DIR")?).join("sway-shell.sock"))
+}
+
+/// A line of JSON like {"command": "toggle-module", "module": "mpd"}, the
+/// commands being show, hide, toggle-module, lock, unlock, toggle-lock,
+/// timer with an action of start, pause, toggle, reset or adjust with
+/// seconds, set-theme with a theme, reload, get-layout and screenshot with an
+/// absolute path
+pub fn parse_command(line: &str) -> Result<Command, String> {
+    let command: JsonValue = line.parse().map_err(|e| format!("Invalid JSON: {e}"))?;
+    let command: &HashMap<String, JsonValue> = command
+        .get()
+        .ok_or("Commands have to be JSON objects")?;
+    let field = |name: &str| {
+        command
+            .get(name)
+            .and_then(|value| value.get::<String>())
+            .ok_or(format!("Missing the string field {name:?}"))
+    };
+    Ok(Command::Message(match field("command")?.as_str() {
+        "show" => IpcMessage::Show,
+        "hide" => IpcMessage::Hide,
+        "toggle-module" => IpcMessage::ToggleModule(field("module")?.clone()),
+        "lock" => IpcMessage::Lock,
+        "unlock" => IpcMessage::Unlock,
+        "toggle-lock" => IpcMessage::ToggleLock,
+        "timer" => IpcMessage::Timer(match field("action")?.as_str() {
+            "start" => TimerAction::Start,
+            "pause" => TimerAction::Pause,
+            "toggle" => TimerAction::Toggle,
+            "reset" => TimerAction::Reset,
+            "adjust" => TimerAction::Adjust(
+                command
+                    .get("seconds")
+                    .and_then(|value| value.get::<f64>())
+                    .ok_or("Missing the number field \"seconds\"")? as i64,
+            ),
+            action => return Err(format!("Unknown timer action {action:?}")),
+        }),
+        "set-theme" => IpcMessage::SetTheme(
+            Config::load_theme(field("theme")?).map_err(|e| format!("Could not load the theme: {e:?}"))?,
+        ),
+        "reload" => IpcMessage::Reload(Box::new(
+            Config::try_load().map_err(|e| format!("Could not load the config: {e:?}"))?,
+        )),
+        "get-layout" => return Ok(Command::GetLayout),
+        "screenshot" => {
+            let path = PathBuf::from(field("path")?);
+            if !path.is_absolute() {
+                return Err(format!("The path {path:?} has to be absolute"));
+            }
+            return Ok(Command::Screenshot(path));
+        }
+        command => return Err(format!("Unknown command {command:?}")),
+    }))
+}
+
+/// {"name": "clock", "x": 1700, "y": 0, "width": 80, "height": 15} for every
+/// module, from left to right
+fn layout(hit_regions: &HitRegions) -> JsonValue {
+    let mut modules: Vec<_> = hit_regions
+        .read()
+        .expect("The hit regions lock to never be poisoned")
+        .iter()
+        .filter_map(|region| {
+            let name = region.module.clone()?;
+            Some((name, region.x.clone()))
+        })
+        .collect();
+    modules.sort_by(|(_, a), (_, b)| a.start.total_cmp(&b.start));
+    JsonValue::Array(
+        modules
+            .into_iter()
+            .map(|(name, x)| {
+                let x = x.start.round()..x.end.round();
+                JsonValue::Object(HashMap::from([
+                    ("name".to_string(), JsonValue::String(name.to_string())),
+                    ("x".to_string(), JsonValue::Number(x.start as f64)),
+                    ("y".to_string(), JsonValue::Number(0.)),
+                    (
+                        "width".to_string(),
+                        JsonValue::Number((x.end - x.start) as f64),
+                    ),
+                    ("height".to_string(), JsonValue::Number(BAR_HEIGHT as f64)),
+                ]))
+            })
+            .collect(),
+    )
+}
+
+/// {"ok": true} with the fields of the answer, or {"ok": false, "error": "..."}
+fn response(result: Result<HashMap<String, JsonValue>, String>) -> String {
+    let mut response = HashMap::new();
+    response.insert("ok".to_string(), JsonValue::Boolean(result.is_ok()));
+    match result {
+        Ok(fields) => response.extend(fields),
+        Err(e) => {
+            response.insert("error".to_string(), JsonValue::String(e));
+        }
+    }
+    JsonValue::Object(response)
+        .stringify()
+        .expect("Finite numbers, strings and booleans to always be valid JSON")
+}
+
+/// Sends a command with string fields to the running bar, returning the
+/// fields of its answer or the error it gave
+pub fn send_command<'a>(
+    command: &str,
+    fields: impl IntoIterator<Item = (&'a str, String)>,
+) -> Result<HashMap<String, JsonValue>, String> {
+    let mut request: HashMap<String, JsonValue> = fields
+        .into_iter()
+        .map(|(name, value)| (name.to_string(), JsonValue::String(value)))
+        .collect();
+    request.insert(
+        "command".to_string(),
+        JsonValue::String(command.to_string()),
+    );
+    let line = JsonValue::Object(request)
+        .stringify()
+        .expect("Strings to always be valid JSON");
+    let mut answer = String::new();
+    let path = socket_path().map_err(|e| format!("No socket to connect to: {e}"))?;
+    let mut stream =
+        UnixStream::connect(&path).map_err(|e| format!("Is the bar running? {path:?}: {e}"))?;
+    writeln!(stream, "{line}")
+        .and_then(|_| BufReader::new(stream).read_line(&mut answer))
+        .map_err(|e| format!("Could not talk to the bar: {e}"))?;
+    let answer: JsonValue = answer
+        .parse()
+        .map_err(|e| format!("Invalid answer from the bar: {e}"))?;
+    let JsonValue::Object(mut answer) = answer else {
+        return Err("The answer of the bar is not a JSON object".to_string());
+    };
+    match answer.remove("ok") {
+        Some(JsonValue::Boolean(true)) => Ok(answer),
+        _ => Err(match answer.remove("error") {
+            Some(JsonValue::String(e)) => e,
+            _ => "The bar gave no reason".to_string(),
+        }),
+    }
+}
+
+/// Answers every command on its own line, in order
+fn handle_client(
+    stream: UnixStream,
+    sender: &Sender<Message>,
+    hit_regions: &HitRegions,
+    renderer: &Sender<RenderCommand>,
+) -> Result<(), IpcError> {
+    let mut writer = stream.try_clone()?;
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let result = parse_command(&line).and_then(|command| match command {
+            Command::Message(message) => sender
+                .blocking_send(Message::Ipc(message))
+                .map(|_| HashMap::new())
+                .map_err(|_| "The bar is shutting down".to_string()),
+            Command::GetLayout => Ok(HashMap::from([(
+                "modules".to_string(),
+                layout(hit_regions),
+            )])),
+            Command::Screenshot(path) => {
+                let (done, result) = oneshot::channel();
+                renderer
+                    .blocking_send(RenderCommand::Screenshot(Screenshot { path, done }))
+                    .map_err(|_| "The bar is shutting down".to_string())?;
+                result
+                    .blocking_recv()
+                    .map_err(|_| "The bar is shutting down".to_string())?
+                    .map(|_| HashMap::new())
+                    .map_err(|e| format!("Could not take the screenshot: {e:?}"))
+            }
+        });
+        writeln!(writer, "{}", response(result))?;
+    }
+    Ok(())
+}
+
+fn ipc_generator(
+    sender: Sender<Message>,
+    hit_regions: &HitRegions,
+    renderer: &Sender<RenderCommand>,
+) -> Result<(), IpcError> {
+    let path = socket_path()?;
+    if UnixStream::connect(&path).is_ok() {
+        return Err(IpcError::AlreadyRunning(path));
+    }
+    // Left behind by a bar that didn't exit cleanly
+    let _ = fs::remove_file(&path);
+    let listener = UnixListener::bind(&path)?;
+    log::info!("Listening for commands on {path:?}");
+    for stream in listener.incoming() {
+        let stream = stream?;
+        let sender = sender.clone();
+        let hit_regions = hit_regions.clone();
+        let renderer = renderer.clone();
+        thread::spawn(move || {
+            if let Err(e) = handle_client(stream, &sender, &hit_regions, &renderer) {
+                log::warn!("IPC client went away: {e:?}");
+            }
+        });
+    }
+    Ok(())
+}
+
+pub fn ipc_subscription(
+    rt: Handle,
+    hit_regions: HitRegions,
+    renderer: Sender<RenderCommand>,
+) -> ReceiverStream<Message> {
+    let (sender, receiver) = channel(1);
+    supervise_blocking(
+        &rt,
+        "ipc",
+        Backoff::starting_at(Duration::from_secs(5)),
+        sender,
+        move |sender| ipc_generator(sender, &hit_regions, &renderer),
+    );
+    ReceiverStream::new(receiver)
+}
@@ -0,0 +1,211 @@
+use std::time::Duration;
+
+use tokio::{
+    runtime::Handle,
+    sync::{
+        mpsc::{Sender, channel, error::SendError},
+        watch,
+    },
+};
+use tokio_stream::wrappers::ReceiverStream;
+use zbus::Connection;
+
+use crate::action::Action;
+use crate::format::display_percent;
+use crate::renderer::Renderable;
+use crate::state::{Message, State};
+use crate::supervisor::{Backoff, supervise, tick_unless_resumed};
+use crate::widget::Widget;
+
+#[derive(Debug)]
+pub enum KdeConnectError {
+    DbusError(zbus::Error),
+    SendError(SendError<Message>),
+}
+
+impl std::fmt::Display for KdeConnectError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            KdeConnectError::DbusError(e) => write!(f, "D-Bus Error: {e}"),
+            KdeConnectError::SendError(e) => write!(f, "Channel Error: {e}"),
+        }
+    }
+}
+
+impl From<zbus::Error> for KdeConnectError {
+    fn from(value: zbus::Error) -> Self {
+        Self::DbusError(value)
+    }
+}
+
+impl From<SendError<Message>> for KdeConnectError {
+    fn from(value: SendError<Message>) -> Self {
+        Self::SendError(value)
+    }
+}
+
+#[derive(Debug)]
+pub enum KdeConnectMessage {
+    DevicesUpdate(Vec<Phone>),
+}
+
+#[derive(Debug, Clone)]
+pub struct Phone {
+    /// kdeconnect device id, needed to address the device from kdeconnect-cli
+    pub id: String,
+    pub name: String,
+    /// None when the battery plugin is disabled on either side
+    pub charge: Option<i32>,
+    pub charging: bool,
+    pub notifications: usize,
+}
+
+#[zbus::proxy(
+    interface = "org.kde.kdeconnect.daemon",
+    default_service = "org.kde.kdeconnect",
+    default_path = "/modules/kdeconnect"
+)]
+trait Daemon {
+    #[zbus(name = "devices")]
+    fn devices(&self, only_reachable: bool, only_paired: bool) -> zbus::Result<Vec<String>>;
+}
+
+#[zbus::proxy(
+    interface = "org.kde.kdeconnect.device",
+    default_service = "org.kde.kdeconnect"
+)]
+trait Device {
+    #[zbus(property, name = "name")]
+    fn name(&self) -> zbus::Result<String>;
+}
+
+#[zbus::proxy(
+    interface = "org.kde.kdeconnect.device.battery",
+    default_service = "org.kde.kdeconnect"
+)]
+trait Battery {
+    #[zbus(property, name = "charge")]
+    fn charge(&self) -> zbus::Result<i32>;
+
+    #[zbus(property, name = "isCharging")]
+    fn is_charging(&self) -> zbus::Result<bool>;
+}
+
+#[zbus::proxy(
+    interface = "org.kde.kdeconnect.device.notifications",
+    default_service = "org.kde.kdeconnect"
+)]
+trait Notifications {
+    #[zbus(name = "activeNotifications")]
+    fn active_notifications(&self) -> zbus::Result<Vec<String>>;
+}
+
+async fn phone(conn: &Connection, id: String) -> Result<Phone, KdeConnectError> {
+    let device_path = format!("/modules/kdeconnect/devices/{id}");
+    let device = DeviceProxy::builder(conn)
+        .path(device_path.clone())?
+        .build()
+        .await?;
+    let battery = BatteryProxy::builder(conn)
+        .path(format!("{device_path}/battery"))?
+        .build()
+        .await?;
+    let notifications = NotificationsProxy::builder(conn)
+        .path(format!("{device_path}/notifications"))?
+        .build()
+        .await?;
+    // The plugin objects only exist while the plugin is enabled, so their
+    // absence isn't an error
+    Ok(Phone {
+        name: device.name().await?,
+        charge: battery.charge().await.ok(),
+        charging: battery.is_charging().await.unwrap_or(false),
+        notifications: notifications
+            .active_notifications()
+            .await
+            .map(|v| v.len())
+            .unwrap_or(0),
+        id,
+    })
+}
+
+async fn kdeconnect_generator(
+    sender: Sender<Message>,
+    mut resumes: watch::Receiver<()>,
+) -> Result<(), KdeConnectError> {
+    let conn = Connection::session().await?;
+    let daemon = DaemonProxy::new(&conn).await?;
+    let mut interval = tokio::time::interval(Duration::from_secs(30));
+    resumes.mark_unchanged();
+    loop {
+        tick_unless_resumed(&mut interval, &mut resumes).await;
+        let mut phones = Vec::new();
+        for id in daemon.devices(true, true).await? {
+            phones.push(phone(&conn, id).await?);
+        }
+        sender
+            .send(Message::KdeConnect(KdeConnectMessage::DevicesUpdate(phones)))
+            .await?;
+    }
+}
+
+pub fn kdeconnect_subscription(
+    rt: Handle,
+    resumes: watch::Receiver<()>,
+) -> ReceiverStream<Message> {
+    let (sender, receiver) = channel(1);
+    // kdeconnectd is usually started on demand, don't spin while it's absent
+    supervise(
+        &rt,
+        "kdeconnect",
+        Backoff::starting_at(Duration::from_secs(30)),
+        sender,
+        move |sender| kdeconnect_generator(sender, resumes.clone()),
+    );
+    ReceiverStream::new(receiver)
+}
+
+/// The charge and notifications of each paired phone
+pub const WIDGET: Widget = Widget {
+    name: "kdeconnect",
+    render,
+};
+
+fn render(state: &State) -> Vec<Renderable> {
+    let style = state.config.theme.style("kdeconnect");
+    let mut module = Vec::new();
+    for phone in state.phones.iter() {
+        let mut text = match phone.charge {
+            Some(charge) if phone.charging => {
+                format!("phone {}+", display_percent(charge as f32))
+            }
+            Some(charge) => format!("phone {}", display_percent(charge as f32)),
+            None => "phone".to_string(),
+        };
+        if phone.notifications > 0 {
+            text.push_str(&format!(" {}", phone.notifications));
+        }
+        if !module.is_empty() {
+            module.push(Renderable::Space(1.));
+        }
+        module.push(Renderable::Region {
+            tooltip: Some(format!(
+                "{}\n{} notifications",
+                phone.name, phone.notifications
+            )),
+            on_click: Some(Action::Exec(vec![
+                "kdeconnect-cli".to_string(),
+                "-d".to_string(),
+                phone.id.clone(),
+                "--ring".to_string(),
+            ])),
+            on_scroll: None,
+            children: vec![Renderable::Text {
+                text: text.into(),
+                fg: style.fg,
+                bg: style.bg,
+            }],
+        });
+    }
+    module
+}
@@ -1,4 +1,9 @@
 
+use std::{
+    sync::{Arc, Mutex, PoisonError},
+    time::Duration,
+};
+
 use tokio::{
     runtime::Handle,
     sync::mpsc::Sender,
@@ -6,20 +11,25 @@ use tokio::{
 };
 
 use wayland_client::{
-    Connection, DispatchError, EventQueue, QueueHandle,
-    globals::{GlobalList, registry_queue_init},
+    ConnectError, Connection, Dispatch, DispatchError, EventQueue, QueueHandle, delegate_noop,
+    globals::{GlobalError, GlobalList, registry_queue_init},
     protocol::wl_surface::WlSurface,
     protocol::{
         wl_keyboard::{self, WlKeyboard},
+        wl_output::WlOutput,
         wl_pointer::{self, WlPointer},
-        wl_surface,
+        wl_shm, wl_surface,
     },
 };
+use wayland_protocols::wp::pointer_gestures::zv1::client::{
+    zwp_pointer_gesture_swipe_v1::{self, ZwpPointerGestureSwipeV1},
+    zwp_pointer_gestures_v1::ZwpPointerGesturesV1,
+};
 
 use smithay_client_toolkit::{
-    compositor::{CompositorHandler, CompositorState},
+    compositor::{CompositorHandler, CompositorState, Region},
     delegate_compositor, delegate_keyboard, delegate_layer, delegate_output, delegate_pointer,
-    delegate_registry, delegate_seat,
+    delegate_registry, delegate_seat, delegate_shm,
     output::{OutputHandler, OutputState},
     registry::{ProvidesRegistryState, RegistryState},
     registry_handlers,
@@ -34,12 +44,40 @@ use smithay_client_toolkit::{
             Anchor, KeyboardInteractivity, Layer, LayerShell, LayerShellHandler, LayerSurface,
         },
     },
+    shm::{
+        Shm, ShmHandler,
+        slot::{Buffer, SlotPool},
+    },
 };
 
-use crate::{font::Vec2, state::Message};
+use crate::{
+    config::BarPosition,
+    font::{FontError, Vec2},
+    renderer::RenderCommand,
+    state::Message,
+    supervisor::{Backoff, retry},
+    text_input::KeyInput,
+};
+
+/// Quick retries at first, as sway comes up within moments when the bar
+/// is started from its config
+pub const STARTUP_BACKOFF: Backoff = Backoff {
+    initial: Duration::from_millis(100),
+    max: Duration::from_secs(2),
+};
 
 pub enum DisplayMessage {
     Configure { width: u32, height: u32 },
+    /// The compositor is ready for the next frame
+    Frame,
+    /// The old layer surface was closed along with its output, drawing moves
+    /// to this one once it is configured
+    NewLayer(LayerSurface),
+    /// A tooltip layer surface from TooltipLayers is ready to be drawn on
+    TooltipConfigure(LayerSurface),
+    /// The layer surface was closed and there is no output left to put a
+    /// new one on, until new_output makes one
+    Closed,
 }
 
 #[derive(Debug)]
@@ -51,56 +89,138 @@ pub struct Display {
     pub seat_state: SeatState,
     pub output_state: OutputState,
     pub compositor: CompositorState,
-    pub layer_shell: LayerShell,
+    /// Shared with TooltipLayers
+    pub layer_shell: Arc<LayerShell>,
+    /// The surface of the open menu, set by TooltipLayers::create_menu so
+    /// presses on it can be told from ones on the OSD
+    pub menu_surface: Arc<Mutex<Option<WlSurface>>>,
+    pub shm: Shm,
     pub exit: bool,
+    /// Along the bar and across it, which on a side bar are its height and
+    /// width
     pub width: u32,
     pub height: u32,
+    pub position: BarPosition,
     pub layer: LayerSurface,
+    /// Set while there is no output for the bar to be on
+    pub layer_closed: bool,
+    /// xdg-output name of the output the bar is on, like "DP-1"
+    pub bar_output: Option<String>,
+    /// The output the bar first showed up on. The wl_output of a monitor is
+    /// a new one every time it is plugged in, so it is told by its name, and
+    /// the bar moves back to it when it comes back after an undock
+    pub home_output: Option<String>,
+    /// Whether the bar gives its space to windows and slides away, see
+    /// HotZone
+    pub autohide: bool,
+    /// Only there with autohide and an output to put it on
+    pub hot_zone: Option<HotZone>,
     pub keyboard: Option<WlKeyboard>,
     pub pointer: Option<WlPointer>,
-    pub display_sender: Sender<DisplayMessage>,
+    /// Not every compositor forwards touchpad gestures
+    pub pointer_gestures: Option<ZwpPointerGesturesV1>,
+    pub swipe_gesture: Option<ZwpPointerGestureSwipeV1>,
+    /// The swipe going on over the bar
+    pub swipe: Option<Swipe>,
+    /// Scrolling of a high resolution wheel short of a whole notch, in
+    /// 120ths of one
+    pub scroll_value120: i32,
+    pub modifiers: Modifiers,
+    pub display_sender: Sender<RenderCommand>,
     pub state_sender: Sender<Message>,
 }
 
+/// A touchpad swipe that started on the bar, added up so far
+#[derive(Debug)]
+pub struct Swipe {
+    fingers: u32,
+    dx: f64,
+    dy: f64,
+}
+
+/// Height of the bar in surface pixels, which layout takes as its unit
+pub const BAR_HEIGHT: u32 = 15;
+
+/// Fingers of the swipe that switches workspaces
+const SWIPE_FINGERS: u32 = 3;
+
+/// How far in surface pixels a swipe has to go to switch workspaces
+const SWIPE_DISTANCE: f64 = 50.;
+
+/// The strip along the edge of the output that brings an autohiding bar
+/// back. It is a pixel thick and transparent, but takes pointer events
+#[derive(Debug)]
+pub struct HotZone {
+    layer: LayerSurface,
+    pool: Option<SlotPool>,
+    /// Attached to the surface, the compositor reads from it while mapped
+    buffer: Option<Buffer>,
+    size: (u32, u32),
+}
+
+/// The edge of the output and the two corners the bar stretches between
+fn anchor(position: BarPosition) -> Anchor {
+    match position {
+        BarPosition::Top => Anchor::TOP.union(Anchor::LEFT).union(Anchor::RIGHT),
+        BarPosition::Left => Anchor::LEFT.union(Anchor::TOP).union(Anchor::BOTTOM),
+        BarPosition::Right => Anchor::RIGHT.union(Anchor::TOP).union(Anchor::BOTTOM),
+    }
+}
+
 impl Display {
+    /// Connects to the compositor, trying again until give_up_after has
+    /// passed, or for as long as it takes without it
     pub async fn new(
         height: u32,
-        display_sender: Sender<DisplayMessage>,
+        position: BarPosition,
+        autohide: bool,
+        give_up_after: Option<Duration>,
+        display_sender: Sender<RenderCommand>,
         state_sender: Sender<Message>,
-    ) -> (Self, EventQueue<Self>) {
-        let wayland_conn =
-            Connection::connect_to_env().expect("To be able to connect to the compositor");
-        let (globals, event_queue) = registry_queue_init(&wayland_conn)
-            .expect("To be able to initialize the registry queue from the compositor");
+    ) -> Result<(Self, EventQueue<Self>), StartupError> {
+        let connect = || {
+            let wayland_conn = Connection::connect_to_env()?;
+            let registry = registry_queue_init(&wayland_conn)?;
+            Ok::<_, StartupError>((wayland_conn, registry))
+        };
+        let (wayland_conn, (globals, event_queue)) = block_in_place(|| {
+            retry(
+                "connect to the compositor",
+                &STARTUP_BACKOFF,
+                give_up_after,
+                connect,
+            )
+        })?;
         let qh = event_queue.handle();
         let compositor =
             CompositorState::bind(&globals, &qh).expect("wl_compositor is not available");
-        let layer_shell = LayerShell::bind(&globals, &qh).expect("layer shell is not available");
-
-        let wayland_surface = compositor.create_surface(&qh);
-
-        let compositor = CompositorState::bind(&globals, &qh)
-            .expect("wl_compositor is not available, whatever that means");
+        let layer_shell =
+            Arc::new(LayerShell::bind(&globals, &qh).expect("layer shell is not available"));
+        let shm = Shm::bind(&globals, &qh).expect("wl_shm is not available");
+        let pointer_gestures = globals
+            .bind(&qh, 1..=1, ())
+            .inspect_err(|e| log::info!("Touchpad gestures are not available: {e}"))
+            .ok();
 
-        // NOTE: This surface cloning might not be fine
-        let layer = layer_shell.create_layer_surface(
+        // Made first so the bar stacks above it while shown
+        let hot_zone =
+            autohide.then(|| Self::create_hot_zone(&compositor, &layer_shell, &qh, position));
+        let layer = Self::create_layer(
+            &compositor,
+            &layer_shell,
             &qh,
-            wayland_surface.clone(),
-            Layer::Top,
-            Some("sway-shell"),
+            height,
+            position,
+            autohide,
             None,
         );
-
-        layer.set_keyboard_interactivity(KeyboardInteractivity::OnDemand);
-
-        layer.set_anchor(Anchor::TOP.union(Anchor::LEFT).union(Anchor::RIGHT));
-        layer.set_size(0, height);
+        let wayland_surface = layer.wl_surface().clone();
         /*display_sender
                     .send(DisplayMessage::Configure { width: 100, height })
                     .await
                     .expect("To be able to send message for configuring rendering");
         */
-        (
+        Ok((
             Display {
                 display_sender,
                 state_sender,
@@ -108,19 +228,205 @@ impl Display {
                 wayland_conn,
                 compositor,
                 layer_shell,
+                menu_surface: Arc::default(),
+                shm,
                 registry_state: RegistryState::new(&globals),
                 seat_state: SeatState::new(&globals, &qh),
                 output_state: OutputState::new(&globals, &qh),
                 exit: false,
                 width: 256 * 4,
                 height,
+                position,
                 layer,
+                layer_closed: false,
+                bar_output: None,
+                home_output: None,
+                autohide,
+                hot_zone,
                 keyboard: None,
                 pointer: None,
+                pointer_gestures,
+                swipe_gesture: None,
+                swipe: None,
+                scroll_value120: 0,
+                modifiers: Modifiers::default(),
                 globals,
             },
             event_queue,
-        )
+        ))
+    }
+
+    /// A bar along the edge of whichever output the compositor puts it on,
+    /// the compositor configures it with the length of that edge
+    fn create_layer(
+        compositor: &CompositorState,
+        layer_shell: &LayerShell,
+        qh: &QueueHandle<Self>,
+        height: u32,
+        position: BarPosition,
+        autohide: bool,
+        output: Option<&WlOutput>,
+    ) -> LayerSurface {
+        let layer = layer_shell.create_layer_surface(
+            qh,
+            compositor.create_surface(qh),
+            Layer::Top,
+            Some("sway-shell"),
+            output,
+        );
+        layer.set_keyboard_interactivity(KeyboardInteractivity::OnDemand);
+        layer.set_anchor(anchor(position));
+        let size = position.along((0, height));
+        layer.set_size(size.0, size.1);
+        layer.set_exclusive_zone(if autohide { 0 } else { height as i32 });
+        layer.commit();
+        layer
+    }
+
+    /// Nothing is attached until it is configured with the length of the
+    /// edge, see attach_hot_zone_buffer
+    fn create_hot_zone(
+        compositor: &CompositorState,
+        layer_shell: &LayerShell,
+        qh: &QueueHandle<Self>,
+        position: BarPosition,
+    ) -> HotZone {
+        let layer = layer_shell.create_layer_surface(
+            qh,
+            compositor.create_surface(qh),
+            Layer::Top,
+            Some("sway-shell-hot-zone"),
+            None,
+        );
+        layer.set_keyboard_interactivity(KeyboardInteractivity::None);
+        layer.set_anchor(anchor(position));
+        let size = position.along((0, 1));
+        layer.set_size(size.0, size.1);
+        // Has to stay on the edge even below other bars
+        layer.set_exclusive_zone(-1);
+        layer.commit();
+        HotZone {
+            layer,
+            pool: None,
+            buffer: None,
+            size: (0, 0),
+        }
+    }
+
+    /// A layer surface is only mapped, and gets pointer events, once a
+    /// buffer is attached. All zeroes is fully transparent
+    fn attach_hot_zone_buffer(&mut self, (width, height): (u32, u32)) {
+        let Some(hot_zone) = &mut self.hot_zone else {
+            return;
+        };
+        if width == 0
+            || height == 0
+            || (hot_zone.size == (width, height) && hot_zone.buffer.is_some())
+        {
+            return;
+        }
+        let stride = width as i32 * 4;
+        let pool = match &mut hot_zone.pool {
+            Some(pool) => pool,
+            None => match SlotPool::new(stride as usize, &self.shm) {
+                Ok(pool) => hot_zone.pool.insert(pool),
+                Err(e) => {
+                    log::error!("Could not create the buffer pool of the hot zone: {e}");
+                    return;
+                }
+            },
+        };
+        let buffer = match pool.create_buffer(
+            width as i32,
+            height as i32,
+            stride,
+            wl_shm::Format::Argb8888,
+        ) {
+            Ok((buffer, canvas)) => {
+                canvas.fill(0);
+                buffer
+            }
+            Err(e) => {
+                log::error!("Could not create the buffer of the hot zone: {e}");
+                return;
+            }
+        };
+        if let Err(e) = buffer.attach_to(hot_zone.layer.wl_surface()) {
+            log::error!("Could not attach the buffer of the hot zone: {e}");
+            return;
+        }
+        hot_zone
+            .layer
+            .wl_surface()
+            .damage_buffer(0, 0, width as i32, height as i32);
+        hot_zone.layer.commit();
+        hot_zone.buffer = Some(buffer);
+        hot_zone.size = (width, height);
+    }
+
+    /// For the renderer to make tooltip surfaces with, their events still
+    /// come in here
+    pub fn tooltip_layers(&self, qh: &QueueHandle<Self>) -> TooltipLayers {
+        TooltipLayers {
+            wayland_conn: self.wayland_conn.clone(),
+            compositor: self.compositor.clone(),
+            layer_shell: self.layer_shell.clone(),
+            menu_surface: self.menu_surface.clone(),
+            queue_handle: qh.clone(),
+            position: self.position,
+        }
+    }
+
+    /// Replaces the layer surface with a new one on the output, or wherever
+    /// the compositor puts it without one. The renderer gets the new one
+    /// before any configure for it can arrive
+    fn recreate_layer(&mut self, qh: &QueueHandle<Self>, output: Option<&WlOutput>) {
+        log::info!("Creating a new layer surface for the bar");
+        let layer = Self::create_layer(
+            &self.compositor,
+            &self.layer_shell,
+            qh,
+            self.height,
+            self.position,
+            self.autohide,
+            output,
+        );
+        self.bar_output = None;
+        self.wayland_surface = layer.wl_surface().clone();
+        self.layer = layer.clone();
+        self.layer_closed = false;
+        self.send_display_message(DisplayMessage::NewLayer(layer));
+    }
+
+    /// The name xdg-output gives the output, which stays the same across
+    /// plugging it in again unlike the wl_output
+    fn output_name(&self, output: &WlOutput) -> Option<String> {
+        self.output_state.info(output).and_then(|info| info.name)
+    }
+
+    /// A pointer position on the bar surface with x along the bar, which is
+    /// down a side bar
+    fn bar_point(&self, position: (f64, f64)) -> Vec2 {
+        let (x, y) = self.position.along(position);
+        Vec2 {
+            x: x as f32,
+            y: y as f32,
+        }
+    }
+
+    /// The state loop only stops when the bar exits, until then pointer
+    /// and key events are dropped rather than taking the event loop down
+    fn send_state_message(&self, message: Message) {
+        if block_in_place(|| self.state_sender.blocking_send(message)).is_err() {
+            log::error!("The state is gone, could not send it a message");
+        }
+    }
+
+    /// Sends in order with the messages before it, unlike the spawned sends
+    fn send_display_message(&self, message: DisplayMessage) {
+        if block_in_place(|| self.display_sender.blocking_send(RenderCommand::Display(message))).is_err() {
+            log::error!("The renderer is gone, could not send it a display message");
+        }
     }
 
     /// Actual rendering happens in CompositorHandler::frame
@@ -149,6 +455,145 @@ impl Display {
     }
 }
 
+/// Creates the layer surfaces tooltips are drawn on, from outside of the
+/// Wayland event loop
+#[derive(Debug, Clone)]
+pub struct TooltipLayers {
+    wayland_conn: Connection,
+    compositor: CompositorState,
+    layer_shell: Arc<LayerShell>,
+    menu_surface: Arc<Mutex<Option<WlSurface>>>,
+    queue_handle: QueueHandle<Display>,
+    position: BarPosition,
+}
+
+impl TooltipLayers {
+    /// A surface just below the bar, x pixels from the left of the output.
+    /// Next to a side bar, x pixels from the top. Nothing may be drawn on it
+    /// until DisplayMessage::TooltipConfigure
+    pub fn create(&self, x: u32, width: u32, height: u32) -> LayerSurface {
+        let layer = self.below_bar("sway-shell-tooltip", x);
+        // Clicks go through to what is below, only the OSD and the menu
+        // take them
+        match Region::new(&self.compositor) {
+            Ok(region) => layer
+                .wl_surface()
+                .set_input_region(Some(region.wl_region())),
+            Err(e) => log::error!("Could not make the tooltip click through: {e}"),
+        }
+        layer.set_size(width, height);
+        layer.commit();
+        self.flush();
+        layer
+    }
+
+    /// A surface placed like a tooltip that takes presses, which come in as
+    /// Message::MenuPress with the line pressed
+    pub fn create_menu(&self, x: u32, width: u32, height: u32) -> LayerSurface {
+        let layer = self.below_bar("sway-shell-menu", x);
+        *self
+            .menu_surface
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = Some(layer.wl_surface().clone());
+        layer.set_size(width, height);
+        layer.commit();
+        self.flush();
+        layer
+    }
+
+    /// Placed as told for create, left to be sized and committed
+    fn below_bar(&self, namespace: &str, x: u32) -> LayerSurface {
+        let layer = self.layer_shell.create_layer_surface(
+            &self.queue_handle,
+            self.compositor.create_surface(&self.queue_handle),
+            Layer::Overlay,
+            Some(namespace),
+            None,
+        );
+        layer.set_keyboard_interactivity(KeyboardInteractivity::None);
+        match self.position {
+            BarPosition::Top => {
+                layer.set_anchor(Anchor::TOP.union(Anchor::LEFT));
+                layer.set_margin(0, 0, 0, x as i32);
+            }
+            BarPosition::Left => {
+                layer.set_anchor(Anchor::TOP.union(Anchor::LEFT));
+                layer.set_margin(x as i32, 0, 0, 0);
+            }
+            BarPosition::Right => {
+                layer.set_anchor(Anchor::TOP.union(Anchor::RIGHT));
+                layer.set_margin(x as i32, 0, 0, 0);
+            }
+        }
+        layer
+    }
+
+    /// A surface in the middle of the output above everything, for what
+    /// can't be missed like the suspend countdown. Presses on it come in as
+    /// Message::OsdPress, and it is drawn on after
+    /// DisplayMessage::TooltipConfigure like a tooltip
+    pub fn create_osd(&self, width: u32, height: u32) -> LayerSurface {
+        let layer = self.layer_shell.create_layer_surface(
+            &self.queue_handle,
+            self.compositor.create_surface(&self.queue_handle),
+            Layer::Overlay,
+            Some("sway-shell-osd"),
+            None,
+        );
+        layer.set_keyboard_interactivity(KeyboardInteractivity::None);
+        // Centered without anchors
+        layer.set_size(width, height);
+        layer.commit();
+        self.flush();
+        layer
+    }
+
+    /// The event loop only flushes before blocking, so requests made from
+    /// other threads have to be sent out by hand, like after dropping a
+    /// tooltip surface
+    pub fn flush(&self) {
+        if let Err(e) = self.wayland_conn.flush() {
+            log::error!("Could not flush the Wayland connection: {e}");
+        }
+    }
+}
+
+/// Why the bar could not start, mostly not connecting to the compositor
+#[derive(Debug)]
+pub enum StartupError {
+    /// Like WAYLAND_DISPLAY not being set yet, or the socket not being there
+    ConnectError(ConnectError),
+    GlobalError(GlobalError),
+    FontError(FontError),
+    #[cfg(feature = "x11")]
+    X11Error(crate::x11::X11Error),
+}
+
+impl From<ConnectError> for StartupError {
+    fn from(value: ConnectError) -> Self {
+        Self::ConnectError(value)
+    }
+}
+
+impl From<GlobalError> for StartupError {
+    fn from(value: GlobalError) -> Self {
+        Self::GlobalError(value)
+    }
+}
+
+impl From<FontError> for StartupError {
+    fn from(value: FontError) -> Self {
+        Self::FontError(value)
+    }
+}
+
+#[cfg(feature = "x11")]
+impl From<crate::x11::X11Error> for StartupError {
+    fn from(value: crate::x11::X11Error) -> Self {
+        Self::X11Error(value)
+    }
+}
+
 #[derive(Debug)]
 pub enum EventLoopError {
     EventQueueDispathError(DispatchError),
@@ -168,8 +613,31 @@ impl From<JoinError> for EventLoopError {
 }
 
 impl LayerShellHandler for Display {
-    fn closed(&mut self, _conn: &Connection, _qh: &QueueHandle<Self>, _layer: &LayerSurface) {
-        self.exit = true;
+    /// Sent when the output the bar is on goes away, like when undocking.
+    /// The bar moves to another output or waits for one to show up
+    fn closed(&mut self, _conn: &Connection, qh: &QueueHandle<Self>, layer: &LayerSurface) {
+        if self.hot_zone.as_ref().is_some_and(|hot_zone| &hot_zone.layer == layer) {
+            self.hot_zone = None;
+            if self.output_state.outputs().next().is_some() {
+                self.hot_zone = Some(Self::create_hot_zone(
+                    &self.compositor,
+                    &self.layer_shell,
+                    qh,
+                    self.position,
+                ));
+            }
+            return;
+        }
+        if layer.wl_surface() != self.layer.wl_surface() {
+            return;
+        }
+        log::info!("The layer surface on {:?} was closed", self.bar_output);
+        if self.output_state.outputs().next().is_some() {
+            self.recreate_layer(qh, None);
+        } else {
+            self.layer_closed = true;
+            self.send_display_message(DisplayMessage::Closed);
+        }
     }
 
     fn configure(
@@ -180,19 +648,36 @@ impl LayerShellHandler for Display {
         configure: smithay_client_toolkit::shell::wlr_layer::LayerSurfaceConfigure,
         _serial: u32,
     ) {
-        let (new_width, new_height) = configure.new_size;
+        if self.hot_zone.as_ref().is_some_and(|hot_zone| &hot_zone.layer == layer) {
+            self.attach_hot_zone_buffer(configure.new_size);
+            return;
+        }
+        if layer != &self.layer {
+            let display_sender = self.display_sender.clone();
+            let layer = layer.clone();
+            Handle::current().spawn(async move {
+                display_sender
+                    .send(RenderCommand::Display(DisplayMessage::TooltipConfigure(
+                        layer,
+                    )))
+                    .await
+            });
+            return;
+        }
+        let (new_width, new_height) = self.position.along(configure.new_size);
         self.width = new_width;
         self.height = new_height;
         let display_sender = self.display_sender.clone();
         Handle::current().spawn(async move {
             display_sender
-                .send(DisplayMessage::Configure {
+                .send(RenderCommand::Display(DisplayMessage::Configure {
                     width: new_width,
                     height: new_height,
-                })
+                }))
                 .await
         });
-        layer.set_size(self.width, self.height);
+        let (width, height) = self.position.along((self.width, self.height));
+        layer.set_size(width, height);
     }
 }
 
@@ -224,17 +709,30 @@ impl CompositorHandler for Display {
         _surface: &wayland_client::protocol::wl_surface::WlSurface,
         _time: u32,
     ) {
-        log::info!("Wgpu::frame");
+        let display_sender = self.display_sender.clone();
+        Handle::current().spawn(async move {
+            display_sender
+                .send(RenderCommand::Display(DisplayMessage::Frame))
+                .await
+        });
     }
 
     fn surface_enter(
         &mut self,
         _conn: &Connection,
         _qh: &QueueHandle<Self>,
-        _surface: &wayland_client::protocol::wl_surface::WlSurface,
-        _output: &wayland_client::protocol::wl_output::WlOutput,
+        surface: &wayland_client::protocol::wl_surface::WlSurface,
+        output: &wayland_client::protocol::wl_output::WlOutput,
     ) {
-        log::info!("Wgpu::surface_enter");
+        if surface != self.layer.wl_surface() {
+            return;
+        }
+        let name = self.output_name(output);
+        log::info!("The bar is on output {name:?}");
+        if self.home_output.is_none() {
+            self.home_output.clone_from(&name);
+        }
+        self.bar_output = name;
     }
 
     fn surface_leave(
@@ -256,46 +754,50 @@ impl OutputHandler for Display {
     fn new_output(
         &mut self,
         _conn: &Connection,
-        _qh: &QueueHandle<Self>,
+        qh: &QueueHandle<Self>,
         output: wayland_client::protocol::wl_output::WlOutput,
     ) {
-        let output_info = self
-            .output_state
-            .info(&output)
-            .expect("To be able to get the info of the output from current output state");
-        if let Some((width, height)) = output_info.logical_size {
-            self.width = width as u32;
-            self.layer.set_size(self.width, self.height);
-            self.layer.set_exclusive_zone(self.height as i32);
-            let display_sender = self.display_sender.clone();
-            Handle::current().spawn(async move {
-                log::info!("New Output message being sent");
-                display_sender
-                    .send(DisplayMessage::Configure {
-                        width: width as u32,
-                        height: height as u32,
-                    })
-                    .await
-            });
+        let name = self.output_name(&output);
+        log::info!("New output {name:?}");
+        if self.autohide && self.hot_zone.is_none() {
+            self.hot_zone = Some(Self::create_hot_zone(
+                &self.compositor,
+                &self.layer_shell,
+                qh,
+                self.position,
+            ));
+        }
+        let home_again = name.is_some() && name == self.home_output && name != self.bar_output;
+        if self.layer_closed || home_again {
+            self.recreate_layer(qh, Some(&output));
         }
     }
 
+    /// Mode and scale changes of the bar's output come in as a configure of
+    /// the layer surface, there's nothing to do here
     fn update_output(
         &mut self,
         _conn: &Connection,
         _qh: &QueueHandle<Self>,
-        _output: wayland_client::protocol::wl_output::WlOutput,
+        output: wayland_client::protocol::wl_output::WlOutput,
     ) {
-        log::info!("Wgpu::update_output");
+        log::info!("Output changed {:?}", self.output_name(&output));
     }
 
+    /// The compositor closes the layer surface if it was on this output,
+    /// handled in closed
     fn output_destroyed(
         &mut self,
         _conn: &Connection,
         _qh: &QueueHandle<Self>,
-        _output: wayland_client::protocol::wl_output::WlOutput,
+        output: wayland_client::protocol::wl_output::WlOutput,
     ) {
-        log::info!("Wgpu::output_destroyed");
+        let name = self.output_name(&output);
+        if name.is_some() && name == self.bar_output {
+            log::info!("Output {name:?} with the bar on it was removed");
+        } else {
+            log::info!("Output removed {name:?}");
+        }
     }
 }
 
@@ -337,6 +839,9 @@ impl SeatHandler for Display {
                 .seat_state
                 .get_pointer(qh, &seat)
                 .expect("Failed to create pointer");
+            if let Some(pointer_gestures) = &self.pointer_gestures {
+                self.swipe_gesture = Some(pointer_gestures.get_swipe_gesture(&pointer, qh, ()));
+            }
             self.pointer = Some(pointer);
         }
     }
@@ -355,6 +860,9 @@ impl SeatHandler for Display {
 
         if capability == Capability::Pointer && self.pointer.is_some() {
             println!("Unset pointer capability");
+            if let Some(swipe_gesture) = self.swipe_gesture.take() {
+                swipe_gesture.destroy();
+            }
             self.pointer.take().unwrap().release();
         }
     }
@@ -379,41 +887,63 @@ impl PointerHandler for Display {
     ) {
         use PointerEventKind::*;
         for event in events {
-            // Ignore events for other surfaces
+            if self
+                .hot_zone
+                .as_ref()
+                .is_some_and(|hot_zone| hot_zone.layer.wl_surface() == &event.surface)
+            {
+                let message = match event.kind {
+                    Enter { .. } => Message::EdgeEnter,
+                    Leave { .. } => Message::EdgeLeave,
+                    _ => continue,
+                };
+                self.send_state_message(message);
+                continue;
+            }
+            // Tooltips let clicks through, so the OSD and the menu are the
+            // other surfaces that get them
             if &event.surface != self.layer.wl_surface() {
+                if let Press { .. } = event.kind {
+                    let on_menu = self
+                        .menu_surface
+                        .lock()
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .as_ref()
+                        == Some(&event.surface);
+                    // The menu has a line a bar height tall per entry
+                    self.send_state_message(if on_menu {
+                        Message::MenuPress {
+                            line: (event.position.1 / self.height as f64) as usize,
+                        }
+                    } else {
+                        Message::OsdPress
+                    });
+                }
                 continue;
             }
             match event.kind {
-                Enter { .. } => {
-                    log::info!("Pointer entered @{:?}", event.position);
+                Enter { .. } | Motion { .. } => {
+                    self.send_state_message(Message::PointerMotion {
+                        pos: self.bar_point(event.position),
+                    });
                 }
                 Leave { .. } => {
                     log::info!("Pointer left");
+                    self.send_state_message(Message::PointerLeave);
                 }
-                Motion { .. } => {}
                 Press { button, .. } => {
                     log::info!("Press {:x} @ {:?}", button, event.position);
-                    block_in_place(|| {
-                        self.state_sender.blocking_send(Message::PointerPress {
-                            pos: Vec2 {
-                                x: event.position.0 as f32,
-                                y: event.position.1 as f32,
-                            },
-                        })
-                    })
-                    .expect("To be able to send a state message when mouse is clicked");
+                    self.send_state_message(Message::PointerPress {
+                        pos: self.bar_point(event.position),
+                        button,
+                    });
                 }
                 Release { button, .. } => {
                     log::info!("Release {:x} @ {:?}", button, event.position);
-                    block_in_place(|| {
-                        self.state_sender.blocking_send(Message::PointerRelease {
-                            pos: Vec2 {
-                                x: event.position.0 as f32,
-                                y: event.position.1 as f32,
-                            },
-                        })
-                    })
-                    .expect("To be able to send a state message when mouse is released");
+                    self.send_state_message(Message::PointerRelease {
+                        pos: self.bar_point(event.position),
+                        button,
+                    });
                 }
                 Axis {
                     horizontal,
@@ -421,8 +951,78 @@ impl PointerHandler for Display {
                     ..
                 } => {
                     log::info!("Scroll H:{horizontal:?}, V:{vertical:?}");
+                    // wl_pointer 8 and up sends axis_value120 in place of
+                    // axis_discrete, a notch being 120
+                    let discrete = if vertical.value120 != 0 {
+                        self.scroll_value120 += vertical.value120;
+                        let notches = self.scroll_value120 / 120;
+                        self.scroll_value120 -= notches * 120;
+                        // High resolution wheels send parts of a notch,
+                        // which wait for the rest of it
+                        if notches == 0 {
+                            continue;
+                        }
+                        notches
+                    } else {
+                        vertical.discrete
+                    };
+                    if vertical.absolute != 0. || discrete != 0 {
+                        self.send_state_message(Message::PointerScroll {
+                            pos: self.bar_point(event.position),
+                            delta: vertical.absolute as f32,
+                            discrete,
+                        });
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Dispatch<ZwpPointerGestureSwipeV1, ()> for Display {
+    fn event(
+        state: &mut Self,
+        _: &ZwpPointerGestureSwipeV1,
+        event: zwp_pointer_gesture_swipe_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        use zwp_pointer_gesture_swipe_v1::Event::*;
+        match event {
+            // Swipes starting anywhere else are for other clients
+            Begin {
+                surface, fingers, ..
+            } => {
+                state.swipe = (&surface == state.layer.wl_surface()).then_some(Swipe {
+                    fingers,
+                    dx: 0.,
+                    dy: 0.,
+                });
+            }
+            Update { dx, dy, .. } => {
+                if let Some(swipe) = &mut state.swipe {
+                    swipe.dx += dx;
+                    swipe.dy += dy;
+                }
+            }
+            End { cancelled, .. } => {
+                let Some(swipe) = state.swipe.take() else {
+                    return;
+                };
+                // Along a side bar is up and down
+                let (dx, dy) = state.position.along((swipe.dx, swipe.dy));
+                if cancelled != 0
+                    || swipe.fingers != SWIPE_FINGERS
+                    || dx.abs() < SWIPE_DISTANCE
+                    || dx.abs() < dy.abs()
+                {
+                    return;
                 }
+                log::info!("Swipe of {dx:.0}px");
+                state.send_state_message(Message::PointerSwipe { dx: dx as f32 });
             }
+            _ => {}
         }
     }
 }
@@ -465,10 +1065,12 @@ impl KeyboardHandler for Display {
         event: KeyEvent,
     ) {
         log::info!("Key press: {event:?}");
-        // press 'esc' to exit
-        if event.keysym == Keysym::Escape {
-            self.exit = true;
-        }
+        self.send_state_message(Message::Key(KeyInput {
+            keysym: event.keysym,
+            utf8: event.utf8,
+            ctrl: self.modifiers.ctrl,
+            shift: self.modifiers.shift,
+        }));
     }
 
     fn release_key(
@@ -492,6 +1094,7 @@ impl KeyboardHandler for Display {
         _layout: u32,
     ) {
         log::info!("Update modifiers: {modifiers:?}");
+        self.modifiers = modifiers;
     }
 }
 
@@ -501,11 +1104,20 @@ delegate_output!(Display);
 delegate_seat!(Display);
 delegate_keyboard!(Display);
 delegate_pointer!(Display);
+delegate_noop!(Display: ignore ZwpPointerGesturesV1);
 
 delegate_layer!(Display);
 
+delegate_shm!(Display);
+
 delegate_registry!(Display);
 
+impl ShmHandler for Display {
+    fn shm_state(&mut self) -> &mut Shm {
+        &mut self.shm
+    }
+}
+
 impl ProvidesRegistryState for Display {
     fn registry(&mut self) -> &mut RegistryState {
         &mut self.registry_state
@@ -0,0 +1,112 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+/// Time between looks at the resident memory
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+/// Samples in a row the memory outside the caches has to grow over, an
+/// hour of them
+const GROWING_SAMPLES: usize = 6;
+
+/// Growth over those samples past which it is reported, as allocators hold
+/// on to some memory on their own
+const LEAK_THRESHOLD: usize = 32 * 1024 * 1024;
+
+/// Watches the resident memory of the bar for growth the caches don't
+/// explain. The caches stay within their budget, so memory that keeps
+/// growing outside of them over an hour points at a leak
+#[derive(Debug, Default)]
+pub struct LeakCheck {
+    /// Resident bytes minus the caches, oldest first
+    samples: VecDeque<usize>,
+    sampled_at: Option<Instant>,
+    /// Set once the memory grew over GROWING_SAMPLES in a row, for the
+    /// cache overlay
+    pub suspected: bool,
+}
+
+impl LeakCheck {
+    /// Takes a sample once one is due, caches being the bytes the caches
+    /// take up
+    pub fn tick(&mut self, caches: usize) {
+        let now = Instant::now();
+        if self
+            .sampled_at
+            .is_some_and(|sampled_at| now - sampled_at < SAMPLE_INTERVAL)
+        {
+            return;
+        }
+        self.sampled_at = Some(now);
+        if let Some(resident) = resident_bytes() {
+            self.record(resident.saturating_sub(caches));
+        }
+    }
+
+    fn record(&mut self, outside_caches: usize) {
+        self.samples.push_back(outside_caches);
+        if self.samples.len() > GROWING_SAMPLES + 1 {
+            self.samples.pop_front();
+        }
+        let growing = self.samples.len() == GROWING_SAMPLES + 1
+            && self
+                .samples
+                .iter()
+                .zip(self.samples.iter().skip(1))
+                .all(|(a, b)| a < b);
+        let grown = self.samples.back().unwrap_or(&0) - self.samples.front().unwrap_or(&0);
+        let suspected = growing && grown > LEAK_THRESHOLD;
+        if suspected && !self.suspected {
+            log::warn!(
+                "Memory outside the caches grew by {} MiB over the last hour and keeps growing, \
+                 something may be leaking",
+                grown / (1024 * 1024)
+            );
+        }
+        self.suspected = suspected;
+    }
+}
+
+/// VmRSS of /proc/self/status
+fn resident_bytes() -> Option<usize> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let kib = status
+        .lines()
+        .find_map(|line| line.strip_prefix("VmRSS:"))?
+        .trim()
+        .strip_suffix("kB")?
+        .trim()
+        .parse::<usize>()
+        .ok()?;
+    Some(kib * 1024)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MIB: usize = 1024 * 1024;
+
+    #[test]
+    fn suspects_steady_growth_only() {
+        let mut check = LeakCheck::default();
+        for i in 0..=GROWING_SAMPLES {
+            assert!(!check.suspected);
+            check.record(100 * MIB + i * 10 * MIB);
+        }
+        assert!(check.suspected);
+        // Memory given back clears it again
+        check.record(90 * MIB);
+        assert!(!check.suspected);
+    }
+
+    #[test]
+    fn ignores_small_growth() {
+        let mut check = LeakCheck::default();
+        for i in 0..=GROWING_SAMPLES * 2 {
+            check.record(100 * MIB + i * MIB);
+        }
+        assert!(!check.suspected);
+    }
+}
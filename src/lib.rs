@@ -0,0 +1,63 @@
+#![feature(sort_floats)]
+#![feature(iter_array_chunks)]
+
+pub mod action;
+pub mod ambient;
+pub mod atlas;
+pub mod bar;
+pub mod config;
+pub mod crash;
+pub mod cpu;
+pub mod custom;
+pub mod docker;
+pub mod error;
+pub mod font;
+pub mod hosts;
+pub mod hyprland;
+pub mod ipc;
+pub mod layer;
+pub mod leak;
+pub mod markup;
+pub mod mpd;
+pub mod mpris;
+pub mod mqtt;
+pub mod memory;
+pub mod mic;
+pub mod nvme;
+pub mod palette;
+pub mod preview;
+pub mod privacy;
+pub mod processes;
+pub mod renderer;
+pub mod shaper;
+pub mod state;
+pub mod supervisor;
+pub mod surface;
+pub mod taskbar;
+pub mod update;
+pub mod upower;
+pub mod sway;
+pub mod text_input;
+pub mod network;
+pub mod night_light;
+pub mod netlink;
+pub mod backlight;
+pub mod audio;
+pub mod battery;
+pub mod files;
+pub mod format;
+pub mod libvirt;
+pub mod kdeconnect;
+pub mod logind;
+pub mod camera;
+pub mod clock;
+pub mod compositor;
+pub mod systemd;
+pub mod thermal;
+pub mod theme;
+pub mod timer;
+pub mod watch;
+pub mod widget;
+pub mod wlr;
+#[cfg(feature = "x11")]
+pub mod x11;
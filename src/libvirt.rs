@@ -0,0 +1,173 @@
+use std::{process::Command, string::FromUtf8Error, time::Duration};
+
+use tokio::{
+    runtime::Handle,
+    sync::{
+        mpsc::{Sender, channel, error::SendError},
+        watch,
+    },
+};
+use tokio_stream::wrappers::ReceiverStream;
+
+use crate::{
+    config::LibvirtConfig,
+    renderer::Renderable,
+    state::{Message, State},
+    supervisor::{Backoff, sleep_unless_resumed, supervise_blocking},
+    widget::Widget,
+};
+
+#[derive(Debug)]
+pub enum LibvirtError {
+    StdIoError(std::io::Error),
+    Utf8Error(FromUtf8Error),
+    VirshError(String),
+    SendError(SendError<Message>),
+}
+
+impl std::fmt::Display for LibvirtError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LibvirtError::StdIoError(e) => write!(f, "IO Error: {e}"),
+            LibvirtError::Utf8Error(e) => write!(f, "The output of virsh is not UTF-8: {e}"),
+            LibvirtError::VirshError(stderr) => write!(f, "virsh failed: {stderr}"),
+            LibvirtError::SendError(e) => write!(f, "Channel Error: {e}"),
+        }
+    }
+}
+
+impl From<std::io::Error> for LibvirtError {
+    fn from(value: std::io::Error) -> Self {
+        Self::StdIoError(value)
+    }
+}
+
+impl From<FromUtf8Error> for LibvirtError {
+    fn from(value: FromUtf8Error) -> Self {
+        Self::Utf8Error(value)
+    }
+}
+
+impl From<SendError<Message>> for LibvirtError {
+    fn from(value: SendError<Message>) -> Self {
+        Self::SendError(value)
+    }
+}
+
+#[derive(Debug)]
+pub enum LibvirtMessage {
+    DomainsUpdate(Vec<Domain>),
+}
+
+#[derive(Debug, Clone)]
+pub struct Domain {
+    pub name: String,
+    /// As reported by virsh, e.g. running, paused, shut off
+    pub state: String,
+}
+
+impl Domain {
+    pub fn running(&self) -> bool {
+        self.state == "running"
+    }
+}
+
+/// States virsh can print in the last column, some of them contain spaces
+/// so the table can't simply be split on whitespace
+const DOMAIN_STATES: [&str; 8] = [
+    "running",
+    "idle",
+    "paused",
+    "in shutdown",
+    "shut off",
+    "crashed",
+    "pmsuspended",
+    "blocked",
+];
+
+/// Parses the table printed by `virsh list --all`
+fn parse_domains(output: &str) -> Vec<Domain> {
+    output
+        .lines()
+        .skip_while(|line| !line.starts_with("---"))
+        .skip(1)
+        .filter_map(|line| {
+            let line = line.trim();
+            let state = DOMAIN_STATES
+                .iter()
+                .find(|state| line.ends_with(*state))?;
+            let (_id, name) = line
+                .strip_suffix(state)?
+                .trim()
+                .split_once(char::is_whitespace)?;
+            Some(Domain {
+                name: name.trim().to_string(),
+                state: state.to_string(),
+            })
+        })
+        .collect()
+}
+
+fn libvirt_generator(
+    sender: Sender<Message>,
+    config: &LibvirtConfig,
+    resumes: &mut watch::Receiver<()>,
+) -> Result<(), LibvirtError> {
+    loop {
+        let output = Command::new("virsh")
+            .args(["-c", &config.uri, "list", "--all"])
+            .output()?;
+        if !output.status.success() {
+            return Err(LibvirtError::VirshError(String::from_utf8(output.stderr)?));
+        }
+        sender.blocking_send(Message::Libvirt(LibvirtMessage::DomainsUpdate(
+            parse_domains(&String::from_utf8(output.stdout)?),
+        )))?;
+        sleep_unless_resumed(resumes, Duration::from_secs(config.interval));
+    }
+}
+
+pub fn libvirt_subscription(
+    rt: Handle,
+    config: LibvirtConfig,
+    mut resumes: watch::Receiver<()>,
+) -> ReceiverStream<Message> {
+    let (sender, receiver) = channel(1);
+    let backoff = Backoff::starting_at(Duration::from_secs(config.interval));
+    supervise_blocking(&rt, "libvirt", backoff, sender, move |sender| {
+        libvirt_generator(sender, &config, &mut resumes)
+    });
+    ReceiverStream::new(receiver)
+}
+
+/// The running virtual machines
+pub const WIDGET: Widget = Widget {
+    name: "libvirt",
+    render,
+};
+
+fn render(state: &State) -> Vec<Renderable> {
+    let style = state.config.theme.style("libvirt");
+    let mut module = Vec::new();
+    let running_domains = state.domains.iter().filter(|v| v.running()).count();
+    if running_domains > 0 {
+        module.push(Renderable::Region {
+            tooltip: Some(
+                state
+                    .domains
+                    .iter()
+                    .map(|domain| format!("{}: {}", domain.name, domain.state))
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+            ),
+            on_click: None,
+            on_scroll: None,
+            children: vec![Renderable::Text {
+                text: format!("vm {running_domains}").into(),
+                fg: style.fg,
+                bg: style.bg,
+            }],
+        });
+    }
+    module
+}
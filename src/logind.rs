@@ -0,0 +1,131 @@
+use std::time::Duration;
+
+use tokio::{
+    runtime::Handle,
+    sync::mpsc::{Sender, channel, error::SendError},
+};
+use tokio_stream::{StreamExt, wrappers::ReceiverStream};
+use zbus::Connection;
+
+use crate::{
+    state::Message,
+    supervisor::{Backoff, supervise},
+};
+
+#[derive(Debug)]
+pub enum LogindError {
+    DbusError(zbus::Error),
+    SendError(SendError<Message>),
+}
+
+impl std::fmt::Display for LogindError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LogindError::DbusError(e) => write!(f, "D-Bus Error: {e}"),
+            LogindError::SendError(e) => write!(f, "Channel Error: {e}"),
+        }
+    }
+}
+
+impl From<zbus::Error> for LogindError {
+    fn from(value: zbus::Error) -> Self {
+        Self::DbusError(value)
+    }
+}
+
+impl From<SendError<Message>> for LogindError {
+    fn from(value: SendError<Message>) -> Self {
+        Self::SendError(value)
+    }
+}
+
+#[derive(Debug)]
+pub enum LogindMessage {
+    /// The system is about to suspend or hibernate when true, and just woke
+    /// up when false
+    Sleep(bool),
+    /// The screen locker locked or unlocked the session
+    Locked(bool),
+}
+
+#[zbus::proxy(
+    interface = "org.freedesktop.login1.Manager",
+    default_service = "org.freedesktop.login1",
+    default_path = "/org/freedesktop/login1"
+)]
+trait Manager {
+    #[zbus(signal)]
+    fn prepare_for_sleep(&self, start: bool) -> zbus::Result<()>;
+}
+
+/// The auto path stands for the session of whoever asks
+#[zbus::proxy(
+    interface = "org.freedesktop.login1.Session",
+    default_service = "org.freedesktop.login1",
+    default_path = "/org/freedesktop/login1/session/auto"
+)]
+trait Session {
+    /// Set by lockers like swaylock, the Lock and Unlock signals only ask
+    /// for it to happen
+    #[zbus(property)]
+    fn locked_hint(&self) -> zbus::Result<bool>;
+}
+
+async fn logind_generator(sender: Sender<Message>) -> Result<(), LogindError> {
+    let conn = Connection::system().await?;
+    let manager = ManagerProxy::new(&conn).await?;
+    let mut sleep = manager.receive_prepare_for_sleep().await?;
+    // Without a session, like when started outside of one, the bar still
+    // has to know about suspends
+    let session = match SessionProxy::new(&conn).await {
+        Ok(session) => Some(session),
+        Err(e) => {
+            log::error!("Could not get the logind session, not following locks: {e}");
+            None
+        }
+    };
+    let mut locked = match &session {
+        Some(session) => {
+            match session.locked_hint().await {
+                Ok(hint) => {
+                    sender
+                        .send(Message::Logind(LogindMessage::Locked(hint)))
+                        .await?;
+                }
+                Err(e) => log::error!("Could not read whether the session is locked: {e}"),
+            }
+            Some(session.receive_locked_hint_changed().await)
+        }
+        None => None,
+    };
+    loop {
+        tokio::select! {
+            Some(signal) = sleep.next() => {
+                let start = signal.args()?.start;
+                sender.send(Message::Logind(LogindMessage::Sleep(start))).await?;
+            }
+            Some(change) = async {
+                match &mut locked {
+                    Some(locked) => locked.next().await,
+                    None => None,
+                }
+            } => match change.get().await {
+                Ok(hint) => sender.send(Message::Logind(LogindMessage::Locked(hint))).await?,
+                Err(e) => log::error!("Could not read whether the session is locked: {e}"),
+            },
+            else => return Ok(()),
+        }
+    }
+}
+
+pub fn logind_subscription(rt: Handle) -> ReceiverStream<Message> {
+    let (sender, receiver) = channel(1);
+    supervise(
+        &rt,
+        "logind",
+        Backoff::starting_at(Duration::from_secs(5)),
+        sender,
+        logind_generator,
+    );
+    ReceiverStream::new(receiver)
+}
@@ -1,85 +1,44 @@
-#![feature(sort_floats)]
-#![feature(iter_array_chunks)]
-
-pub mod font;
-pub mod layer;
-pub mod mpd;
-pub mod renderer;
-pub mod state;
-pub mod sway;
-pub mod network;
-pub mod netlink;
-pub mod backlight;
-pub mod audio;
-pub mod battery;
-pub mod files;
-pub mod clock;
-
-
-use layer::Display;
-use mpd::mpd_subscription;
-use renderer::Renderer;
-use std::sync::Arc;
-use tokio::sync::mpsc::channel;
-
-use tokio::runtime::Runtime;
-use tokio_stream::{StreamExt, StreamMap};
-
-use state::State;
-use sway::sway_subscription;
-
-use crate::{audio::audio_subscription, backlight::backlight_subscription, battery::battery_subscription, clock::clock_subscription, network::network_subscription};
+use sway_shell::bar::Bar;
+use sway_shell::ipc;
 
 fn main() {
     pretty_env_logger::init();
-    let rt = Arc::new(Runtime::new().expect("To be able to initalize a tokio runtime"));
-
-    let mut streams = StreamMap::new();
-
-    let state = State::new();
-    let (render_sender, render_receiver) = channel(1);
-    let (state_sender, state_receiver) = channel(1);
-    let state_stream = tokio_stream::wrappers::ReceiverStream::new(state_receiver);
-    streams.insert("sway", sway_subscription(rt.handle().clone()));
-    streams.insert("mpd", mpd_subscription(rt.handle().clone()));
-    streams.insert("network", network_subscription(rt.handle().clone()));
-    streams.insert("audio", audio_subscription(rt.handle().clone()));
-    streams.insert("backlight", backlight_subscription(rt.handle().clone()));
-    streams.insert("battery", battery_subscription(rt.handle().clone()));
-    streams.insert("clock", clock_subscription(rt.handle().clone()));
-    streams.insert("display", state_stream);
-    let (display_sender, display_receiver) = channel(1);
-    // Currently using the merge method, ideally would use a StreamMap
-    let state_event_loop_handle =
-        rt.spawn(state.run_event_loop(streams.map(|(_, v)| v), render_sender));
-    // IDK how else to do this
-    const HEIGHT: u32 = 15;
-    let (display, event_queue) = rt.block_on(Display::new(HEIGHT, display_sender, state_sender));
-    let wayland_conn = display.wayland_conn.clone();
-    let wayland_surface = display.wayland_surface.clone();
-
-    let renderer_event_loop_handle = rt.spawn(async move {
-        let renderer = Renderer::new(&wayland_conn, &wayland_surface, 100, HEIGHT).await;
-        renderer
-            .run_event_loop(display_receiver, render_receiver)
-            .await;
-    });
-
-    let display_event_loop_handle = rt.spawn_blocking(|| {
-        display
-            .run_event_loop(event_queue)
-            .expect("To never exit the event loop");
-    });
+    sway_shell::crash::install_panic_hook();
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    // Started before sway, like from a systemd unit, rather than from its
+    // config where the compositor only needs a moment
+    let wait_for_compositor = args.iter().any(|arg| arg == "--wait-for-compositor");
+    args.retain(|arg| arg != "--wait-for-compositor");
+    let mut bar = Bar::builder().wait_for_compositor(wait_for_compositor);
+    match args.as_slice() {
+        [command, path] if command == "screenshot" => std::process::exit(screenshot(path)),
+        // Absolute, so it is read as a path rather than a theme name
+        [flag, path] if flag == "--preview-theme" => {
+            bar = bar.preview_theme(std::path::absolute(path).unwrap_or_else(|_| path.into()));
+        }
+        _ => {}
+    }
+    if let Err(e) = bar.build().run() {
+        eprintln!("Could not start the bar: {e:?}");
+        std::process::exit(1);
+    }
+}
 
-    rt.block_on(async {
-        state_event_loop_handle
-            .await
-            .expect("Never erroring out in the state event loop");
-        renderer_event_loop_handle
-            .await
-            .expect("Never erroring out in the renderer event loop");
-        display_event_loop_handle
-            .await
-            .expect("Never erroring out in the display event loop");
-    });
+/// Asks the running bar to draw itself into a PNG, returns the exit code
+fn screenshot(path: &str) -> i32 {
+    // The bar resolves relative paths from its own working directory
+    let path = match std::path::absolute(path) {
+        Ok(path) => path,
+        Err(e) => {
+            eprintln!("Invalid path {path:?}: {e}");
+            return 1;
+        }
+    };
+    match ipc::send_command("screenshot", [("path", path.to_string_lossy().into_owned())]) {
+        Ok(_) => 0,
+        Err(e) => {
+            eprintln!("Could not take a screenshot: {e}");
+            1
+        }
+    }
 }
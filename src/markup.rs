@@ -0,0 +1,226 @@
+use crate::{renderer::Renderable, theme::Color};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct SpanStyle {
+    fg: u32,
+    bg: u32,
+    bold: bool,
+}
+
+/// Escapes text so it shows as is when put into markup
+pub fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Turns Pango-like markup, such as `<span fg="#ff0000">red</span> and
+/// <b>bold</b>`, into a text renderable per run of the same style. Spans take
+/// fg, bg and weight, other tags and attributes keep the style they are in.
+/// Markup that doesn't parse is shown as is
+pub fn parse(markup: &str, fg: u32, bg: u32) -> Vec<Renderable> {
+    let style = SpanStyle {
+        fg,
+        bg,
+        bold: false,
+    };
+    let spans = spans(markup, style).unwrap_or_else(|| vec![(markup.to_string(), style)]);
+    spans
+        .into_iter()
+        .map(|(text, style)| {
+            let text = Renderable::Text {
+                text: text.into(),
+                fg: style.fg,
+                bg: style.bg,
+            };
+            if style.bold {
+                Renderable::Bold(vec![text])
+            } else {
+                text
+            }
+        })
+        .collect()
+}
+
+fn spans(markup: &str, style: SpanStyle) -> Option<Vec<(String, SpanStyle)>> {
+    let mut spans: Vec<(String, SpanStyle)> = Vec::new();
+    // Open tags by name, with the style inside them
+    let mut open: Vec<(&str, SpanStyle)> = Vec::new();
+    let mut rest = markup;
+    while !rest.is_empty() {
+        let current = open.last().map_or(style, |(_, style)| *style);
+        let (text, after) = match rest.find(['<', '&']) {
+            Some(i) => rest.split_at(i),
+            None => (rest, ""),
+        };
+        push_text(&mut spans, text, current);
+        rest = after;
+        if let Some(after) = rest.strip_prefix('<') {
+            let (tag, after) = after.split_once('>')?;
+            rest = after;
+            match tag.strip_prefix('/') {
+                Some(name) => {
+                    let (open_name, _) = open.pop()?;
+                    if open_name != name.trim() {
+                        return None;
+                    }
+                }
+                None => {
+                    let (name, attributes) =
+                        tag.split_once(char::is_whitespace).unwrap_or((tag, ""));
+                    open.push((name, tag_style(name, attributes, current)?));
+                }
+            }
+        } else if let Some(after) = rest.strip_prefix('&') {
+            let (entity, after) = after.split_once(';')?;
+            rest = after;
+            let mut c = [0; 4];
+            push_text(&mut spans, unescape(entity)?.encode_utf8(&mut c), current);
+        }
+    }
+    open.is_empty().then_some(spans)
+}
+
+/// Appends to the last span when it has the same style
+fn push_text(spans: &mut Vec<(String, SpanStyle)>, text: &str, style: SpanStyle) {
+    if text.is_empty() {
+        return;
+    }
+    match spans.last_mut() {
+        Some((last, last_style)) if *last_style == style => last.push_str(text),
+        _ => spans.push((text.to_string(), style)),
+    }
+}
+
+fn tag_style(name: &str, mut attributes: &str, mut style: SpanStyle) -> Option<SpanStyle> {
+    match name {
+        "b" => style.bold = true,
+        "span" => loop {
+            attributes = attributes.trim_start();
+            if attributes.is_empty() {
+                break;
+            }
+            let (key, after) = attributes.split_once('=')?;
+            let after = after.trim_start();
+            let quote = after.chars().next().filter(|c| matches!(c, '"' | '\''))?;
+            let (value, after) = after[1..].split_once(quote)?;
+            attributes = after;
+            match key.trim() {
+                "fg" | "foreground" | "color" => style.fg = Color::parse(value)?.into(),
+                "bg" | "background" | "bgcolor" => style.bg = Color::parse(value)?.into(),
+                "weight" | "font_weight" => {
+                    style.bold = matches!(value, "bold" | "semibold" | "ultrabold" | "heavy")
+                        || value.parse::<u16>().is_ok_and(|weight| weight >= 600)
+                }
+                _ => {}
+            }
+        },
+        _ => {}
+    }
+    Some(style)
+}
+
+fn unescape(entity: &str) -> Option<char> {
+    match entity {
+        "amp" => Some('&'),
+        "lt" => Some('<'),
+        "gt" => Some('>'),
+        "quot" => Some('"'),
+        "apos" => Some('\''),
+        _ => {
+            let number = entity.strip_prefix('#')?;
+            let code = match number.strip_prefix(['x', 'X']) {
+                Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                None => number.parse().ok()?,
+            };
+            char::from_u32(code)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FG: u32 = 0xffffffff;
+    const BG: u32 = 0;
+
+    fn text(text: &str, fg: u32) -> Renderable {
+        Renderable::Text {
+            text: text.to_string().into(),
+            fg,
+            bg: BG,
+        }
+    }
+
+    fn assert_renders(markup: &str, expected: Vec<Renderable>) {
+        assert_eq!(
+            format!("{:?}", parse(markup, FG, BG)),
+            format!("{expected:?}")
+        );
+    }
+
+    #[test]
+    fn plain_text_is_one_span() {
+        assert_renders("hello world", vec![text("hello world", FG)]);
+    }
+
+    #[test]
+    fn spans_color_their_text() {
+        assert_renders(
+            "a <span fg=\"#ff0000\">red</span> b",
+            vec![text("a ", FG), text("red", 0xff0000ff), text(" b", FG)],
+        );
+    }
+
+    #[test]
+    fn bold_nests_in_spans() {
+        assert_renders(
+            "<span color='#00ff00'>x<b>y</b></span>",
+            vec![
+                text("x", 0xff00ff00),
+                Renderable::Bold(vec![text("y", 0xff00ff00)]),
+            ],
+        );
+        assert_renders(
+            "<span weight=\"bold\">y</span>",
+            vec![Renderable::Bold(vec![text("y", FG)])],
+        );
+    }
+
+    #[test]
+    fn entities_are_unescaped_into_the_span() {
+        assert_renders(
+            "a &lt;b&gt; &amp; &#x263A;",
+            vec![text("a <b> & \u{263a}", FG)],
+        );
+    }
+
+    #[test]
+    fn invalid_markup_is_shown_as_is() {
+        for markup in [
+            "<b>unclosed",
+            "<b>x</span>",
+            "1 < 2",
+            "a & b",
+            "<span fg=\"red\">x</span>",
+        ] {
+            assert_renders(markup, vec![text(markup, FG)]);
+        }
+    }
+
+    #[test]
+    fn escaped_text_round_trips() {
+        let title = "Rock & Roll <Live> \"'\"";
+        assert_renders(&escape(title), vec![text(title, FG)]);
+    }
+}
@@ -0,0 +1,262 @@
+use std::{fs, num::ParseIntError, time::Duration};
+
+use tokio::{
+    runtime::Handle,
+    sync::{
+        mpsc::{Sender, channel, error::SendError},
+        watch,
+    },
+};
+use tokio_stream::wrappers::ReceiverStream;
+
+use crate::{
+    files::read_string_from_file_path,
+    format::{display_bytes, display_percent},
+    renderer::Renderable,
+    state::{Message, State},
+    supervisor::{Backoff, sleep_unless_resumed, supervise_blocking},
+    widget::Widget,
+};
+
+#[derive(Debug)]
+pub enum MemoryError {
+    StdIoError(std::io::Error),
+    ParseIntError(ParseIntError),
+    /// /proc/meminfo lacks one of the fields read from it
+    MissingField(&'static str),
+    SendError(SendError<Message>),
+}
+
+impl std::fmt::Display for MemoryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MemoryError::StdIoError(e) => write!(f, "IO Error: {e}"),
+            MemoryError::ParseIntError(e) => write!(f, "Invalid number in /proc/meminfo: {e}"),
+            MemoryError::MissingField(field) => write!(f, "/proc/meminfo has no {field}"),
+            MemoryError::SendError(e) => write!(f, "Channel Error: {e}"),
+        }
+    }
+}
+
+impl From<std::io::Error> for MemoryError {
+    fn from(value: std::io::Error) -> Self {
+        Self::StdIoError(value)
+    }
+}
+
+impl From<ParseIntError> for MemoryError {
+    fn from(value: ParseIntError) -> Self {
+        Self::ParseIntError(value)
+    }
+}
+
+impl From<SendError<Message>> for MemoryError {
+    fn from(value: SendError<Message>) -> Self {
+        Self::SendError(value)
+    }
+}
+
+#[derive(Debug)]
+pub enum MemoryMessage {
+    Update(MemoryStats),
+}
+
+/// Sizes are in bytes
+#[derive(Debug, Clone, Default)]
+pub struct MemoryStats {
+    pub total: u64,
+    /// What can be handed out without swapping, counting reclaimable caches
+    pub available: u64,
+    pub swap_total: u64,
+    pub swap_free: u64,
+    pub zram: Vec<Zram>,
+}
+
+impl MemoryStats {
+    pub fn used(&self) -> u64 {
+        self.total.saturating_sub(self.available)
+    }
+
+    pub fn swap_used(&self) -> u64 {
+        self.swap_total.saturating_sub(self.swap_free)
+    }
+}
+
+/// A compressed RAM block device, usually used as swap
+#[derive(Debug, Clone)]
+pub struct Zram {
+    /// Like zram0
+    pub name: String,
+    /// Size of the data stored before compressing it
+    pub original: u64,
+    pub compressed: u64,
+    /// Memory taken, with the allocator's overhead
+    pub used: u64,
+}
+
+impl Zram {
+    /// How many times smaller the data got, None while it is empty
+    pub fn ratio(&self) -> Option<f32> {
+        (self.compressed > 0).then(|| self.original as f32 / self.compressed as f32)
+    }
+}
+
+fn meminfo_field(meminfo: &str, name: &'static str) -> Result<u64, MemoryError> {
+    let value = meminfo
+        .lines()
+        .find_map(|line| line.strip_prefix(name)?.strip_prefix(':'))
+        .ok_or(MemoryError::MissingField(name))?;
+    // In kB, despite the name meaning KiB
+    Ok(value.trim().trim_end_matches("kB").trim().parse::<u64>()? * 1024)
+}
+
+/// Devices that aren't set up have an empty mm_stat and are left out
+fn zram_devices() -> Result<Vec<Zram>, MemoryError> {
+    let mut devices = Vec::new();
+    for entry in fs::read_dir("/sys/block")? {
+        let path = entry?.path();
+        let Some(name) = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .filter(|name| name.starts_with("zram"))
+        else {
+            continue;
+        };
+        let Ok(mm_stat) = read_string_from_file_path(path.join("mm_stat")) else {
+            continue;
+        };
+        // orig_data_size compr_data_size mem_used_total and more after
+        let fields = mm_stat
+            .split_whitespace()
+            .take(3)
+            .map(str::parse)
+            .collect::<Result<Vec<u64>, _>>()?;
+        if let [original, compressed, used] = fields[..] {
+            devices.push(Zram {
+                name: name.to_string(),
+                original,
+                compressed,
+                used,
+            });
+        }
+    }
+    devices.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(devices)
+}
+
+fn memory_stats() -> Result<MemoryStats, MemoryError> {
+    let meminfo = fs::read_to_string("/proc/meminfo")?;
+    Ok(MemoryStats {
+        total: meminfo_field(&meminfo, "MemTotal")?,
+        available: meminfo_field(&meminfo, "MemAvailable")?,
+        swap_total: meminfo_field(&meminfo, "SwapTotal")?,
+        swap_free: meminfo_field(&meminfo, "SwapFree")?,
+        zram: zram_devices()?,
+    })
+}
+
+fn memory_generator(
+    sender: Sender<Message>,
+    resumes: &mut watch::Receiver<()>,
+) -> Result<(), MemoryError> {
+    loop {
+        sender.blocking_send(Message::Memory(MemoryMessage::Update(memory_stats()?)))?;
+        sleep_unless_resumed(resumes, Duration::from_secs(2));
+    }
+}
+
+pub fn memory_subscription(
+    rt: Handle,
+    mut resumes: watch::Receiver<()>,
+) -> ReceiverStream<Message> {
+    let (sender, receiver) = channel(1);
+    supervise_blocking(
+        &rt,
+        "memory",
+        Backoff::starting_at(Duration::from_secs(5)),
+        sender,
+        move |sender| memory_generator(sender, &mut resumes),
+    );
+    ReceiverStream::new(receiver)
+}
+
+/// The memory and swap in use
+pub const WIDGET: Widget = Widget {
+    name: "memory",
+    render,
+};
+
+fn render(state: &State) -> Vec<Renderable> {
+    let style = state.config.theme.style("memory");
+    let Some(memory) = &state.memory else {
+        return vec![];
+    };
+    let percent = |used: u64, total: u64| {
+        if total == 0 {
+            f32::NAN
+        } else {
+            100. * used as f32 / total as f32
+        }
+    };
+    let mut tooltip = vec![format!(
+        "Memory {} of {} used",
+        display_bytes(memory.used()).trim(),
+        display_bytes(memory.total).trim()
+    )];
+    let mut children = vec![Renderable::Text {
+        text: format!(
+            "mem {}",
+            display_percent(percent(memory.used(), memory.total))
+        )
+        .into(),
+        fg: style.fg,
+        bg: style.bg,
+    }];
+    // Swap only takes up space while something is swapped out
+    if memory.swap_used() > 0 {
+        children.push(Renderable::Text {
+            text: format!(
+                " swap {}",
+                display_percent(percent(memory.swap_used(), memory.swap_total))
+            )
+            .into(),
+            fg: style.fg,
+            bg: style.bg,
+        });
+        tooltip.push(format!(
+            "Swap {} of {} used",
+            display_bytes(memory.swap_used()).trim(),
+            display_bytes(memory.swap_total).trim()
+        ));
+        for zram in &memory.zram {
+            if let Some(ratio) = zram.ratio() {
+                tooltip.push(format!(
+                    "{} holds {} in {}, compressed {ratio:.1}x",
+                    zram.name,
+                    display_bytes(zram.original).trim(),
+                    display_bytes(zram.used).trim()
+                ));
+            }
+        }
+    }
+    if let Some(top) = state
+        .top_processes
+        .as_ref()
+        .filter(|top| !top.memory.is_empty())
+    {
+        tooltip.push(format!(
+            "Top memory: {}",
+            top.memory
+                .iter()
+                .map(|(name, memory)| format!("{name} {}", display_bytes(*memory).trim()))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ));
+    }
+    vec![Renderable::Region {
+        tooltip: Some(tooltip.join("\n")),
+        on_click: None,
+        on_scroll: None,
+        children,
+    }]
+}
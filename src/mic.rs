@@ -0,0 +1,242 @@
+use std::{cell::Cell, io::Cursor, rc::Rc, time::Duration};
+
+use libspa::{
+    param::{
+        ParamType,
+        audio::{AudioFormat, AudioInfoRaw},
+    },
+    pod::{Object, Pod, Value, serialize::PodSerializer},
+    utils::{Direction, SpaTypes},
+};
+use pipewire::{
+    context::ContextRc,
+    keys,
+    main_loop::MainLoopRc,
+    properties::properties,
+    stream::{StreamBox, StreamFlags},
+};
+use tokio::{
+    runtime::Handle,
+    sync::{
+        mpsc::{Sender, channel, error::SendError},
+        watch,
+    },
+};
+use tokio_stream::wrappers::ReceiverStream;
+
+use crate::action::Action;
+use crate::renderer::Renderable;
+use crate::state::{Message, State};
+use crate::supervisor::{Backoff, supervise_blocking};
+use crate::widget::Widget;
+
+#[derive(Debug)]
+pub enum MicError {
+    PipewireError(pipewire::Error),
+    /// The state holding the switch for the meter is gone
+    RecvError(watch::error::RecvError),
+    SendError(SendError<Message>),
+}
+
+impl std::fmt::Display for MicError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MicError::PipewireError(e) => write!(f, "PipeWire Error: {e}"),
+            MicError::RecvError(e) => write!(f, "The meter switch is gone: {e}"),
+            MicError::SendError(e) => write!(f, "Channel Error: {e}"),
+        }
+    }
+}
+
+impl From<pipewire::Error> for MicError {
+    fn from(value: pipewire::Error) -> Self {
+        Self::PipewireError(value)
+    }
+}
+
+impl From<watch::error::RecvError> for MicError {
+    fn from(value: watch::error::RecvError) -> Self {
+        Self::RecvError(value)
+    }
+}
+
+impl From<SendError<Message>> for MicError {
+    fn from(value: SendError<Message>) -> Self {
+        Self::SendError(value)
+    }
+}
+
+#[derive(Debug)]
+pub enum MicMessage {
+    /// Loudest sample since the last level, linear from 0 to 1. None once
+    /// the meter is switched off
+    Level(Option<f32>),
+}
+
+/// Node name of the meter stream, which the privacy module leaves out
+pub const METER_NODE_NAME: &str = "sway-shell-mic-meter";
+
+/// How often the level is sent while the meter is on
+const LEVEL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Captures the default source until the meter is switched off
+fn capture(sender: &Sender<Message>, enabled: &watch::Receiver<bool>) -> Result<(), MicError> {
+    let mainloop = MainLoopRc::new(None)?;
+    let context = ContextRc::new(&mainloop, None)?;
+    let core = context.connect_rc(None)?;
+
+    // Without a target the stream is linked to the default source, and
+    // follows it when it changes
+    let stream = StreamBox::new(
+        &core,
+        METER_NODE_NAME,
+        properties! {
+            *keys::NODE_NAME => METER_NODE_NAME,
+            *keys::MEDIA_TYPE => "Audio",
+            *keys::MEDIA_CATEGORY => "Capture",
+            *keys::NODE_DESCRIPTION => "Microphone level meter",
+        },
+    )?;
+    let peak = Rc::new(Cell::new(0f32));
+    let _listener = stream
+        .add_local_listener_with_user_data(peak.clone())
+        .process(|stream, peak| {
+            let Some(mut buffer) = stream.dequeue_buffer() else {
+                return;
+            };
+            let Some(data) = buffer.datas_mut().first_mut() else {
+                return;
+            };
+            let size = data.chunk().size() as usize;
+            let Some(samples) = data.data() else {
+                return;
+            };
+            let loudest = samples[..size.min(samples.len())]
+                .chunks_exact(4)
+                .map(|sample| f32::from_le_bytes([sample[0], sample[1], sample[2], sample[3]]).abs())
+                .fold(peak.get(), f32::max);
+            peak.set(loudest);
+        })
+        .register()?;
+
+    // Any rate and channel count will do, as only the loudest sample matters
+    let mut audio_info = AudioInfoRaw::new();
+    audio_info.set_format(AudioFormat::F32LE);
+    let format = PodSerializer::serialize(
+        Cursor::new(Vec::new()),
+        &Value::Object(Object {
+            type_: SpaTypes::ObjectParamFormat.as_raw(),
+            id: ParamType::EnumFormat.as_raw(),
+            properties: audio_info.into(),
+        }),
+    )
+    .expect("The audio format to serialize")
+    .0
+    .into_inner();
+    let mut params = [Pod::from_bytes(&format).expect("The serialized audio format to be a pod")];
+    stream.connect(
+        Direction::Input,
+        None,
+        StreamFlags::AUTOCONNECT | StreamFlags::MAP_BUFFERS,
+        &mut params,
+    )?;
+
+    let mainloop_weak = mainloop.downgrade();
+    let timer_sender = sender.clone();
+    let enabled = enabled.clone();
+    let timer = mainloop.loop_().add_timer(move |_| {
+        if !*enabled.borrow() {
+            if let Some(mainloop) = mainloop_weak.upgrade() {
+                mainloop.quit();
+            }
+            return;
+        }
+        // Dropped when the bar is behind, the next level is just as good
+        let _ = timer_sender.try_send(Message::Mic(MicMessage::Level(Some(peak.replace(0.).min(1.)))));
+    });
+    if let Err(e) = timer.update_timer(Some(LEVEL_INTERVAL), Some(LEVEL_INTERVAL)).into_result() {
+        log::error!("Could not start the microphone level timer: {e:?}");
+    }
+
+    mainloop.run();
+    Ok(())
+}
+
+fn mic_generator(sender: &Sender<Message>, enabled: &mut watch::Receiver<bool>, rt: &Handle) -> Result<(), MicError> {
+    loop {
+        // Nothing is captured while the meter is off, so the microphone
+        // doesn't show as in use
+        rt.block_on(enabled.wait_for(|enabled| *enabled))?;
+        capture(sender, enabled)?;
+        sender.blocking_send(Message::Mic(MicMessage::Level(None)))?;
+    }
+}
+
+/// Captures the default source only while enabled is true
+pub fn mic_subscription(rt: Handle, mut enabled: watch::Receiver<bool>) -> ReceiverStream<Message> {
+    let (sender, receiver) = channel(1);
+    let generator_rt = rt.clone();
+    supervise_blocking(
+        &rt,
+        "mic",
+        Backoff::starting_at(Duration::from_secs(5)),
+        sender,
+        move |sender| mic_generator(&sender, &mut enabled, &generator_rt),
+    );
+    ReceiverStream::new(receiver)
+}
+
+/// A microphone, with its level while capturing
+pub const WIDGET: Widget = Widget {
+    name: "mic",
+    render,
+};
+
+fn render(state: &State) -> Vec<Renderable> {
+    let style = state.config.theme.style("mic");
+    let mut children = vec![Renderable::Text {
+        // A microphone from the nerd fonts
+        text: "\u{f036c}".into(),
+        fg: if state.mic_level.is_some() {
+            style.fg
+        } else {
+            style.dim
+        },
+        bg: style.bg,
+    }];
+    if let Some(level) = state.mic_level {
+        // Scaled over the 60 dB above the noise floor, so speaking
+        // fills most of it instead of barely moving a linear meter
+        let height = ((20. * level.max(1e-6).log10() + 60.) / 60.).clamp(0., 1.);
+        let color = if level >= 1. {
+            style.urgent
+        } else {
+            style.good
+        };
+        children.push(Renderable::Space(0.3));
+        children.push(Renderable::Box {
+            fg: style.dim,
+            bg: style.dim,
+            width: 0.4,
+            height: 1.,
+            skip: 0.,
+        });
+        children.push(Renderable::Box {
+            fg: color,
+            bg: color,
+            width: 0.4,
+            height,
+            skip: 0.4,
+        });
+    }
+    vec![Renderable::Region {
+        tooltip: Some(if state.mic_level.is_some() {
+            "Microphone level, click to stop capturing".to_string()
+        } else {
+            "Click to show the microphone level".to_string()
+        }),
+        on_click: Some(Action::ToggleMicMeter),
+        on_scroll: None,
+        children,
+    }]
+}
@@ -1,4 +1,12 @@
-use std::{env::VarError, fmt::Display, os::unix::net::UnixStream, path::PathBuf};
+use std::{
+    env::VarError,
+    fmt::Display,
+    io::{BufRead, BufReader, Read, Write},
+    os::unix::net::UnixStream,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
 
 use mpd::{Idle, Subsystem};
 use tokio::{
@@ -7,14 +15,24 @@ use tokio::{
     time::MissedTickBehavior,
 };
 
-use crate::state::Message;
+use crate::{
+    atlas::Image,
+    markup,
+    mpris::PlaybackStatus,
+    renderer::Renderable,
+    state::{Message, State},
+    supervisor::{Backoff, supervise_blocking},
+    widget::Widget,
+};
 
 #[derive(Debug)]
-enum MpdError {
+pub enum MpdError {
     VarError(VarError),
     StdIOError(std::io::Error),
     MpdInternalError(mpd::error::Error),
     SendError(SendError<Message>),
+    /// A reply that doesn't follow the protocol, while reading album art
+    ProtocolError(String),
 }
 
 #[derive(Debug)]
@@ -22,6 +40,8 @@ pub enum MpdMessage {
     MpdPlayerUpdate { status: mpd::Status },
     MpdSongUpdate { song: Option<mpd::Song> },
     MpdTimeElapsed { status: mpd::Status },
+    /// Album art of the current song, None when it has none
+    MpdCoverUpdate { cover: Option<Arc<Image>> },
 }
 
 impl Display for MpdError {
@@ -35,6 +55,9 @@ impl Display for MpdError {
             MpdError::SendError(send_error) => {
                 f.write_fmt(format_args!("Channel Error: {}", send_error))
             }
+            MpdError::ProtocolError(line) => {
+                f.write_fmt(format_args!("Unexpected MPD reply: {}", line))
+            }
         }
     }
 }
@@ -66,7 +89,9 @@ impl From<SendError<Message>> for MpdError {
 async fn song_duration_generator(output: Sender<Message>, mpd_socket_conn: PathBuf) {
     let mut interval = tokio::time::interval(tokio::time::Duration::from_secs(1));
     interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
-    let conn = mpd::client::Client::new(UnixStream::connect(mpd_socket_conn.clone()).unwrap());
+    let conn = UnixStream::connect(mpd_socket_conn.clone())
+        .map_err(mpd::error::Error::from)
+        .and_then(mpd::client::Client::new);
 
     if let Ok(mut conn) = conn {
         loop {
@@ -90,6 +115,124 @@ async fn song_duration_generator(output: Sender<Message>, mpd_socket_conn: PathB
     }
 }
 
+/// Covers bigger than this are surely not covers, and not worth the memory
+const MAX_COVER_SIZE: usize = 16 << 20;
+
+/// How long a reply to a cover request may take
+const COVER_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Reads one line of a reply, without the newline
+fn read_line(reader: &mut impl BufRead) -> Result<String, MpdError> {
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+        return Err(MpdError::ProtocolError("connection closed".to_string()));
+    }
+    Ok(line.trim_end_matches('\n').to_string())
+}
+
+/// Reads the whole picture with a binary command, in the chunks MPD sends
+/// it in. None when MPD answers with ACK, as albumart does for songs without
+/// one, or with a bare OK, as readpicture does
+fn read_binary(
+    reader: &mut BufReader<UnixStream>,
+    command: &str,
+    uri: &str,
+) -> Result<Option<Vec<u8>>, MpdError> {
+    let uri = uri.replace('\\', "\\\\").replace('"', "\\\"");
+    let mut data = Vec::new();
+    loop {
+        writeln!(reader.get_mut(), "{command} \"{uri}\" {}", data.len())?;
+        let mut size = None;
+        let mut chunk = None;
+        loop {
+            let line = read_line(reader)?;
+            if line.starts_with("ACK") {
+                return Ok(None);
+            }
+            if line == "OK" {
+                break;
+            }
+            if let Some(value) = line.strip_prefix("size: ") {
+                size = value.parse::<usize>().ok();
+            } else if let Some(value) = line.strip_prefix("binary: ") {
+                let length = value
+                    .parse::<usize>()
+                    .map_err(|_| MpdError::ProtocolError(line.clone()))?;
+                let mut buffer = vec![0; length.min(MAX_COVER_SIZE)];
+                reader.read_exact(&mut buffer)?;
+                // The chunk ends with a newline of its own
+                read_line(reader)?;
+                chunk = Some(buffer);
+            }
+        }
+        if size.is_none() && chunk.is_none() && data.is_empty() {
+            return Ok(None);
+        }
+        let (Some(size), Some(chunk)) = (size, chunk) else {
+            return Err(MpdError::ProtocolError(format!("{command} reply without data")));
+        };
+        if size > MAX_COVER_SIZE {
+            return Err(MpdError::ProtocolError(format!("cover of {size} bytes")));
+        }
+        if chunk.is_empty() && data.len() < size {
+            return Err(MpdError::ProtocolError(format!("{command} reply cut short")));
+        }
+        data.extend_from_slice(&chunk);
+        if data.len() >= size {
+            return Ok(Some(data));
+        }
+    }
+}
+
+/// Album art of the song, preferring the picture embedded in the file over
+/// a cover file in its directory. The mpd crate has no binary responses, so
+/// this talks the protocol on a connection of its own
+fn read_cover(socket: &Path, uri: &str) -> Result<Option<Image>, MpdError> {
+    let stream = UnixStream::connect(socket)?;
+    // A stuck MPD would otherwise hang the player feed with it
+    stream.set_read_timeout(Some(COVER_TIMEOUT))?;
+    stream.set_write_timeout(Some(COVER_TIMEOUT))?;
+    let mut reader = BufReader::new(stream);
+    let greeting = read_line(&mut reader)?;
+    if !greeting.starts_with("OK MPD") {
+        return Err(MpdError::ProtocolError(greeting));
+    }
+    for command in ["readpicture", "albumart"] {
+        if let Some(data) = read_binary(&mut reader, command, uri)? {
+            match Image::decode(&data) {
+                Ok(image) => return Ok(Some(image)),
+                Err(e) => log::warn!("Could not decode the {command} cover of {uri}: {e}"),
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// Sends the cover when the song changed, a missing cover is only logged
+fn send_cover(
+    output: &Sender<Message>,
+    socket: &Path,
+    song: Option<&mpd::Song>,
+    previous_file: &mut Option<String>,
+) -> Result<(), MpdError> {
+    let file = song.map(|song| song.file.clone());
+    if file == *previous_file {
+        return Ok(());
+    }
+    *previous_file = file;
+    let cover = match previous_file.as_deref() {
+        Some(file) => read_cover(socket, file).unwrap_or_else(|e| {
+            log::error!("Could not read the cover of {file}: {e}");
+            None
+        }),
+        None => None,
+    };
+    output.blocking_send(Message::Mpd(MpdMessage::MpdCoverUpdate {
+        cover: cover.map(Arc::new),
+    }))?;
+    Ok(())
+}
+
 fn mpd_generator(output: Sender<Message>, rt: Handle) -> Result<(), MpdError> {
     let a = PathBuf::from(std::env::var("XDG_RUNTIME_DIR")?).join("mpd/socket");
     let mut conn = mpd::client::Client::new(UnixStream::connect(a.clone())?)?;
@@ -101,9 +244,10 @@ fn mpd_generator(output: Sender<Message>, rt: Handle) -> Result<(), MpdError> {
         None
     };
     output.blocking_send(Message::Mpd(MpdMessage::MpdPlayerUpdate { status }))?;
-    output.blocking_send(Message::Mpd(MpdMessage::MpdSongUpdate {
-        song: conn.currentsong()?,
-    }))?;
+    let song = conn.currentsong()?;
+    let mut previous_file = None;
+    send_cover(&output, &a, song.as_ref(), &mut previous_file)?;
+    output.blocking_send(Message::Mpd(MpdMessage::MpdSongUpdate { song }))?;
     loop {
         let events = conn.wait(&[Subsystem::Player])?;
         for event in &events {
@@ -137,6 +281,7 @@ fn mpd_generator(output: Sender<Message>, rt: Handle) -> Result<(), MpdError> {
                     }
                     output.blocking_send(Message::Mpd(MpdMessage::MpdPlayerUpdate { status }))?;
                     let song = conn.currentsong()?;
+                    send_cover(&output, &a, song.as_ref(), &mut previous_file)?;
                     output.blocking_send(Message::Mpd(MpdMessage::MpdSongUpdate { song }))?;
                 }
                 _ => {}
@@ -147,14 +292,132 @@ fn mpd_generator(output: Sender<Message>, rt: Handle) -> Result<(), MpdError> {
 
 pub fn mpd_subscription(rt: Handle) -> tokio_stream::wrappers::ReceiverStream<Message> {
     let (sender, receiver) = channel(1);
-    rt.clone().spawn_blocking(move || {
-        loop {
-            log::error!(
-                "Sway subscription event loop returned, this should never happen, trying to reconnect {:?}",
-                mpd_generator(sender.clone(), rt.clone())
-            )
-        }
+    let generator_rt = rt.clone();
+    supervise_blocking(&rt, "mpd", Backoff::DEFAULT, sender, move |sender| mpd_generator(sender, generator_rt.clone()));
+    tokio_stream::wrappers::ReceiverStream::new(receiver)
+}
+
+/// Side of the album cover next to the song title, in bar heights
+const COVER_SIZE: f32 = 0.85;
+
+/// The song playing on MPD or an MPRIS player, with its progress
+pub const WIDGET: Widget = Widget {
+    name: "mpd",
+    render,
+};
+
+fn render(state: &State) -> Vec<Renderable> {
+    let style = state.config.theme.style("mpd");
+    let mut module = Vec::new();
+    let Some(playing) = now_playing(state) else {
+        return module;
+    };
+    if let Some((elapsed, total)) = playing.progress.filter(|(_, total)| !total.is_zero()) {
+        let completed = (elapsed.as_secs_f32() / total.as_secs_f32()).min(1.);
+        module.push(Renderable::Box {
+            fg: style.dim,
+            bg: style.dim,
+            width: 10.,
+            height: 10.,
+            skip: 0.,
+        });
+        module.push(if playing.status == PlaybackStatus::Playing {
+            Renderable::Box {
+                fg: style.accent,
+                bg: style.accent,
+                width: 10. * completed,
+                height: 10.,
+                skip: 10.,
+            }
+        } else {
+            Renderable::Box {
+                fg: style.fg,
+                bg: style.fg,
+                width: 10. * completed,
+                height: 10.,
+                skip: 10.,
+            }
+        });
+    }
+
+    if !module.is_empty() {
+        module.push(Renderable::Space(1.));
+    }
+    let tooltip = match playing.artist {
+        Some(artist) => format!("{}\n{artist}", playing.title),
+        None => playing.title.to_string(),
+    };
+    let mut children = Vec::new();
+    if let Some(cover) = playing.cover {
+        children.push(Renderable::Image {
+            image: cover.clone(),
+            size: COVER_SIZE,
+        });
+        children.push(Renderable::Space(0.25));
+    }
+    let text = state
+        .config
+        .media
+        .format
+        .replace("{title}", &markup::escape(playing.title))
+        .replace(
+            "{artist}",
+            &markup::escape(playing.artist.unwrap_or_default()),
+        );
+    children.extend(markup::parse(&text, style.fg, style.bg));
+    module.push(Renderable::Region {
+        tooltip: Some(tooltip),
+        on_click: None,
+        on_scroll: None,
+        children,
     });
+    module
+}
 
-    tokio_stream::wrappers::ReceiverStream::new(receiver)
+/// What the media module shows, from MPD or an MPRIS player
+struct NowPlaying<'a> {
+    title: &'a str,
+    artist: Option<&'a str>,
+    status: PlaybackStatus,
+    /// Elapsed and total time
+    progress: Option<(Duration, Duration)>,
+    cover: Option<&'a Arc<Image>>,
+}
+
+/// The song of the player to show, the one playing before the paused
+/// ones before the stopped ones, going by the configured priority next
+fn now_playing(state: &State) -> Option<NowPlaying<'_>> {
+    let mpd = state.mpd_current_song.as_ref().and_then(|song| {
+        let status = match state.mpd_status.as_ref().map(|status| &status.state) {
+            Some(mpd::status::State::Play) => PlaybackStatus::Playing,
+            Some(mpd::status::State::Pause) => PlaybackStatus::Paused,
+            _ => PlaybackStatus::Stopped,
+        };
+        let playing = NowPlaying {
+            title: song.title.as_deref()?,
+            artist: song.artist.as_deref(),
+            status,
+            progress: state.mpd_status.as_ref().and_then(|status| status.time),
+            cover: state.mpd_cover.as_ref(),
+        };
+        Some(("mpd", playing))
+    });
+    let mpris = state.mpris_players.iter().filter_map(|player| {
+        let playing = NowPlaying {
+            title: player.title.as_deref()?,
+            artist: player.artist.as_deref(),
+            status: player.status,
+            progress: player.position.zip(player.length),
+            cover: None,
+        };
+        Some((player.name.as_str(), playing))
+    });
+    let priority = &state.config.media.priority;
+    mpd.into_iter()
+        .chain(mpris)
+        .min_by_key(|(name, playing)| {
+            let rank = priority.iter().position(|player| player == name);
+            (playing.status, rank.unwrap_or(priority.len()))
+        })
+        .map(|(_, playing)| playing)
 }
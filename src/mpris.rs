@@ -0,0 +1,193 @@
+use std::{collections::HashMap, time::Duration};
+
+use tokio::{
+    runtime::Handle,
+    sync::mpsc::{Sender, channel, error::SendError},
+};
+use tokio_stream::wrappers::ReceiverStream;
+use zbus::{Connection, fdo::DBusProxy, proxy::CacheProperties, zvariant::OwnedValue};
+
+use crate::state::Message;
+use crate::supervisor::{Backoff, supervise_watched};
+
+/// Players own a bus name starting with this, followed by their name
+const MPRIS_PREFIX: &str = "org.mpris.MediaPlayer2.";
+
+#[derive(Debug)]
+pub enum MprisError {
+    DbusError(zbus::Error),
+    FdoError(zbus::fdo::Error),
+    SendError(SendError<Message>),
+}
+
+impl std::fmt::Display for MprisError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MprisError::DbusError(e) => write!(f, "D-Bus Error: {e}"),
+            MprisError::FdoError(e) => write!(f, "D-Bus Error: {e}"),
+            MprisError::SendError(e) => write!(f, "Channel Error: {e}"),
+        }
+    }
+}
+
+impl From<zbus::Error> for MprisError {
+    fn from(value: zbus::Error) -> Self {
+        Self::DbusError(value)
+    }
+}
+
+impl From<zbus::fdo::Error> for MprisError {
+    fn from(value: zbus::fdo::Error) -> Self {
+        Self::FdoError(value)
+    }
+}
+
+impl From<SendError<Message>> for MprisError {
+    fn from(value: SendError<Message>) -> Self {
+        Self::SendError(value)
+    }
+}
+
+#[derive(Debug)]
+pub enum MprisMessage {
+    PlayersUpdate(Vec<MprisPlayer>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum PlaybackStatus {
+    Playing,
+    Paused,
+    Stopped,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MprisPlayer {
+    /// The bus name without the MPRIS prefix and instance suffix, like
+    /// "spotify" or "firefox"
+    pub name: String,
+    pub status: PlaybackStatus,
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub length: Option<Duration>,
+    pub position: Option<Duration>,
+}
+
+#[zbus::proxy(
+    interface = "org.mpris.MediaPlayer2.Player",
+    default_path = "/org/mpris/MediaPlayer2"
+)]
+trait Player {
+    #[zbus(property)]
+    fn playback_status(&self) -> zbus::Result<String>;
+
+    #[zbus(property)]
+    fn metadata(&self) -> zbus::Result<HashMap<String, OwnedValue>>;
+
+    /// In microseconds
+    #[zbus(property)]
+    fn position(&self) -> zbus::Result<i64>;
+}
+
+/// Firefox and Chromium add ".instance<pid>" to tell their windows apart
+fn player_name(bus_name: &str) -> String {
+    let name = bus_name.strip_prefix(MPRIS_PREFIX).unwrap_or(bus_name);
+    name.split('.').next().unwrap_or(name).to_string()
+}
+
+/// Lengths are meant to be i64, but some players send u64
+fn microseconds(value: OwnedValue) -> Option<Duration> {
+    let micros = i64::try_from(value.try_clone().ok()?)
+        .ok()
+        .and_then(|micros| u64::try_from(micros).ok())
+        .or_else(|| u64::try_from(value).ok())?;
+    Some(Duration::from_micros(micros))
+}
+
+async fn player(conn: &Connection, bus_name: &str) -> Result<MprisPlayer, MprisError> {
+    // Position changes all the time without a signal, so nothing is cached
+    let proxy = PlayerProxy::builder(conn)
+        .destination(bus_name.to_string())?
+        .cache_properties(CacheProperties::No)
+        .build()
+        .await?;
+    let status = match proxy.playback_status().await?.as_str() {
+        "Playing" => PlaybackStatus::Playing,
+        "Paused" => PlaybackStatus::Paused,
+        _ => PlaybackStatus::Stopped,
+    };
+    let mut metadata = proxy.metadata().await.unwrap_or_default();
+    let title = metadata
+        .remove("xesam:title")
+        .and_then(|title| String::try_from(title).ok())
+        .filter(|title| !title.is_empty());
+    let artist = metadata
+        .remove("xesam:artist")
+        .and_then(|artists| Vec::<String>::try_from(artists).ok())
+        .filter(|artists| !artists.is_empty())
+        .map(|artists| artists.join(", "));
+    let length = metadata.remove("mpris:length").and_then(microseconds);
+    // Streams and some browsers don't have a position
+    let position = proxy
+        .position()
+        .await
+        .ok()
+        .and_then(|micros| u64::try_from(micros).ok())
+        .map(Duration::from_micros);
+    Ok(MprisPlayer {
+        name: player_name(bus_name),
+        status,
+        title,
+        artist,
+        length,
+        position,
+    })
+}
+
+/// Polls every second, as the position has no signal to wait on anyway.
+/// Only changes are sent, so paused players and an empty bus cost no redraws,
+/// the polls in between tell the watchdog the bus still answers
+async fn mpris_generator(sender: Sender<Message>) -> Result<(), MprisError> {
+    let conn = Connection::session().await?;
+    let dbus = DBusProxy::new(&conn).await?;
+    let mut interval = tokio::time::interval(Duration::from_secs(1));
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+    let mut previous = None;
+    loop {
+        interval.tick().await;
+        let mut players = Vec::new();
+        for bus_name in dbus.list_names().await? {
+            if !bus_name.starts_with(MPRIS_PREFIX) {
+                continue;
+            }
+            // A player quitting in between isn't a reason to reconnect
+            match player(&conn, &bus_name).await {
+                Ok(player) => players.push(player),
+                Err(e) => log::warn!("Could not read the MPRIS player {bus_name}: {e:?}"),
+            }
+        }
+        if previous.as_ref() == Some(&players) {
+            sender.send(Message::Heartbeat).await?;
+            continue;
+        }
+        previous = Some(players.clone());
+        sender
+            .send(Message::Mpris(MprisMessage::PlayersUpdate(players)))
+            .await?;
+    }
+}
+
+/// Something is sent every second, so this long without it a D-Bus call hung
+const WATCHDOG_DEADLINE: Duration = Duration::from_secs(30);
+
+pub fn mpris_subscription(rt: Handle) -> ReceiverStream<Message> {
+    let (sender, receiver) = channel(1);
+    supervise_watched(
+        &rt,
+        "mpris",
+        Backoff::starting_at(Duration::from_secs(5)),
+        WATCHDOG_DEADLINE,
+        sender,
+        mpris_generator,
+    );
+    ReceiverStream::new(receiver)
+}
@@ -0,0 +1,159 @@
+use std::{collections::HashMap, time::Duration};
+
+use rumqttc::{AsyncClient, Event, MqttOptions, Packet, QoS, SubscribeFilter};
+use tinyjson::JsonValue;
+use tokio::{
+    runtime::Handle,
+    sync::mpsc::{Sender, channel, error::SendError},
+};
+use tokio_stream::wrappers::ReceiverStream;
+
+use crate::{
+    config::{MqttConfig, MqttModuleConfig},
+    custom::{CustomOutput, fill_template},
+    state::Message,
+    supervisor::{Backoff, supervise},
+};
+
+#[derive(Debug)]
+pub enum MqttError {
+    ConnectionError(rumqttc::ConnectionError),
+    ClientError(rumqttc::ClientError),
+    SendError(SendError<Message>),
+}
+
+impl std::fmt::Display for MqttError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MqttError::ConnectionError(e) => write!(f, "MQTT Connection Error: {e}"),
+            MqttError::ClientError(e) => write!(f, "MQTT Client Error: {e}"),
+            MqttError::SendError(e) => write!(f, "Channel Error: {e}"),
+        }
+    }
+}
+
+impl From<rumqttc::ConnectionError> for MqttError {
+    fn from(value: rumqttc::ConnectionError) -> Self {
+        Self::ConnectionError(value)
+    }
+}
+
+impl From<rumqttc::ClientError> for MqttError {
+    fn from(value: rumqttc::ClientError) -> Self {
+        Self::ClientError(value)
+    }
+}
+
+impl From<SendError<Message>> for MqttError {
+    fn from(value: SendError<Message>) -> Self {
+        Self::SendError(value)
+    }
+}
+
+#[derive(Debug)]
+pub enum MqttMessage {
+    /// A payload for the module with this name in the config
+    Output { name: String, output: CustomOutput },
+}
+
+/// Whether the topic matches the filter, with + standing in for one level
+/// and # for all the levels after
+fn topic_matches(filter: &str, topic: &str) -> bool {
+    let mut topic_levels = topic.split('/');
+    for level in filter.split('/') {
+        match (level, topic_levels.next()) {
+            ("#", _) => return true,
+            ("+", Some(_)) => {}
+            (level, Some(topic_level)) if level == topic_level => {}
+            _ => return false,
+        }
+    }
+    topic_levels.next().is_none()
+}
+
+/// Strings without their quotes, everything else as JSON
+fn display_json(value: &JsonValue) -> String {
+    match value {
+        JsonValue::String(string) => string.clone(),
+        JsonValue::Number(number) => number.to_string(),
+        JsonValue::Boolean(boolean) => boolean.to_string(),
+        JsonValue::Null => String::new(),
+        _ => value.stringify().unwrap_or_default(),
+    }
+}
+
+/// Fills in {payload} with the whole payload, and {key} or {key.inner} with
+/// fields of a JSON payload like zigbee2mqtt sends. Missing fields are left
+/// empty
+fn fill_payload(template: &str, payload: &str) -> String {
+    let json = payload.parse::<JsonValue>().ok();
+    fill_template(template, |key| {
+        if key == "payload" {
+            return Some(payload.trim().to_string());
+        }
+        let field = key.split('.').try_fold(json.as_ref()?, |value, key| {
+            value.get::<HashMap<String, JsonValue>>()?.get(key)
+        });
+        Some(field.map(display_json).unwrap_or_default())
+    })
+}
+
+fn output(config: &MqttModuleConfig, payload: &str) -> CustomOutput {
+    CustomOutput {
+        text: fill_payload(&config.format, payload),
+        tooltip: config
+            .tooltip
+            .as_ref()
+            .map(|tooltip| fill_payload(tooltip, payload))
+            .filter(|tooltip| !tooltip.is_empty()),
+        class: None,
+    }
+}
+
+async fn mqtt_generator(sender: Sender<Message>, config: &MqttConfig, host: &str) -> Result<(), MqttError> {
+    let mut options = MqttOptions::new(&config.client_id, host, config.port);
+    options.set_keep_alive(Duration::from_secs(30));
+    if let Some(username) = &config.username {
+        options.set_credentials(username, config.password.clone().unwrap_or_default());
+    }
+    let (client, mut event_loop) = AsyncClient::new(options, 10);
+    let filters = config
+        .modules
+        .values()
+        .map(|module| SubscribeFilter::new(module.topic.clone(), QoS::AtMostOnce))
+        .collect::<Vec<_>>();
+    loop {
+        match event_loop.poll().await? {
+            // Subscriptions don't outlive the session, so they are made on
+            // every connection. Retained payloads come right after
+            Event::Incoming(Packet::ConnAck(_)) => client.try_subscribe_many(filters.clone())?,
+            Event::Incoming(Packet::Publish(publish)) => {
+                let payload = String::from_utf8_lossy(&publish.payload);
+                for (name, module) in &config.modules {
+                    if topic_matches(&module.topic, &publish.topic) {
+                        sender
+                            .send(Message::Mqtt(MqttMessage::Output {
+                                name: name.clone(),
+                                output: output(module, &payload),
+                            }))
+                            .await?;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Connects only with a broker and modules in the config
+pub fn mqtt_subscription(rt: Handle, config: MqttConfig) -> ReceiverStream<Message> {
+    let (sender, receiver) = channel(1);
+    let Some(host) = config.host.clone().filter(|_| !config.modules.is_empty()) else {
+        return ReceiverStream::new(receiver);
+    };
+    supervise(&rt, "mqtt", Backoff::starting_at(Duration::from_secs(5)), sender, move |sender| {
+        let (config, host) = (config.clone(), host.clone());
+        async move { mqtt_generator(sender, &config, &host).await }
+    });
+    ReceiverStream::new(receiver)
+}
@@ -1,4 +1,4 @@
-use std::io::Read;
+use std::{fmt::Debug, io::Read};
 
 pub mod ethtool;
 pub mod nl80211;
@@ -51,6 +51,27 @@ impl FromBytes for MacAddr {
     }
 }
 
+impl std::fmt::Display for MacAddr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MacAddr::Mac6(mac) => std::fmt::Display::fmt(mac, f),
+            MacAddr::Mac8(mac) => std::fmt::Display::fmt(mac, f),
+        }
+    }
+}
+
+/// The payload of an attribute, None with a warning when it doesn't fit
+/// the type, so an odd attribute from the kernel loses only itself
+pub fn skip_malformed<T, E: Debug>(attribute: impl Debug, payload: Result<T, E>) -> Option<T> {
+    match payload {
+        Ok(payload) => Some(payload),
+        Err(e) => {
+            log::warn!("Skipping malformed attribute {attribute:?}: {e:?}");
+            None
+        }
+    }
+}
+
 pub struct Netlink {
     pub nl80211_sock: NlRouter,
     pub ethtool_sock: NlRouter,
@@ -69,6 +90,22 @@ pub enum NetlinkCommandError {
     EthtoolCommandRouterError(EthtoolError),
 }
 
+impl std::fmt::Display for NetlinkCommandError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NetlinkCommandError::MsgHdrError(e) => {
+                write!(f, "Could not build the netlink header: {e}")
+            }
+            NetlinkCommandError::Nl80211CommandRouterError(e) => write!(f, "nl80211 Error: {e}"),
+            NetlinkCommandError::RtStatsCommandRouterError(e) => {
+                write!(f, "Link statistics Error: {e}")
+            }
+            NetlinkCommandError::RtInfoCommandRouterError(e) => write!(f, "Link info Error: {e}"),
+            NetlinkCommandError::EthtoolCommandRouterError(e) => write!(f, "ethtool Error: {e}"),
+        }
+    }
+}
+
 impl From<GenlmsghdrBuilderError> for NetlinkCommandError {
     fn from(value: GenlmsghdrBuilderError) -> Self {
         Self::MsgHdrError(value)
@@ -87,6 +124,19 @@ pub enum NetlinkInitError {
     ),
 }
 
+impl std::fmt::Display for NetlinkInitError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NetlinkInitError::FamilyResolutionError(e) => {
+                write!(f, "Could not resolve the netlink families: {e}")
+            }
+            NetlinkInitError::CommandRouterError(e) => {
+                write!(f, "Could not start the netlink router: {e}")
+            }
+        }
+    }
+}
+
 impl
     From<
         RouterError<
@@ -9,7 +9,7 @@ use neli::{
 
 use bitflags::bitflags;
 
-use crate::netlink::{Netlink, NetlinkCommandError, NetlinkRetrievable};
+use crate::netlink::{Netlink, NetlinkCommandError, NetlinkRetrievable, skip_malformed};
 
 #[derive(Debug, Clone, derive_builder::Builder)]
 #[builder(setter(into))]
@@ -81,6 +81,84 @@ impl Into<NetlinkCommandError> for EthtoolError {
     }
 }
 
+impl EthtoolPhy {
+    /// Like Nl80211Interface::parse, malformed attributes are skipped
+    fn parse(
+        payload: &Genlmsghdr<EthtoolCommand, EthtoolPhyAttribute>,
+    ) -> Result<Self, EthtoolPhyBuilderError> {
+        let mut interface_builder = EthtoolPhyBuilder::default();
+        let attr_handle = payload.attrs().get_attr_handle();
+        for attr in attr_handle.iter() {
+            match attr.nla_type().nla_type() {
+                EthtoolPhyAttribute::Unspecified => {
+                    log::info!("Unspecified Value encountered when parsing get-interfaces result");
+                }
+                EthtoolPhyAttribute::UnrecognizedConst(v) => {
+                    log::info!(
+                        "Unrecognized Const encountered when parsing get-interfaces result: {v}"
+                    );
+                }
+                EthtoolPhyAttribute::ReqHdr => {}
+                EthtoolPhyAttribute::Index => {
+                    if let Some(phy_index) =
+                        skip_malformed(attr.nla_type().nla_type(), attr.get_payload_as::<u32>())
+                    {
+                        interface_builder.phy_index(phy_index);
+                    }
+                }
+                EthtoolPhyAttribute::DrvName => {
+                    if let Some(driver_name) = skip_malformed(
+                        attr.nla_type().nla_type(),
+                        attr.get_payload_as_with_len::<String>(),
+                    ) {
+                        interface_builder.driver_name(driver_name);
+                    }
+                }
+                EthtoolPhyAttribute::Name => {
+                    if let Some(name) = skip_malformed(
+                        attr.nla_type().nla_type(),
+                        attr.get_payload_as_with_len::<String>(),
+                    ) {
+                        interface_builder.name(name);
+                    }
+                }
+                EthtoolPhyAttribute::UpstreamType => {
+                    if let Some(upstream_type) = skip_malformed(
+                        attr.nla_type().nla_type(),
+                        attr.get_payload_as::<EthtoolUpstreamType>(),
+                    ) {
+                        interface_builder.upstream_type(upstream_type);
+                    }
+                }
+                EthtoolPhyAttribute::UpstreamIndex => {
+                    if let Some(upstream_index) =
+                        skip_malformed(attr.nla_type().nla_type(), attr.get_payload_as::<u32>())
+                    {
+                        interface_builder.upstream_index(upstream_index);
+                    }
+                }
+                EthtoolPhyAttribute::UpstreamSfpName => {
+                    if let Some(upstream_sfp_name) = skip_malformed(
+                        attr.nla_type().nla_type(),
+                        attr.get_payload_as_with_len::<String>(),
+                    ) {
+                        interface_builder.upstream_sfp_name(upstream_sfp_name);
+                    }
+                }
+                EthtoolPhyAttribute::DownstreamSfpName => {
+                    if let Some(downstream_sfp_name) = skip_malformed(
+                        attr.nla_type().nla_type(),
+                        attr.get_payload_as_with_len::<String>(),
+                    ) {
+                        interface_builder.downstream_sfp_name(downstream_sfp_name);
+                    }
+                }
+            }
+        }
+        interface_builder.build()
+    }
+}
+
 impl NetlinkRetrievable<EthtoolError> for EthtoolPhy {
     async fn retrieve(netlink: &Netlink) -> Result<Vec<Self>, EthtoolError> {
         let mut recv: NlRouterReceiverHandle<u16, Genlmsghdr<EthtoolCommand, EthtoolPhyAttribute>> =
@@ -107,7 +185,6 @@ impl NetlinkRetrievable<EthtoolError> for EthtoolPhy {
                 .next::<u16, Genlmsghdr<EthtoolCommand, EthtoolPhyAttribute>>()
                 .await;
 
-            let mut interface_builder = EthtoolPhyBuilder::default();
             // Messages with the NlmF::DUMP flag end with an empty payload message
             // Don't parse message unless receive proper payload (non-error, non-empty, non-ack)
             let payload: &Genlmsghdr<_, _> = match msg.nl_payload() {
@@ -117,71 +194,9 @@ impl NetlinkRetrievable<EthtoolError> for EthtoolPhy {
                 }
             };
 
-            let attr_handle = payload.attrs().get_attr_handle();
-            for attr in attr_handle.iter() {
-                match attr.nla_type().nla_type() {
-                    EthtoolPhyAttribute::Unspecified => {
-                        log::info!(
-                            "Unspecified Value encountered when parsing get-interfaces result"
-                        );
-                    }
-                    EthtoolPhyAttribute::UnrecognizedConst(v) => {
-                        log::info!(
-                            "Unrecognized Const encountered when parsing get-interfaces result: {v}"
-                        );
-                    }
-                    EthtoolPhyAttribute::ReqHdr => {}
-                    EthtoolPhyAttribute::Index => {
-                        interface_builder.phy_index(
-                            attr.get_payload_as::<u32>()
-                                .expect("There to be vif radio mask that fits in u32"),
-                        );
-                    }
-                    EthtoolPhyAttribute::DrvName => {
-                        interface_builder.driver_name(
-                            attr.get_payload_as_with_len::<String>()
-                                .expect("There to be vif radio mask that fits in u32"),
-                        );
-                    }
-                    EthtoolPhyAttribute::Name => {
-                        interface_builder.name(
-                            attr.get_payload_as_with_len::<String>()
-                                .expect("There to be vif radio mask that fits in u32"),
-                        );
-                    }
-                    EthtoolPhyAttribute::UpstreamType => {
-                        interface_builder.upstream_type(
-                            attr.get_payload_as::<EthtoolUpstreamType>()
-                                .expect("There to be vif radio mask that fits in u32"),
-                        );
-                    }
-                    EthtoolPhyAttribute::UpstreamIndex => {
-                        interface_builder.upstream_index(
-                            attr.get_payload_as::<u32>()
-                                .expect("There to be vif radio mask that fits in u32"),
-                        );
-                    }
-                    EthtoolPhyAttribute::UpstreamSfpName => {
-                        interface_builder.upstream_sfp_name(
-                            attr.get_payload_as_with_len::<String>()
-                                .expect("There to be vif radio mask that fits in u32"),
-                        );
-                    }
-                    EthtoolPhyAttribute::DownstreamSfpName => {
-                        interface_builder.downstream_sfp_name(
-                            attr.get_payload_as_with_len::<String>()
-                                .expect("There to be vif radio mask that fits in u32"),
-                        );
-                    }
-                }
-            }
-            match interface_builder.build() {
-                Ok(phy) => {
-                    ethernet_interfaces.push(phy);
-                }
-                Err(e) => {
-                    log::error!("{e:?}")
-                }
+            match Self::parse(payload) {
+                Ok(interface) => ethernet_interfaces.push(interface),
+                Err(e) => log::error!("Skipping PHY: {e:?}"),
             }
         }
         Ok(ethernet_interfaces)
@@ -1,8 +1,15 @@
 use neli::{
-    attr::Attribute, consts::nl::NlmF, err::RouterError, genl::{Genlmsghdr, GenlmsghdrBuilder}, nl::NlPayload, router::asynchronous::NlRouterReceiverHandle, FromBytes
+    FromBytes,
+    attr::Attribute,
+    consts::nl::NlmF,
+    err::RouterError,
+    genl::{AttrTypeBuilder, Genlmsghdr, GenlmsghdrBuilder, NlattrBuilder},
+    nl::NlPayload,
+    router::asynchronous::NlRouterReceiverHandle,
+    types::GenlBuffer,
 };
 
-use crate::netlink::{MacAddr, Netlink, NetlinkCommandError, NetlinkRetrievable};
+use crate::netlink::{MacAddr, Netlink, NetlinkCommandError, NetlinkRetrievable, skip_malformed};
 
 #[derive(Debug, Clone, derive_builder::Builder, FromBytes)]
 #[builder(setter(into))]
@@ -39,12 +46,27 @@ pub struct Nl80211Interface {
     pub ssid: Option<String>,
 }
 
+/// The access point a managed interface is associated with
+#[derive(Debug, Clone)]
+pub struct Nl80211Station {
+    pub if_index: u32,
+    /// MAC address of the access point
+    pub bssid: MacAddr,
+    /// In dBm
+    pub signal: Option<i8>,
+    /// In units of 100 kbit/s
+    pub tx_bitrate: Option<u32>,
+    /// In units of 100 kbit/s
+    pub rx_bitrate: Option<u32>,
+}
+
 /// To find the values, look in include/uapi/linux/nl80211.h
 #[neli::neli_enum(serialized_type = "u8")]
 pub enum Nl80211Command {
     Unspecified = 0,
     GetWiPhy = 1,
     GetInterface = 5,
+    GetStation = 17,
     /* Many many more elided */
 }
 impl neli::consts::genl::Cmd for Nl80211Command {}
@@ -71,6 +93,8 @@ pub enum Nl80211InterfaceAttribute {
 
     Mac = 6,
 
+    StaInfo = 21,
+
     Generation = 46,
 
     Ssid = 52,
@@ -88,6 +112,29 @@ pub enum Nl80211InterfaceAttribute {
 }
 impl neli::consts::genl::NlAttrType for Nl80211InterfaceAttribute {}
 
+/// Nested in Nl80211InterfaceAttribute::StaInfo
+#[neli::neli_enum(serialized_type = "u16")]
+pub enum Nl80211StationInfo {
+    Unspecified = 0,
+    Signal = 7,
+    TxBitrate = 8,
+    SignalAvg = 13,
+    RxBitrate = 14,
+    /* Plenty more elided */
+}
+impl neli::consts::genl::NlAttrType for Nl80211StationInfo {}
+
+/// Nested in Nl80211StationInfo::TxBitrate and Nl80211StationInfo::RxBitrate
+#[neli::neli_enum(serialized_type = "u16")]
+pub enum Nl80211RateInfo {
+    Unspecified = 0,
+    /// u16, too small for the fastest links
+    Bitrate = 1,
+    Bitrate32 = 5,
+    /* MCS, widths and such elided */
+}
+impl neli::consts::genl::NlAttrType for Nl80211RateInfo {}
+
 pub type Nl80211Error =
     RouterError<u16, neli::genl::Genlmsghdr<Nl80211Command, Nl80211InterfaceAttribute>>;
 
@@ -97,6 +144,121 @@ impl Into<NetlinkCommandError> for Nl80211Error {
     }
 }
 
+impl Nl80211Interface {
+    /// Malformed attributes are skipped, the interface only fails to parse when
+    /// one every interface has is missing
+    fn parse(
+        payload: &Genlmsghdr<Nl80211Command, Nl80211InterfaceAttribute>,
+    ) -> Result<Self, Nl80211InterfaceBuilderError> {
+        let mut interface_builder = Nl80211InterfaceBuilder::default();
+        let attr_handle = payload.attrs().get_attr_handle();
+        for attr in attr_handle.iter() {
+            match attr.nla_type().nla_type() {
+                Nl80211InterfaceAttribute::WiPhy => {
+                    if let Some(wiphy) =
+                        skip_malformed(attr.nla_type().nla_type(), attr.get_payload_as::<u32>())
+                    {
+                        interface_builder.wiphy(wiphy);
+                    }
+                }
+                Nl80211InterfaceAttribute::IfName => {
+                    if let Some(if_name) = skip_malformed(
+                        attr.nla_type().nla_type(),
+                        attr.get_payload_as_with_len::<String>(),
+                    ) {
+                        interface_builder.if_name(if_name);
+                    }
+                }
+                Nl80211InterfaceAttribute::IfType => {
+                    if let Some(if_type) = skip_malformed(
+                        attr.nla_type().nla_type(),
+                        attr.get_payload_as::<Nl80211IfType>(),
+                    ) {
+                        interface_builder.if_type(if_type);
+                    }
+                }
+                Nl80211InterfaceAttribute::Wdev => {
+                    if let Some(wdev) =
+                        skip_malformed(attr.nla_type().nla_type(), attr.get_payload_as::<u64>())
+                    {
+                        interface_builder.wdev(wdev);
+                    }
+                }
+                Nl80211InterfaceAttribute::Unspecified => {
+                    log::error!("Unspecified Value encountered when parsing get-interfaces result");
+                }
+                Nl80211InterfaceAttribute::IfIndex => {
+                    if let Some(if_index) =
+                        skip_malformed(attr.nla_type().nla_type(), attr.get_payload_as::<u32>())
+                    {
+                        interface_builder.if_index(if_index);
+                    }
+                }
+                Nl80211InterfaceAttribute::Mac => {
+                    if let Some(mac) =
+                        skip_malformed(attr.nla_type().nla_type(), attr.get_payload_as::<MacAddr>())
+                    {
+                        interface_builder.mac(mac);
+                    }
+                }
+                Nl80211InterfaceAttribute::StaInfo => {
+                    log::info!("Station info encountered when parsing get-interfaces result");
+                }
+                Nl80211InterfaceAttribute::Generation => {
+                    if let Some(generation) =
+                        skip_malformed(attr.nla_type().nla_type(), attr.get_payload_as::<u32>())
+                    {
+                        interface_builder.generation(generation);
+                    }
+                }
+                Nl80211InterfaceAttribute::Addr4 => {
+                    if let Some(addr4) =
+                        skip_malformed(attr.nla_type().nla_type(), attr.get_payload_as::<u8>())
+                    {
+                        interface_builder.addr4(addr4);
+                    }
+                }
+                Nl80211InterfaceAttribute::TxqStats => {
+                    if let Some(txq_stats) = skip_malformed(
+                        attr.nla_type().nla_type(),
+                        attr.get_payload_as::<Nl80211TxqStats>(),
+                    ) {
+                        interface_builder.txq_stats(txq_stats);
+                    }
+                }
+                Nl80211InterfaceAttribute::Ssid => {
+                    if let Some(ssid) = skip_malformed(
+                        attr.nla_type().nla_type(),
+                        attr.get_payload_as_with_len::<String>(),
+                    ) {
+                        interface_builder.ssid(ssid);
+                    }
+                }
+                Nl80211InterfaceAttribute::WiPhyTxPowerLevel => {
+                    if let Some(wiphy_tx_power_level) =
+                        skip_malformed(attr.nla_type().nla_type(), attr.get_payload_as::<u32>())
+                    {
+                        interface_builder.wiphy_tx_power_level(wiphy_tx_power_level);
+                    }
+                }
+                Nl80211InterfaceAttribute::VifRadioMask => {
+                    if let Some(vif_radio_mask) =
+                        skip_malformed(attr.nla_type().nla_type(), attr.get_payload_as::<u32>())
+                    {
+                        interface_builder.vif_radio_mask(vif_radio_mask);
+                    }
+                }
+                Nl80211InterfaceAttribute::UnrecognizedConst(v) => {
+                    log::info!(
+                        "Unrecognized Const encountered when parsing get-interfaces result: {v}"
+                    );
+                }
+            }
+        }
+        interface_builder.build()
+    }
+}
+
 impl NetlinkRetrievable<Nl80211Error> for Nl80211Interface {
     async fn retrieve(netlink: &Netlink) -> Result<Vec<Self>, Nl80211Error> {
         let mut recv: NlRouterReceiverHandle<
@@ -120,7 +282,6 @@ impl NetlinkRetrievable<Nl80211Error> for Nl80211Interface {
             .next::<u16, Genlmsghdr<Nl80211Command, Nl80211InterfaceAttribute>>()
             .await
         {
-            let mut interface_builder = Nl80211InterfaceBuilder::default();
             // Messages with the NlmF::DUMP flag end with an empty payload message
             // Don't parse message unless receive proper payload (non-error, non-empty, non-ack)
             let payload: &Genlmsghdr<_, _> = match msg.nl_payload() {
@@ -128,103 +289,233 @@ impl NetlinkRetrievable<Nl80211Error> for Nl80211Interface {
                 _ => continue,
             };
 
-            let attr_handle = payload.attrs().get_attr_handle();
-            for attr in attr_handle.iter() {
-                match attr.nla_type().nla_type() {
-                    Nl80211InterfaceAttribute::WiPhy => {
-                        interface_builder.wiphy(
-                            attr.get_payload_as::<u32>()
-                                .expect("There to be WiPhy as u32 for attribute WiPhy"),
-                        );
-                    }
-                    Nl80211InterfaceAttribute::IfName => {
-                        interface_builder.if_name(
-                            attr.get_payload_as_with_len::<String>()
-                                .expect("There to be IfName as String"),
-                        );
-                    }
-                    Nl80211InterfaceAttribute::IfType => {
-                        interface_builder.if_type(
-                            attr.get_payload_as::<Nl80211IfType>().expect(
-                                "There to to be IfType that fits in Nl80211IfType, i.e. u16",
-                            ),
-                        );
-                    }
-                    Nl80211InterfaceAttribute::Wdev => {
-                        interface_builder.wdev(
-                            attr.get_payload_as::<u64>()
-                                .expect("There to be Wdev id that fits in u64"),
-                        );
-                    }
-                    Nl80211InterfaceAttribute::Unspecified => {
-                        log::error!(
-                            "Unspecified Value encountered when parsing get-interfaces result"
-                        );
-                    }
-                    Nl80211InterfaceAttribute::IfIndex => {
-                        interface_builder.if_index(
-                            attr.get_payload_as::<u32>()
-                                .expect("There to be IfIndex that fits in u32"),
-                        );
-                    }
-                    Nl80211InterfaceAttribute::Mac => {
-                        interface_builder.mac(
-                            attr.get_payload_as::<MacAddr>()
-                                .expect("There to be Mac Address data that fits in MacAddr"),
-                        );
-                    }
-                    Nl80211InterfaceAttribute::Generation => {
-                        interface_builder.generation(
-                            attr.get_payload_as::<u32>()
-                                .expect("There to be Mac Address data that fits in MacAddr"),
-                        );
-                    }
-                    Nl80211InterfaceAttribute::Addr4 => {
-                        interface_builder.addr4(
-                            attr.get_payload_as::<u8>()
-                                .expect("There to be Mac Address data that fits in MacAddr"),
-                        );
-                    }
-                    Nl80211InterfaceAttribute::TxqStats => {
-                        interface_builder.txq_stats(
-                            attr.get_payload_as::<Nl80211TxqStats>()
-                                .expect("There to be Mac Address data that fits in MacAddr"),
-                        );
-                    }
-                    Nl80211InterfaceAttribute::Ssid => {
-                        interface_builder.ssid(
-                            attr.get_payload_as_with_len::<String>()
-                                .expect("There to be SSID that fits in String"),
-                        );
-                    }
-                    Nl80211InterfaceAttribute::WiPhyTxPowerLevel => {
-                        interface_builder.wiphy_tx_power_level(
-                            attr.get_payload_as::<u32>()
-                                .expect("There to be WiPhy TxPower Level that fits in u32"),
-                        );
-                    }
-                    Nl80211InterfaceAttribute::VifRadioMask => {
-                        interface_builder.vif_radio_mask(
-                            attr.get_payload_as::<u32>()
-                                .expect("There to be vif radio mask that fits in u32"),
-                        );
-                    }
-                    Nl80211InterfaceAttribute::UnrecognizedConst(v) => {
-                        log::info!(
-                            "Unrecognized Const encountered when parsing get-interfaces result: {v}"
-                        );
-                    }
-                }
+            match Self::parse(payload) {
+                Ok(interface) => wifi_interfaces.push(interface),
+                Err(e) => log::error!("Skipping wireless interface: {e:?}"),
             }
-            match interface_builder.build() {
-                Ok(wifi) => {
-                    wifi_interfaces.push(wifi);
+        }
+        Ok(wifi_interfaces)
+    }
+}
+
+/// Bitrate in units of 100 kbit/s, from the nested rate info
+fn parse_bitrate(
+    attr: &neli::genl::Nlattr<Nl80211StationInfo, neli::types::Buffer>,
+) -> Option<u32> {
+    let rate_info = attr.get_attr_handle::<Nl80211RateInfo>().ok()?;
+    let mut bitrate = None;
+    for attr in rate_info.iter() {
+        match attr.nla_type().nla_type() {
+            // Preferred when present, as it doesn't overflow
+            Nl80211RateInfo::Bitrate32 => return attr.get_payload_as::<u32>().ok(),
+            Nl80211RateInfo::Bitrate => {
+                bitrate = attr.get_payload_as::<u16>().ok().map(u32::from);
+            }
+            _ => {}
+        }
+    }
+    bitrate
+}
+
+impl Nl80211Station {
+    /// None without the MAC address of the access point, whatever else is
+    /// malformed is left out
+    fn parse(
+        payload: &Genlmsghdr<Nl80211Command, Nl80211InterfaceAttribute>,
+        if_index: u32,
+    ) -> Option<Self> {
+        let mut bssid = None;
+        let mut signal = None;
+        let mut signal_avg = None;
+        let mut tx_bitrate = None;
+        let mut rx_bitrate = None;
+        let attr_handle = payload.attrs().get_attr_handle();
+        for attr in attr_handle.iter() {
+            match attr.nla_type().nla_type() {
+                Nl80211InterfaceAttribute::Mac => {
+                    bssid = skip_malformed(
+                        attr.nla_type().nla_type(),
+                        attr.get_payload_as::<MacAddr>(),
+                    );
                 }
-                Err(e) => {
-                    log::error!("{e:?}")
+                Nl80211InterfaceAttribute::StaInfo => {
+                    let Ok(station_info) = attr.get_attr_handle::<Nl80211StationInfo>() else {
+                        log::warn!("Station info of interface {if_index} could not be parsed");
+                        continue;
+                    };
+                    for attr in station_info.iter() {
+                        match attr.nla_type().nla_type() {
+                            // Signed dBm sent as a u8
+                            Nl80211StationInfo::Signal => {
+                                signal = attr.get_payload_as::<u8>().ok().map(|s| s as i8);
+                            }
+                            Nl80211StationInfo::SignalAvg => {
+                                signal_avg = attr.get_payload_as::<u8>().ok().map(|s| s as i8);
+                            }
+                            Nl80211StationInfo::TxBitrate => tx_bitrate = parse_bitrate(attr),
+                            Nl80211StationInfo::RxBitrate => rx_bitrate = parse_bitrate(attr),
+                            _ => {}
+                        }
+                    }
                 }
+                _ => {}
             }
         }
-        Ok(wifi_interfaces)
+        let Some(bssid) = bssid else {
+            log::error!("Station of interface {if_index} has no MAC address");
+            return None;
+        };
+        Some(Self {
+            if_index,
+            bssid,
+            // The average flickers less, but not every driver has it
+            signal: signal_avg.or(signal),
+            tx_bitrate,
+            rx_bitrate,
+        })
+    }
+
+    /// The stations of one interface, for a managed interface only the access
+    /// point it is associated with
+    async fn retrieve_for(netlink: &Netlink, if_index: u32) -> Result<Vec<Self>, Nl80211Error> {
+        let attrs = std::iter::once(
+            NlattrBuilder::default()
+                .nla_type(
+                    AttrTypeBuilder::default()
+                        .nla_type(Nl80211InterfaceAttribute::IfIndex)
+                        .build()?,
+                )
+                .nla_payload(if_index)
+                .build()?,
+        )
+        .collect::<GenlBuffer<_, _>>();
+        let mut recv: NlRouterReceiverHandle<
+            u16,
+            Genlmsghdr<Nl80211Command, Nl80211InterfaceAttribute>,
+        > = netlink
+            .nl80211_sock
+            .send(
+                netlink.nl80211_family_id,
+                NlmF::DUMP | NlmF::ACK,
+                NlPayload::Payload(
+                    GenlmsghdrBuilder::default()
+                        .cmd(Nl80211Command::GetStation)
+                        .version(1)
+                        .attrs(attrs)
+                        .build()?,
+                ),
+            )
+            .await?;
+        let mut stations = Vec::new();
+        while let Some(Ok(msg)) = recv
+            .next::<u16, Genlmsghdr<Nl80211Command, Nl80211InterfaceAttribute>>()
+            .await
+        {
+            let payload: &Genlmsghdr<_, _> = match msg.nl_payload() {
+                NlPayload::Payload(p) => p,
+                _ => continue,
+            };
+
+            if let Some(station) = Self::parse(payload, if_index) {
+                stations.push(station);
+            }
+        }
+        Ok(stations)
+    }
+}
+
+impl NetlinkRetrievable<Nl80211Error> for Nl80211Station {
+    /// Stations of the interfaces in station mode, the ones connected to an
+    /// access point
+    async fn retrieve(netlink: &Netlink) -> Result<Vec<Self>, Nl80211Error> {
+        let mut stations = Vec::new();
+        for interface in Nl80211Interface::retrieve(netlink).await? {
+            if matches!(interface.if_type, Nl80211IfType::Station) {
+                stations.extend(Self::retrieve_for(netlink, interface.if_index).await?);
+            }
+        }
+        Ok(stations)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use neli::types::Buffer;
+
+    use super::*;
+
+    fn message(
+        attrs: Vec<(Nl80211InterfaceAttribute, Vec<u8>)>,
+    ) -> Genlmsghdr<Nl80211Command, Nl80211InterfaceAttribute> {
+        let attrs = attrs
+            .into_iter()
+            .map(|(nla_type, payload)| {
+                NlattrBuilder::default()
+                    .nla_type(
+                        AttrTypeBuilder::default()
+                            .nla_type(nla_type)
+                            .build()
+                            .expect("The attribute type to build"),
+                    )
+                    .nla_payload(Buffer::from(payload))
+                    .build()
+                    .expect("The attribute to build")
+            })
+            .collect::<GenlBuffer<_, _>>();
+        GenlmsghdrBuilder::default()
+            .cmd(Nl80211Command::GetStation)
+            .version(1)
+            .attrs(attrs)
+            .build()
+            .expect("The message to build")
+    }
+
+    #[test]
+    fn station_with_only_mac() {
+        let station = Nl80211Station::parse(
+            &message(vec![(
+                Nl80211InterfaceAttribute::Mac,
+                vec![0x02, 0, 0, 0, 0, 0x01],
+            )]),
+            3,
+        )
+        .expect("The station to parse");
+        assert_eq!(station.bssid.to_string(), "02:00:00:00:00:01");
+        assert!(station.signal.is_none());
+        assert!(station.tx_bitrate.is_none());
+    }
+
+    #[test]
+    fn station_with_truncated_mac_is_skipped() {
+        let station = Nl80211Station::parse(
+            &message(vec![(
+                Nl80211InterfaceAttribute::Mac,
+                vec![0x02, 0, 0, 0, 0],
+            )]),
+            3,
+        );
+        assert!(station.is_none());
+    }
+
+    #[test]
+    fn station_with_truncated_info_keeps_mac() {
+        let station = Nl80211Station::parse(
+            &message(vec![
+                (Nl80211InterfaceAttribute::Mac, vec![0x02, 0, 0, 0, 0, 0x01]),
+                (Nl80211InterfaceAttribute::StaInfo, vec![0x08, 0x00, 0x07]),
+            ]),
+            3,
+        )
+        .expect("The station to parse");
+        assert!(station.signal.is_none());
+    }
+
+    #[test]
+    fn interface_with_truncated_index_fails() {
+        let interface = Nl80211Interface::parse(&message(vec![
+            (Nl80211InterfaceAttribute::IfName, b"wlan0\0".to_vec()),
+            (Nl80211InterfaceAttribute::IfIndex, vec![0x03, 0x00]),
+        ]));
+        assert!(interface.is_err());
     }
 }
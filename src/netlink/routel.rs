@@ -3,14 +3,14 @@ use neli::{
     attr::Attribute,
     consts::{
         nl::NlmF,
-        rtnl::{IflaStats, RtAddrFamily, Rtm},
+        rtnl::{IflaInfo, IflaStats, RtAddrFamily, Rtm},
     },
     err::RouterError,
     nl::NlPayload,
     rtnl::{Ifinfomsg, IfinfomsgBuilder, Ifstatsmsg, IfstatsmsgBuilder},
 };
 
-use crate::netlink::{MacAddr, Netlink, NetlinkCommandError, NetlinkRetrievable};
+use crate::netlink::{MacAddr, Netlink, NetlinkCommandError, NetlinkRetrievable, skip_malformed};
 
 #[derive(Debug, Clone, FromBytes)]
 pub struct LinkStats64 {
@@ -119,10 +119,10 @@ impl NetlinkRetrievable<RoutelinkStatsError> for LinkStats64 {
 
             let attr_handle = payload.rtattrs().get_attr_handle();
             for attr in attr_handle.iter() {
-                stats.push(
-                    attr.get_payload_as::<LinkStats64>()
-                        .expect("To only get binary stuff that can fit into a Link64 struct"),
-                )
+                stats.extend(skip_malformed(
+                    attr.rta_type(),
+                    attr.get_payload_as::<LinkStats64>(),
+                ));
             }
         }
         Ok(stats)
@@ -304,9 +304,17 @@ impl TypeSize for Inet6Stats {
 #[derive(Debug, Clone, derive_builder::Builder)]
 pub struct LinkInfo {
     pub ifi_index: i32,
-    pub address: MacAddr,
-    pub broadcast: MacAddr,
+    /// None for point to point links like WireGuard and tun, which have no
+    /// link layer addresses
+    #[builder(default)]
+    pub address: Option<MacAddr>,
+    #[builder(default)]
+    pub broadcast: Option<MacAddr>,
     pub ifname: String,
+    /// IFLA_INFO_KIND, like wireguard, tun or bridge. None for physical
+    /// devices
+    #[builder(default)]
+    pub kind: Option<String>,
     pub mtu: u32,
     pub qdisc: String,
     #[builder(default)]
@@ -382,421 +390,536 @@ pub struct LinkInfo {
     pub gro_ipv4_max_size: Option<u32>,
 }
 
-impl NetlinkRetrievable<RoutelinkStatsError> for LinkInfo {
-    async fn retrieve(netlink: &Netlink) -> Result<Vec<Self>, RoutelinkStatsError> {
-        let mut recv = netlink
-            .rtnl
-            .send::<_, _, Rtm, ()>(
-                Rtm::Getlink,
-                NlmF::DUMP | NlmF::ACK,
-                neli::nl::NlPayload::Payload(
-                    IfinfomsgBuilder::default()
-                        .ifi_family(RtAddrFamily::Inet)
-                        .build()?,
-                ),
-            )
-            .await
-            .unwrap();
-        let mut links = Vec::new();
-        while let Some(response) = recv.next::<Rtm, Ifinfomsg>().await {
-            let response = response.unwrap();
-            let payload = {
-                match response.nl_payload() {
-                    NlPayload::Payload(x) => x,
-                    _ => {
-                        continue;
-                    }
+impl LinkInfo {
+    /// Malformed attributes are skipped, the link only fails to parse when
+    /// one every link has is missing
+    fn parse(payload: &Ifinfomsg) -> Result<Self, LinkInfoBuilderError> {
+        let mut link_builder = LinkInfoBuilder::default();
+        link_builder.ifi_index(*payload.ifi_index());
+        let attr_handle = payload.rtattrs().get_attr_handle();
+        for attr in attr_handle.iter() {
+            use neli::consts::rtnl::Ifla::*;
+            match attr.rta_type() {
+                Unspec => {
+                    log::error!("Unspecified Value encountered when parsing Getlink result");
                 }
-            };
-
-            let mut link_builder = LinkInfoBuilder::default();
-            link_builder.ifi_index(*payload.ifi_index());
-            let attr_handle = payload.rtattrs().get_attr_handle();
-            for attr in attr_handle.iter() {
-                use neli::consts::rtnl::Ifla::*;
-                match attr.rta_type() {
-                    Unspec => {
-                        log::error!("Unspecified Value encountered when parsing Getlink result");
-                    }
-                    UnrecognizedConst(v) => {
-                        log::info!(
-                            "Unrecognized Const encountered when parsing get-link result: {v}"
-                        );
-                    }
-                    Address => {
-                        link_builder.address(
-                            attr.get_payload_as()
-                                .expect("There to be mac address that is valid"),
-                        );
-                    }
-                    Broadcast => {
-                        link_builder.broadcast(
-                            attr.get_payload_as()
-                                .expect("There to be a valid broadcast mac address"),
-                        );
-                    }
-                    Ifname => {
-                        link_builder.ifname(
-                            attr.get_payload_as_with_len::<String>()
-                                .expect("Ifname to be a valid string"),
-                        );
-                    }
-                    Mtu => {
-                        link_builder
-                            .mtu(attr.get_payload_as::<u32>().expect("Mtu to be a valid u32"));
-                    }
-                    Link => {
-                        link_builder.link(Some(
-                            attr.get_payload_as::<u32>()
-                                .expect("Link to be a valid u32"),
-                        ));
-                    }
-                    Qdisc => {
-                        link_builder.qdisc(
-                            attr.get_payload_as_with_len::<String>()
-                                .expect("Qdisc to be a valid string"),
-                        );
-                    }
-                    Stats => {
-                        //println!("{:?}", attr.rta_payload().len());
-                        link_builder.stats(
-                            attr.get_payload_as()
-                                .expect("Stats to be a valid LinkStats struct"),
-                        );
-                    }
-                    Cost => {
-                        log::warn!("IFLA_COST is a nested attribute, parsing is not implemented");
-                    }
-                    Priority => {
-                        link_builder.priority(Some(
-                            attr.get_payload_as::<u32>()
-                                .expect("Priority to be a valid u32")
-                                .to_string(),
-                        ));
-                    }
-                    Master => {
-                        link_builder.master(Some(
-                            attr.get_payload_as::<u32>()
-                                .expect("Master to be a valid u32"),
-                        ));
-                    }
-                    Wireless => {
-                        log::warn!(
-                            "IFLA_WIRELESS is a nested attribute, parsing is not implemented"
-                        );
-                    }
-                    Protinfo => {
-                        log::warn!(
-                            "IFLA_PROTINFO is a nested attribute, parsing is not implemented"
-                        );
-                    }
-                    Txqlen => {
-                        link_builder.txqlen(
-                            attr.get_payload_as::<u32>()
-                                .expect("Txqlen to be a valid u32"),
-                        );
-                    }
-                    Map => {
-                        link_builder.map(
-                            attr.get_payload_as()
-                                .expect("Map to be a valid LinkIfMap struct"),
-                        );
-                    }
-                    Weight => {
-                        link_builder.weight(Some(
-                            attr.get_payload_as::<u32>()
-                                .expect("Weight to be a valid u32"),
-                        ));
-                    }
-                    Operstate => {
-                        link_builder.operstate(
-                            attr.get_payload_as::<u8>()
-                                .expect("Operstate to be a valid u8"),
-                        );
-                    }
-                    Linkmode => {
-                        link_builder.linkmode(
-                            attr.get_payload_as::<u8>()
-                                .expect("Linkmode to be a valid u8"),
-                        );
-                    }
-                    Linkinfo => {
-                        log::warn!(
-                            "IFLA_LINKINFO is a complex nested attribute, full parsing is not implemented here."
-                        );
-                    }
-                    NetNsPid => {
-                        link_builder.net_ns_pid(Some(
-                            attr.get_payload_as::<u32>()
-                                .expect("NetNsPid to be a valid u32"),
-                        ));
-                    }
-                    Ifalias => {
-                        link_builder.ifalias(Some(
-                            attr.get_payload_as_with_len::<String>()
-                                .expect("Ifalias to be a valid string"),
-                        ));
-                    }
-                    NumVf => {
-                        link_builder.num_vf(Some(
-                            attr.get_payload_as::<u32>()
-                                .expect("NumVf to be a valid u32"),
-                        ));
-                    }
-                    VfinfoList => {
-                        log::warn!(
-                            "IFLA_VFINFO_LIST is a nested attribute, parsing is not implemented"
-                        );
-                    }
-                    Stats64 => {
-                        link_builder.stats64(
-                            attr.get_payload_as()
-                                .expect("Stats64 to be a valid LinkStats64 struct"),
-                        );
-                    }
-                    VfPorts => {
-                        log::warn!(
-                            "IFLA_VF_PORTS is a nested attribute, parsing is not implemented"
-                        );
-                    }
-                    PortSelf => {
-                        log::warn!(
-                            "IFLA_PORT_SELF is a nested attribute, parsing is not implemented"
-                        );
-                    }
-                    AfSpec => {
-                        log::warn!(
-                            "IFLA_AF_SPEC is a nested attribute, parsing is not implemented"
-                        );
-                    }
-                    Group => {
-                        link_builder.group(
-                            attr.get_payload_as::<u32>()
-                                .expect("Group to be a valid u32"),
-                        );
-                    }
-                    NetNsFd => {
-                        link_builder.net_ns_fd(Some(
-                            attr.get_payload_as::<u32>()
-                                .expect("NetNsFd to be a valid u32"),
-                        ));
-                    }
-                    ExtMask => {
-                        log::debug!("Skipping IFLA_EXT_MASK attribute");
-                    }
-                    Promiscuity => {
-                        link_builder.promiscuity(
-                            attr.get_payload_as::<u32>()
-                                .expect("Promiscuity to be a valid u32"),
-                        );
-                    }
-                    NumTxQueues => {
-                        link_builder.num_tx_queues(
-                            attr.get_payload_as::<u32>()
-                                .expect("NumTxQueues to be a valid u32"),
-                        );
-                    }
-                    NumRxQueues => {
-                        link_builder.num_rx_queues(
-                            attr.get_payload_as::<u32>()
-                                .expect("NumRxQueues to be a valid u32"),
-                        );
-                    }
-                    Carrier => {
-                        link_builder.carrier(
-                            attr.get_payload_as::<u8>()
-                                .expect("Carrier to be a valid u8"),
-                        );
-                    }
-                    PhysPortId => {
-                        log::debug!("Skipping IFLA_PHYS_PORT_ID attribute");
-                    }
-                    CarrierChanges => {
-                        link_builder.carrier_changes(
-                            attr.get_payload_as::<u32>()
-                                .expect("CarrierChanges to be a valid u32"),
-                        );
-                    }
-                    PhysSwitchId => {
-                        log::debug!("Skipping IFLA_PHYS_SWITCH_ID attribute");
-                    }
-                    LinkNetnsid => {
-                        link_builder.link_netnsid(Some(
-                            attr.get_payload_as::<i32>()
-                                .expect("LinkNetnsid to be a valid i32"),
-                        ));
-                    }
-                    PhysPortName => {
-                        link_builder.phys_port_name(Some(
-                            attr.get_payload_as_with_len::<String>()
-                                .expect("PhysPortName to be a valid string"),
-                        ));
-                    }
-                    ProtoDown => {
-                        link_builder.proto_down(
-                            attr.get_payload_as::<u8>()
-                                .expect("ProtoDown to be a valid u8"),
-                        );
-                    }
-                    GsoMaxSegs => {
-                        link_builder.gso_max_segs(
-                            attr.get_payload_as::<u32>()
-                                .expect("GsoMaxSegs to be a valid u32"),
-                        );
-                    }
-                    GsoMaxSize => {
-                        link_builder.gso_max_size(
-                            attr.get_payload_as::<u32>()
-                                .expect("GsoMaxSize to be a valid u32"),
-                        );
-                    }
-                    Pad => { /* Padding attribute, ignored */ }
-                    Xdp => {
-                        log::warn!("IFLA_XDP is a nested attribute, parsing is not implemented");
-                    }
-                    Event => {
-                        link_builder.event(Some(
-                            attr.get_payload_as::<u32>()
-                                .expect("Event to be a valid u32"),
-                        ));
-                    }
-                    NewNetnsid => {
-                        link_builder.new_netnsid(Some(
-                            attr.get_payload_as::<i32>()
-                                .expect("NewNetnsid to be a valid i32"),
-                        ));
+                UnrecognizedConst(v) => {
+                    log::info!("Unrecognized Const encountered when parsing get-link result: {v}");
+                }
+                Address => {
+                    link_builder.address(skip_malformed(attr.rta_type(), attr.get_payload_as()));
+                }
+                Broadcast => {
+                    link_builder.broadcast(skip_malformed(attr.rta_type(), attr.get_payload_as()));
+                }
+                Ifname => {
+                    if let Some(ifname) =
+                        skip_malformed(attr.rta_type(), attr.get_payload_as_with_len::<String>())
+                    {
+                        link_builder.ifname(ifname);
                     }
-                    IfNetnsid => {
-                        link_builder.target_netnsid(Some(
-                            attr.get_payload_as::<i32>()
-                                .expect("IfNetnsid to be a valid i32"),
-                        ));
+                }
+                Mtu => {
+                    if let Some(mtu) = skip_malformed(attr.rta_type(), attr.get_payload_as::<u32>())
+                    {
+                        link_builder.mtu(mtu);
                     }
-                    CarrierUpCount => {
-                        link_builder.carrier_up_count(
-                            attr.get_payload_as::<u32>()
-                                .expect("CarrierUpCount to be a valid u32"),
-                        );
+                }
+                Link => {
+                    link_builder.link(skip_malformed(
+                        attr.rta_type(),
+                        attr.get_payload_as::<u32>(),
+                    ));
+                }
+                Qdisc => {
+                    if let Some(qdisc) =
+                        skip_malformed(attr.rta_type(), attr.get_payload_as_with_len::<String>())
+                    {
+                        link_builder.qdisc(qdisc);
                     }
-                    CarrierDownCount => {
-                        link_builder.carrier_down_count(
-                            attr.get_payload_as::<u32>()
-                                .expect("CarrierDownCount to be a valid u32"),
-                        );
+                }
+                Stats => {
+                    if let Some(stats) = skip_malformed(attr.rta_type(), attr.get_payload_as()) {
+                        link_builder.stats(stats);
                     }
-                    NewIfindex => {
-                        link_builder.new_ifindex(Some(
-                            attr.get_payload_as::<i32>()
-                                .expect("NewIfindex to be a valid i32"),
-                        ));
+                }
+                Cost => {
+                    log::warn!("IFLA_COST is a nested attribute, parsing is not implemented");
+                }
+                Priority => {
+                    link_builder.priority(
+                        skip_malformed(attr.rta_type(), attr.get_payload_as::<u32>())
+                            .map(|priority| priority.to_string()),
+                    );
+                }
+                Master => {
+                    link_builder.master(skip_malformed(
+                        attr.rta_type(),
+                        attr.get_payload_as::<u32>(),
+                    ));
+                }
+                Wireless => {
+                    log::warn!("IFLA_WIRELESS is a nested attribute, parsing is not implemented");
+                }
+                Protinfo => {
+                    log::warn!("IFLA_PROTINFO is a nested attribute, parsing is not implemented");
+                }
+                Txqlen => {
+                    if let Some(txqlen) =
+                        skip_malformed(attr.rta_type(), attr.get_payload_as::<u32>())
+                    {
+                        link_builder.txqlen(txqlen);
                     }
-                    MinMtu => {
-                        link_builder.min_mtu(
-                            attr.get_payload_as::<u32>()
-                                .expect("MinMtu to be a valid u32"),
-                        );
+                }
+                Map => {
+                    if let Some(map) = skip_malformed(attr.rta_type(), attr.get_payload_as()) {
+                        link_builder.map(map);
                     }
-                    MaxMtu => {
-                        link_builder.max_mtu(
-                            attr.get_payload_as::<u32>()
-                                .expect("MaxMtu to be a valid u32"),
-                        );
+                }
+                Weight => {
+                    link_builder.weight(skip_malformed(
+                        attr.rta_type(),
+                        attr.get_payload_as::<u32>(),
+                    ));
+                }
+                Operstate => {
+                    if let Some(operstate) =
+                        skip_malformed(attr.rta_type(), attr.get_payload_as::<u8>())
+                    {
+                        link_builder.operstate(operstate);
                     }
-                    PropList => {
-                        log::warn!(
-                            "IFLA_PROP_LIST is a nested attribute, parsing is not implemented"
-                        );
+                }
+                Linkmode => {
+                    if let Some(linkmode) =
+                        skip_malformed(attr.rta_type(), attr.get_payload_as::<u8>())
+                    {
+                        link_builder.linkmode(linkmode);
                     }
-                    AltIfname => {
-                        link_builder.alt_ifname(Some(
-                            attr.get_payload_as_with_len::<String>()
-                                .expect("AltIfname to be a valid string"),
-                        ));
+                }
+                Linkinfo => {
+                    // Only the kind is parsed, IFLA_INFO_DATA differs for every kind
+                    let Ok(link_info) = attr.get_attr_handle::<IflaInfo>() else {
+                        log::warn!("IFLA_LINKINFO could not be parsed");
+                        continue;
+                    };
+                    for attr in link_info.iter() {
+                        if *attr.rta_type() == IflaInfo::Kind {
+                            link_builder.kind(attr.get_payload_as_with_len::<String>().ok());
+                        }
                     }
-                    PermAddress => {
-                        link_builder.perm_address(
-                            Some( attr.get_payload_as()
-                                .expect("PermAddress to be a valid mac address"), )
-                        );
+                }
+                NetNsPid => {
+                    link_builder.net_ns_pid(skip_malformed(
+                        attr.rta_type(),
+                        attr.get_payload_as::<u32>(),
+                    ));
+                }
+                Ifalias => {
+                    link_builder.ifalias(skip_malformed(
+                        attr.rta_type(),
+                        attr.get_payload_as_with_len::<String>(),
+                    ));
+                }
+                NumVf => {
+                    link_builder.num_vf(skip_malformed(
+                        attr.rta_type(),
+                        attr.get_payload_as::<u32>(),
+                    ));
+                }
+                VfinfoList => {
+                    log::warn!(
+                        "IFLA_VFINFO_LIST is a nested attribute, parsing is not implemented"
+                    );
+                }
+                Stats64 => {
+                    if let Some(stats64) = skip_malformed(attr.rta_type(), attr.get_payload_as()) {
+                        link_builder.stats64(stats64);
                     }
-                    ProtoDownReason => {
-                        log::warn!(
-                            "IFLA_PROTODOWN_REASON is a nested attribute, parsing is not implemented"
-                        );
+                }
+                VfPorts => {
+                    log::warn!("IFLA_VF_PORTS is a nested attribute, parsing is not implemented");
+                }
+                PortSelf => {
+                    log::warn!("IFLA_PORT_SELF is a nested attribute, parsing is not implemented");
+                }
+                AfSpec => {
+                    log::warn!("IFLA_AF_SPEC is a nested attribute, parsing is not implemented");
+                }
+                Group => {
+                    if let Some(group) =
+                        skip_malformed(attr.rta_type(), attr.get_payload_as::<u32>())
+                    {
+                        link_builder.group(group);
                     }
-                    IflaDevlinkPort => {
-                        log::warn!(
-                            "IFLA_DEVLINK_PORT is a nested attribute, parsing is not implemented"
-                        );
+                }
+                NetNsFd => {
+                    link_builder.net_ns_fd(skip_malformed(
+                        attr.rta_type(),
+                        attr.get_payload_as::<u32>(),
+                    ));
+                }
+                ExtMask => {
+                    log::debug!("Skipping IFLA_EXT_MASK attribute");
+                }
+                Promiscuity => {
+                    if let Some(promiscuity) =
+                        skip_malformed(attr.rta_type(), attr.get_payload_as::<u32>())
+                    {
+                        link_builder.promiscuity(promiscuity);
                     }
-                    IflaGsoIpv4MaxSize => {
-                        link_builder.gso_ipv4_max_size(Some(
-                            attr.get_payload_as::<u32>()
-                                .expect("IflaGsoIpv4MaxSize to be a valid u32"),
-                        ));
+                }
+                NumTxQueues => {
+                    if let Some(num_tx_queues) =
+                        skip_malformed(attr.rta_type(), attr.get_payload_as::<u32>())
+                    {
+                        link_builder.num_tx_queues(num_tx_queues);
                     }
-                    IflaGroIpv4MaxSize => {
-                        link_builder.gro_ipv4_max_size(Some(
-                            attr.get_payload_as::<u32>()
-                                .expect("IflaGroIpv4MaxSize to be a valid u32"),
-                        ));
+                }
+                NumRxQueues => {
+                    if let Some(num_rx_queues) =
+                        skip_malformed(attr.rta_type(), attr.get_payload_as::<u32>())
+                    {
+                        link_builder.num_rx_queues(num_rx_queues);
                     }
-                    IflaDpllPin => {
-                        log::warn!("IFLA_DPLL_PIN parsing is not implemented");
+                }
+                Carrier => {
+                    if let Some(carrier) =
+                        skip_malformed(attr.rta_type(), attr.get_payload_as::<u8>())
+                    {
+                        link_builder.carrier(carrier);
                     }
-                    IflaMaxPacingOffloadHorizon => {
-                        log::warn!("IFLA_MAX_PACING_OFFLOAD_HORIZON parsing is not implemented");
+                }
+                PhysPortId => {
+                    log::debug!("Skipping IFLA_PHYS_PORT_ID attribute");
+                }
+                CarrierChanges => {
+                    if let Some(carrier_changes) =
+                        skip_malformed(attr.rta_type(), attr.get_payload_as::<u32>())
+                    {
+                        link_builder.carrier_changes(carrier_changes);
                     }
-                    IflaNetnsImmutable => {
-                        log::warn!("IFLA_NETNS_IMMUTABLE parsing is not implemented");
+                }
+                PhysSwitchId => {
+                    log::debug!("Skipping IFLA_PHYS_SWITCH_ID attribute");
+                }
+                LinkNetnsid => {
+                    link_builder.link_netnsid(skip_malformed(
+                        attr.rta_type(),
+                        attr.get_payload_as::<i32>(),
+                    ));
+                }
+                PhysPortName => {
+                    link_builder.phys_port_name(skip_malformed(
+                        attr.rta_type(),
+                        attr.get_payload_as_with_len::<String>(),
+                    ));
+                }
+                ProtoDown => {
+                    if let Some(proto_down) =
+                        skip_malformed(attr.rta_type(), attr.get_payload_as::<u8>())
+                    {
+                        link_builder.proto_down(proto_down);
                     }
-                    IflaParentDevName => {
-                        link_builder.parent_dev_name(Some(
-                            attr.get_payload_as_with_len::<String>()
-                                .expect("IflaParentDevName to be a valid String"),
-                        ));
+                }
+                GsoMaxSegs => {
+                    if let Some(gso_max_segs) =
+                        skip_malformed(attr.rta_type(), attr.get_payload_as::<u32>())
+                    {
+                        link_builder.gso_max_segs(gso_max_segs);
                     }
-                    IflaParentDevBusName => {
-                        link_builder.parent_dev_bus_name(Some(
-                            attr.get_payload_as_with_len::<String>()
-                                .expect("IflaParentDevBusName to be a valid String"),
-                        ));
+                }
+                GsoMaxSize => {
+                    if let Some(gso_max_size) =
+                        skip_malformed(attr.rta_type(), attr.get_payload_as::<u32>())
+                    {
+                        link_builder.gso_max_size(gso_max_size);
                     }
-                    IflaGroMaxSize => {
-                        link_builder.gro_max_size(Some(
-                            attr.get_payload_as::<u32>()
-                                .expect("IflaGroMaxSize to be a valid u32"),
-                        ));
+                }
+                Pad => { /* Padding attribute, ignored */ }
+                Xdp => {
+                    log::warn!("IFLA_XDP is a nested attribute, parsing is not implemented");
+                }
+                Event => {
+                    link_builder.event(skip_malformed(
+                        attr.rta_type(),
+                        attr.get_payload_as::<u32>(),
+                    ));
+                }
+                NewNetnsid => {
+                    link_builder.new_netnsid(skip_malformed(
+                        attr.rta_type(),
+                        attr.get_payload_as::<i32>(),
+                    ));
+                }
+                IfNetnsid => {
+                    link_builder.target_netnsid(skip_malformed(
+                        attr.rta_type(),
+                        attr.get_payload_as::<i32>(),
+                    ));
+                }
+                CarrierUpCount => {
+                    if let Some(carrier_up_count) =
+                        skip_malformed(attr.rta_type(), attr.get_payload_as::<u32>())
+                    {
+                        link_builder.carrier_up_count(carrier_up_count);
                     }
-                    IflaTsoMaxSize => {
-                        link_builder.tso_max_size(Some(
-                            attr.get_payload_as::<u32>()
-                                .expect("IflaTsoMaxSize to be a valid u32"),
-                        ));
+                }
+                CarrierDownCount => {
+                    if let Some(carrier_down_count) =
+                        skip_malformed(attr.rta_type(), attr.get_payload_as::<u32>())
+                    {
+                        link_builder.carrier_down_count(carrier_down_count);
                     }
-                    IflaTsoMaxSegs => {
-                        link_builder.tso_max_segs(Some(
-                            attr.get_payload_as::<u32>()
-                                .expect("IflaTsoMaxSegs to be a valid u32"),
-                        ));
+                }
+                NewIfindex => {
+                    link_builder.new_ifindex(skip_malformed(
+                        attr.rta_type(),
+                        attr.get_payload_as::<i32>(),
+                    ));
+                }
+                MinMtu => {
+                    if let Some(min_mtu) =
+                        skip_malformed(attr.rta_type(), attr.get_payload_as::<u32>())
+                    {
+                        link_builder.min_mtu(min_mtu);
                     }
-                    IflaAllmulti => {
-                        link_builder.allmulti(Some(
-                            attr.get_payload_as::<u32>()
-                                .expect("IflaAllmulti to be a valid u32"),
-                        ));
+                }
+                MaxMtu => {
+                    if let Some(max_mtu) =
+                        skip_malformed(attr.rta_type(), attr.get_payload_as::<u32>())
+                    {
+                        link_builder.max_mtu(max_mtu);
                     }
                 }
-            }
-            match link_builder.build() {
-                Ok(link) => {
-                    links.push(link);
+                PropList => {
+                    log::warn!("IFLA_PROP_LIST is a nested attribute, parsing is not implemented");
+                }
+                AltIfname => {
+                    link_builder.alt_ifname(skip_malformed(
+                        attr.rta_type(),
+                        attr.get_payload_as_with_len::<String>(),
+                    ));
+                }
+                PermAddress => {
+                    link_builder
+                        .perm_address(skip_malformed(attr.rta_type(), attr.get_payload_as()));
+                }
+                ProtoDownReason => {
+                    log::warn!(
+                        "IFLA_PROTODOWN_REASON is a nested attribute, parsing is not implemented"
+                    );
+                }
+                IflaDevlinkPort => {
+                    log::warn!(
+                        "IFLA_DEVLINK_PORT is a nested attribute, parsing is not implemented"
+                    );
+                }
+                IflaGsoIpv4MaxSize => {
+                    link_builder.gso_ipv4_max_size(skip_malformed(
+                        attr.rta_type(),
+                        attr.get_payload_as::<u32>(),
+                    ));
                 }
-                Err(e) => {
-                    log::error!("{e:?}")
+                IflaGroIpv4MaxSize => {
+                    link_builder.gro_ipv4_max_size(skip_malformed(
+                        attr.rta_type(),
+                        attr.get_payload_as::<u32>(),
+                    ));
+                }
+                IflaDpllPin => {
+                    log::warn!("IFLA_DPLL_PIN parsing is not implemented");
+                }
+                IflaMaxPacingOffloadHorizon => {
+                    log::warn!("IFLA_MAX_PACING_OFFLOAD_HORIZON parsing is not implemented");
+                }
+                IflaNetnsImmutable => {
+                    log::warn!("IFLA_NETNS_IMMUTABLE parsing is not implemented");
+                }
+                IflaParentDevName => {
+                    link_builder.parent_dev_name(skip_malformed(
+                        attr.rta_type(),
+                        attr.get_payload_as_with_len::<String>(),
+                    ));
+                }
+                IflaParentDevBusName => {
+                    link_builder.parent_dev_bus_name(skip_malformed(
+                        attr.rta_type(),
+                        attr.get_payload_as_with_len::<String>(),
+                    ));
+                }
+                IflaGroMaxSize => {
+                    link_builder.gro_max_size(skip_malformed(
+                        attr.rta_type(),
+                        attr.get_payload_as::<u32>(),
+                    ));
+                }
+                IflaTsoMaxSize => {
+                    link_builder.tso_max_size(skip_malformed(
+                        attr.rta_type(),
+                        attr.get_payload_as::<u32>(),
+                    ));
+                }
+                IflaTsoMaxSegs => {
+                    link_builder.tso_max_segs(skip_malformed(
+                        attr.rta_type(),
+                        attr.get_payload_as::<u32>(),
+                    ));
+                }
+                IflaAllmulti => {
+                    link_builder.allmulti(skip_malformed(
+                        attr.rta_type(),
+                        attr.get_payload_as::<u32>(),
+                    ));
                 }
             }
         }
+        link_builder.build()
+    }
+}
+
+impl NetlinkRetrievable<RoutelinkInfoError> for LinkInfo {
+    async fn retrieve(netlink: &Netlink) -> Result<Vec<Self>, RoutelinkInfoError> {
+        let mut recv = netlink
+            .rtnl
+            .send::<_, _, Rtm, ()>(
+                Rtm::Getlink,
+                NlmF::DUMP | NlmF::ACK,
+                neli::nl::NlPayload::Payload(
+                    IfinfomsgBuilder::default()
+                        .ifi_family(RtAddrFamily::Inet)
+                        .build()?,
+                ),
+            )
+            .await?;
+        let mut links = Vec::new();
+        while let Some(response) = recv.next::<Rtm, Ifinfomsg>().await {
+            let response = response?;
+            let payload = {
+                match response.nl_payload() {
+                    NlPayload::Payload(x) => x,
+                    _ => {
+                        continue;
+                    }
+                }
+            };
+
+            match Self::parse(payload) {
+                Ok(link) => links.push(link),
+                Err(e) => log::error!("Skipping link {}: {e:?}", payload.ifi_index()),
+            }
+        }
         Ok(links)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use neli::{
+        consts::rtnl::Ifla,
+        rtnl::RtattrBuilder,
+        types::{Buffer, RtBuffer},
+    };
+
+    use super::*;
+
+    fn message(attrs: Vec<(Ifla, Vec<u8>)>) -> Ifinfomsg {
+        let rtattrs = attrs
+            .into_iter()
+            .map(|(rta_type, payload)| {
+                RtattrBuilder::default()
+                    .rta_type(rta_type)
+                    .rta_payload(Buffer::from(payload))
+                    .build()
+                    .expect("The attribute to build")
+            })
+            .collect::<RtBuffer<_, _>>();
+        IfinfomsgBuilder::default()
+            .ifi_family(RtAddrFamily::Unspecified)
+            .ifi_index(2)
+            .rtattrs(rtattrs)
+            .build()
+            .expect("The message to build")
+    }
+
+    fn u32_payload(value: u32) -> Vec<u8> {
+        value.to_ne_bytes().to_vec()
+    }
+
+    /// The attributes the kernel sends for every link
+    fn complete_link() -> Vec<(Ifla, Vec<u8>)> {
+        vec![
+            (Ifla::Ifname, b"eth0\0".to_vec()),
+            (Ifla::Mtu, u32_payload(1500)),
+            (Ifla::Qdisc, b"fq_codel\0".to_vec()),
+            (Ifla::Stats, vec![0; 24 * 4]),
+            (Ifla::Txqlen, u32_payload(1000)),
+            (Ifla::Map, vec![0; 3 * 8 + 2 + 1 + 1]),
+            (Ifla::Operstate, vec![6]),
+            (Ifla::Linkmode, vec![0]),
+            (Ifla::Stats64, vec![0; 25 * 8]),
+            (Ifla::Group, u32_payload(0)),
+            (Ifla::Promiscuity, u32_payload(0)),
+            (Ifla::NumTxQueues, u32_payload(1)),
+            (Ifla::NumRxQueues, u32_payload(1)),
+            (Ifla::Carrier, vec![1]),
+            (Ifla::CarrierChanges, u32_payload(2)),
+            (Ifla::ProtoDown, vec![0]),
+            (Ifla::GsoMaxSegs, u32_payload(65535)),
+            (Ifla::GsoMaxSize, u32_payload(65536)),
+            (Ifla::CarrierUpCount, u32_payload(1)),
+            (Ifla::CarrierDownCount, u32_payload(1)),
+            (Ifla::MinMtu, u32_payload(68)),
+            (Ifla::MaxMtu, u32_payload(9000)),
+        ]
+    }
+
+    #[test]
+    fn parses_complete_link() {
+        let link = LinkInfo::parse(&message(complete_link())).expect("The link to parse");
+        assert_eq!(link.ifi_index, 2);
+        assert_eq!(link.ifname, "eth0");
+        assert_eq!(link.mtu, 1500);
+        assert!(link.address.is_none());
+    }
+
+    #[test]
+    fn malformed_optional_attributes_are_left_out() {
+        let mut attrs = complete_link();
+        // A MAC address one byte short, and a u32 cut to a byte
+        attrs.push((Ifla::Address, vec![0x02, 0, 0, 0, 0]));
+        attrs.push((Ifla::Link, vec![1]));
+        attrs.push((Ifla::Ifalias, vec![0xff, 0xfe, 0]));
+        let link = LinkInfo::parse(&message(attrs)).expect("The link to parse");
+        assert!(link.address.is_none());
+        assert!(link.link.is_none());
+        assert!(link.ifalias.is_none());
+        assert_eq!(link.mtu, 1500);
+    }
+
+    #[test]
+    fn malformed_required_attribute_skips_link() {
+        let attrs = complete_link()
+            .into_iter()
+            .map(|(rta_type, payload)| match rta_type {
+                Ifla::Mtu => (rta_type, vec![0xdc, 0x05]),
+                _ => (rta_type, payload),
+            })
+            .collect();
+        assert!(LinkInfo::parse(&message(attrs)).is_err());
+    }
+
+    #[test]
+    fn empty_and_truncated_payloads_do_not_panic() {
+        for rta_type in [
+            Ifla::Ifname,
+            Ifla::Stats,
+            Ifla::Stats64,
+            Ifla::Map,
+            Ifla::Linkinfo,
+        ] {
+            for payload in [vec![], vec![0x01], vec![0xff; 7]] {
+                let _ = LinkInfo::parse(&message(vec![(rta_type, payload)]));
+            }
+        }
+    }
+}
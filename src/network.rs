@@ -2,13 +2,20 @@ use std::time::Duration;
 
 use tokio::sync::mpsc::channel;
 use tokio::sync::mpsc::error::SendError;
-use tokio::{runtime::Handle, sync::mpsc::Sender};
+use tokio::{
+    runtime::Handle,
+    sync::{mpsc::Sender, watch},
+};
 
+use crate::format::display_bytes;
 use crate::netlink::ethtool::EthtoolPhy;
-use crate::netlink::nl80211::Nl80211Interface;
+use crate::netlink::nl80211::{Nl80211Interface, Nl80211Station};
 use crate::netlink::routel::LinkInfo;
-use crate::netlink::{Netlink, NetlinkCommandError, NetlinkInitError};
-use crate::state::Message;
+use crate::netlink::{MacAddr, Netlink, NetlinkCommandError, NetlinkInitError};
+use crate::renderer::Renderable;
+use crate::state::{Message, State};
+use crate::supervisor::{Backoff, supervise_watched};
+use crate::widget::Widget;
 
 #[derive(Debug, Clone)]
 pub enum Network {
@@ -16,6 +23,14 @@ pub enum Network {
         if_index: i32,
         if_name: String,
         ssid: Option<String>,
+        /// Of the access point, None while not associated
+        bssid: Option<MacAddr>,
+        /// In dBm
+        signal: Option<i8>,
+        /// In units of 100 kbit/s
+        tx_bitrate: Option<u32>,
+        /// In units of 100 kbit/s
+        rx_bitrate: Option<u32>,
         up: u64,
         down: u64,
         up_rate: u64,
@@ -29,12 +44,33 @@ pub enum Network {
         up_rate: u64,
         down_rate: u64,
     },
+    /// A WireGuard or tun link, what VPNs tunnel through
+    Vpn {
+        if_index: i32,
+        name: String,
+        /// The link kind, wireguard or tun
+        kind: String,
+        /// Whether the link is up with a carrier, which a tun only has
+        /// while the VPN client holds it open
+        connected: bool,
+        up: u64,
+        down: u64,
+        up_rate: u64,
+        down_rate: u64,
+    },
 }
 
+/// Link kinds counted as VPN tunnels
+const VPN_KINDS: [&str; 2] = ["wireguard", "tun"];
+
+/// IF_OPER_DOWN from include/uapi/linux/if.h
+const IF_OPER_DOWN: u8 = 2;
+
 impl Network {
     fn from_linkinfo(
         link_info: Vec<LinkInfo>,
         wifi_interfaces: Vec<Nl80211Interface>,
+        stations: Vec<Nl80211Station>,
         _ethtool_interfaces: Vec<EthtoolPhy>,
         prev_link_info: Vec<Self>,
         interval: Duration,
@@ -47,6 +83,10 @@ impl Network {
                         if_index,
                         if_name: _,
                         ssid: _,
+                        bssid: _,
+                        signal: _,
+                        tx_bitrate: _,
+                        rx_bitrate: _,
                         up,
                         down,
                         up_rate: _,
@@ -72,15 +112,38 @@ impl Network {
                             None
                         }
                     }
+                    Network::Vpn {
+                        if_index,
+                        name: _,
+                        kind: _,
+                        connected: _,
+                        up,
+                        down,
+                        up_rate: _,
+                        down_rate: _,
+                    } => {
+                        if *if_index == link.ifi_index {
+                            Some((up, down))
+                        } else {
+                            None
+                        }
+                    }
                 });
                 if let Some(wifi_interface) = wifi_interfaces
                     .iter()
                     .find(|iface| iface.if_index as i32 == link.ifi_index)
                 {
+                    let station = stations
+                        .iter()
+                        .find(|station| station.if_index == wifi_interface.if_index);
                     Self::Wifi {
                         if_index: link.ifi_index,
                         if_name: link.ifname,
                         ssid: wifi_interface.ssid.clone(),
+                        bssid: station.map(|station| station.bssid.clone()),
+                        signal: station.and_then(|station| station.signal),
+                        tx_bitrate: station.and_then(|station| station.tx_bitrate),
+                        rx_bitrate: station.and_then(|station| station.rx_bitrate),
                         up: link.stats64.tx_bytes,
                         down: link.stats64.rx_bytes,
                         up_rate: prev_link_stats
@@ -95,6 +158,29 @@ impl Network {
                             })
                             .unwrap_or_default(),
                     }
+                } else if let Some(kind) = link
+                    .kind
+                    .clone()
+                    .filter(|kind| VPN_KINDS.contains(&kind.as_str()))
+                {
+                    Self::Vpn {
+                        if_index: link.ifi_index,
+                        name: link.ifname,
+                        kind,
+                        connected: link.carrier == 1 && link.operstate != IF_OPER_DOWN,
+                        up: link.stats64.tx_bytes,
+                        down: link.stats64.rx_bytes,
+                        up_rate: prev_link_stats
+                            .map(|(prev_up, _)| {
+                                (link.stats64.tx_bytes.saturating_sub(*prev_up)) / interval.as_secs()
+                            })
+                            .unwrap_or_default(),
+                        down_rate: prev_link_stats
+                            .map(|(_, prev_down)| {
+                                (link.stats64.rx_bytes.saturating_sub(*prev_down)) / interval.as_secs()
+                            })
+                            .unwrap_or_default(),
+                    }
                 } else {
                     Self::Network {
                         if_index: link.ifi_index,
@@ -127,6 +213,16 @@ pub enum NetworkError {
     SendError(SendError<Message>),
 }
 
+impl std::fmt::Display for NetworkError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NetworkError::NetlinkInitError(e) => write!(f, "{e}"),
+            NetworkError::NetlinkCommandError(e) => write!(f, "{e}"),
+            NetworkError::SendError(e) => write!(f, "Channel Error: {e}"),
+        }
+    }
+}
+
 impl From<NetlinkInitError> for NetworkError {
     fn from(value: NetlinkInitError) -> Self {
         Self::NetlinkInitError(value)
@@ -145,13 +241,29 @@ impl From<SendError<Message>> for NetworkError {
     }
 }
 
-async fn network_generator(sender: Sender<Message>) -> Result<(), NetworkError> {
+async fn network_generator(
+    sender: Sender<Message>,
+    mut resumes: watch::Receiver<()>,
+) -> Result<(), NetworkError> {
     let netlink = Netlink::connect().await?;
     let mut interval = tokio::time::interval(tokio::time::Duration::from_secs(1));
     let mut prev_instant = interval.tick().await;
     let mut prev_link_info = Vec::new();
+    resumes.mark_unchanged();
     loop {
-        let new_instant = interval.tick().await;
+        let new_instant = tokio::select! {
+            instant = interval.tick() => instant,
+            // The counters went on while the clock stood still, so rates
+            // across the suspend would be made up. Shown right away at 0
+            Ok(()) = resumes.changed() => {
+                // Taken before the reset, so the next tick is at least a
+                // second later
+                let now = tokio::time::Instant::now();
+                prev_link_info.clear();
+                interval.reset();
+                now
+            }
+        };
         let duration = new_instant - prev_instant;
         prev_instant = new_instant;
 
@@ -159,6 +271,7 @@ async fn network_generator(sender: Sender<Message>) -> Result<(), NetworkError>
             netlink.retrieve().await?,
             netlink.retrieve().await?,
             netlink.retrieve().await?,
+            netlink.retrieve().await?,
             prev_link_info.clone(),
             duration,
         );
@@ -167,16 +280,203 @@ async fn network_generator(sender: Sender<Message>) -> Result<(), NetworkError>
     }
 }
 
+/// The rates come every second, so this long without them netlink hung
+const WATCHDOG_DEADLINE: Duration = Duration::from_secs(30);
+
 // TODO: USE NOTIFICATIONS INSTEAD OF TIMER
-pub fn network_subscription(rt: Handle) -> tokio_stream::wrappers::ReceiverStream<Message> {
+/// Starts the rates over whenever resumes changes
+pub fn network_subscription(
+    rt: Handle,
+    resumes: watch::Receiver<()>,
+) -> tokio_stream::wrappers::ReceiverStream<Message> {
     let (sender, receiver) = channel(1);
-    rt.clone().spawn(async move {
-        loop {
-            log::error!(
-                "Network event loop returned, this should never happen, trying to reconnect {:?}",
-                network_generator(sender.clone()).await
-            );
-        }
-    });
+    supervise_watched(
+        &rt,
+        "network",
+        Backoff::DEFAULT,
+        WATCHDOG_DEADLINE,
+        sender,
+        move |sender| network_generator(sender, resumes.clone()),
+    );
     tokio_stream::wrappers::ReceiverStream::new(receiver)
 }
+
+/// Bars of the Wi-Fi signal strength
+const SIGNAL_BARS: u32 = 4;
+
+/// The transfer rates of each network, with the signal of Wi-Fi
+pub const WIDGET: Widget = Widget {
+    name: "network",
+    render,
+};
+
+fn render(state: &State) -> Vec<Renderable> {
+    let style = state.config.theme.style("network");
+    let mut module = Vec::new();
+    for network in state.networks.iter() {
+        if matches!(network, Network::Network { name, .. } if name == "lo")
+            || matches!(
+                network,
+                Network::Vpn {
+                    connected: false,
+                    ..
+                }
+            )
+        {
+            continue;
+        }
+        if !module.is_empty() {
+            module.push(Renderable::Space(1.));
+        }
+        match network {
+            Network::Wifi {
+                if_index: _,
+                if_name,
+                ssid,
+                bssid,
+                signal,
+                tx_bitrate,
+                rx_bitrate,
+                up,
+                down,
+                up_rate,
+                down_rate,
+            } => {
+                let mut tooltip =
+                    format!("{} on {if_name}", ssid.as_deref().unwrap_or("No network"));
+                if let Some(bssid) = bssid {
+                    tooltip += &format!(" via {bssid}");
+                }
+                if let Some(signal) = signal {
+                    tooltip += &format!("\nSignal {signal} dBm");
+                }
+                // Bitrates come in units of 100 kbit/s
+                if let (Some(tx_bitrate), Some(rx_bitrate)) = (tx_bitrate, rx_bitrate) {
+                    tooltip += &format!(
+                        "\nLink {:.1}↓ {:.1}↑ Mbit/s",
+                        *rx_bitrate as f32 / 10.,
+                        *tx_bitrate as f32 / 10.
+                    );
+                }
+                tooltip += &format!("\n{}", display_transferred(*up, *down));
+                let mut children = Vec::new();
+                if let Some(signal) = signal {
+                    let bars = signal_bars(*signal);
+                    let level = if bars <= 1 {
+                        style.warning
+                    } else {
+                        style.accent
+                    };
+                    for bar in 1..=SIGNAL_BARS {
+                        let color = if bar <= bars { level } else { style.dim };
+                        children.push(Renderable::Box {
+                            fg: color,
+                            bg: color,
+                            width: 0.2,
+                            height: bar as f32 / SIGNAL_BARS as f32,
+                            skip: 0.3,
+                        });
+                    }
+                    children.push(Renderable::Space(0.3));
+                }
+                children.push(Renderable::Text {
+                    text: format!(
+                        "{} {}↓ {}↑",
+                        if let Some(ssid) = ssid { ssid } else { "" }.to_string(),
+                        display_bytes(*up_rate) + "/s",
+                        display_bytes(*down_rate) + "/s",
+                    )
+                    .into(),
+                    fg: style.fg,
+                    bg: style.bg,
+                });
+                module.push(Renderable::Region {
+                    tooltip: Some(tooltip),
+                    on_click: None,
+                    on_scroll: None,
+                    children,
+                });
+            }
+            Network::Network {
+                if_index: _,
+                name,
+                up,
+                down,
+                up_rate,
+                down_rate,
+            } => {
+                module.push(Renderable::Region {
+                    tooltip: Some(format!("{name}\n{}", display_transferred(*up, *down))),
+                    on_click: None,
+                    on_scroll: None,
+                    children: vec![Renderable::Text {
+                        text: format!(
+                            "{} {}↓ {}↑",
+                            name,
+                            display_bytes(*up_rate) + "/s",
+                            display_bytes(*down_rate) + "/s",
+                        )
+                        .into(),
+                        fg: style.fg,
+                        bg: style.bg,
+                    }],
+                });
+            }
+            Network::Vpn {
+                if_index: _,
+                name,
+                kind,
+                connected: _,
+                up,
+                down,
+                up_rate,
+                down_rate,
+            } => {
+                // A tunnel carrying nothing may well have lost its peer
+                let active = up_rate + down_rate > 0;
+                module.push(Renderable::Region {
+                    tooltip: Some(format!(
+                        "{name} ({kind}){}\n{}",
+                        if active { "" } else { ", idle" },
+                        display_transferred(*up, *down)
+                    )),
+                    on_click: None,
+                    on_scroll: None,
+                    children: vec![
+                        // A lock from the nerd fonts
+                        Renderable::Text {
+                            text: "\u{f033e} ".into(),
+                            fg: if active { style.good } else { style.dim },
+                            bg: style.bg,
+                        },
+                        Renderable::Text {
+                            text: name.clone().into(),
+                            fg: style.fg,
+                            bg: style.bg,
+                        },
+                    ],
+                });
+            }
+        }
+    }
+    module
+}
+
+/// Bars lit for a signal in dBm, the thresholds being roughly what phones use
+fn signal_bars(signal: i8) -> u32 {
+    match signal {
+        -55.. => 4,
+        -67..=-56 => 3,
+        -75..=-68 => 2,
+        -85..=-76 => 1,
+        _ => 0,
+    }
+}
+
+fn display_transferred(up: u64, down: u64) -> String {
+    format!(
+        "{} received, {} sent",
+        display_bytes(down).trim_start(),
+        display_bytes(up).trim_start()
+    )
+}
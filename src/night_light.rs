@@ -0,0 +1,349 @@
+use std::{
+    process::{Child, Command, Stdio},
+    sync::{
+        Arc, Mutex, PoisonError,
+        atomic::{AtomicU64, Ordering},
+    },
+    thread,
+    time::Duration,
+};
+
+use serde::Deserialize;
+
+use crate::{
+    action::{Action, ScrollActions},
+    config::{NightLightBackend, NightLightConfig},
+    renderer::Renderable,
+    state::State,
+    widget::Widget,
+};
+
+/// What clicks and scrolling do to the night light module
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NightLightAction {
+    Toggle,
+    /// Changes the temperature by this many kelvin, warmer when negative
+    Adjust(i32),
+}
+
+/// How long scrolling has to pause before the command starts again at the
+/// new temperature, rather than once per step
+const ADJUST_DEBOUNCE: Duration = Duration::from_millis(300);
+
+#[derive(Debug, Clone)]
+pub struct NightLight {
+    backend: NightLightBackend,
+    /// In kelvin, what the command backend starts the command with
+    pub temperature: u32,
+    /// Whether the daemon of the signal backend was last toggled on. It
+    /// can't be asked, so it is taken to be on if it runs at startup
+    signalled_on: bool,
+    /// The running command of the command backend. Shared as the state is
+    /// cloned, there is still only one
+    child: Arc<Mutex<Running>>,
+    /// Counts the scroll steps and toggles, a restart waiting out
+    /// ADJUST_DEBOUNCE only goes ahead if nothing came after its step
+    changes: Arc<AtomicU64>,
+}
+
+/// The command of the command backend, killed once the last clone of the
+/// night light is gone so it doesn't outlive the bar
+#[derive(Debug, Default)]
+struct Running(Option<Child>);
+
+impl Drop for Running {
+    fn drop(&mut self) {
+        if let Some(mut child) = self.0.take() {
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+    }
+}
+
+/// Whether a process of that name runs
+fn running(process: &str) -> bool {
+    Command::new("pgrep")
+        .args(["-x", process])
+        .stdout(Stdio::null())
+        .status()
+        .is_ok_and(|status| status.success())
+}
+
+/// The temperature a scroll by kelvin gets to, within the configured range
+fn adjusted(temperature: u32, kelvin: i32, config: &NightLightConfig) -> u32 {
+    let (min, max) = (
+        config.min_temperature,
+        config.max_temperature.max(config.min_temperature),
+    );
+    (temperature as i64 + kelvin as i64).clamp(min as i64, max as i64) as u32
+}
+
+impl NightLight {
+    pub fn new(config: &NightLightConfig) -> Self {
+        Self {
+            backend: config.backend,
+            temperature: config.temperature,
+            signalled_on: config.backend == NightLightBackend::Signal && running(&config.process),
+            child: Arc::new(Mutex::new(Running::default())),
+            changes: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    pub fn backend(&self) -> NightLightBackend {
+        self.backend
+    }
+
+    pub fn on(&self) -> bool {
+        match self.backend {
+            NightLightBackend::Command => {
+                let mut child = self.child.lock().unwrap_or_else(PoisonError::into_inner);
+                // Exited on its own, like a command that isn't installed
+                if let Some(command) = &mut child.0
+                    && let Ok(Some(status)) = command.try_wait()
+                {
+                    log::warn!("The night light exited with {status}");
+                    child.0 = None;
+                }
+                child.0.is_some()
+            }
+            NightLightBackend::Signal => self.signalled_on,
+        }
+    }
+
+    pub fn run(&mut self, action: NightLightAction, config: &NightLightConfig) {
+        match (action, self.backend) {
+            (NightLightAction::Toggle, NightLightBackend::Command) => {
+                self.changes.fetch_add(1, Ordering::Relaxed);
+                if self.stop().is_none() {
+                    self.start(&config.command);
+                }
+            }
+            (NightLightAction::Toggle, NightLightBackend::Signal) => {
+                // Both gammastep and wlsunset toggle on SIGUSR1. pkill fails
+                // when no process matched, which toggled nothing
+                let signalled = Command::new("pkill")
+                    .args(["-USR1", "-x", &config.process])
+                    .status();
+                match signalled {
+                    Ok(status) if status.success() => self.signalled_on = !self.signalled_on,
+                    Ok(_) => log::warn!("No {:?} runs to toggle", config.process),
+                    Err(e) => log::error!("Could not signal {:?}: {e}", config.process),
+                }
+            }
+            (NightLightAction::Adjust(kelvin), NightLightBackend::Command) => {
+                self.temperature = adjusted(self.temperature, kelvin, config);
+                if !self.on() {
+                    return;
+                }
+                // Started again at the new temperature once scrolling pauses
+                let change = self.changes.fetch_add(1, Ordering::Relaxed) + 1;
+                let night_light = self.clone();
+                let command = config.command.clone();
+                thread::spawn(move || {
+                    thread::sleep(ADJUST_DEBOUNCE);
+                    if night_light.changes.load(Ordering::Relaxed) == change
+                        && night_light.stop().is_some()
+                    {
+                        night_light.start(&command);
+                    }
+                });
+            }
+            // Neither gammastep nor wlsunset can be told a temperature while
+            // running, which is why the module doesn't scroll for them
+            (NightLightAction::Adjust(_), NightLightBackend::Signal) => {
+                log::warn!(
+                    "The signal backend can't change the temperature of {:?}",
+                    config.process
+                );
+            }
+        }
+    }
+
+    fn start(&self, command: &str) {
+        let command = command.replace("{temperature}", &self.temperature.to_string());
+        // Exec'd so killing the child stops the command and not only sh
+        match Command::new("sh")
+            .arg("-c")
+            .arg(format!("exec {command}"))
+            .spawn()
+        {
+            Ok(child) => self.child.lock().unwrap_or_else(PoisonError::into_inner).0 = Some(child),
+            Err(e) => log::error!("Could not start the night light {command:?}: {e}"),
+        }
+    }
+
+    /// Stops the running command, None if there was none or it exited
+    fn stop(&self) -> Option<()> {
+        let mut child = self
+            .child
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .0
+            .take()?;
+        if let Ok(Some(_)) = child.try_wait() {
+            return None;
+        }
+        if let Err(e) = child.kill() {
+            log::error!("Could not stop the night light: {e}");
+        }
+        // Reap the child in the background so it doesn't stay a zombie
+        thread::spawn(move || child.wait());
+        Some(())
+    }
+}
+
+/// A moon while the night light is on. Clicking toggles it, scrolling
+/// changes the temperature the command runs at
+pub const WIDGET: Widget = Widget {
+    name: "night-light",
+    render,
+};
+
+fn render(state: &State) -> Vec<Renderable> {
+    let style = state.config.theme.style("night-light");
+    let config = &state.config.night_light;
+    let on = state.night_light.on();
+    let (icon, fg) = if on {
+        ("\u{f0594}", style.accent)
+    } else {
+        ("\u{f0599}", style.dim)
+    };
+    let (text, on_scroll) = match state.night_light.backend() {
+        NightLightBackend::Command => {
+            let step = config.step as i32;
+            (
+                format!("{icon} {}K", state.night_light.temperature),
+                Some(ScrollActions {
+                    up: Action::NightLight(NightLightAction::Adjust(step)),
+                    down: Action::NightLight(NightLightAction::Adjust(-step)),
+                    sensitivity: state.scroll_sensitivity("night-light"),
+                }),
+            )
+        }
+        NightLightBackend::Signal => (icon.to_string(), None),
+    };
+    let tooltip = match (on, on_scroll.is_some()) {
+        (true, true) => "Night light on, click to turn off, scroll to change the temperature",
+        (false, true) => "Night light off, click to turn on, scroll to change the temperature",
+        (true, false) => "Night light on, click to turn off",
+        (false, false) => "Night light off, click to turn on",
+    };
+    vec![Renderable::Region {
+        tooltip: Some(tooltip.to_string()),
+        on_click: Some(Action::NightLight(NightLightAction::Toggle)),
+        on_scroll,
+        children: vec![Renderable::Text {
+            text: text.into(),
+            fg,
+            bg: style.bg,
+        }],
+    }]
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use super::*;
+
+    fn config(backend: NightLightBackend, command: &str) -> NightLightConfig {
+        NightLightConfig {
+            backend,
+            command: command.to_string(),
+            process: "no-such-daemon".to_string(),
+            ..NightLightConfig::default()
+        }
+    }
+
+    #[test]
+    fn clamps_the_temperature() {
+        let config = NightLightConfig::default();
+        assert_eq!(adjusted(4500, 250, &config), 4750);
+        assert_eq!(adjusted(6400, 250, &config), 6500);
+        assert_eq!(adjusted(2600, -250, &config), 2500);
+        assert_eq!(adjusted(2500, i32::MIN, &config), 2500);
+        assert_eq!(adjusted(6500, i32::MAX, &config), 6500);
+        // A range the wrong way round ends up at the minimum
+        let inverted = NightLightConfig {
+            min_temperature: 5000,
+            max_temperature: 3000,
+            ..config
+        };
+        assert_eq!(adjusted(4500, 250, &inverted), 5000);
+    }
+
+    #[test]
+    fn toggles_the_command() {
+        let config = config(NightLightBackend::Command, "sleep 60");
+        let mut night_light = NightLight::new(&config);
+        assert!(!night_light.on());
+        night_light.run(NightLightAction::Toggle, &config);
+        assert!(night_light.on());
+        night_light.run(NightLightAction::Adjust(-250), &config);
+        assert!(night_light.on());
+        assert_eq!(night_light.temperature, 4250);
+        night_light.run(NightLightAction::Toggle, &config);
+        assert!(!night_light.on());
+        // Adjusting doesn't turn it on
+        night_light.run(NightLightAction::Adjust(250), &config);
+        assert!(!night_light.on());
+        assert_eq!(night_light.temperature, 4500);
+    }
+
+    fn pid(night_light: &NightLight) -> Option<u32> {
+        let child = night_light
+            .child
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        child.0.as_ref().map(Child::id)
+    }
+
+    #[test]
+    fn restarts_the_command_once_scrolling_pauses() {
+        let config = config(NightLightBackend::Command, "sleep 60");
+        let mut night_light = NightLight::new(&config);
+        night_light.run(NightLightAction::Toggle, &config);
+        let started = pid(&night_light);
+        for _ in 0..3 {
+            night_light.run(NightLightAction::Adjust(-250), &config);
+        }
+        assert_eq!(night_light.temperature, 3750);
+        assert_eq!(pid(&night_light), started);
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while pid(&night_light) == started && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(10));
+        }
+        let restarted = pid(&night_light);
+        assert!(restarted.is_some() && restarted != started);
+        // Only the last step restarted it
+        thread::sleep(ADJUST_DEBOUNCE * 2);
+        assert_eq!(pid(&night_light), restarted);
+    }
+
+    #[test]
+    fn notices_the_command_exiting() {
+        let config = config(NightLightBackend::Command, "true");
+        let mut night_light = NightLight::new(&config);
+        night_light.run(NightLightAction::Toggle, &config);
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while night_light.on() && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert!(!night_light.on());
+    }
+
+    #[test]
+    fn toggles_the_signal() {
+        let config = config(NightLightBackend::Signal, "");
+        let mut night_light = NightLight::new(&config);
+        // Nothing to signal runs, so it is off and stays off
+        assert!(!night_light.on());
+        night_light.run(NightLightAction::Toggle, &config);
+        assert!(!night_light.on());
+        // The daemon keeps its own temperature
+        night_light.run(NightLightAction::Adjust(250), &config);
+        assert!(!night_light.on());
+        assert_eq!(night_light.temperature, config.temperature);
+    }
+}
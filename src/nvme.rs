@@ -0,0 +1,255 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    io::ErrorKind,
+    process::Command,
+    string::FromUtf8Error,
+    time::Duration,
+};
+
+use tinyjson::JsonValue;
+use tokio::{
+    runtime::Handle,
+    sync::{
+        mpsc::{Sender, channel, error::SendError},
+        watch,
+    },
+};
+use tokio_stream::wrappers::ReceiverStream;
+
+use crate::{
+    config::NvmeConfig,
+    renderer::Renderable,
+    state::{Message, State},
+    supervisor::{Backoff, sleep_unless_resumed, supervise_blocking},
+    widget::Widget,
+};
+
+#[derive(Debug)]
+pub enum NvmeError {
+    StdIoError(std::io::Error),
+    Utf8Error(FromUtf8Error),
+    JsonError(tinyjson::JsonParseError),
+    /// smartctl printed JSON without a health log, with its messages
+    NoHealthLog(String),
+    SendError(SendError<Message>),
+}
+
+impl std::fmt::Display for NvmeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NvmeError::StdIoError(e) => write!(f, "IO Error: {e}"),
+            NvmeError::Utf8Error(e) => write!(f, "The output of smartctl is not UTF-8: {e}"),
+            NvmeError::JsonError(e) => write!(f, "JSON Error: {e}"),
+            NvmeError::NoHealthLog(messages) => {
+                write!(f, "smartctl printed no health log: {messages}")
+            }
+            NvmeError::SendError(e) => write!(f, "Channel Error: {e}"),
+        }
+    }
+}
+
+impl From<std::io::Error> for NvmeError {
+    fn from(value: std::io::Error) -> Self {
+        Self::StdIoError(value)
+    }
+}
+
+impl From<FromUtf8Error> for NvmeError {
+    fn from(value: FromUtf8Error) -> Self {
+        Self::Utf8Error(value)
+    }
+}
+
+impl From<tinyjson::JsonParseError> for NvmeError {
+    fn from(value: tinyjson::JsonParseError) -> Self {
+        Self::JsonError(value)
+    }
+}
+
+impl From<SendError<Message>> for NvmeError {
+    fn from(value: SendError<Message>) -> Self {
+        Self::SendError(value)
+    }
+}
+
+#[derive(Debug)]
+pub enum NvmeMessage {
+    Update(Vec<NvmeHealth>),
+}
+
+/// The SMART health log of an NVMe drive
+#[derive(Debug, Clone)]
+pub struct NvmeHealth {
+    /// Like nvme0
+    pub name: String,
+    pub model: Option<String>,
+    /// Estimate of the rated endurance used up, can go past 100
+    pub percentage_used: u32,
+    pub temperature: Option<f32>,
+    /// Percent of the spare blocks left
+    pub available_spare: Option<u32>,
+    pub media_errors: u64,
+    pub power_on_hours: Option<u64>,
+    /// Bits the drive sets on its own for failing spare, temperature,
+    /// reliability or read only media, 0 when all is well
+    pub critical_warning: u32,
+}
+
+/// Controllers, not namespaces, as the health log is per controller.
+/// Machines without NVMe drives have no /sys/class/nvme at all
+fn nvme_devices() -> Result<Vec<String>, NvmeError> {
+    let entries = match fs::read_dir("/sys/class/nvme") {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    let mut devices = entries
+        .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+        .collect::<Vec<_>>();
+    devices.sort();
+    Ok(devices)
+}
+
+fn parse_health(name: &str, json: &str) -> Result<NvmeHealth, NvmeError> {
+    let json: JsonValue = json.parse()?;
+    let root = json.get::<HashMap<String, JsonValue>>();
+    let field = |name: &str| root.and_then(|root| root.get(name));
+    let number = |value: Option<&JsonValue>| value.and_then(|v| v.get::<f64>()).copied();
+    let Some(log) = field("nvme_smart_health_information_log")
+        .and_then(|log| log.get::<HashMap<String, JsonValue>>())
+    else {
+        // Like missing permissions to open the device
+        let messages = field("smartctl")
+            .and_then(|smartctl| smartctl.get::<HashMap<String, JsonValue>>())
+            .and_then(|smartctl| smartctl.get("messages"))
+            .and_then(|messages| messages.get::<Vec<JsonValue>>())
+            .into_iter()
+            .flatten()
+            .filter_map(|message| {
+                message.get::<HashMap<String, JsonValue>>()?.get("string")?.get::<String>().cloned()
+            })
+            .collect::<Vec<_>>();
+        return Err(NvmeError::NoHealthLog(messages.join(", ")));
+    };
+    Ok(NvmeHealth {
+        name: name.to_string(),
+        model: field("model_name").and_then(|model| model.get::<String>()).cloned(),
+        percentage_used: number(log.get("percentage_used")).unwrap_or(0.) as u32,
+        temperature: number(log.get("temperature")).map(|temperature| temperature as f32),
+        available_spare: number(log.get("available_spare")).map(|spare| spare as u32),
+        media_errors: number(log.get("media_errors")).unwrap_or(0.) as u64,
+        power_on_hours: number(log.get("power_on_hours")).map(|hours| hours as u64),
+        critical_warning: number(log.get("critical_warning")).unwrap_or(0.) as u32,
+    })
+}
+
+fn nvme_health(name: &str, config: &NvmeConfig) -> Result<NvmeHealth, NvmeError> {
+    let command = config.command.replace("{device}", &format!("/dev/{name}"));
+    // smartctl's exit status is a bit mask that is set for plenty of things
+    // short of failing, so only the JSON tells whether it worked
+    let output = Command::new("sh").arg("-c").arg(command).output()?;
+    parse_health(name, &String::from_utf8(output.stdout)?)
+}
+
+fn nvme_generator(
+    sender: &Sender<Message>,
+    config: &NvmeConfig,
+    resumes: &mut watch::Receiver<()>,
+) -> Result<(), NvmeError> {
+    // Drives that failed, warned about once until they can be read again
+    let mut failing = HashSet::new();
+    loop {
+        let mut drives = Vec::new();
+        for name in nvme_devices()? {
+            match nvme_health(&name, config) {
+                Ok(health) => {
+                    failing.remove(&name);
+                    drives.push(health);
+                }
+                Err(e) => {
+                    if failing.insert(name.clone()) {
+                        log::warn!("Could not read the health of {name}: {e:?}");
+                    }
+                }
+            }
+        }
+        sender.blocking_send(Message::Nvme(NvmeMessage::Update(drives)))?;
+        sleep_unless_resumed(resumes, Duration::from_secs(config.interval));
+    }
+}
+
+pub fn nvme_subscription(
+    rt: Handle,
+    config: NvmeConfig,
+    mut resumes: watch::Receiver<()>,
+) -> ReceiverStream<Message> {
+    let (sender, receiver) = channel(1);
+    let backoff = Backoff::starting_at(Duration::from_secs(config.interval));
+    supervise_blocking(&rt, "nvme", backoff, sender, move |sender| {
+        nvme_generator(&sender, &config, &mut resumes)
+    });
+    ReceiverStream::new(receiver)
+}
+
+/// The wear of each NVMe drive, warning about failing ones
+pub const WIDGET: Widget = Widget {
+    name: "nvme",
+    render,
+};
+
+fn render(state: &State) -> Vec<Renderable> {
+    let style = state.config.theme.style("nvme");
+    let mut module = Vec::new();
+    for drive in &state.nvme_drives {
+        if !module.is_empty() {
+            module.push(Renderable::Space(1.));
+        }
+        let worn = drive.percentage_used >= state.config.nvme.wear_warning;
+        let failing = drive.critical_warning != 0 || drive.media_errors > 0;
+        let mut tooltip = vec![match &drive.model {
+            Some(model) => format!("{} {model}", drive.name),
+            None => drive.name.clone(),
+        }];
+        tooltip.push(format!(
+            "{}% of the rated endurance used",
+            drive.percentage_used
+        ));
+        if let Some(spare) = drive.available_spare {
+            tooltip.push(format!("{spare}% of the spare blocks left"));
+        }
+        if let Some(temperature) = drive.temperature {
+            tooltip.push(format!("{temperature:.0}°C"));
+        }
+        if let Some(hours) = drive.power_on_hours {
+            tooltip.push(format!("Powered on for {hours} hours"));
+        }
+        if drive.media_errors > 0 {
+            tooltip.push(format!("{} media errors", drive.media_errors));
+        }
+        if drive.critical_warning != 0 {
+            tooltip.push(format!("Critical warning {:#04x}", drive.critical_warning));
+        }
+        let mut children = Vec::new();
+        if worn || failing {
+            // An alert triangle from the nerd fonts
+            children.push(Renderable::Text {
+                text: "\u{f0026} ".into(),
+                fg: if failing { style.urgent } else { style.warning },
+                bg: style.bg,
+            });
+        }
+        children.push(Renderable::Text {
+            text: format!("ssd {}%", drive.percentage_used).into(),
+            fg: style.fg,
+            bg: style.bg,
+        });
+        module.push(Renderable::Region {
+            tooltip: Some(tooltip.join("\n")),
+            on_click: None,
+            on_scroll: None,
+            children,
+        });
+    }
+    module
+}
@@ -0,0 +1,353 @@
+use serde::Deserialize;
+use smithay_client_toolkit::seat::keyboard::Keysym;
+
+use crate::{
+    action::Action,
+    renderer::Renderable,
+    state::State,
+    sway::quote,
+    text_input::{KeyInput, TextInput, TextInputEvent},
+    widget::Widget,
+};
+
+/// How many matches are shown after the input
+const MAX_RESULTS: usize = 5;
+
+/// How deep parentheses, signs and powers may nest before evaluate gives up,
+/// instead of overflowing the stack on a pasted wall of (((
+const MAX_DEPTH: usize = 64;
+
+/// Something the palette can run, matched against what is typed
+#[derive(Debug, Clone)]
+pub struct PaletteEntry {
+    pub label: String,
+    pub action: Action,
+}
+
+pub enum PaletteEvent {
+    None,
+    Close,
+    Run(Action),
+}
+
+/// What the palette lists, each opened by its own sway binding
+#[derive(Debug, Clone, Copy, PartialEq, Hash, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PaletteMode {
+    /// Actions and commands, also evaluates math
+    #[default]
+    Commands,
+    /// Windows from the sway tree, to focus one
+    Windows,
+    /// cpufreq governors to switch to, opened from the governor module
+    Governors,
+    /// Profiles of the Bluetooth audio devices to switch to, opened by
+    /// right clicking the bluetooth module
+    Profiles,
+    /// Outputs to move the focused workspace to, or what is typed to rename
+    /// it to, opened by right clicking the workspaces
+    Workspace,
+    /// Shutting down, rebooting and the like, opened from the power module
+    Power,
+    /// Whether to go ahead with an action that asked first
+    #[serde(skip)]
+    Confirm,
+}
+
+/// Popup opened from a sway binding, typing either evaluates math or fuzzy
+/// matches the entries the state hands it
+#[derive(Debug, Clone, Default)]
+pub struct Palette {
+    pub mode: PaletteMode,
+    pub input: TextInput,
+    /// Index into the current matches
+    pub selected: usize,
+    /// What the confirm mode asks about, with the action run on yes
+    pub confirm: Option<PaletteEntry>,
+}
+
+impl Palette {
+    pub fn new(mode: PaletteMode) -> Self {
+        Self {
+            mode,
+            ..Self::default()
+        }
+    }
+
+    /// Asks whether to run the action, named by the label
+    pub fn confirm(label: String, action: Action) -> Self {
+        Self {
+            confirm: Some(PaletteEntry { label, action }),
+            ..Self::new(PaletteMode::Confirm)
+        }
+    }
+
+    /// The result if the input is math, plain numbers aren't as they are
+    /// more likely the start of something like a workspace name
+    pub fn math_result(&self) -> Option<f64> {
+        let text = self.input.text().trim();
+        if self.mode != PaletteMode::Commands || text.parse::<f64>().is_ok() {
+            return None;
+        }
+        evaluate(text)
+    }
+
+    /// Best matches first, an empty input keeps the entries in order. The
+    /// typed text can always be run as a command as the last resort, or
+    /// taken as the new name in the workspace menu
+    pub fn matches(&self, entries: &[PaletteEntry]) -> Vec<PaletteEntry> {
+        let query = self.input.text().trim();
+        let mut matches = if query.is_empty() {
+            entries.to_vec()
+        } else {
+            let mut scored = entries
+                .iter()
+                .filter_map(|entry| Some((fuzzy_score(query, &entry.label)?, entry)))
+                .collect::<Vec<_>>();
+            scored.sort_by_key(|(score, _)| -score);
+            scored.into_iter().map(|(_, entry)| entry.clone()).collect()
+        };
+        matches.truncate(MAX_RESULTS);
+        if self.mode == PaletteMode::Commands && !query.is_empty() {
+            matches.push(PaletteEntry {
+                label: format!("run {query}"),
+                action: Action::Spawn(query.to_string()),
+            });
+        }
+        if self.mode == PaletteMode::Workspace && !query.is_empty() {
+            matches.push(PaletteEntry {
+                label: format!("rename to {query}"),
+                action: Action::SwayCommand(format!("rename workspace to {}", quote(query))),
+            });
+        }
+        matches
+    }
+
+    pub fn handle_key(&mut self, key: &KeyInput, entries: &[PaletteEntry]) -> PaletteEvent {
+        let matches = self.matches(entries);
+        match key.keysym {
+            Keysym::Down => {
+                self.selected = (self.selected + 1).min(matches.len().saturating_sub(1));
+                return PaletteEvent::None;
+            }
+            Keysym::Tab if !key.shift => {
+                self.selected = (self.selected + 1).min(matches.len().saturating_sub(1));
+                return PaletteEvent::None;
+            }
+            // Shift+Tab usually arrives as ISO_Left_Tab
+            Keysym::Up | Keysym::ISO_Left_Tab | Keysym::Tab => {
+                self.selected = self.selected.saturating_sub(1);
+                return PaletteEvent::None;
+            }
+            _ => {}
+        }
+        match self.input.handle_key(key) {
+            TextInputEvent::None => PaletteEvent::None,
+            TextInputEvent::Changed => {
+                self.selected = 0;
+                PaletteEvent::None
+            }
+            TextInputEvent::Cancel => PaletteEvent::Close,
+            TextInputEvent::Submit => match self.math_result() {
+                Some(result) => {
+                    PaletteEvent::Run(Action::Spawn(format!("wl-copy {}", display_number(result))))
+                }
+                None => match matches.into_iter().nth(self.selected) {
+                    Some(entry) => PaletteEvent::Run(entry.action),
+                    None => PaletteEvent::Close,
+                },
+            },
+        }
+    }
+
+    pub fn to_renderables(&self, entries: &[PaletteEntry], fg: u32, bg: u32) -> Vec<Renderable> {
+        let mut renderables = self.input.to_renderables(fg, bg);
+        renderables.push(Renderable::Space(1.));
+        if let Some(result) = self.math_result() {
+            renderables.push(Renderable::Text {
+                text: format!("= {}", display_number(result)).into(),
+                fg,
+                bg: 0x00000000,
+            });
+            return renderables;
+        }
+        // Clicking an entry runs it, so the palette doubles as a menu
+        for (i, entry) in self.matches(entries).into_iter().enumerate() {
+            let selected = i == self.selected;
+            renderables.push(Renderable::Region {
+                tooltip: None,
+                on_click: Some(entry.action),
+                on_scroll: None,
+                children: vec![Renderable::Text {
+                    text: entry.label.into(),
+                    fg: if selected { bg } else { fg },
+                    bg: if selected { fg } else { 0x00000000 },
+                }],
+            });
+            renderables.push(Renderable::Space(1.));
+        }
+        renderables
+    }
+}
+
+/// Scores how well the query matches as a subsequence of the candidate,
+/// ignoring case. Matches at word starts and runs of matches count more,
+/// skipped chars count against it. None when some query char is missing
+pub fn fuzzy_score(query: &str, candidate: &str) -> Option<i32> {
+    let mut candidate_chars = candidate.chars();
+    let mut previous: Option<char> = None;
+    let mut previous_matched = false;
+    let mut score = 0;
+    for query_char in query.chars().filter(|c| !c.is_whitespace()) {
+        loop {
+            let c = candidate_chars.next()?;
+            let word_start = previous.is_none_or(|previous| !previous.is_alphanumeric());
+            previous = Some(c);
+            if c.to_lowercase().eq(query_char.to_lowercase()) {
+                score += 1;
+                if previous_matched {
+                    score += 5;
+                }
+                if word_start {
+                    score += 10;
+                }
+                previous_matched = true;
+                break;
+            }
+            previous_matched = false;
+            score -= 1;
+        }
+    }
+    Some(score)
+}
+
+/// Up to 10 decimals without trailing zeros, so 0.1 + 0.2 shows as 0.3
+pub fn display_number(x: f64) -> String {
+    let text = format!("{x:.10}");
+    let text = text.trim_end_matches('0').trim_end_matches('.');
+    match text {
+        "-0" => "0".to_string(),
+        text => text.to_string(),
+    }
+}
+
+/// Evaluates + - * / % ^ and parentheses over decimal numbers, None if the
+/// text isn't such an expression or the result isn't finite
+pub fn evaluate(text: &str) -> Option<f64> {
+    let mut parser = Parser {
+        chars: text.chars().filter(|c| !c.is_whitespace()).collect(),
+        position: 0,
+        depth: 0,
+    };
+    let result = parser.expression()?;
+    (parser.position == parser.chars.len() && result.is_finite()).then_some(result)
+}
+
+struct Parser {
+    chars: Vec<char>,
+    position: usize,
+    depth: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.position).copied()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        let matches = self.peek() == Some(c);
+        if matches {
+            self.position += 1;
+        }
+        matches
+    }
+
+    fn expression(&mut self) -> Option<f64> {
+        let mut value = self.term()?;
+        loop {
+            if self.eat('+') {
+                value += self.term()?;
+            } else if self.eat('-') {
+                value -= self.term()?;
+            } else {
+                return Some(value);
+            }
+        }
+    }
+
+    fn term(&mut self) -> Option<f64> {
+        let mut value = self.unary()?;
+        loop {
+            if self.eat('*') {
+                value *= self.unary()?;
+            } else if self.eat('/') {
+                value /= self.unary()?;
+            } else if self.eat('%') {
+                value %= self.unary()?;
+            } else {
+                return Some(value);
+            }
+        }
+    }
+
+    /// Binds looser than ^, so -2^2 is -4. Every nesting goes through here,
+    /// so this is where the depth is kept
+    fn unary(&mut self) -> Option<f64> {
+        if self.depth == MAX_DEPTH {
+            return None;
+        }
+        self.depth += 1;
+        let value = if self.eat('-') {
+            self.unary().map(|value| -value)
+        } else {
+            self.power()
+        };
+        self.depth -= 1;
+        value
+    }
+
+    fn power(&mut self) -> Option<f64> {
+        let base = self.primary()?;
+        if self.eat('^') {
+            // Right associative, 2^3^2 is 2^9
+            return Some(base.powf(self.unary()?));
+        }
+        Some(base)
+    }
+
+    fn primary(&mut self) -> Option<f64> {
+        if self.eat('(') {
+            let value = self.expression()?;
+            return self.eat(')').then_some(value);
+        }
+        let start = self.position;
+        while self.peek().is_some_and(|c| c.is_ascii_digit() || c == '.') {
+            self.position += 1;
+        }
+        self.chars[start..self.position]
+            .iter()
+            .collect::<String>()
+            .parse()
+            .ok()
+    }
+}
+
+/// A power button opening the power menu below it
+pub const POWER: Widget = Widget {
+    name: "power",
+    render: render_power,
+};
+
+fn render_power(state: &State) -> Vec<Renderable> {
+    let style = state.config.theme.style("power");
+    vec![Renderable::Region {
+        tooltip: Some("Power".to_string()),
+        on_click: Some(Action::ToggleMenu(PaletteMode::Power)),
+        on_scroll: None,
+        children: vec![Renderable::Text {
+            text: "\u{f0425}".into(),
+            fg: style.fg,
+            bg: style.bg,
+        }],
+    }]
+}
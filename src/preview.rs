@@ -0,0 +1,175 @@
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use inotify::{Inotify, WatchMask};
+use swayipc::Rect;
+use tokio::{
+    runtime::Handle,
+    sync::mpsc::{Sender, channel, error::SendError},
+};
+use tokio_stream::wrappers::ReceiverStream;
+
+use crate::{
+    battery::{BatteryMessage, PowerSupply, PowerSupplyStatus},
+    config::Config,
+    ipc::IpcMessage,
+    mpd::MpdMessage,
+    network::Network,
+    state::Message,
+    supervisor::{Backoff, supervise_blocking},
+    sway::{SwayMessage, Workspace},
+};
+
+#[derive(Debug)]
+pub enum PreviewError {
+    StdIoError(std::io::Error),
+    /// The theme has no parent directory to watch
+    NoDirectory(PathBuf),
+    SendError(SendError<Message>),
+}
+
+impl std::fmt::Display for PreviewError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PreviewError::StdIoError(e) => write!(f, "IO Error: {e}"),
+            PreviewError::NoDirectory(path) => {
+                write!(f, "{} has no directory to watch", path.display())
+            }
+            PreviewError::SendError(e) => write!(f, "Channel Error: {e}"),
+        }
+    }
+}
+
+impl From<std::io::Error> for PreviewError {
+    fn from(value: std::io::Error) -> Self {
+        Self::StdIoError(value)
+    }
+}
+
+impl From<SendError<Message>> for PreviewError {
+    fn from(value: SendError<Message>) -> Self {
+        Self::SendError(value)
+    }
+}
+
+fn workspace(id: i64, focused: bool, visible: bool, urgent: bool) -> Message {
+    Message::Sway(SwayMessage::WorkspaceAdd(Workspace {
+        id,
+        num: id as i32,
+        name: Some(id.to_string()),
+        layout: "splith".to_string(),
+        visible,
+        focused,
+        urgent,
+        representation: None,
+        orientation: "none".to_string(),
+        rect: Rect {
+            x: 0,
+            y: 0,
+            width: 1920,
+            height: 1080,
+        },
+        output: "PREVIEW-1".to_string(),
+        focus: Vec::new(),
+    }))
+}
+
+/// Made up workspaces, a song, a Wi-Fi connection and a battery, so every
+/// color of a theme shows up somewhere without any of them being set up
+fn sample_messages() -> Vec<Message> {
+    let song = mpd::Song {
+        title: Some("Theme Preview".to_string()),
+        artist: Some("sway-shell".to_string()),
+        ..Default::default()
+    };
+    let status = mpd::Status {
+        state: mpd::status::State::Play,
+        time: Some((Duration::from_secs(83), Duration::from_secs(215))),
+        ..Default::default()
+    };
+    vec![
+        workspace(1, true, true, false),
+        workspace(2, false, false, false),
+        workspace(3, false, true, false),
+        workspace(4, false, false, true),
+        Message::Sway(SwayMessage::WindowFocusedChange {
+            window_name: Some("Editor — theme.toml".to_string()),
+        }),
+        Message::Mpd(MpdMessage::MpdSongUpdate { song: Some(song) }),
+        Message::Mpd(MpdMessage::MpdPlayerUpdate { status }),
+        Message::Network(vec![Network::Wifi {
+            if_index: 1,
+            if_name: "wlan0".to_string(),
+            ssid: Some("Preview".to_string()),
+            bssid: None,
+            signal: Some(-58),
+            tx_bitrate: Some(8667),
+            rx_bitrate: Some(7800),
+            up: 0,
+            down: 0,
+            up_rate: 12_000,
+            down_rate: 840_000,
+        }]),
+        Message::Battery(BatteryMessage::UpdatePowerSupplies(vec![
+            PowerSupply::Battery {
+                status: PowerSupplyStatus::Discharging,
+                capacity: 64,
+                time_remaining: Some(Duration::from_secs(2 * 3600 + 35 * 60)),
+                power: Some(8.4),
+            },
+        ])),
+    ]
+}
+
+fn preview_generator(
+    sender: &Sender<Message>,
+    theme: &Path,
+    samples: Vec<Message>,
+) -> Result<(), PreviewError> {
+    for message in samples {
+        sender.blocking_send(message)?;
+    }
+    let (Some(directory), Some(file_name)) = (theme.parent(), theme.file_name()) else {
+        return Err(PreviewError::NoDirectory(theme.to_path_buf()));
+    };
+    // Editors tend to write a new file and rename it over the old one, so
+    // the directory is watched, same as for the watch modules
+    let mut inotify = Inotify::init()?;
+    inotify.watches().add(
+        directory,
+        WatchMask::CLOSE_WRITE | WatchMask::MOVED_TO | WatchMask::CREATE,
+    )?;
+    let mut buffer = [0; 4096];
+    loop {
+        match Config::load_theme(&theme.to_string_lossy()) {
+            Ok(theme) => sender.blocking_send(Message::Ipc(IpcMessage::SetTheme(theme)))?,
+            Err(e) => log::error!("Could not load the theme, keeping the last one: {e:?}"),
+        }
+        loop {
+            let mut events = inotify.read_events_blocking(&mut buffer)?;
+            if events.any(|event| event.name == Some(file_name)) {
+                break;
+            }
+        }
+    }
+}
+
+/// Stands in for the sway, media, network and battery subscriptions with
+/// sample data, and sets the theme at the absolute path again whenever it
+/// is saved
+pub fn preview_subscription(rt: Handle, theme: PathBuf) -> ReceiverStream<Message> {
+    let (sender, receiver) = channel(1);
+    // Only sent on the first run, as the state adds workspaces up rather
+    // than replacing them
+    let mut samples = sample_messages();
+    supervise_blocking(
+        &rt,
+        "preview",
+        Backoff::starting_at(Duration::from_secs(5)),
+        sender,
+        move |sender| preview_generator(&sender, &theme, std::mem::take(&mut samples)),
+    );
+    ReceiverStream::new(receiver)
+}
@@ -0,0 +1,235 @@
+use std::{cell::RefCell, collections::HashMap, rc::Rc, time::Duration};
+
+use pipewire::{
+    context::ContextRc,
+    main_loop::MainLoopRc,
+    node::{Node, NodeListener, NodeState},
+    spa::utils::dict::DictRef,
+    types::ObjectType,
+};
+use tokio::{
+    runtime::Handle,
+    sync::mpsc::{Sender, channel},
+};
+use tokio_stream::wrappers::ReceiverStream;
+
+use crate::{
+    mic::METER_NODE_NAME,
+    renderer::Renderable,
+    state::{Message, State},
+    supervisor::{Backoff, supervise_blocking},
+    widget::Widget,
+};
+
+#[derive(Debug)]
+pub enum PrivacyError {
+    PipewireError(pipewire::Error),
+}
+
+impl std::fmt::Display for PrivacyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PrivacyError::PipewireError(e) => write!(f, "PipeWire Error: {e}"),
+        }
+    }
+}
+
+impl From<pipewire::Error> for PrivacyError {
+    fn from(value: pipewire::Error) -> Self {
+        Self::PipewireError(value)
+    }
+}
+
+/// What a stream records
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum PrivacyKind {
+    Microphone,
+    Camera,
+    Screen,
+}
+
+impl PrivacyKind {
+    /// Apps recording audio have an input stream, cameras and screen casts
+    /// are video sources that only run while something reads from them
+    fn of(props: &DictRef) -> Option<Self> {
+        match props.get("media.class")? {
+            // The bar listening itself for the mic module
+            "Stream/Input/Audio" if props.get("node.name") != Some(METER_NODE_NAME) => {
+                Some(Self::Microphone)
+            }
+            "Video/Source" => {
+                let camera =
+                    props.get("media.role") == Some("Camera") || props.get("device.api").is_some();
+                Some(if camera { Self::Camera } else { Self::Screen })
+            }
+            _ => None,
+        }
+    }
+}
+
+/// A stream recording right now
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct PrivacyStream {
+    pub kind: PrivacyKind,
+    /// The application behind the stream, or the node for sources
+    pub name: String,
+}
+
+#[derive(Debug)]
+pub enum PrivacyMessage {
+    /// Every stream recording, empty when nothing is
+    Streams(Vec<PrivacyStream>),
+}
+
+/// A node that could record, with whether it runs
+struct Watched {
+    stream: PrivacyStream,
+    running: bool,
+    _node: Node,
+    _listener: NodeListener,
+}
+
+fn send_streams(sender: &Sender<Message>, watched: &HashMap<u32, Watched>) {
+    let mut streams: Vec<PrivacyStream> = watched
+        .values()
+        .filter(|watched| watched.running)
+        .map(|watched| watched.stream.clone())
+        .collect();
+    streams.sort();
+    streams.dedup();
+    if let Err(e) = sender.blocking_send(Message::Privacy(PrivacyMessage::Streams(streams))) {
+        log::error!("Privacy Error: {e:?}");
+    }
+}
+
+fn privacy_generator(sender: Sender<Message>) -> Result<(), PrivacyError> {
+    let mainloop = MainLoopRc::new(None)?;
+    let context = ContextRc::new(&mainloop, None)?;
+    let core = context.connect_rc(None)?;
+    let registry = core.get_registry_rc()?;
+    let registry_weak = registry.downgrade();
+    let watched: Rc<RefCell<HashMap<u32, Watched>>> = Rc::default();
+
+    let global_watched = watched.clone();
+    let global_sender = sender.clone();
+    let _listener = registry
+        .add_listener_local()
+        .global(move |global| {
+            if global.type_ != ObjectType::Node {
+                return;
+            }
+            let Some(props) = global.props else {
+                return;
+            };
+            let Some(kind) = PrivacyKind::of(props) else {
+                return;
+            };
+            let Some(registry) = registry_weak.upgrade() else {
+                return;
+            };
+            let node: Node = match registry.bind(global) {
+                Ok(node) => node,
+                Err(e) => {
+                    log::error!("Could not bind node {}: {e}", global.id);
+                    return;
+                }
+            };
+            let name = ["application.name", "node.description", "node.name"]
+                .into_iter()
+                .find_map(|key| props.get(key))
+                .unwrap_or("Unknown")
+                .to_string();
+            let id = global.id;
+            let info_watched = Rc::downgrade(&global_watched);
+            let info_sender = global_sender.clone();
+            // The state comes with the first info, and again whenever it
+            // changes
+            let listener = node
+                .add_listener_local()
+                .info(move |info| {
+                    let Some(watched) = info_watched.upgrade() else {
+                        return;
+                    };
+                    let running = matches!(info.state(), NodeState::Running);
+                    let mut watched = watched.borrow_mut();
+                    match watched.get_mut(&id) {
+                        Some(node) if node.running != running => node.running = running,
+                        _ => return,
+                    }
+                    send_streams(&info_sender, &watched);
+                })
+                .register();
+            global_watched.borrow_mut().insert(
+                id,
+                Watched {
+                    stream: PrivacyStream { kind, name },
+                    running: false,
+                    _node: node,
+                    _listener: listener,
+                },
+            );
+        })
+        .global_remove(move |id| {
+            let mut watched = watched.borrow_mut();
+            if watched.remove(&id).is_some_and(|node| node.running) {
+                send_streams(&sender, &watched);
+            }
+        })
+        .register();
+
+    mainloop.run();
+    Ok(())
+}
+
+pub fn privacy_subscription(rt: Handle) -> ReceiverStream<Message> {
+    let (sender, receiver) = channel(1);
+    supervise_blocking(
+        &rt,
+        "privacy",
+        Backoff::starting_at(Duration::from_secs(5)),
+        sender,
+        privacy_generator,
+    );
+    ReceiverStream::new(receiver)
+}
+
+/// A red glyph for each of the microphone, camera and screen while
+/// something records it, naming what does in the tooltip
+pub const WIDGET: Widget = Widget {
+    name: "privacy",
+    render,
+};
+
+fn render(state: &State) -> Vec<Renderable> {
+    let style = state.config.theme.style("privacy");
+    let mut module = Vec::new();
+    for (kind, glyph, what) in [
+        (PrivacyKind::Microphone, "\u{f036c}", "Microphone"),
+        (PrivacyKind::Camera, "\u{f0100}", "Camera"),
+        (PrivacyKind::Screen, "\u{f0379}", "Screen"),
+    ] {
+        let names: Vec<&str> = state
+            .privacy
+            .iter()
+            .filter(|stream| stream.kind == kind)
+            .map(|stream| stream.name.as_str())
+            .collect();
+        if names.is_empty() {
+            continue;
+        }
+        if !module.is_empty() {
+            module.push(Renderable::Space(0.5));
+        }
+        module.push(Renderable::Region {
+            tooltip: Some(format!("{what} in use by {}", names.join(", "))),
+            on_click: None,
+            on_scroll: None,
+            children: vec![Renderable::Text {
+                text: glyph.into(),
+                fg: style.urgent,
+                bg: style.bg,
+            }],
+        });
+    }
+    module
+}
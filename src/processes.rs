@@ -0,0 +1,111 @@
+use std::{collections::HashMap, fs, time::Instant};
+
+/// Clock ticks per second the times in /proc/[pid]/stat are counted in,
+/// USER_HZ is 100 on every architecture Linux runs on
+const CLOCK_TICKS: f32 = 100.;
+
+#[derive(Debug, Clone)]
+struct Process {
+    name: String,
+    /// User and system time in clock ticks
+    cpu_time: u64,
+    /// Resident memory in bytes
+    memory: u64,
+}
+
+/// Every process at one point in time, read from /proc. Reading it takes a
+/// few milliseconds, so it is only done when a tooltip asks for it
+#[derive(Debug, Clone)]
+pub struct ProcessSample {
+    processes: HashMap<u32, Process>,
+    taken_at: Instant,
+}
+
+/// The processes using the most of the CPU and memory, for tooltips
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TopProcesses {
+    /// Name and percent of one core, like top shows it
+    pub cpu: Vec<(String, f32)>,
+    /// Name and resident memory in bytes
+    pub memory: Vec<(String, u64)>,
+}
+
+/// Kernel threads and processes that exit while being read are left out
+fn read_process(pid: u32) -> Option<Process> {
+    let stat = fs::read_to_string(format!("/proc/{pid}/stat")).ok()?;
+    // The name is in parentheses and can have spaces and parentheses of its
+    // own, so the fields after it are found from the last one
+    let (head, fields) = stat.rsplit_once(')')?;
+    let name = head.split_once('(')?.1.to_string();
+    // utime and stime are the 14th and 15th fields, counting the pid and
+    // the name as the first two
+    let mut fields = fields.split_whitespace().skip(11);
+    let utime: u64 = fields.next()?.parse().ok()?;
+    let stime: u64 = fields.next()?.parse().ok()?;
+    let status = fs::read_to_string(format!("/proc/{pid}/status")).ok()?;
+    let memory = status
+        .lines()
+        .find_map(|line| line.strip_prefix("VmRSS:"))?
+        .trim()
+        .trim_end_matches("kB")
+        .trim()
+        .parse::<u64>()
+        .ok()?
+        * 1024;
+    Some(Process {
+        name,
+        cpu_time: utime + stime,
+        memory,
+    })
+}
+
+impl ProcessSample {
+    pub fn take() -> Self {
+        let processes = fs::read_dir("/proc")
+            .into_iter()
+            .flatten()
+            .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse::<u32>().ok())
+            .filter_map(|pid| Some((pid, read_process(pid)?)))
+            .collect();
+        Self {
+            processes,
+            taken_at: Instant::now(),
+        }
+    }
+
+    /// Takes the sample on a blocking thread, keeping the state loop it is
+    /// asked for on free. None if that thread panicked
+    pub async fn take_blocking() -> Option<Self> {
+        tokio::task::spawn_blocking(Self::take)
+            .await
+            .inspect_err(|e| log::error!("Could not sample the processes: {e}"))
+            .ok()
+    }
+
+    /// The count processes using the most CPU time between the earlier
+    /// sample and this one, and the most memory in this one
+    pub fn top(&self, earlier: &ProcessSample, count: usize) -> TopProcesses {
+        let elapsed = self.taken_at.duration_since(earlier.taken_at).as_secs_f32();
+        let mut cpu = self
+            .processes
+            .iter()
+            .filter_map(|(pid, process)| {
+                // Processes started in between have no earlier time to go by
+                let earlier = earlier.processes.get(pid)?;
+                let ticks = process.cpu_time.saturating_sub(earlier.cpu_time);
+                (ticks > 0 && elapsed > 0.)
+                    .then(|| (process.name.clone(), 100. * ticks as f32 / CLOCK_TICKS / elapsed))
+            })
+            .collect::<Vec<_>>();
+        cpu.sort_by(|a, b| b.1.total_cmp(&a.1));
+        cpu.truncate(count);
+        let mut memory = self
+            .processes
+            .values()
+            .map(|process| (process.name.clone(), process.memory))
+            .collect::<Vec<_>>();
+        memory.sort_by(|a, b| b.1.cmp(&a.1));
+        memory.truncate(count);
+        TopProcesses { cpu, memory }
+    }
+}
@@ -1,23 +1,38 @@
-use itertools::Itertools;
 use std::mem;
 
-use std::{borrow::Cow, ptr::NonNull, sync::Arc};
+use std::{
+    borrow::Cow,
+    hash::{DefaultHasher, Hash, Hasher},
+    ops::Range,
+    path::{Path, PathBuf},
+    ptr::NonNull,
+    sync::{Arc, PoisonError},
+    time::{Duration, Instant},
+};
 
-use ab_glyph::Font;
 use bytemuck::Zeroable;
 use raw_window_handle::{
     RawDisplayHandle, RawWindowHandle, WaylandDisplayHandle, WaylandWindowHandle,
 };
 use tokio::{
-    runtime::Handle,
-    sync::{RwLock, mpsc::Receiver},
+    sync::{mpsc::Receiver, oneshot, watch},
+    time::sleep_until,
 };
-use wayland_client::{Proxy, protocol::wl_surface::WlSurface};
+use smithay_client_toolkit::shell::wlr_layer::{KeyboardInteractivity, LayerSurface};
+use wayland_client::{Proxy, QueueHandle};
 use wgpu::{AddressMode, DeviceDescriptor, FilterMode, SamplerDescriptor};
 use wgpu::{Buffer, BufferDescriptor, IndexFormat, PresentMode, RenderPipeline, util::DeviceExt};
 
-use crate::font::{FontContainer, GlyphOffLen};
-use crate::layer::DisplayMessage;
+use crate::action::{Action, MouseBindings, ScrollActions};
+use crate::atlas::{ATLAS_BYTES, ATLAS_SIZE, IMAGE_SIZE, Image, ImageAtlas};
+use crate::config::{BarPosition, CacheConfig, CenterCollision, Config, FontConfig, GpuConfig};
+use crate::font::{FontContainer, FontSystem, GlyphOffLen};
+use crate::layer::{Display, DisplayMessage, TooltipLayers};
+use crate::leak::LeakCheck;
+use crate::supervisor::watchdog_restarts;
+use crate::surface::BoundSurface;
+#[cfg(feature = "x11")]
+use crate::x11::X11Window;
 
 #[repr(C)]
 #[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
@@ -32,6 +47,9 @@ struct Vertex {
 struct GlobalTransformUniform {
     scale: [f32; 2],
     translate: [f32; 2],
+    /// Drives animations, see ANIMATION_WRAP
+    time: f32,
+    _padding: [f32; 3],
 }
 
 impl GlobalTransformUniform {
@@ -39,10 +57,48 @@ impl GlobalTransformUniform {
         Self {
             scale: [1., 1.],
             translate: [0., 0.],
+            time: 0.,
+            _padding: [0.; 3],
+        }
+    }
+
+    /// Maps layout units onto a surface of the given size in pixels, x from
+    /// the left edge in bar heights and y from the vertical center in half
+    /// bar heights
+    fn for_surface(width: u32, height: u32, bar_height: u32, time: f32) -> Self {
+        Self {
+            scale: [
+                2.0 * bar_height as f32 / width as f32,
+                bar_height as f32 / height as f32,
+            ],
+            translate: [-1., 0.],
+            time,
+            _padding: [0.; 3],
         }
     }
 }
 
+/// Animation time wraps around after this many seconds, so it keeps its
+/// precision as an f32 over weeks of uptime. Animations have to loop in a
+/// whole number of seconds to not jump at the wrap
+const ANIMATION_WRAP: f32 = 3600.;
+
+bitflags::bitflags! {
+    /// Instance::flags, mirrored as constants in shader.wgsl
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct InstanceFlags: u32 {
+        /// Fades the instance in and out, for things that need attention
+        const PULSE = 1;
+        /// Draws the image in Instance::image_slot of the atlas
+        const IMAGE = 2;
+        /// Thickens the outline of a glyph
+        const BOLD = 4;
+        /// Turns the instance a quarter clockwise on the surface, see
+        /// BarPosition
+        const ROTATED = 8;
+    }
+}
+
 impl Vertex {
     fn desc() -> wgpu::VertexBufferLayout<'static> {
         wgpu::VertexBufferLayout {
@@ -74,9 +130,28 @@ pub struct Instance {
     pub lines_off: GlyphOffLen,
     pub quadratic_off: GlyphOffLen,
     pub cubic_off: GlyphOffLen,
+    /// Corner radius in bar heights, anything above 0 draws a filled rounded
+    /// rectangle in bg instead of a glyph
+    pub radius: f32,
+    /// Color of the rounded rectangle's outline
+    pub border: u32,
+    /// Width of the outline in bar heights, drawn inside the rectangle
+    pub border_width: f32,
+    /// InstanceFlags bits
+    pub flags: u32,
+    /// Slot of the image atlas drawn with InstanceFlags::IMAGE
+    pub image_slot: u32,
 }
 
 impl Instance {
+    /// The same instance x bar heights further right
+    fn shifted(self, x: f32) -> Self {
+        Self {
+            position: [self.position[0] + x, self.position[1]],
+            ..self
+        }
+    }
+
     fn desc() -> wgpu::VertexBufferLayout<'static> {
         wgpu::VertexBufferLayout {
             array_stride: mem::size_of::<Instance>() as wgpu::BufferAddress,
@@ -123,35 +198,460 @@ impl Instance {
                     shader_location: 8,
                     format: wgpu::VertexFormat::Uint32x2,
                 },
+                wgpu::VertexAttribute {
+                    offset: 48,
+                    shader_location: 9,
+                    format: wgpu::VertexFormat::Float32,
+                },
+                wgpu::VertexAttribute {
+                    offset: 52,
+                    shader_location: 10,
+                    format: wgpu::VertexFormat::Unorm8x4,
+                },
+                wgpu::VertexAttribute {
+                    offset: 56,
+                    shader_location: 11,
+                    format: wgpu::VertexFormat::Float32,
+                },
+                wgpu::VertexAttribute {
+                    offset: 60,
+                    shader_location: 12,
+                    format: wgpu::VertexFormat::Uint32,
+                },
+                wgpu::VertexAttribute {
+                    offset: 64,
+                    shader_location: 13,
+                    format: wgpu::VertexFormat::Uint32,
+                },
             ],
         }
     }
 }
 
+/// What the wgpu surface of a layer surface holds raw pointers into
+pub struct WaylandTarget {
+    pub wayland_conn: wayland_client::Connection,
+    /// Keyboard interactivity is changed along with the frame that needs it,
+    /// and frame callbacks are requested on its wl_surface to pace drawing
+    pub layer: LayerSurface,
+}
+
+impl WaylandTarget {
+    fn raw_handles(&self) -> (RawDisplayHandle, RawWindowHandle) {
+        let raw_display_handle = RawDisplayHandle::Wayland(WaylandDisplayHandle::new(
+            NonNull::new(self.wayland_conn.backend().display_ptr() as *mut _)
+                .expect("Wayland display pointer to be not null"),
+        ));
+        let raw_window_handle = RawWindowHandle::Wayland(WaylandWindowHandle::new(
+            NonNull::new(self.layer.wl_surface().id().as_ptr() as *mut _)
+                .expect("Wayland surface pointer to be not null"),
+        ));
+        (raw_display_handle, raw_window_handle)
+    }
+}
+
+/// What the wgpu surface of the bar holds raw pointers into
+pub enum SurfaceTarget {
+    Wayland(WaylandTarget),
+    #[cfg(feature = "x11")]
+    X11(Arc<X11Window>),
+}
+
+impl SurfaceTarget {
+    fn raw_handles(&self) -> (RawDisplayHandle, RawWindowHandle) {
+        match self {
+            Self::Wayland(target) => target.raw_handles(),
+            #[cfg(feature = "x11")]
+            Self::X11(window) => window.raw_handles(),
+        }
+    }
+
+    fn wayland(&self) -> Option<&WaylandTarget> {
+        match self {
+            Self::Wayland(target) => Some(target),
+            #[cfg(feature = "x11")]
+            Self::X11(_) => None,
+        }
+    }
+}
+
+/// What only a bar on Wayland has, to pace frames with callbacks and show
+/// tooltips on layer surfaces of their own
+pub struct WaylandOutput {
+    pub queue_handle: QueueHandle<Display>,
+    pub tooltip_layers: TooltipLayers,
+}
+
+/// A tooltip, the OSD or a menu on its own layer surface, drawn once it is
+/// configured and then left alone until it changes
+pub struct TooltipSurface {
+    pub shown: Tooltip,
+    pub surface: BoundSurface<wgpu::Surface<'static>, WaylandTarget>,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// The surfaces drawn besides the bar, all laid out like a tooltip
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Popup {
+    Tooltip,
+    Osd,
+    Menu,
+}
+
+/// The bar moving off the top of the screen or back, as the fraction of
+/// its height that is off the screen
+#[derive(Debug, Clone, Copy)]
+pub struct Slide {
+    pub from: f32,
+    pub to: f32,
+    pub started_at: Instant,
+    pub duration: Duration,
+}
+
+impl Slide {
+    fn shown(duration: Duration) -> Self {
+        Self {
+            from: 0.,
+            to: 0.,
+            started_at: Instant::now(),
+            duration,
+        }
+    }
+
+    fn progress(&self) -> f32 {
+        if self.duration.is_zero() {
+            return 1.;
+        }
+        (self.started_at.elapsed().as_secs_f32() / self.duration.as_secs_f32()).min(1.)
+    }
+
+    /// Eases out, so the bar slows down as it arrives
+    fn offset(&self) -> f32 {
+        let eased = 1. - (1. - self.progress()).powi(3);
+        self.from + (self.to - self.from) * eased
+    }
+
+    fn done(&self) -> bool {
+        self.progress() >= 1.
+    }
+
+    /// Slides from wherever the bar is now
+    fn towards(&mut self, to: f32) {
+        *self = Self {
+            from: self.offset(),
+            to,
+            started_at: Instant::now(),
+            duration: self.duration,
+        };
+    }
+}
+
 pub struct Renderer {
     pub width: u32,
     pub height: u32,
+    /// Kept to create surfaces for new layer surfaces
+    pub wgpu_instance: wgpu::Instance,
     pub adapter: wgpu::Adapter,
     pub device: wgpu::Device,
     pub queue: wgpu::Queue,
-    pub surface: wgpu::Surface<'static>,
+    pub surface: BoundSurface<wgpu::Surface<'static>, SurfaceTarget>,
     pub render_pipeline: RenderPipeline,
+    /// What the pipeline draws in, the first format the surface supports
+    pub format: wgpu::TextureFormat,
     pub square_vb: Buffer,
     pub square_ib: Buffer,
     pub square_num_vertices: u32,
     pub global_transform_uniform_buffer: Buffer,
     pub pipeline_bind_group: wgpu::BindGroup,
+    /// Kept to rebuild the bind group when a font buffer is reallocated
+    pub bind_group_layout: wgpu::BindGroupLayout,
+    pub sampler: wgpu::Sampler,
     pub instance_buffer: Buffer,
+    /// Laid out into every frame, swapped with drawn_instances before that
+    /// so both keep the capacity they grew to
+    pub instances: Vec<Instance>,
+    /// The instances of the last frame, which the instance buffer holds
+    pub drawn_instances: Vec<Instance>,
     pub font_lines_points_buffer: Buffer,
     pub font_quadratic_points_buffer: Buffer,
     pub font_cubic_points_buffer: Buffer,
     pub font_sdf: FontContainer,
+    /// The same the font container holds, for shaping
+    pub fonts: Arc<FontSystem>,
+    pub image_atlas: ImageAtlas,
+    pub image_texture: wgpu::Texture,
+    /// Kept to rebuild the bind group with
+    pub image_view: wgpu::TextureView,
+    pub image_sampler: wgpu::Sampler,
+    pub cache: CacheConfig,
+    pub leak_check: LeakCheck,
+    pub center_collision: CenterCollision,
+    pub hit_regions: HitRegions,
+    /// Filled with the extents of the hit regions of the last frame
+    pub extent_pool: ExtentPool,
+    /// None on X11, which draws without frame callbacks or tooltips
+    pub wayland: Option<WaylandOutput>,
+    pub tooltip: Option<TooltipSurface>,
+    pub osd: Option<TooltipSurface>,
+    pub menu: Option<TooltipSurface>,
+    /// Set while there is no output for the bar to be on
+    pub layer_closed: bool,
+    pub keyboard_focus: bool,
+    /// Whether the layer surface is moved off the screen
+    pub hidden: bool,
+    /// Moves the layer surface with its margin, one step every frame
+    pub slide: Slide,
+    /// The bar never reserves space, see AutohideConfig
+    pub autohide: bool,
+    /// On a side bar, width and height are along the bar and across it
+    pub position: BarPosition,
+    /// The latest state received, drawn once the compositor wants a frame
+    pub latest_state: Option<RenderState>,
+    /// Hash of the state currently on screen, None when it needs a redraw
+    pub drawn_state_hash: Option<u64>,
+    /// Cleared after drawing and set again by the frame callback
+    pub frame_ready: bool,
+    /// When the frame callback still pending was requested
+    pub frame_requested_at: Option<Instant>,
+    /// Tells the state whether to bother sending states, see OCCLUSION_TIMEOUT
+    pub occluded: watch::Sender<bool>,
+    /// Where the animation time counts from
+    pub started_at: Instant,
+    /// Whether something on screen animates, which redraws on every frame
+    /// callback even if the state stays the same
+    pub animating: bool,
+    /// When the state last changed or the bar got input
+    pub last_activity: Instant,
+    pub last_drawn: Instant,
+    /// Animations are drawn every idle_frame once nothing happened for this
+    /// long, zero when they never slow down
+    pub idle_after: Duration,
+    pub idle_frame: Duration,
+}
+
+/// Asks for the bar to be drawn into a PNG at path, see Renderer::screenshot
+#[derive(Debug)]
+pub struct Screenshot {
+    pub path: PathBuf,
+    pub done: oneshot::Sender<Result<(), ScreenshotError>>,
+}
+
+/// What the renderer task is asked to do, in the one queue it reads so
+/// that a resize can't come in the middle of a frame
+pub enum RenderCommand {
+    /// From the Wayland event loop, like the bar being configured to a new
+    /// size or the compositor being ready for a frame
+    Display(DisplayMessage),
+    /// Draws the latest state of the state loop once a frame is due
+    Draw(RenderState),
+    /// The config was reloaded with other fonts
+    ReloadFont(FontConfig),
+    /// The pointer moved over the bar or something was pressed, which
+    /// brings animations back to full speed
+    Input,
+    /// Gives up the keyboard focus a click on the bar took
+    ReleaseKeyboard,
+    Screenshot(Screenshot),
 }
 
 #[derive(Debug)]
+pub enum ScreenshotError {
+    /// No state came in yet, or the bar has no size
+    NothingToDraw,
+    /// The bar draws in a format that doesn't map to 8 bit RGBA
+    UnsupportedFormat(wgpu::TextureFormat),
+    PollError(wgpu::PollError),
+    BufferAsyncError(wgpu::BufferAsyncError),
+    ImageError(image::ImageError),
+}
+
+impl From<wgpu::PollError> for ScreenshotError {
+    fn from(value: wgpu::PollError) -> Self {
+        Self::PollError(value)
+    }
+}
+
+impl From<wgpu::BufferAsyncError> for ScreenshotError {
+    fn from(value: wgpu::BufferAsyncError) -> Self {
+        Self::BufferAsyncError(value)
+    }
+}
+
+impl From<image::ImageError> for ScreenshotError {
+    fn from(value: image::ImageError) -> Self {
+        Self::ImageError(value)
+    }
+}
+
+/// A frame drawn for a screenshot and being copied into a buffer, which
+/// can be read on another thread once the GPU is done with it
+struct OffscreenCopy {
+    buffer: wgpu::Buffer,
+    width: u32,
+    height: u32,
+    /// Rows are copied out padded to the alignment wgpu wants
+    padded_row_bytes: u32,
+    /// Whether the red and blue bytes have to be swapped for RGBA
+    bgra: bool,
+}
+
+impl OffscreenCopy {
+    /// Waits for the GPU, so it is left to a blocking thread
+    fn read(self, device: &wgpu::Device) -> Result<Vec<u8>, ScreenshotError> {
+        let (sender, receiver) = std::sync::mpsc::channel();
+        self.buffer
+            .slice(..)
+            .map_async(wgpu::MapMode::Read, move |result| {
+                let _ = sender.send(result);
+            });
+        device.poll(wgpu::PollType::Wait)?;
+        receiver.try_recv().unwrap_or(Err(wgpu::BufferAsyncError))?;
+        let row_bytes = 4 * self.width as usize;
+        let mut pixels: Vec<u8> = self
+            .buffer
+            .slice(..)
+            .get_mapped_range()
+            .chunks_exact(self.padded_row_bytes as usize)
+            .flat_map(|row| &row[..row_bytes])
+            .copied()
+            .collect();
+        self.buffer.unmap();
+        if self.bgra {
+            for pixel in pixels.chunks_exact_mut(4) {
+                pixel.swap(0, 2);
+            }
+        }
+        Ok(pixels)
+    }
+
+    fn save(self, device: &wgpu::Device, path: &Path) -> Result<(), ScreenshotError> {
+        let (width, height) = (self.width, self.height);
+        let pixels = self.read(device)?;
+        image::save_buffer_with_format(
+            path,
+            &pixels,
+            width,
+            height,
+            image::ExtendedColorType::Rgba8,
+            image::ImageFormat::Png,
+        )?;
+        Ok(())
+    }
+}
+
+/// Where an interactive widget ended up on the bar after layout
+#[derive(Debug, Clone)]
+pub struct HitRegion {
+    /// Horizontal extent in surface pixels
+    pub x: Range<f32>,
+    pub tooltip: Option<String>,
+    pub on_click: Option<Action>,
+    pub on_middle_click: Option<Action>,
+    pub on_right_click: Option<Action>,
+    pub on_scroll: Option<ScrollActions>,
+    /// Name of the module, for the region spanning a whole module
+    pub module: Option<Arc<str>>,
+    /// The text laid out in the region and not in a region within it, in
+    /// the order of the renderables. Only Region renderables record these
+    pub texts: Vec<TextExtent>,
+}
+
+impl HitRegion {
+    fn shifted(self, by: f32) -> Self {
+        self.mapped(|x| x + by)
+    }
+
+    fn scaled(self, by: f32) -> Self {
+        self.mapped(|x| x * by)
+    }
+
+    fn mapped(mut self, f: impl Fn(f32) -> f32) -> Self {
+        self.x = f(self.x.start)..f(self.x.end);
+        for text in &mut self.texts {
+            text.x = f(text.x.start)..f(text.x.end);
+            for cluster in &mut text.clusters {
+                cluster.x = f(cluster.x.start)..f(cluster.x.end);
+            }
+        }
+        self
+    }
+
+    /// The text and the cluster in it under x, as their index in texts and
+    /// the byte offset of the cluster in the text
+    pub fn cluster_at(&self, x: f32) -> Option<(usize, usize)> {
+        self.texts.iter().enumerate().find_map(|(index, text)| {
+            let cluster = text
+                .clusters
+                .iter()
+                .find(|cluster| cluster.x.contains(&x))?;
+            Some((index, cluster.byte))
+        })
+    }
+}
+
+/// Where a text renderable ended up, going by the advances of its shaped
+/// glyphs. In the units of the hit region holding it
+#[derive(Debug, Clone)]
+pub struct TextExtent {
+    pub x: Range<f32>,
+    /// In visual order, which is the reverse of the text for right to left
+    /// runs. An ellipsized text has the clusters of what is left of it
+    pub clusters: Vec<ClusterExtent>,
+}
+
+/// A cluster of glyphs that goes together, like a letter with its marks
+/// or a ligature
+#[derive(Debug, Clone)]
+pub struct ClusterExtent {
+    /// Offset of the first char of the cluster in the text
+    pub byte: usize,
+    pub x: Range<f32>,
+}
+
+/// The text and cluster vectors of the hit regions of earlier frames,
+/// emptied to be filled again, so steady frames don't allocate for them
+#[derive(Debug, Default)]
+pub struct ExtentPool {
+    texts: Vec<Vec<TextExtent>>,
+    clusters: Vec<Vec<ClusterExtent>>,
+}
+
+impl ExtentPool {
+    /// Keeps the vectors of the regions, which are done with. Backwards, so
+    /// laying the same out again takes back the vectors it filled before,
+    /// which have the capacity it needs
+    pub fn reclaim(
+        &mut self,
+        regions: impl IntoIterator<Item = HitRegion, IntoIter: DoubleEndedIterator>,
+    ) {
+        for mut region in regions.into_iter().rev() {
+            for mut text in region.texts.drain(..).rev() {
+                text.clusters.clear();
+                self.clusters.push(text.clusters);
+            }
+            self.texts.push(region.texts);
+        }
+    }
+
+    fn texts(&mut self) -> Vec<TextExtent> {
+        self.texts.pop().unwrap_or_default()
+    }
+
+    fn clusters(&mut self) -> Vec<ClusterExtent> {
+        self.clusters.pop().unwrap_or_default()
+    }
+}
+
+/// Written by the renderer after every frame, read by the state to resolve
+/// pointer events to widgets
+pub type HitRegions = Arc<std::sync::RwLock<Vec<HitRegion>>>;
+
+#[derive(Debug, Clone)]
 pub enum Renderable {
     Text {
-        text: String,
+        /// Borrowed for the fixed labels and icons, which are most of them
+        text: Cow<'static, str>,
         fg: u32,
         bg: u32,
     },
@@ -163,12 +663,278 @@ pub enum Renderable {
         height: f32,
         skip: f32,
     },
+    /// A group of renderables belonging to a single widget, along with the
+    /// extended information shown for it on hover
+    Region {
+        tooltip: Option<String>,
+        on_click: Option<Action>,
+        on_scroll: Option<ScrollActions>,
+        children: Vec<Renderable>,
+    },
+    /// Draws the children on a rounded rectangle, padded by half its height
+    /// on either side
+    Pill {
+        bg: u32,
+        /// Corner radius in bar heights
+        radius: f32,
+        border: u32,
+        /// In bar heights, 0 for no outline
+        border_width: f32,
+        children: Vec<Renderable>,
+    },
+    /// Fades the children in and out, redrawing every frame while shown
+    Pulse(Vec<Renderable>),
+    /// Draws the glyphs of the children with thicker outlines
+    Bold(Vec<Renderable>),
+    /// Runs the bound actions for clicks on the children, in place of what
+    /// the children do for those buttons
+    Bindings {
+        bindings: MouseBindings,
+        children: Vec<Renderable>,
+    },
+    /// Marks the children as the module of the name, so where it ended up
+    /// can be looked up in the hit regions
+    Module {
+        /// Shared with the layout config, so naming a module copies no string
+        name: Arc<str>,
+        children: Vec<Renderable>,
+    },
+    /// Cuts the children down to max_width bar heights, the text that
+    /// overflows ending in an ellipsis and whatever comes after it left out
+    Ellipsize {
+        max_width: f32,
+        children: Vec<Renderable>,
+    },
+    /// A square picture, size bar heights wide and tall
+    Image { image: Arc<Image>, size: f32 },
+    /// Draws a highlight behind the children, moving from behind the child
+    /// at from to the one at to over HIGHLIGHT_SLIDE_DURATION after since
+    Slide {
+        bg: u32,
+        from: usize,
+        to: usize,
+        since: Instant,
+        children: Vec<Renderable>,
+    },
+}
+
+/// Where the center section of the given width goes between the end of the
+/// left section and the start of the right one. Returns the x it starts at
+/// and the part of the bar it may be drawn on, all in bar heights, or None
+/// when nothing of it is left to draw
+pub fn place_center(
+    collision: CenterCollision,
+    width: f32,
+    left_end: f32,
+    right_start: f32,
+    bar_width: f32,
+) -> Option<(f32, Range<f32>)> {
+    let centered = bar_width / 2. - width / 2.;
+    let (start, visible) = match collision {
+        CenterCollision::Push => {
+            // Against the left section when there is no room either way
+            let start = centered.min(right_start - width).max(left_end);
+            (start, start..(start + width).min(right_start))
+        }
+        CenterCollision::Clip => (
+            centered,
+            centered.max(left_end)..(centered + width).min(right_start),
+        ),
+        CenterCollision::Yield => {
+            if centered < left_end || centered + width > right_start {
+                return None;
+            }
+            (centered, centered..centered + width)
+        }
+    };
+    (visible.start < visible.end).then_some((start, visible))
+}
+
+/// Width of the renderables once laid out, in bar heights
+pub fn measure(fonts: &FontSystem, renderables: &[Renderable]) -> f32 {
+    renderables
+        .iter()
+        .map(|renderable| match renderable {
+            Renderable::Text { text, .. } => fonts.width(text),
+            Renderable::Space(space) => *space,
+            Renderable::Box { skip, .. } => *skip,
+            Renderable::Image { size, .. } => *size,
+            Renderable::Pill { children, .. } => measure(fonts, children) + PILL_HEIGHT,
+            Renderable::Ellipsize {
+                max_width,
+                children,
+            } => measure(fonts, children).min(*max_width),
+            Renderable::Region { children, .. }
+            | Renderable::Pulse(children)
+            | Renderable::Bold(children)
+            | Renderable::Bindings { children, .. }
+            | Renderable::Module { children, .. }
+            | Renderable::Slide { children, .. } => measure(fonts, children),
+        })
+        .sum()
+}
+
+/// The renderables cut down to max_width bar heights, see
+/// Renderable::Ellipsize
+fn ellipsize(
+    fonts: &FontSystem,
+    renderables: &[Renderable],
+    mut max_width: f32,
+) -> Vec<Renderable> {
+    let mut cut = Vec::new();
+    for renderable in renderables {
+        let width = measure(fonts, std::slice::from_ref(renderable));
+        if width <= max_width {
+            cut.push(renderable.clone());
+            max_width -= width;
+            continue;
+        }
+        let mut renderable = renderable.clone();
+        match &mut renderable {
+            Renderable::Text { text, .. } => {
+                *text = fonts.ellipsize(text, max_width).into_owned().into()
+            }
+            Renderable::Pill { children, .. } => {
+                *children = ellipsize(fonts, children, max_width - PILL_HEIGHT)
+            }
+            Renderable::Region { children, .. }
+            | Renderable::Pulse(children)
+            | Renderable::Bold(children)
+            | Renderable::Bindings { children, .. }
+            | Renderable::Ellipsize { children, .. }
+            | Renderable::Module { children, .. }
+            | Renderable::Slide { children, .. } => {
+                *children = ellipsize(fonts, children, max_width)
+            }
+            // Can't be cut, so it goes with the rest
+            Renderable::Space(_) | Renderable::Box { .. } | Renderable::Image { .. } => break,
+        }
+        cut.push(renderable);
+        break;
+    }
+    cut
+}
+
+/// Whether a highlight is still on its way somewhere in the renderables
+fn sliding(renderables: &[Renderable]) -> bool {
+    renderables.iter().any(|renderable| match renderable {
+        Renderable::Slide { since, .. } => since.elapsed() < HIGHLIGHT_SLIDE_DURATION,
+        Renderable::Region { children, .. }
+        | Renderable::Pill { children, .. }
+        | Renderable::Pulse(children)
+        | Renderable::Bold(children)
+        | Renderable::Bindings { children, .. }
+        | Renderable::Ellipsize { children, .. }
+        | Renderable::Module { children, .. } => sliding(children),
+        _ => false,
+    })
+}
+
+impl Hash for Renderable {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        mem::discriminant(self).hash(state);
+        match self {
+            Renderable::Text { text, fg, bg } => (text, fg, bg).hash(state),
+            Renderable::Space(space) => space.to_bits().hash(state),
+            Renderable::Box {
+                fg,
+                bg,
+                width,
+                height,
+                skip,
+            } => (fg, bg, width.to_bits(), height.to_bits(), skip.to_bits()).hash(state),
+            Renderable::Region {
+                tooltip,
+                on_click,
+                on_scroll,
+                children,
+            } => (tooltip, on_click, on_scroll, children).hash(state),
+            Renderable::Pill {
+                bg,
+                radius,
+                border,
+                border_width,
+                children,
+            } => (bg, radius.to_bits(), border, border_width.to_bits(), children).hash(state),
+            Renderable::Pulse(children) => children.hash(state),
+            Renderable::Bold(children) => children.hash(state),
+            Renderable::Bindings { bindings, children } => (bindings, children).hash(state),
+            Renderable::Ellipsize {
+                max_width,
+                children,
+            } => (max_width.to_bits(), children).hash(state),
+            Renderable::Module { name, children } => (name, children).hash(state),
+            Renderable::Image { image, size } => (image.id, size.to_bits()).hash(state),
+            Renderable::Slide {
+                bg,
+                from,
+                to,
+                since,
+                children,
+            } => (bg, from, to, since, children).hash(state),
+        }
+    }
+}
+
+/// The widgets bar mode moves between, the regions with a click action from
+/// left to right. Regions around others come before them
+pub fn bar_mode_widgets(regions: &[HitRegion]) -> Vec<&HitRegion> {
+    let mut widgets: Vec<_> = regions
+        .iter()
+        .filter(|region| region.on_click.is_some())
+        .collect();
+    widgets.sort_by(|a, b| {
+        a.x.start
+            .total_cmp(&b.x.start)
+            .then(b.x.end.total_cmp(&a.x.end))
+    });
+    widgets
 }
 
+/// Outline around the widget picked in bar mode
+#[derive(Debug, Clone, Copy, PartialEq, Hash)]
+pub struct Highlight {
+    /// Index into bar_mode_widgets, past the end picks the last one
+    pub widget: usize,
+    pub color: u32,
+}
+
+#[derive(Hash)]
 pub struct RenderState {
     pub left: Vec<Renderable>,
     pub right: Vec<Renderable>,
     pub center: Vec<Renderable>,
+    /// Whether the bar should grab the keyboard, like while a popup is open
+    pub keyboard_focus: bool,
+    /// Color behind everything, in the same layout as the renderable colors
+    pub background: u32,
+    pub tooltip: Option<Tooltip>,
+    /// Shown in the middle of the output above the windows, x is left at 0
+    pub osd: Option<Tooltip>,
+    /// Shown below the bar like a tooltip, one entry per line
+    pub menu: Option<Tooltip>,
+    /// Moves the bar off the screen, see Renderer::update_visibility
+    pub hidden: bool,
+    pub highlight: Option<Highlight>,
+}
+
+/// Extended information on the hovered widget, shown below the bar
+#[derive(Debug, Clone, PartialEq, Hash)]
+pub struct Tooltip {
+    /// One line of the tooltip per line of text
+    pub text: String,
+    /// Surface pixels from the left of the bar
+    pub x: u32,
+    pub fg: u32,
+    pub bg: u32,
+}
+
+impl RenderState {
+    fn hash_value(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.hash(&mut hasher);
+        hasher.finish()
+    }
 }
 
 const SQUARE: &[Vertex] = &[
@@ -192,43 +958,72 @@ const SQUARE: &[Vertex] = &[
 
 const SQUARE_INDICES: &[u16] = &[0, 1, 3, 3, 1, 2];
 
+/// Glyphs made before the first frame, the others as they come up
+const PRELOADED_CHARS: &str =
+    "|QWERTYUIOPASDFGHJKLZXCVBNMqwertyuiopasdfghjklzxcvbnm1234567890[];',./<>?:\"{}+_)(*&^%$#@!~󱞁`= ";
+
+/// Instances the renderer starts out with, enough for a typical bar
+const INITIAL_INSTANCE_CAPACITY: u64 = 1024;
+
+/// Height of pills in bar heights, leaving a little of the bar around them
+const PILL_HEIGHT: f32 = 0.85;
+
+/// Corner radius and outline width of the bar mode highlight, in bar heights
+const HIGHLIGHT_RADIUS: f32 = 0.2;
+const HIGHLIGHT_BORDER_WIDTH: f32 = 0.06;
+
+/// How long a highlight takes to slide over, see Renderable::Slide
+const HIGHLIGHT_SLIDE_DURATION: Duration = Duration::from_millis(150);
+
+/// Compositors hold back frame callbacks for surfaces that can't be seen, so
+/// one pending for this long means something covers the bar
+const OCCLUSION_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// How often animations are drawn on X11, which has no frame callbacks
+const X11_FRAME: Duration = Duration::from_millis(16);
+
+/// Size in bytes each font curve buffer starts out with, grown in update_font
+const INITIAL_FONT_BUFFER_SIZE: u64 = 1024 * 1024;
+
 impl Renderer {
+    #[allow(clippy::too_many_arguments)]
     pub async fn new(
-        wayland_conn: &wayland_client::Connection,
-        wayland_surface: &WlSurface,
+        target: SurfaceTarget,
         width: u32,
         height: u32,
+        hit_regions: HitRegions,
+        config: &Config,
+        fonts: Arc<FontSystem>,
+        wayland: Option<WaylandOutput>,
+        occluded: watch::Sender<bool>,
     ) -> Self {
         let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor {
             backends: wgpu::Backends::all(),
             ..Default::default()
         });
+        let surface = BoundSurface::new(target, |target| {
+            Self::create_surface(&instance, target.raw_handles())
+        });
 
-        let raw_display_handle = RawDisplayHandle::Wayland(WaylandDisplayHandle::new(
-            NonNull::new(wayland_conn.backend().display_ptr() as *mut _)
-                .expect("Wayland display pointer to be not null"),
-        ));
-
-        let raw_window_handle = RawWindowHandle::Wayland(WaylandWindowHandle::new(
-            NonNull::new(wayland_surface.id().as_ptr() as *mut _)
-                .expect("Wayland surface pointer to be not null"),
-        ));
-        let surface = unsafe {
-            instance
-                .create_surface_unsafe(wgpu::SurfaceTargetUnsafe::RawHandle {
-                    raw_display_handle,
-                    raw_window_handle,
+        let gpu_config = &config.gpu;
+        let adapter = match Self::adapter_by_name(&instance, &surface, gpu_config) {
+            Some(adapter) => adapter,
+            None => instance
+                .request_adapter(&wgpu::RequestAdapterOptions {
+                    power_preference: gpu_config.power_preference.into(),
+                    compatible_surface: Some(&*surface),
+                    ..Default::default()
                 })
-                .unwrap()
+                .await
+                .expect("Failed to find suitable adapter"),
         };
-
-        let adapter = instance
-            .request_adapter(&wgpu::RequestAdapterOptions {
-                compatible_surface: Some(&surface),
-                ..Default::default()
-            })
-            .await
-            .expect("Failed to find suitable adapter");
+        let adapter_info = adapter.get_info();
+        log::info!(
+            "Using {} ({:?}, {:?})",
+            adapter_info.name,
+            adapter_info.device_type,
+            adapter_info.backend
+        );
 
         let device_descriptor = DeviceDescriptor {
             ..Default::default()
@@ -238,11 +1033,7 @@ impl Renderer {
             .await
             .expect("Failed to request device");
 
-        // Loading the font
-        // Need to write custom code for this part
-        let font_container = FontContainer::new(
-            "|QWERTYUIOPASDFGHJKLZXCVBNMqwertyuiopasdfghjklzxcvbnm1234567890[];',./<>?:\"{}+_)(*&^%$#@!~󱞁`= ",
-        );
+        let font_container = FontContainer::new(PRELOADED_CHARS, fonts.clone());
         // Load the shaders from disk
         let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
             label: None,
@@ -256,7 +1047,7 @@ impl Renderer {
                 contents: bytemuck::cast_slice(&[global_transform_uniform]),
                 usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
             });
-        let pipeline_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
             entries: &[
                 wgpu::BindGroupLayoutEntry {
                     binding: 0,
@@ -304,6 +1095,22 @@ impl Renderer {
                     },
                     count: None,
                 },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 5,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 6,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
             ],
             label: Some("total_bind_group_layout"),
         });
@@ -323,57 +1130,54 @@ impl Renderer {
             border_color: None,
         });
 
-        let font_lines_points_buffer =
-            device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                label: Some("Font Lines texture"),
-                contents: &[0; 1024 * 1024],
-                usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
-            });
+        // Images are scaled down to their size on the bar, which only takes
+        // a few pixels of blur from linear filtering
+        let image_sampler = device.create_sampler(&SamplerDescriptor {
+            label: Some("Image Sampler"),
+            mag_filter: FilterMode::Linear,
+            min_filter: FilterMode::Linear,
+            ..Default::default()
+        });
+        let image_texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Image Atlas"),
+            size: wgpu::Extent3d {
+                width: ATLAS_SIZE,
+                height: ATLAS_SIZE,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            // Not sRGB, so image colors come out like every other color
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        let image_view = image_texture.create_view(&wgpu::TextureViewDescriptor::default());
 
+        let font_lines_points_buffer =
+            Self::create_font_buffer(&device, "Font Lines texture", INITIAL_FONT_BUFFER_SIZE);
         let font_quadratic_points_buffer =
-            device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                label: Some("Font Quad texture"),
-                contents: &[0; 1024 * 1024],
-                usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
-            });
-
+            Self::create_font_buffer(&device, "Font Quad texture", INITIAL_FONT_BUFFER_SIZE);
         let font_cubic_points_buffer =
-            device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                label: Some("Font Cubic texture"),
-                contents: &[0; 1024 * 1024],
-                usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
-            });
+            Self::create_font_buffer(&device, "Font Cubic texture", INITIAL_FONT_BUFFER_SIZE);
 
-        let pipeline_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &pipeline_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: global_transform_uniform_buffer.as_entire_binding(),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::Sampler(&sampler),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 2,
-                    resource: font_lines_points_buffer.as_entire_binding(),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 3,
-                    resource: font_quadratic_points_buffer.as_entire_binding(),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 4,
-                    resource: font_cubic_points_buffer.as_entire_binding(),
-                },
+        let pipeline_bind_group = Self::create_bind_group(
+            &device,
+            &bind_group_layout,
+            &global_transform_uniform_buffer,
+            &sampler,
+            [
+                &font_lines_points_buffer,
+                &font_quadratic_points_buffer,
+                &font_cubic_points_buffer,
             ],
-            label: Some("pipeline_bind_group"),
-        });
+            (&image_view, &image_sampler),
+        );
 
         let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
             label: None,
-            bind_group_layouts: &[&pipeline_layout],
+            bind_group_layouts: &[&bind_group_layout],
             push_constant_ranges: &[],
         });
 
@@ -393,7 +1197,13 @@ impl Renderer {
                 module: &shader,
                 entry_point: Some("fs_main"),
                 compilation_options: Default::default(),
-                targets: &[Some(swapchain_format.into())],
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: swapchain_format,
+                    // Widgets with a transparent background show the bar
+                    // background through
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
             }),
             primitive: wgpu::PrimitiveState::default(),
             depth_stencil: None,
@@ -401,6 +1211,7 @@ impl Renderer {
             multiview: None,
             cache: None,
         });
+        let format = swapchain_format;
 
         let square_vb = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("Square Vertex Buffer"),
@@ -414,19 +1225,48 @@ impl Renderer {
             usage: wgpu::BufferUsages::INDEX,
         });
 
-        // You can now only create 128 squares
-        let instance_buffer = device.create_buffer(&BufferDescriptor {
-            label: Some("Instance Buffer"),
-            size: 1024 * mem::size_of::<Instance>() as u64,
-            mapped_at_creation: false,
-            usage: wgpu::BufferUsages::VERTEX.union(wgpu::BufferUsages::COPY_DST),
-        });
+        // Grown in draw_frame when a bar needs more instances than this
+        let instance_buffer = Self::create_instance_buffer(&device, INITIAL_INSTANCE_CAPACITY);
 
         Self {
+            wgpu_instance: instance,
             font_lines_points_buffer,
             font_quadratic_points_buffer,
             font_cubic_points_buffer,
+            fonts,
             font_sdf: font_container,
+            image_atlas: ImageAtlas::default(),
+            image_texture,
+            image_view,
+            image_sampler,
+            cache: config.cache.clone(),
+            leak_check: LeakCheck::default(),
+            center_collision: config.layout.center_collision,
+            hit_regions,
+            extent_pool: ExtentPool::default(),
+            wayland,
+            tooltip: None,
+            osd: None,
+            menu: None,
+            layer_closed: false,
+            frame_requested_at: None,
+            occluded,
+            started_at: Instant::now(),
+            animating: false,
+            last_activity: Instant::now(),
+            last_drawn: Instant::now(),
+            idle_after: Duration::from_secs(config.gpu.idle_after),
+            idle_frame: Duration::from_secs(1) / config.gpu.idle_fps.max(1),
+            keyboard_focus: false,
+            hidden: false,
+            slide: Slide::shown(Duration::from_millis(config.autohide.duration)),
+            autohide: config.autohide.enabled,
+            position: config.position,
+            latest_state: None,
+            drawn_state_hash: None,
+            instances: Vec::with_capacity(INITIAL_INSTANCE_CAPACITY as usize),
+            drawn_instances: Vec::with_capacity(INITIAL_INSTANCE_CAPACITY as usize),
+            frame_ready: true,
             width,
             height,
             adapter,
@@ -434,98 +1274,382 @@ impl Renderer {
             queue,
             surface,
             render_pipeline,
+            format,
             square_vb,
             square_ib,
             instance_buffer,
             square_num_vertices: SQUARE_INDICES.len() as u32,
             global_transform_uniform_buffer,
             pipeline_bind_group,
+            bind_group_layout,
+            sampler,
         }
     }
 
-    fn update_font(&self) {
-        self.queue.write_buffer(
-            &self.font_lines_points_buffer,
-            0,
-            bytemuck::cast_slice(&self.font_sdf.linear_points_buffer),
-        );
-        self.queue.write_buffer(
-            &self.font_quadratic_points_buffer,
-            0,
-            bytemuck::cast_slice(&self.font_sdf.quadratic_points_buffer),
-        );
-        self.queue.write_buffer(
-            &self.font_cubic_points_buffer,
-            0,
-            bytemuck::cast_slice(&self.font_sdf.cubic_points_buffer),
-        );
+    fn create_instance_buffer(device: &wgpu::Device, capacity: u64) -> Buffer {
+        device.create_buffer(&BufferDescriptor {
+            label: Some("Instance Buffer"),
+            size: capacity * mem::size_of::<Instance>() as u64,
+            mapped_at_creation: false,
+            usage: wgpu::BufferUsages::VERTEX.union(wgpu::BufferUsages::COPY_DST),
+        })
     }
 
-    fn to_renderable(
-        &mut self,
-        renderables: &Vec<Renderable>,
-        initial_skip: f32,
-    ) -> (Vec<Instance>, f32) {
-        let mut instances = Vec::new();
+    /// The surface keeps raw pointers into the target the handles are of,
+    /// which BoundSurface keeps alive for as long as the surface
+    fn create_surface(
+        instance: &wgpu::Instance,
+        (raw_display_handle, raw_window_handle): (RawDisplayHandle, RawWindowHandle),
+    ) -> wgpu::Surface<'static> {
+        unsafe {
+            instance
+                .create_surface_unsafe(wgpu::SurfaceTargetUnsafe::RawHandle {
+                    raw_display_handle,
+                    raw_window_handle,
+                })
+                .unwrap()
+        }
+    }
+
+    /// Moves drawing over to a layer surface made after the old one was
+    /// closed, nothing is drawn until it is configured
+    fn replace_layer(&mut self, layer: LayerSurface) {
+        let Some(wayland_conn) = self
+            .surface
+            .owner()
+            .wayland()
+            .map(|target| target.wayland_conn.clone())
+        else {
+            return;
+        };
+        let target = SurfaceTarget::Wayland(WaylandTarget {
+            wayland_conn,
+            layer,
+        });
+        let instance = &self.wgpu_instance;
+        self.surface.replace(target, |target| {
+            Self::create_surface(instance, target.raw_handles())
+        });
+        self.keyboard_focus = false;
+        // The new layer surface starts out shown
+        self.hidden = false;
+        self.slide = Slide::shown(self.slide.duration);
+        self.update_visibility();
+        self.layer_closed = false;
+        self.drawn_state_hash = None;
+        self.frame_ready = false;
+        self.frame_requested_at = None;
+    }
+
+    /// Starts sliding the bar off the top of the screen or back, giving the
+    /// space it reserves to windows while it's away. The margin moves with
+    /// every frame drawn, see apply_slide
+    fn update_visibility(&mut self) {
+        let Some(hidden) = self.latest_state.as_ref().map(|state| state.hidden) else {
+            return;
+        };
+        if hidden == self.hidden {
+            return;
+        }
+        self.hidden = hidden;
+        // Nothing slides on X11, the window goes away
+        #[cfg(feature = "x11")]
+        if let SurfaceTarget::X11(window) = self.surface.owner() {
+            window.set_hidden(hidden);
+            return;
+        }
+        let Some(WaylandTarget {
+            wayland_conn,
+            layer,
+        }) = self.surface.owner().wayland()
+        else {
+            return;
+        };
+        layer.set_exclusive_zone(if hidden || self.autohide { 0 } else { self.height as i32 });
+        if hidden {
+            self.slide.towards(1.);
+            return;
+        }
+        self.slide.towards(0.);
+        // No frame callbacks come for a surface that is all off the screen,
+        // so a pixel of it is brought back to get them going again
+        let peek = 1. - 1. / self.height as f32;
+        self.slide.from = self.slide.from.min(peek);
+        self.apply_slide();
+        layer.commit();
+        if let Err(e) = wayland_conn.flush() {
+            log::error!("Could not flush the Wayland connection: {e}");
+        }
+        self.frame_ready = true;
+    }
+
+    /// Moves the layer surface to where the slide is now, taking effect with
+    /// the next commit
+    fn apply_slide(&self) {
+        let Some(WaylandTarget { layer, .. }) = self.surface.owner().wayland() else {
+            return;
+        };
+        let offset = (self.slide.offset() * self.height as f32).floor() as i32;
+        match self.position {
+            BarPosition::Top => layer.set_margin(-offset, 0, 0, 0),
+            BarPosition::Left => layer.set_margin(0, 0, 0, -offset),
+            BarPosition::Right => layer.set_margin(0, -offset, 0, 0),
+        }
+    }
+
+    /// Width and height of the bar surface in pixels
+    fn surface_size(&self) -> (u32, u32) {
+        self.position.along((self.width, self.height))
+    }
+
+    /// Reallocates the instance buffer if it can't hold count instances,
+    /// doubling so a slowly growing title doesn't reallocate every frame.
+    /// Returns whether it did, leaving the new buffer empty
+    fn ensure_instance_capacity(&mut self, count: usize) -> bool {
+        let capacity = self.instance_buffer.size() / mem::size_of::<Instance>() as u64;
+        if count as u64 <= capacity {
+            return false;
+        }
+        let new_capacity = (count as u64).next_power_of_two();
+        log::info!("Growing the instance buffer from {capacity} to {new_capacity} instances");
+        self.instance_buffer.destroy();
+        self.instance_buffer = Self::create_instance_buffer(&self.device, new_capacity);
+        true
+    }
+
+    /// The adapter named in the config, if there is one that can draw to the
+    /// surface
+    fn adapter_by_name(
+        instance: &wgpu::Instance,
+        surface: &wgpu::Surface,
+        gpu_config: &GpuConfig,
+    ) -> Option<wgpu::Adapter> {
+        let name = gpu_config.adapter.as_ref()?.to_lowercase();
+        let adapters = instance.enumerate_adapters(wgpu::Backends::all());
+        let adapter = adapters.into_iter().find(|adapter| {
+            adapter.get_info().name.to_lowercase().contains(&name)
+                && adapter.is_surface_supported(surface)
+        });
+        if adapter.is_none() {
+            log::warn!("No adapter matching {name:?} can draw the bar, falling back to the power preference");
+        }
+        adapter
+    }
+
+    fn create_font_buffer(device: &wgpu::Device, label: &str, size: u64) -> Buffer {
+        device.create_buffer(&BufferDescriptor {
+            label: Some(label),
+            size,
+            mapped_at_creation: false,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        })
+    }
+
+    fn create_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        global_transform_uniform_buffer: &Buffer,
+        sampler: &wgpu::Sampler,
+        [lines, quadratic, cubic]: [&Buffer; 3],
+        (image_view, image_sampler): (&wgpu::TextureView, &wgpu::Sampler),
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: global_transform_uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: lines.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: quadratic.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: cubic.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: wgpu::BindingResource::TextureView(image_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 6,
+                    resource: wgpu::BindingResource::Sampler(image_sampler),
+                },
+            ],
+            label: Some("pipeline_bind_group"),
+        })
+    }
+
+    /// Reallocates the font buffer if the curves don't fit anymore, returns
+    /// whether it did, in which case the bind group is stale
+    fn grow_font_buffer(
+        device: &wgpu::Device,
+        buffer: &mut Buffer,
+        label: &str,
+        needed: u64,
+    ) -> bool {
+        if needed <= buffer.size() {
+            return false;
+        }
+        let max_size = device.limits().max_storage_buffer_binding_size as u64;
+        let new_size = needed.next_power_of_two().min(max_size);
+        if new_size < needed {
+            log::error!("Font curves need {needed} bytes, more than the GPU allows in a single buffer ({max_size})");
+        }
+        log::info!("Growing {label} from {} to {new_size} bytes", buffer.size());
+        buffer.destroy();
+        *buffer = Self::create_font_buffer(device, label, new_size);
+        true
+    }
+
+    /// Uploads the images the atlas took in since the last frame
+    fn update_images(&mut self) {
+        for (slot, image) in self.image_atlas.take_pending() {
+            let (x, y) = ImageAtlas::slot_origin(slot);
+            self.queue.write_texture(
+                wgpu::TexelCopyTextureInfo {
+                    texture: &self.image_texture,
+                    mip_level: 0,
+                    origin: wgpu::Origin3d { x, y, z: 0 },
+                    aspect: wgpu::TextureAspect::All,
+                },
+                &image.pixels,
+                wgpu::TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(4 * IMAGE_SIZE),
+                    rows_per_image: Some(IMAGE_SIZE),
+                },
+                wgpu::Extent3d {
+                    width: IMAGE_SIZE,
+                    height: IMAGE_SIZE,
+                    depth_or_array_layers: 1,
+                },
+            );
+        }
+    }
+
+    fn update_font(&mut self) {
+        let sizes = [
+            self.font_sdf.linear_points_buffer.len(),
+            self.font_sdf.quadratic_points_buffer.len(),
+            self.font_sdf.cubic_points_buffer.len(),
+        ]
+        .map(|len| (len * mem::size_of::<f32>()) as u64);
+        let mut grown = false;
+        for ((buffer, label), size) in [
+            (&mut self.font_lines_points_buffer, "Font Lines texture"),
+            (&mut self.font_quadratic_points_buffer, "Font Quad texture"),
+            (&mut self.font_cubic_points_buffer, "Font Cubic texture"),
+        ]
+        .into_iter()
+        .zip(sizes)
+        {
+            grown |= Self::grow_font_buffer(&self.device, buffer, label, size);
+        }
+        if grown {
+            self.pipeline_bind_group = Self::create_bind_group(
+                &self.device,
+                &self.bind_group_layout,
+                &self.global_transform_uniform_buffer,
+                &self.sampler,
+                [
+                    &self.font_lines_points_buffer,
+                    &self.font_quadratic_points_buffer,
+                    &self.font_cubic_points_buffer,
+                ],
+                (&self.image_view, &self.image_sampler),
+            );
+        }
+        for (buffer, points) in [
+            (&self.font_lines_points_buffer, &self.font_sdf.linear_points_buffer),
+            (&self.font_quadratic_points_buffer, &self.font_sdf.quadratic_points_buffer),
+            (&self.font_cubic_points_buffer, &self.font_sdf.cubic_points_buffer),
+        ] {
+            // Only possible when the GPU limit was hit, better to lose some
+            // glyphs than to fail validation
+            let len = points.len().min(buffer.size() as usize / mem::size_of::<f32>());
+            self.queue
+                .write_buffer(buffer, 0, bytemuck::cast_slice(&points[..len]));
+        }
+    }
+
+
+    /// Lays the renderables out from initial_skip on, loading the glyphs
+    /// and images they need into the font container and atlas. Appends the
+    /// instances, so a vector kept from the last frame doesn't allocate
+    /// again, and returns where the last one ended, with the regions in
+    /// units of the bar height. The extents of the regions come out of the
+    /// pool. Uploading newly loaded glyphs and images is up to the caller
+    #[allow(clippy::too_many_arguments)]
+    pub fn layout(
+        font_sdf: &mut FontContainer,
+        fonts: &FontSystem,
+        images: &mut ImageAtlas,
+        renderables: &[Renderable],
+        initial_skip: f32,
+        instances: &mut Vec<Instance>,
+        regions: &mut Vec<HitRegion>,
+        pool: &mut ExtentPool,
+        mut texts: Option<&mut Vec<TextExtent>>,
+    ) -> f32 {
         let mut skip = initial_skip;
         for item in renderables.into_iter() {
             match item {
                 Renderable::Text { text, fg, bg } => {
-                    let id = match text
-                        .chars()
-                        .map(|c| self.font_sdf.font_arc.glyph_id(c))
-                        .next()
-                    {
-                        Some(id) => id,
-                        None => continue,
+                    let start = skip;
+                    let mut clusters = match texts {
+                        Some(_) => pool.clusters(),
+                        None => Vec::new(),
                     };
-
-                    let glyph_info = match self.font_sdf.load_char_with_id(id) {
-                        Some(x) => x,
-                        None => {
-                            skip += self.font_sdf.font_arc.h_advance_unscaled(id)
-                                / self.font_sdf.units_per_em;
-                            continue;
+                    for glyph in fonts.shape_normalized(text).iter() {
+                        if let Some(glyph_info) = font_sdf.load_glyph(glyph.face, glyph.glyph_id) {
+                            instances.push(Instance {
+                                position: [
+                                    skip + glyph.x_offset + glyph_info.offset.x,
+                                    -0.5 + glyph.y_offset + glyph_info.offset.y,
+                                ],
+                                scale: [glyph_info.dimensions.x, -glyph_info.dimensions.y],
+                                fg: *fg,
+                                bg: *bg,
+                                lines_off: glyph_info.line_off,
+                                quadratic_off: glyph_info.bez2_off,
+                                cubic_off: glyph_info.bez3_off,
+                                radius: 0.,
+                                border: 0,
+                                border_width: 0.,
+                                flags: 0,
+                                image_slot: 0,
+                            });
                         }
-                    };
-                    instances.push(Instance {
-                        position: [skip + glyph_info.offset.x, -0.5 + glyph_info.offset.y],
-                        scale: [glyph_info.dimensions.x, -glyph_info.dimensions.y],
-                        fg: *fg,
-                        bg: *bg,
-                        lines_off: glyph_info.line_off,
-                        quadratic_off: glyph_info.bez2_off,
-                        cubic_off: glyph_info.bez3_off,
-                    });
-                    skip += glyph_info.advance;
-
-                    for (prev_id, id) in
-                        Vec::from_iter(text.chars().map(|c| self.font_sdf.font_arc.glyph_id(c)))
-                            .into_iter()
-                            .tuple_windows()
-                    {
-                        skip -= self.font_sdf.font_arc.kern_unscaled(prev_id, id);
-                        let glyph_info = match self.font_sdf.load_char_with_id(id) {
-                            Some(x) => {
-                                self.update_font();
-                                x
-                            }
-                            None => {
-                                skip += self.font_sdf.font_arc.h_advance_unscaled(id)
-                                    / self.font_sdf.units_per_em;
-                                continue;
+                        if texts.is_some() {
+                            let x = skip..skip + glyph.x_advance;
+                            match clusters.last_mut() {
+                                Some(cluster) if cluster.byte == glyph.cluster as usize => {
+                                    cluster.x.end = x.end;
+                                }
+                                _ => clusters.push(ClusterExtent {
+                                    byte: glyph.cluster as usize,
+                                    x,
+                                }),
                             }
-                        };
-                        instances.push(Instance {
-                            position: [skip + glyph_info.offset.x, -0.5 + glyph_info.offset.y],
-                            scale: [glyph_info.dimensions.x, -glyph_info.dimensions.y],
-                            fg: *fg,
-                            bg: *bg,
-                            lines_off: glyph_info.line_off,
-                            quadratic_off: glyph_info.bez2_off,
-                            cubic_off: glyph_info.bez3_off,
+                        }
+                        // Glyphs without an outline, like spaces, still advance
+                        skip += glyph.x_advance;
+                    }
+                    if let Some(texts) = texts.as_deref_mut() {
+                        texts.push(TextExtent {
+                            x: start..skip,
+                            clusters,
                         });
-                        skip += glyph_info.advance;
                     }
                 }
                 Renderable::Space(space) => {
@@ -546,26 +1670,315 @@ impl Renderer {
                         lines_off: GlyphOffLen::zeroed(),
                         quadratic_off: GlyphOffLen::zeroed(),
                         cubic_off: GlyphOffLen::zeroed(),
+                        radius: 0.,
+                        border: 0,
+                        border_width: 0.,
+                        flags: 0,
+                        image_slot: 0,
                     });
                     skip += off
                 }
+                Renderable::Region {
+                    tooltip,
+                    on_click,
+                    on_scroll,
+                    children,
+                } => {
+                    let mut region_texts = pool.texts();
+                    let child_skip = Self::layout(
+                        font_sdf,
+                        fonts,
+                        images,
+                        children,
+                        skip,
+                        instances,
+                        regions,
+                        pool,
+                        Some(&mut region_texts),
+                    );
+                    regions.push(HitRegion {
+                        x: skip..child_skip,
+                        tooltip: tooltip.clone(),
+                        on_click: on_click.clone(),
+                        on_middle_click: None,
+                        on_right_click: None,
+                        on_scroll: on_scroll.clone(),
+                        module: None,
+                        texts: region_texts,
+                    });
+                    skip = child_skip;
+                }
+                Renderable::Ellipsize {
+                    max_width,
+                    children,
+                } => {
+                    skip = if measure(fonts, children) <= *max_width {
+                        Self::layout(
+                            font_sdf,
+                            fonts,
+                            images,
+                            children,
+                            skip,
+                            instances,
+                            regions,
+                            pool,
+                            texts.as_deref_mut(),
+                        )
+                    } else {
+                        let children = ellipsize(fonts, children, *max_width);
+                        Self::layout(
+                            font_sdf,
+                            fonts,
+                            images,
+                            &children,
+                            skip,
+                            instances,
+                            regions,
+                            pool,
+                            texts.as_deref_mut(),
+                        )
+                    };
+                }
+                Renderable::Module { name, children } => {
+                    let child_skip = Self::layout(
+                        font_sdf,
+                        fonts,
+                        images,
+                        children,
+                        skip,
+                        instances,
+                        regions,
+                        pool,
+                        texts.as_deref_mut(),
+                    );
+                    regions.push(HitRegion {
+                        x: skip..child_skip,
+                        tooltip: None,
+                        on_click: None,
+                        on_middle_click: None,
+                        on_right_click: None,
+                        on_scroll: None,
+                        module: Some(name.clone()),
+                        texts: Vec::new(),
+                    });
+                    skip = child_skip;
+                }
+                Renderable::Bindings { bindings, children } => {
+                    // Goes before the regions of the children, so clicks
+                    // find it first
+                    let index = regions.len();
+                    regions.push(HitRegion {
+                        x: skip..skip,
+                        tooltip: None,
+                        on_click: bindings.left.clone(),
+                        on_middle_click: bindings.middle.clone(),
+                        on_right_click: bindings.right.clone(),
+                        on_scroll: None,
+                        module: None,
+                        texts: Vec::new(),
+                    });
+                    let child_skip = Self::layout(
+                        font_sdf,
+                        fonts,
+                        images,
+                        children,
+                        skip,
+                        instances,
+                        regions,
+                        pool,
+                        texts.as_deref_mut(),
+                    );
+                    regions[index].x = skip..child_skip;
+                    skip = child_skip;
+                }
+                Renderable::Pill {
+                    bg,
+                    radius,
+                    border,
+                    border_width,
+                    children,
+                } => {
+                    let padding = PILL_HEIGHT / 2.;
+                    // Pushed first so it ends up behind the children, its
+                    // width is only known once they are laid out
+                    let index = instances.len();
+                    instances.push(Instance {
+                        position: [skip, 0.],
+                        scale: [0., PILL_HEIGHT],
+                        fg: *bg,
+                        bg: *bg,
+                        lines_off: GlyphOffLen::zeroed(),
+                        quadratic_off: GlyphOffLen::zeroed(),
+                        cubic_off: GlyphOffLen::zeroed(),
+                        // A radius of 0 would be taken for a glyph
+                        radius: radius.max(f32::EPSILON),
+                        border: *border,
+                        border_width: *border_width,
+                        flags: 0,
+                        image_slot: 0,
+                    });
+                    let child_skip = Self::layout(
+                        font_sdf,
+                        fonts,
+                        images,
+                        children,
+                        skip + padding,
+                        instances,
+                        regions,
+                        pool,
+                        texts.as_deref_mut(),
+                    );
+                    instances[index].scale[0] = child_skip + padding - skip;
+                    skip = child_skip + padding;
+                }
+                Renderable::Image { image, size } => {
+                    instances.push(Instance {
+                        position: [skip, 0.],
+                        scale: [*size, *size],
+                        fg: 0,
+                        bg: 0,
+                        lines_off: GlyphOffLen::zeroed(),
+                        quadratic_off: GlyphOffLen::zeroed(),
+                        cubic_off: GlyphOffLen::zeroed(),
+                        radius: 0.,
+                        border: 0,
+                        border_width: 0.,
+                        flags: InstanceFlags::IMAGE.bits(),
+                        image_slot: images.load(image),
+                    });
+                    skip += size;
+                }
+                Renderable::Pulse(children) => {
+                    let first = instances.len();
+                    skip = Self::layout(
+                        font_sdf,
+                        fonts,
+                        images,
+                        children,
+                        skip,
+                        instances,
+                        regions,
+                        pool,
+                        texts.as_deref_mut(),
+                    );
+                    for instance in &mut instances[first..] {
+                        instance.flags |= InstanceFlags::PULSE.bits();
+                    }
+                }
+                Renderable::Bold(children) => {
+                    let first = instances.len();
+                    skip = Self::layout(
+                        font_sdf,
+                        fonts,
+                        images,
+                        children,
+                        skip,
+                        instances,
+                        regions,
+                        pool,
+                        texts.as_deref_mut(),
+                    );
+                    for instance in &mut instances[first..] {
+                        instance.flags |= InstanceFlags::BOLD.bits();
+                    }
+                }
+                Renderable::Slide {
+                    bg,
+                    from,
+                    to,
+                    since,
+                    children,
+                } => {
+                    // Laid out one by one for where the two children end up
+                    let first = instances.len();
+                    let (mut from_extent, mut to_extent) = (None, None);
+                    for (i, child) in children.iter().enumerate() {
+                        let child_skip = Self::layout(
+                            font_sdf,
+                            fonts,
+                            images,
+                            std::slice::from_ref(child),
+                            skip,
+                            instances,
+                            regions,
+                            pool,
+                            texts.as_deref_mut(),
+                        );
+                        if i == *from {
+                            from_extent = Some(skip..child_skip);
+                        }
+                        if i == *to {
+                            to_extent = Some(skip..child_skip);
+                        }
+                        skip = child_skip;
+                    }
+                    if let (Some(from), Some(to)) = (from_extent, to_extent) {
+                        let t = (since.elapsed().as_secs_f32()
+                            / HIGHLIGHT_SLIDE_DURATION.as_secs_f32())
+                        .min(1.);
+                        // Eases out, so it settles gently on the new child
+                        let progress = 1. - (1. - t).powi(3);
+                        let start = from.start + (to.start - from.start) * progress;
+                        let end = from.end + (to.end - from.end) * progress;
+                        // Goes in before the children so it ends up behind
+                        // them
+                        instances.insert(
+                            first,
+                            Instance {
+                                position: [start, 0.],
+                                scale: [end - start, PILL_HEIGHT],
+                                fg: *bg,
+                                bg: *bg,
+                                lines_off: GlyphOffLen::zeroed(),
+                                quadratic_off: GlyphOffLen::zeroed(),
+                                cubic_off: GlyphOffLen::zeroed(),
+                                // A radius of 0 would be taken for a glyph
+                                radius: f32::EPSILON,
+                                border: 0,
+                                border_width: 0.,
+                                flags: 0,
+                                image_slot: 0,
+                            },
+                        );
+                    }
+                }
             }
         }
-        (instances, skip)
+        skip
+    }
+
+    /// Dropping to no interactivity for a commit takes the focus away, on
+    /// demand comes back with the next frame so clicks focus the bar again
+    fn release_keyboard(&self) {
+        if self.keyboard_focus {
+            return;
+        }
+        if let Some(WaylandTarget { layer, .. }) = self.surface.owner().wayland() {
+            layer.set_keyboard_interactivity(KeyboardInteractivity::None);
+            layer.commit();
+            layer.set_keyboard_interactivity(KeyboardInteractivity::OnDemand);
+        }
     }
 
     fn draw_frame(&mut self, state: &RenderState) {
+        if state.keyboard_focus != self.keyboard_focus {
+            self.keyboard_focus = state.keyboard_focus;
+            // Takes effect with the commit presenting this frame
+            if let Some(WaylandTarget { layer, .. }) = self.surface.owner().wayland() {
+                layer.set_keyboard_interactivity(if state.keyboard_focus {
+                    KeyboardInteractivity::Exclusive
+                } else {
+                    KeyboardInteractivity::OnDemand
+                });
+            }
+        }
         let surface = &self.surface;
         let device = &self.device.clone();
         let queue = &self.queue.clone();
 
         // Wait for GPU to do stuff, so that get_current_texture doesn't timeout
-        surface.configure(
-            device,
-            &surface
-                .get_default_config(&self.adapter, self.width, self.height)
-                .expect("To be able to get default config for the surface"),
-        );
+        let (width, height) = self.surface_size();
+        surface.configure(device, &self.surface_config(surface, width, height));
 
         let surface_texture = surface
             .get_current_texture()
@@ -574,55 +1987,340 @@ impl Renderer {
             .texture
             .create_view(&wgpu::TextureViewDescriptor::default());
 
-        let (mut instances, left_skip) = self.to_renderable(&state.left, 0.0);
+        let clip = self.prepare_frame(state);
 
-        let (center_instances, center_skip) = self.to_renderable(&state.center, left_skip);
+        self.apply_slide();
+        self.animating = !self.slide.done()
+            || [&state.left, &state.center, &state.right]
+                .into_iter()
+                .any(|renderables| sliding(renderables))
+            || self
+                .instances
+                .iter()
+                .any(|instance| instance.flags & InstanceFlags::PULSE.bits() != 0);
+        queue.write_buffer(
+            &self.global_transform_uniform_buffer,
+            mem::offset_of!(GlobalTransformUniform, time) as wgpu::BufferAddress,
+            bytemuck::bytes_of(&self.animation_time()),
+        );
 
-        let width = center_skip - left_skip;
-        let bar_width = self.width as f32 / self.height as f32;
-        for instance in center_instances.into_iter() {
-            instances.push(Instance {
-                position: [
-                    instance.position[0] - left_skip + bar_width / 2. - width / 2.,
-                    instance.position[1],
-                ],
-                ..instance
-            });
+        let commands = self.encode_draw(
+            &texture_view,
+            self.clear_color(&self.surface, state.background),
+            &self.instance_buffer,
+            self.instances.len() as u32,
+            clip,
+        );
+        // Submit the command in the queue to execute
+        queue.submit(Some(commands));
+        match (self.surface.owner().wayland(), &self.wayland) {
+            // Requested before present so it is part of the commit wgpu does
+            (Some(WaylandTarget { layer, .. }), Some(wayland)) => {
+                let wayland_surface = layer.wl_surface();
+                wayland_surface.frame(&wayland.queue_handle, wayland_surface.clone());
+                self.frame_requested_at = Some(Instant::now());
+            }
+            // Presenting waits for the one before, the next frame is timed
+            // by next_timed_frame
+            _ => self.frame_ready = true,
         }
+        self.last_drawn = Instant::now();
+        surface_texture.present();
+    }
 
-        let (right_instances, right_skip) = self.to_renderable(&state.right, center_skip);
+    /// Bytes the glyphs, shaped text and image atlas take up
+    fn cache_usage(&self) -> usize {
+        let shaped = self
+            .fonts
+            .shaped
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .memory_usage();
+        self.font_sdf.memory_usage() + shaped + ATLAS_BYTES
+    }
 
-        let width = right_skip - center_skip;
+    /// Lays the state out into the instances, updates the hit regions and
+    /// uploads the instances, glyphs and images. Returns the clip for
+    /// encode_draw
+    fn prepare_frame(&mut self, state: &RenderState) -> Option<(Range<u32>, Range<u32>)> {
+        // Nothing laid out before this frame is drawn anymore, so the
+        // offsets trimming invalidates don't matter
+        let budget = self.cache.budget_bytes();
+        let shaped_budget = budget / 4;
+        self.fonts
+            .shaped
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .trim(shaped_budget);
+        self.font_sdf
+            .trim(budget.saturating_sub(shaped_budget + ATLAS_BYTES));
+        self.leak_check.tick(self.cache_usage());
+        self.font_sdf.begin_frame();
+        self.image_atlas.begin_frame();
 
+        // The last frame stays around to compare against, this one goes in
+        // the vector of the frame before, with the capacity it grew to
+        mem::swap(&mut self.instances, &mut self.drawn_instances);
+        self.instances.clear();
 
-        for instance in right_instances.into_iter() {
-            instances.push(Instance {
-                position: [
-                    instance.position[0] - center_skip + bar_width - width,
-                    instance.position[1],
-                ],
-                ..instance
-            });
+        let mut regions = Vec::new();
+        let mut left_width = Self::layout(&mut self.font_sdf, &self.fonts, &mut self.image_atlas, &state.left, 0.0, &mut self.instances, &mut regions, &mut self.extent_pool, None);
+        if self.cache.overlay {
+            let overlay = [
+                Renderable::Space(1.),
+                Renderable::Text {
+                    text: format!(
+                        "cache {:.1}/{}MiB glyphs {} runs {} restarts {}{}",
+                        self.cache_usage() as f32 / (1024. * 1024.),
+                        self.cache.budget,
+                        self.font_sdf.glyph_locations.len(),
+                        self.fonts
+                            .shaped
+                            .lock()
+                            .unwrap_or_else(PoisonError::into_inner)
+                            .len(),
+                        watchdog_restarts(),
+                        if self.leak_check.suspected {
+                            " leak?"
+                        } else {
+                            ""
+                        }
+                    )
+                    .into(),
+                    fg: 0xff00ffff,
+                    bg: 0x00000000,
+                },
+            ];
+            left_width = Self::layout(&mut self.font_sdf, &self.fonts, &mut self.image_atlas, &overlay, left_width, &mut self.instances, &mut regions, &mut self.extent_pool, None);
         }
 
+        // The other sections are laid out from 0 on their own, and moved
+        // into place once all of them are measured
+        let center_first = self.instances.len();
+        let mut center_regions = Vec::new();
+        let center_width =
+            Self::layout(&mut self.font_sdf, &self.fonts, &mut self.image_atlas, &state.center, 0., &mut self.instances, &mut center_regions, &mut self.extent_pool, None);
+        let right_first = self.instances.len();
+        let mut right_regions = Vec::new();
+        let right_width =
+            Self::layout(&mut self.font_sdf, &self.fonts, &mut self.image_atlas, &state.right, 0., &mut self.instances, &mut right_regions, &mut self.extent_pool, None);
 
-        queue.write_buffer(
-            &self.instance_buffer,
-            0,
-            bytemuck::cast_slice(instances.as_slice()),
+        let bar_width = self.width as f32 / self.height as f32;
+        let right_start = bar_width - right_width;
+        let center = place_center(
+            self.center_collision,
+            center_width,
+            left_width,
+            right_start,
+            bar_width,
         );
+        for instance in &mut self.instances[right_first..] {
+            *instance = instance.shifted(right_start);
+        }
+        let mut clip = None;
+        if let Some((start, visible)) = center {
+            for instance in &mut self.instances[center_first..right_first] {
+                *instance = instance.shifted(start);
+            }
+            regions.extend(center_regions.into_iter().filter_map(|region| {
+                let region = region.shifted(start);
+                let x = region.x.start.max(visible.start)..region.x.end.min(visible.end);
+                (x.start < x.end).then_some(HitRegion { x, ..region })
+            }));
+            let height = self.height as f32;
+            clip = Some((
+                center_first as u32..right_first as u32,
+                (visible.start * height).floor().max(0.) as u32
+                    ..((visible.end * height).ceil() as u32).min(self.width),
+            ));
+        } else {
+            self.instances.drain(center_first..right_first);
+        }
+
+        regions.extend(right_regions.into_iter().map(|region| region.shifted(right_start)));
+
+        // Last so it is drawn over the widget, and outside of the center clip
+        if let Some(highlight) = state.highlight {
+            let widgets = bar_mode_widgets(&regions);
+            if let Some(widget) = widgets.get(highlight.widget).or(widgets.last()) {
+                self.instances.push(Instance {
+                    position: [widget.x.start, 0.],
+                    scale: [widget.x.end - widget.x.start, PILL_HEIGHT],
+                    fg: 0,
+                    bg: 0,
+                    lines_off: GlyphOffLen::zeroed(),
+                    quadratic_off: GlyphOffLen::zeroed(),
+                    cubic_off: GlyphOffLen::zeroed(),
+                    radius: HIGHLIGHT_RADIUS,
+                    border: highlight.color,
+                    border_width: HIGHLIGHT_BORDER_WIDTH,
+                    flags: 0,
+                    image_slot: 0,
+                });
+            }
+        }
+
+        // Layout happens in units of the bar height, pointer events are in pixels
+        let height = self.height as f32;
+        let mut hit_regions = self
+            .hit_regions
+            .write()
+            .expect("The hit regions lock to never be poisoned");
+        self.extent_pool.reclaim(hit_regions.drain(..));
+        hit_regions.extend(regions.into_iter().map(|region| region.scaled(height)));
+        drop(hit_regions);
+
+        if self.position.vertical() {
+            for instance in &mut self.instances {
+                instance.flags |= InstanceFlags::ROTATED.bits();
+            }
+        }
+
+        // The buffer still holds the last frame, which a redraw for the
+        // tooltip or a pulse often leaves as it was
+        let grown = self.ensure_instance_capacity(self.instances.len());
+        let unchanged = bytemuck::cast_slice::<Instance, u8>(&self.instances)
+            == bytemuck::cast_slice::<Instance, u8>(&self.drawn_instances);
+        if grown || !unchanged {
+            self.queue.write_buffer(
+                &self.instance_buffer,
+                0,
+                bytemuck::cast_slice(self.instances.as_slice()),
+            );
+        }
 
         self.update_font();
+        self.update_images();
+        clip
+    }
+
+    /// Draws the latest state like draw_frame, but on a texture of the same
+    /// size instead of the surface, and writes that to a PNG at the path of
+    /// the screenshot. Waiting for the GPU and encoding happen on a blocking
+    /// thread, the renderer goes on drawing in the meantime
+    fn screenshot(&mut self, screenshot: Screenshot) {
+        let Some(state) = self.latest_state.take() else {
+            // The client may have hung up in the meantime
+            let _ = screenshot.done.send(Err(ScreenshotError::NothingToDraw));
+            return;
+        };
+        let copy = self.draw_offscreen(&state);
+        self.latest_state = Some(state);
+        let copy = match copy {
+            Ok(copy) => copy,
+            Err(e) => {
+                let _ = screenshot.done.send(Err(e));
+                return;
+            }
+        };
+        let device = self.device.clone();
+        tokio::task::spawn_blocking(move || {
+            let _ = screenshot.done.send(copy.save(&device, &screenshot.path));
+        });
+    }
+
+    /// Submits the state drawn on a texture and copied into a buffer, in
+    /// rows of 4 bytes a pixel
+    fn draw_offscreen(&mut self, state: &RenderState) -> Result<OffscreenCopy, ScreenshotError> {
+        if self.width == 0 || self.height == 0 {
+            return Err(ScreenshotError::NothingToDraw);
+        }
+        if !matches!(
+            self.format,
+            wgpu::TextureFormat::Rgba8Unorm
+                | wgpu::TextureFormat::Rgba8UnormSrgb
+                | wgpu::TextureFormat::Bgra8Unorm
+                | wgpu::TextureFormat::Bgra8UnormSrgb
+        ) {
+            return Err(ScreenshotError::UnsupportedFormat(self.format));
+        }
+        let (width, height) = self.surface_size();
+        let size = wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        };
+        let texture = self.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Screenshot Texture"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: self.format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        // Rows are copied out padded to the alignment wgpu wants
+        let row_bytes = 4 * width;
+        let padded_row_bytes = row_bytes.div_ceil(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT)
+            * wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        let buffer = self.device.create_buffer(&BufferDescriptor {
+            label: Some("Screenshot Buffer"),
+            size: padded_row_bytes as u64 * height as u64,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let clip = self.prepare_frame(state);
+        let draw = self.encode_draw(
+            &view,
+            self.clear_color(&self.surface, state.background),
+            &self.instance_buffer,
+            self.instances.len() as u32,
+            clip,
+        );
+        let mut encoder = self.device.create_command_encoder(&Default::default());
+        encoder.copy_texture_to_buffer(
+            wgpu::TexelCopyTextureInfo {
+                texture: &texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::TexelCopyBufferInfo {
+                buffer: &buffer,
+                layout: wgpu::TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_row_bytes),
+                    rows_per_image: Some(height),
+                },
+            },
+            size,
+        );
+        self.queue.submit([draw, encoder.finish()]);
+        Ok(OffscreenCopy {
+            buffer,
+            width,
+            height,
+            padded_row_bytes,
+            bgra: matches!(
+                self.format,
+                wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb
+            ),
+        })
+    }
 
-        let mut encoder = device.create_command_encoder(&Default::default());
+    /// Clears the view and draws count instances from the buffer on it. With
+    /// a clip, the instances in its range are only drawn on its range of
+    /// pixels along the bar, which have to be on the bar surface
+    fn encode_draw(
+        &self,
+        view: &wgpu::TextureView,
+        clear: wgpu::Color,
+        instance_buffer: &Buffer,
+        count: u32,
+        clip: Option<(Range<u32>, Range<u32>)>,
+    ) -> wgpu::CommandBuffer {
+        let mut encoder = self.device.create_command_encoder(&Default::default());
         {
             let mut renderpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                 label: None,
                 color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: &texture_view,
+                    view,
                     resolve_target: None,
                     ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                        load: wgpu::LoadOp::Clear(clear),
                         store: wgpu::StoreOp::Store,
                     },
                 })],
@@ -630,69 +2328,492 @@ impl Renderer {
                 timestamp_writes: None,
                 occlusion_query_set: None,
             });
-            renderpass.set_bind_group(0, &self.pipeline_bind_group, &[]);
-            renderpass.set_pipeline(&self.render_pipeline);
-            renderpass.set_vertex_buffer(0, self.square_vb.slice(..));
-            renderpass.set_vertex_buffer(1, self.instance_buffer.slice(..));
-            renderpass.set_index_buffer(self.square_ib.slice(..), IndexFormat::Uint16);
-            renderpass.draw_indexed(0..self.square_num_vertices, 0, 0..(instances.len() as u32));
+            if count > 0 {
+                renderpass.set_bind_group(0, &self.pipeline_bind_group, &[]);
+                renderpass.set_pipeline(&self.render_pipeline);
+                renderpass.set_vertex_buffer(0, self.square_vb.slice(..));
+                renderpass.set_vertex_buffer(1, instance_buffer.slice(..));
+                renderpass.set_index_buffer(self.square_ib.slice(..), IndexFormat::Uint16);
+                match clip {
+                    Some((clipped, x)) => {
+                        renderpass.draw_indexed(0..self.square_num_vertices, 0, 0..clipped.start);
+                        if x.start < x.end {
+                            let (width, height) = self.surface_size();
+                            if self.position.vertical() {
+                                renderpass.set_scissor_rect(0, x.start, width, x.end - x.start);
+                            } else {
+                                renderpass.set_scissor_rect(x.start, 0, x.end - x.start, height);
+                            }
+                            renderpass.draw_indexed(0..self.square_num_vertices, 0, clipped.clone());
+                            renderpass.set_scissor_rect(0, 0, width, height);
+                        }
+                        renderpass.draw_indexed(0..self.square_num_vertices, 0, clipped.end..count);
+                    }
+                    None => renderpass.draw_indexed(0..self.square_num_vertices, 0, 0..count),
+                }
+            }
         }
+        encoder.finish()
+    }
 
-        // Submit the command in the queue to execute
-        queue.submit(Some(encoder.finish()));
-        surface_texture.present();
+    /// Lays the tooltip out a bar height per line, padded on either side like
+    /// a pill. Returns the instances and the size of the surface in pixels
+    fn layout_tooltip(&mut self, tooltip: &Tooltip) -> (Vec<Instance>, u32, u32) {
+        let lines: Vec<&str> = tooltip.text.lines().collect();
+        let padding = PILL_HEIGHT / 2.;
+        let mut instances = Vec::new();
+        let mut width = 2. * padding;
+        let mut regions = Vec::new();
+        for (i, line) in lines.iter().enumerate() {
+            let text = [Renderable::Text {
+                text: line.to_string().into(),
+                fg: tooltip.fg,
+                bg: tooltip.bg,
+            }];
+            let first = instances.len();
+            let skip =
+                Self::layout(&mut self.font_sdf, &self.fonts, &mut self.image_atlas, &text, padding, &mut instances, &mut regions, &mut self.extent_pool, None);
+            // Lines go down from the top, in half bar heights from the center
+            let y = lines.len() as f32 - 2. * i as f32 - 1.;
+            for instance in &mut instances[first..] {
+                instance.position[1] += y;
+            }
+            width = width.max(skip + padding);
+        }
+        let height = self.height * lines.len().max(1) as u32;
+        (instances, (width * self.height as f32).ceil() as u32, height)
+    }
+
+    fn popup(&mut self, popup: Popup) -> &mut Option<TooltipSurface> {
+        match popup {
+            Popup::Tooltip => &mut self.tooltip,
+            Popup::Osd => &mut self.osd,
+            Popup::Menu => &mut self.menu,
+        }
+    }
+
+    /// Makes the surface for the popup of the latest state, or
+    /// resizes the one already there if it stays in place
+    fn update_popup(&mut self, popup: Popup) {
+        let wanted = self.latest_state.as_ref().and_then(|state| match popup {
+            Popup::Tooltip => state.tooltip.clone(),
+            Popup::Osd => state.osd.clone(),
+            Popup::Menu => state.menu.clone(),
+        });
+        if self.popup(popup).as_ref().map(|tooltip| &tooltip.shown) == wanted.as_ref() {
+            return;
+        }
+        let Some(wanted) = wanted.filter(|_| !self.layer_closed) else {
+            let dropped = self.popup(popup).take().is_some();
+            if let Some(wayland) = self.wayland.as_ref().filter(|_| dropped) {
+                wayland.tooltip_layers.flush();
+            }
+            return;
+        };
+        let (_, width, height) = self.layout_tooltip(&wanted);
+        let slot = match popup {
+            Popup::Tooltip => &mut self.tooltip,
+            Popup::Osd => &mut self.osd,
+            Popup::Menu => &mut self.menu,
+        };
+        let Some(wayland) = &self.wayland else {
+            return;
+        };
+        if let Some(tooltip) = slot.as_mut().filter(|tooltip| tooltip.shown.x == wanted.x) {
+            // Only the text changed, it is redrawn once the new size is
+            // configured
+            let layer = &tooltip.surface.owner().layer;
+            layer.set_size(width, height);
+            layer.commit();
+            wayland.tooltip_layers.flush();
+            tooltip.shown = wanted;
+            tooltip.width = width;
+            tooltip.height = height;
+            return;
+        }
+        // The old one is gone before the new one shows up
+        *slot = None;
+        let Some(wayland_conn) = self
+            .surface
+            .owner()
+            .wayland()
+            .map(|target| target.wayland_conn.clone())
+        else {
+            return;
+        };
+        let layer = match popup {
+            Popup::Tooltip => wayland.tooltip_layers.create(wanted.x, width, height),
+            Popup::Osd => wayland.tooltip_layers.create_osd(width, height),
+            Popup::Menu => wayland.tooltip_layers.create_menu(wanted.x, width, height),
+        };
+        let target = WaylandTarget { wayland_conn, layer };
+        let instance = &self.wgpu_instance;
+        *slot = Some(TooltipSurface {
+            shown: wanted,
+            surface: BoundSurface::new(target, |target| {
+                Self::create_surface(instance, target.raw_handles())
+            }),
+            width,
+            height,
+        });
+    }
+
+    fn draw_popup(&mut self, popup: Popup) {
+        let Some(tooltip) = self.popup(popup).take() else {
+            return;
+        };
+        let (instances, _, _) = self.layout_tooltip(&tooltip.shown);
+        self.update_font();
+        let surface = &tooltip.surface;
+        surface.configure(&self.device, &self.surface_config(surface, tooltip.width, tooltip.height));
+        match surface.get_current_texture() {
+            Ok(surface_texture) => {
+                let view = surface_texture
+                    .texture
+                    .create_view(&wgpu::TextureViewDescriptor::default());
+                let instance_buffer = self.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some("Tooltip Instance Buffer"),
+                    contents: bytemuck::cast_slice(&instances),
+                    usage: wgpu::BufferUsages::VERTEX,
+                });
+                // The bar draws with the same uniform, so it is put back
+                // after this submit
+                self.queue.write_buffer(
+                    &self.global_transform_uniform_buffer,
+                    0,
+                    bytemuck::bytes_of(&GlobalTransformUniform::for_surface(
+                        tooltip.width,
+                        tooltip.height,
+                        self.height,
+                        self.animation_time(),
+                    )),
+                );
+                let commands = self.encode_draw(
+                    &view,
+                    self.clear_color(surface, tooltip.shown.bg),
+                    &instance_buffer,
+                    instances.len() as u32,
+                    None,
+                );
+                self.queue.submit(Some(commands));
+                self.write_bar_transform();
+                surface_texture.present();
+            }
+            Err(e) => log::error!("Could not draw the {popup:?}: {e}"),
+        }
+        *self.popup(popup) = Some(tooltip);
+    }
+
+    fn animation_time(&self) -> f32 {
+        (self.started_at.elapsed().as_secs_f64() % ANIMATION_WRAP as f64) as f32
+    }
+
+    /// Draws the latest state if the compositor is ready for a frame and it
+    /// differs from what is already on screen, or is animating
+    fn draw_if_needed(&mut self) {
+        if !self.frame_ready {
+            return;
+        }
+        let state = match self.latest_state.take() {
+            Some(state) => state,
+            None => return,
+        };
+        let state_hash = state.hash_value();
+        let animate =
+            self.animating && self.next_timed_frame().is_none_or(|at| at <= Instant::now());
+        if self.drawn_state_hash != Some(state_hash) || animate || !self.slide.done() {
+            self.frame_ready = false;
+            self.drawn_state_hash = Some(state_hash);
+            self.draw_frame(&state);
+        }
+        self.latest_state = Some(state);
+    }
+
+    fn idle(&self) -> bool {
+        !self.idle_after.is_zero() && self.last_activity.elapsed() >= self.idle_after
+    }
+
+    /// When the next frame of an animation is due without a frame callback
+    /// to draw it: while idle, as the callbacks that came in the meantime
+    /// were let go, and always on X11 which has none. The event loop wakes
+    /// up for it
+    fn next_timed_frame(&self) -> Option<Instant> {
+        if !self.animating {
+            None
+        } else if self.idle() {
+            Some(self.last_drawn + self.idle_frame)
+        } else if self.wayland.is_none() {
+            Some(self.last_drawn + X11_FRAME)
+        } else {
+            None
+        }
+    }
+
+    /// The compositor only answers frame callbacks of visible surfaces, so
+    /// the bar is considered occluded after waiting too long for one
+    fn check_occluded(&mut self) {
+        let occluded = self.layer_closed
+            || (!self.frame_ready
+                && self
+                    .frame_requested_at
+                    .is_some_and(|requested_at| requested_at.elapsed() >= OCCLUSION_TIMEOUT));
+        self.set_occluded(occluded);
+    }
+
+    fn set_occluded(&self, occluded: bool) {
+        self.occluded.send_if_modified(|current| {
+            if *current == occluded {
+                return false;
+            }
+            log::info!("Bar occluded: {occluded}, {} rendering", if occluded { "pausing" } else { "resuming" });
+            *current = occluded;
+            true
+        });
     }
 
     fn resize(&mut self, width: u32, height: u32) {
         self.width = width;
         self.height = height;
+        self.write_bar_transform();
+        let (width, height) = self.surface_size();
+        self.surface
+            .configure(&self.device, &self.surface_config(&self.surface, width, height));
+        self.queue.submit([]);
+    }
+
+    fn write_bar_transform(&self) {
         self.queue.write_buffer(
             &self.global_transform_uniform_buffer,
             0,
-            bytemuck::bytes_of(&GlobalTransformUniform {
-                scale: [2.0 * self.height as f32 / self.width as f32, 1.],
-                translate: [-1., 0.],
-            }),
+            bytemuck::bytes_of(&GlobalTransformUniform::for_surface(
+                self.width,
+                self.height,
+                self.height,
+                self.animation_time(),
+            )),
         );
-        let mut config = self
-            .surface
-            .get_default_config(&self.adapter, self.width, self.height)
+    }
+
+    fn surface_config(
+        &self,
+        surface: &wgpu::Surface,
+        width: u32,
+        height: u32,
+    ) -> wgpu::SurfaceConfiguration {
+        let mut config = surface
+            .get_default_config(&self.adapter, width, height)
             .expect("To be able to get the default config from a surface");
         config.desired_maximum_frame_latency = 1;
         // Change this back to Mailbox
         config.present_mode = PresentMode::Fifo;
-        self.surface.configure(&self.device, &config);
-        self.queue.submit([]);
+        // The first supported mode is usually opaque, which would ignore the
+        // alpha of a translucent background
+        let alpha_modes = surface.get_capabilities(&self.adapter).alpha_modes;
+        if let Some(alpha_mode) = [
+            wgpu::CompositeAlphaMode::PreMultiplied,
+            wgpu::CompositeAlphaMode::PostMultiplied,
+        ]
+        .into_iter()
+        .find(|mode| alpha_modes.contains(mode))
+        {
+            config.alpha_mode = alpha_mode;
+        }
+        config
     }
 
-    pub async fn run_event_loop(
-        self,
-        mut display_receiver: Receiver<DisplayMessage>,
-        mut render_receiver: Receiver<RenderState>,
-    ) {
-        let renderer = Arc::new(RwLock::new(self));
-        let handle = Handle::current();
-        let renderer1 = Arc::clone(&renderer);
-        let display_handle = handle.spawn(async move {
-            while let Some(message) = display_receiver.recv().await {
-                match message {
-                    DisplayMessage::Configure { width, height } => {
-                        renderer1.write().await.resize(width, height);
+    /// The background as the clear color, premultiplied if the compositor
+    /// expects it to be
+    fn clear_color(&self, surface: &wgpu::Surface, background: u32) -> wgpu::Color {
+        let [r, g, b, a] = background.to_le_bytes().map(|channel| channel as f64 / 255.);
+        let alpha_modes = surface.get_capabilities(&self.adapter).alpha_modes;
+        if alpha_modes.contains(&wgpu::CompositeAlphaMode::PreMultiplied) {
+            wgpu::Color { r: r * a, g: g * a, b: b * a, a }
+        } else {
+            wgpu::Color { r, g, b, a }
+        }
+    }
+
+    /// Starts over with the fonts of a reloaded config, everything is laid
+    /// out with them again
+    fn reload_font(&mut self, config: &FontConfig) {
+        self.fonts = match FontSystem::from_config(config) {
+            Ok(fonts) => fonts,
+            Err(e) => {
+                log::error!("Could not reload the fonts: {e}");
+                return;
+            }
+        };
+        self.font_sdf = FontContainer::new(PRELOADED_CHARS, self.fonts.clone());
+        self.drawn_state_hash = None;
+        for popup in [Popup::Tooltip, Popup::Osd, Popup::Menu] {
+            self.draw_popup(popup);
+        }
+        self.draw_if_needed();
+    }
+
+    fn handle_display_message(&mut self, message: DisplayMessage) {
+        match message {
+            DisplayMessage::Configure { width, height } => {
+                self.resize(width, height);
+                // The old frame is gone with the old configuration
+                self.drawn_state_hash = None;
+                self.frame_ready = true;
+            }
+            DisplayMessage::Frame => {
+                self.frame_ready = true;
+                self.frame_requested_at = None;
+            }
+            DisplayMessage::NewLayer(layer) => {
+                self.replace_layer(layer);
+                return;
+            }
+            DisplayMessage::TooltipConfigure(layer) => {
+                for popup in [Popup::Tooltip, Popup::Osd, Popup::Menu] {
+                    if self
+                        .popup(popup)
+                        .as_ref()
+                        .is_some_and(|tooltip| tooltip.surface.owner().layer == layer)
+                    {
+                        self.draw_popup(popup);
                     }
                 }
+                return;
             }
-        });
+            DisplayMessage::Closed => {
+                self.tooltip = None;
+                self.osd = None;
+                self.menu = None;
+                self.layer_closed = true;
+                self.frame_ready = false;
+                self.set_occluded(true);
+                return;
+            }
+        }
+        self.set_occluded(false);
+        self.draw_if_needed();
+    }
 
-        let render_handle = handle.spawn(async move {
-            while let Some(state) = render_receiver.recv().await {
-                renderer.write().await.draw_frame(&state);
+    /// The task owning the renderer, which does what it is sent one command
+    /// after the other and draws the timed frames of animations in between
+    pub async fn run_event_loop(mut self, mut commands: Receiver<RenderCommand>) {
+        loop {
+            let timed_frame = self.next_timed_frame().filter(|_| self.frame_ready);
+            let command = tokio::select! {
+                command = commands.recv() => command,
+                _ = sleep_until(timed_frame.unwrap_or_else(Instant::now).into()), if timed_frame.is_some() => {
+                    self.draw_if_needed();
+                    continue;
+                }
+            };
+            let Some(command) = command else {
+                break;
+            };
+            match command {
+                RenderCommand::Display(message) => self.handle_display_message(message),
+                RenderCommand::Draw(state) => {
+                    if Some(state.hash_value()) != self.drawn_state_hash {
+                        self.last_activity = Instant::now();
+                    }
+                    self.latest_state = Some(state);
+                    for popup in [Popup::Tooltip, Popup::Osd, Popup::Menu] {
+                        self.update_popup(popup);
+                    }
+                    self.update_visibility();
+                    self.check_occluded();
+                    self.draw_if_needed();
+                }
+                RenderCommand::ReloadFont(config) => self.reload_font(&config),
+                RenderCommand::Input => {
+                    self.last_activity = Instant::now();
+                    self.draw_if_needed();
+                }
+                RenderCommand::ReleaseKeyboard => self.release_keyboard(),
+                RenderCommand::Screenshot(screenshot) => self.screenshot(screenshot),
             }
-        });
-        display_handle
-            .await
-            .expect("No error happending when reading display messages");
-        render_handle
-            .await
-            .expect("No error happending when reading render messages");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A bar 100 bar heights wide, so the center is at 50
+    const BAR: f32 = 100.;
+
+    #[test]
+    fn center_fitting_stays_centered() {
+        for collision in [
+            CenterCollision::Push,
+            CenterCollision::Clip,
+            CenterCollision::Yield,
+        ] {
+            assert_eq!(
+                place_center(collision, 20., 10., 90., BAR),
+                Some((40., 40.0..60.))
+            );
+        }
+    }
+
+    #[test]
+    fn center_pushed_away_from_either_side() {
+        assert_eq!(
+            place_center(CenterCollision::Push, 20., 45., 90., BAR),
+            Some((45., 45.0..65.))
+        );
+        assert_eq!(
+            place_center(CenterCollision::Push, 20., 10., 52., BAR),
+            Some((32., 32.0..52.))
+        );
+    }
+
+    #[test]
+    fn center_pushed_without_room_is_cut_off() {
+        assert_eq!(
+            place_center(CenterCollision::Push, 20., 45., 55., BAR),
+            Some((45., 45.0..55.))
+        );
+        assert_eq!(
+            place_center(CenterCollision::Push, 20., 60., 55., BAR),
+            None
+        );
+    }
+
+    #[test]
+    fn center_clipped_where_it_overlaps() {
+        assert_eq!(
+            place_center(CenterCollision::Clip, 20., 45., 90., BAR),
+            Some((40., 45.0..60.))
+        );
+        assert_eq!(
+            place_center(CenterCollision::Clip, 20., 45., 55., BAR),
+            Some((40., 45.0..55.))
+        );
+        assert_eq!(
+            place_center(CenterCollision::Clip, 20., 70., 90., BAR),
+            None
+        );
+    }
+
+    #[test]
+    fn center_yields_to_either_side() {
+        assert_eq!(
+            place_center(CenterCollision::Yield, 20., 41., 90., BAR),
+            None
+        );
+        assert_eq!(
+            place_center(CenterCollision::Yield, 20., 10., 59., BAR),
+            None
+        );
+        // Touching isn't overlapping
+        assert_eq!(
+            place_center(CenterCollision::Yield, 20., 40., 60., BAR),
+            Some((40., 40.0..60.))
+        );
+    }
+
+    #[test]
+    fn empty_center_is_not_drawn() {
+        assert_eq!(place_center(CenterCollision::Push, 0., 10., 90., BAR), None);
     }
 }
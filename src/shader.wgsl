@@ -1,8 +1,22 @@
 struct GlobalTransformUniform {
     scale: vec2<f32>,
     translate: vec2<f32>,
+    /// Seconds, wrapping around every hour
+    time: f32,
 };
 
+/// Instance flags, same as InstanceFlags
+const PULSE: u32 = 1u;
+const IMAGE: u32 = 2u;
+const BOLD: u32 = 4u;
+const ROTATED: u32 = 8u;
+
+/// How far the edge of a bold glyph is moved out, in em
+const BOLD_WEIGHT: f32 = 0.02;
+
+/// Images along each side of the atlas, same as ATLAS_SLOTS_PER_SIDE
+const ATLAS_SLOTS_PER_SIDE: u32 = 4u;
+
 @group(0) @binding(0)
 var<uniform> global_transform: GlobalTransformUniform;
 
@@ -18,6 +32,12 @@ var<storage, read> quadratic_points: array<f32>;
 @group(0) @binding(4)
 var<storage, read> cubic_points: array<f32>;
 
+@group(0) @binding(5)
+var image_atlas: texture_2d<f32>;
+
+@group(0) @binding(6)
+var image_sampler: sampler;
+
 
 struct VertexInput {
     @location(0) position: vec2<f32>,
@@ -34,6 +54,11 @@ struct InstanceInput {
     @location(6) lines_off: vec2<u32>,
     @location(7) quadratic_off: vec2<u32>,
     @location(8) cubic_off: vec2<u32>,
+    @location(9) radius: f32,
+    @location(10) border: vec4<f32>,
+    @location(11) border_width: f32,
+    @location(12) flags: u32,
+    @location(13) image_slot: u32,
 }
 
 
@@ -45,20 +70,36 @@ struct VertexOutput {
     @location(4) lines_off: vec2<u32>,
     @location(5) quadratic_off: vec2<u32>,
     @location(6) cubic_off: vec2<u32>,
+    @location(7) radius: f32,
+    @location(8) scale: vec2<f32>,
+    @location(9) border: vec4<f32>,
+    @location(10) border_width: f32,
+    @location(11) flags: u32,
+    @location(12) image_slot: u32,
 }
 
 @vertex
 fn vs_main(input: VertexInput, instance: InstanceInput) -> VertexOutput {
     var out: VertexOutput;
     out.tex_coords = input.tex_coords ;
-    out.clip_position = vec4<f32>(
-        (input.position * instance.scale + instance.position) * global_transform.scale + global_transform.translate, 0., 1.
-    );
+    var position = (input.position * instance.scale + instance.position) * global_transform.scale + global_transform.translate;
+    // The start of the bar goes to the top and the top of the text to the
+    // right, the scale is already that of the surface turned this way
+    if ((instance.flags & ROTATED) != 0u) {
+        position = vec2<f32>(position.y, -position.x);
+    }
+    out.clip_position = vec4<f32>(position, 0., 1.);
     out.bg = instance.bg;
     out.fg = instance.fg;
     out.lines_off = instance.lines_off;
     out.quadratic_off = instance.quadratic_off;
     out.cubic_off = instance.cubic_off;
+    out.radius = instance.radius;
+    out.scale = instance.scale;
+    out.border = instance.border;
+    out.border_width = instance.border_width;
+    out.flags = instance.flags;
+    out.image_slot = instance.image_slot;
     return out;
 }
 
@@ -144,8 +185,53 @@ fn sdQuadratic(pos: vec2<f32>, A: vec2<f32>, B: vec2<f32>, C: vec2<f32>) -> vec2
     return vec2<f32>(res, orthogonality);
 }
 
+// Distance to the edge of a box centered on the origin, negative inside
+fn sdRoundBox(p: vec2<f32>, half_size: vec2<f32>, radius: f32) -> f32 {
+    let q = abs(p) - half_size + radius;
+    return length(max(q, vec2<f32>(0.))) + min(max(q.x, q.y), 0.) - radius;
+}
+
 @fragment
 fn fs_main(input: VertexOutput) -> @location(0) vec4<f32> {
+    var color = shade(input);
+    if (input.flags & PULSE) != 0u {
+        // Fades between full and 40% once a second
+        color.a *= 0.7 + 0.3 * cos(global_transform.time * 6.2831853);
+    }
+    return color;
+}
+
+fn shade(input: VertexOutput) -> vec4<f32> {
+    // Boxes are scaled in x and y differently, so the distance is taken in
+    // bar heights, where the corners come out round. Derivatives have to be
+    // taken outside of branches, so this is done for glyphs too
+    let size = abs(input.scale);
+    let half_size = size / 2.;
+    let box_dist = sdRoundBox(input.tex_coords * size - half_size, half_size, min(input.radius, min(half_size.x, half_size.y)));
+    let box_pixel = fwidth(box_dist);
+    let box_coverage = clamp(0.5 - box_dist / box_pixel, 0., 1.);
+    // How much of the pixel is inside the outline, so bg instead of border
+    let box_inner = select(
+        clamp(0.5 - (box_dist + input.border_width) / box_pixel, 0., 1.),
+        1.,
+        input.border_width <= 0.,
+    );
+    if (input.flags & IMAGE) != 0u {
+        // Kept half a texel inside the slot, so filtering doesn't bleed in
+        // the image next to it
+        let slots = f32(ATLAS_SLOTS_PER_SIDE);
+        let half_texel = 0.5 / f32(textureDimensions(image_atlas).x) * slots;
+        let uv = clamp(input.tex_coords, vec2<f32>(half_texel), vec2<f32>(1. - half_texel));
+        let slot = vec2<f32>(f32(input.image_slot % ATLAS_SLOTS_PER_SIDE), f32(input.image_slot / ATLAS_SLOTS_PER_SIDE));
+        return textureSampleLevel(image_atlas, image_sampler, (slot + uv) / slots, 0.);
+    }
+    if input.radius > 0. {
+        // Mixed premultiplied, so a transparent bg doesn't darken the outline
+        let alpha = mix(input.border.a, input.bg.a, box_inner);
+        let color = mix(input.border.rgb * input.border.a, input.bg.rgb * input.bg.a, box_inner);
+        return vec4<f32>(color / max(alpha, 0.0001), alpha * box_coverage);
+    }
+
     //var winding = 0;
 	// x has the distance, y has the orthogonality
     var min_dist = vec2<f32>(999., -999.);
@@ -206,5 +292,6 @@ fn fs_main(input: VertexOutput) -> @location(0) vec4<f32> {
 		}
     }
 	
-    return mix(input.fg, input.bg, ( min_dist.x * 16. ) );
+    let weight = select(0., BOLD_WEIGHT, (input.flags & BOLD) != 0u);
+    return mix(input.fg, input.bg, ( (min_dist.x - weight) * 16. ) );
 }
@@ -0,0 +1,255 @@
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, HashMap},
+    mem,
+    sync::{Arc, PoisonError},
+};
+
+use ab_glyph::GlyphId;
+use rustybuzz::{Direction, UnicodeBuffer};
+use unicode_bidi::BidiInfo;
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::font::FontSystem;
+
+/// A glyph positioned by the shaper, all distances are in em, same as the
+/// normalized GlyphInfo dimensions
+#[derive(Debug, Clone, Copy)]
+pub struct ShapedGlyph {
+    /// Index of the face in the fallback chain the glyph belongs to
+    pub face: usize,
+    pub glyph_id: GlyphId,
+    /// Byte offset of the first char in the source text that produced this glyph
+    pub cluster: u32,
+    pub x_advance: f32,
+    pub x_offset: f32,
+    pub y_offset: f32,
+}
+
+/// Shaped text by the text, so what stays on the bar isn't shaped again every
+/// frame. Counts toward the cache budget like the glyphs
+#[derive(Debug, Default)]
+pub struct ShapeCache {
+    runs: HashMap<String, (Arc<[ShapedGlyph]>, u64)>,
+    /// Bumped on every lookup, the runs remember the lookup they were last
+    /// used in
+    uses: u64,
+    /// Bytes taken up by the runs, kept as they come and go
+    usage: usize,
+}
+
+impl ShapeCache {
+    fn run_usage(text: &str, glyphs: &[ShapedGlyph]) -> usize {
+        mem::size_of::<(String, (Arc<[ShapedGlyph]>, u64))>()
+            + text.len()
+            + mem::size_of_val(glyphs)
+    }
+
+    fn get(&mut self, text: &str) -> Option<Arc<[ShapedGlyph]>> {
+        self.uses += 1;
+        let (glyphs, last_used) = self.runs.get_mut(text)?;
+        *last_used = self.uses;
+        Some(glyphs.clone())
+    }
+
+    fn insert(&mut self, text: &str, glyphs: Arc<[ShapedGlyph]>) {
+        self.usage += Self::run_usage(text, &glyphs);
+        if let Some((old, _)) = self.runs.insert(text.to_string(), (glyphs, self.uses)) {
+            self.usage -= Self::run_usage(text, &old);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.runs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.runs.is_empty()
+    }
+
+    pub fn memory_usage(&self) -> usize {
+        self.usage
+    }
+
+    /// Drops the least recently used runs once over the budget in bytes,
+    /// down to half of it like FontContainer::trim. Returns whether anything
+    /// was dropped
+    pub fn trim(&mut self, budget: usize) -> bool {
+        if self.usage <= budget {
+            return false;
+        }
+        let mut runs = self
+            .runs
+            .iter()
+            .map(|(text, (glyphs, last_used))| (*last_used, Self::run_usage(text, glyphs)))
+            .collect::<Vec<_>>();
+        runs.sort_unstable_by_key(|(last_used, _)| std::cmp::Reverse(*last_used));
+        let mut kept_usage = 0;
+        // Every run used after this one is kept
+        let oldest_kept = runs
+            .into_iter()
+            .take_while(|(_, usage)| {
+                kept_usage += usage;
+                kept_usage <= budget / 2
+            })
+            .last()
+            .map_or(u64::MAX, |(last_used, _)| last_used);
+        self.runs
+            .retain(|_, (_, last_used)| *last_used >= oldest_kept);
+        self.usage = self
+            .runs
+            .iter()
+            .map(|(text, (glyphs, _))| Self::run_usage(text, glyphs))
+            .sum();
+        true
+    }
+}
+
+/// Runs text through harfbuzz (rustybuzz) so ligatures, kerning, marks and
+/// complex scripts come out the way the font wants them to
+impl FontSystem {
+    /// Splits the text into runs which a single face can render, whitespace
+    /// stays with the run it is in so fallbacks don't break words apart
+    fn runs<'a>(&self, text: &'a str) -> Vec<(usize, usize, &'a str)> {
+        let mut runs = Vec::new();
+        let mut run_start = 0;
+        let mut run_face = None;
+        for (i, c) in text.char_indices() {
+            if c.is_whitespace() {
+                continue;
+            }
+            let face = self.face_for_char(c);
+            match run_face {
+                Some(run_face) if run_face != face => {
+                    runs.push((run_face, run_start, &text[run_start..i]));
+                    run_start = i;
+                }
+                _ => {}
+            }
+            run_face = Some(face);
+        }
+        runs.push((run_face.unwrap_or(0), run_start, &text[run_start..]));
+        runs
+    }
+
+    /// Shapes the text run by run, with the glyphs in visual order. The bidi
+    /// algorithm splits the text into runs of one direction, which are split
+    /// further into runs of one face, the script is guessed per run
+    pub fn shape_normalized(&self, text: &str) -> Arc<[ShapedGlyph]> {
+        let mut cache = self.shaped.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(glyphs) = cache.get(text) {
+            return glyphs;
+        }
+        let glyphs: Arc<[ShapedGlyph]> = self.shape(text).into();
+        cache.insert(text, glyphs.clone());
+        glyphs
+    }
+
+    /// Shapes the text without looking in the cache or keeping the result
+    pub fn shape(&self, text: &str) -> Vec<ShapedGlyph> {
+        let mut glyphs = Vec::new();
+        let bidi = BidiInfo::new(text, None);
+        for paragraph in &bidi.paragraphs {
+            let (levels, directional_runs) = bidi.visual_runs(paragraph, paragraph.range.clone());
+            for directional_run in directional_runs {
+                let rtl = levels[directional_run.start].is_rtl();
+                let mut runs = self.runs(&text[directional_run.clone()]);
+                // Right to left, the run starting the text goes rightmost
+                if rtl {
+                    runs.reverse();
+                }
+                for (face_index, run_start, run) in runs {
+                    self.shape_run(
+                        face_index,
+                        directional_run.start + run_start,
+                        run,
+                        rtl,
+                        &mut glyphs,
+                    );
+                }
+            }
+        }
+        glyphs
+    }
+
+    /// Appends the glyphs of a run of one face and direction, run_start being
+    /// its byte offset in the whole text
+    fn shape_run(
+        &self,
+        face_index: usize,
+        run_start: usize,
+        run: &str,
+        rtl: bool,
+        glyphs: &mut Vec<ShapedGlyph>,
+    ) {
+        let Some(face) = self.faces.get(face_index) else {
+            return;
+        };
+        let units_per_em = face.units_per_em;
+        let mut buffer = UnicodeBuffer::new();
+        buffer.push_str(run);
+        buffer.set_direction(if rtl {
+            Direction::RightToLeft
+        } else {
+            Direction::LeftToRight
+        });
+        buffer.guess_segment_properties();
+        let glyph_buffer = rustybuzz::shape(&face.shaping, &[], buffer);
+        glyphs.extend(
+            glyph_buffer
+                .glyph_infos()
+                .iter()
+                .zip(glyph_buffer.glyph_positions())
+                .map(|(info, position)| ShapedGlyph {
+                    face: face_index,
+                    glyph_id: GlyphId(info.glyph_id as u16),
+                    cluster: run_start as u32 + info.cluster,
+                    x_advance: position.x_advance as f32 / units_per_em,
+                    x_offset: position.x_offset as f32 / units_per_em,
+                    y_offset: position.y_offset as f32 / units_per_em,
+                }),
+        );
+    }
+
+    /// Advance of the whole text, in em
+    pub fn width(&self, text: &str) -> f32 {
+        self.shape_normalized(text)
+            .iter()
+            .map(|glyph| glyph.x_advance)
+            .sum()
+    }
+
+    /// The text cut where it overflows max_width em, ending in an ellipsis.
+    /// Cut between graphemes, so no cluster ends up half drawn. Empty when
+    /// not even the ellipsis fits
+    pub fn ellipsize<'a>(&self, text: &'a str, max_width: f32) -> Cow<'a, str> {
+        let glyphs = self.shape_normalized(text);
+        if glyphs.iter().map(|glyph| glyph.x_advance).sum::<f32>() <= max_width {
+            return Cow::Borrowed(text);
+        }
+        let budget = max_width - self.width("…");
+        if budget < 0. {
+            return Cow::Borrowed("");
+        }
+        // Added up by cluster, as right to left runs have their glyphs in
+        // reverse order
+        let mut advances = BTreeMap::new();
+        for glyph in glyphs.iter() {
+            *advances.entry(glyph.cluster as usize).or_insert(0.) += glyph.x_advance;
+        }
+        let mut width = 0.;
+        let mut cut = 0;
+        for (start, grapheme) in text.grapheme_indices(true) {
+            let end = start + grapheme.len();
+            width += advances
+                .range(start..end)
+                .map(|(_, advance)| advance)
+                .sum::<f32>();
+            if width > budget {
+                break;
+            }
+            cut = end;
+        }
+        Cow::Owned(format!("{}…", text[..cut].trim_end()))
+    }
+}
@@ -1,289 +1,1418 @@
-use std::time::SystemTime;
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    panic::{AssertUnwindSafe, catch_unwind, resume_unwind},
+    sync::{Arc, PoisonError},
+    time::{Duration, Instant},
+};
 
 use mpd::Status;
-use tokio::sync::mpsc::Sender;
+use smithay_client_toolkit::seat::keyboard::Keysym;
+use tokio::{
+    sync::{mpsc::Sender, watch},
+    time::sleep_until,
+};
 use tokio_stream::StreamExt;
 
 use crate::{
-    audio::{AudioMessage, AudioState},
+    action::{Action, MouseBindings},
+    ambient::AmbientMessage,
+    atlas::Image,
+    audio::{AudioMessage, AudioState, JACK_OSD_DURATION},
     backlight::{Backlight, BacklightMessage},
+    bar::{ModuleMessage, SharedModule},
     battery::{BatteryMessage, PowerSupply, PowerSupplyStatus},
+    camera::CameraMessage,
     clock::ClockMessage,
+    compositor::run_command,
+    config::{AutohideConfig, BatteryConfig, Config, FontConfig, WorkspaceSort},
+    cpu::{CpuMessage, CpuStats},
+    crash,
+    custom::{CustomMessage, CustomOutput},
+    docker::{Container, DockerMessage},
     font::{Line, Segment, Vec2},
+    hosts::{Host, HostsMessage},
+    ipc::IpcMessage,
+    kdeconnect::{KdeConnectMessage, Phone},
+    layer::BAR_HEIGHT,
+    memory::{MemoryMessage, MemoryStats},
+    mic::MicMessage,
+    libvirt::{Domain, LibvirtMessage},
+    logind::LogindMessage,
+    markup,
     mpd::MpdMessage,
+    mpris::{MprisMessage, MprisPlayer},
+    mqtt::MqttMessage,
     network::{Network, NetworkMessage},
-    renderer::{RenderState, Renderable},
-    sway::{SwayMessage, Workspace},
+    night_light::NightLight,
+    nvme::{NvmeHealth, NvmeMessage},
+    renderer::{
+        Highlight, HitRegions, RenderCommand, RenderState, Renderable, Tooltip, bar_mode_widgets,
+    },
+    supervisor::ModuleStatus,
+    sway::{SwayMessage, Window, Workspace, quote},
+    systemd::{SystemdMessage, SystemdTimer},
+    taskbar::{TaskbarMessage, TaskbarWindow},
+    palette::{Palette, PaletteEntry, PaletteEvent, PaletteMode},
+    privacy::{PrivacyMessage, PrivacyStream},
+    processes::{ProcessSample, TopProcesses},
+    text_input::KeyInput,
+    theme::{Color, Style},
+    thermal::{Sensor, ThermalMessage, Throttling},
+    timer::{self, Timer, TimerAction},
+    update::{Release, UpdateMessage},
+    watch::WatchMessage,
+    widget::{self, WIDGETS},
 };
 
 #[derive(Debug, Clone)]
 pub struct State {
     pub workspaces: Vec<Workspace>,
+    /// The workspace focused before the last focus change on its output and
+    /// when it changed, the highlight slides over from it
+    pub workspace_slide: Option<(i64, Instant)>,
+    /// Ids of the workspaces, the most recently focused first
+    pub workspace_history: Vec<i64>,
     pub mpd_status: Option<Status>,
     pub mpd_current_song: Option<mpd::Song>,
+    pub mpd_cover: Option<Arc<Image>>,
+    pub mpris_players: Vec<MprisPlayer>,
     pub press_position: Vec2,
+    /// Scrolled distance not yet run as a scroll action
+    pub scroll_delta: f32,
+    pub hover: Option<Hover>,
     pub segments: Vec<Segment>,
     pub networks: Vec<Network>,
     pub audio_state: AudioState,
+    /// Whether the level meter captures the default source, watched by the
+    /// mic subscription
+    pub mic_meter: watch::Sender<bool>,
+    /// Sent to after waking up, so subscriptions polling on a timer poll
+    /// again right away and ones whose samples don't make sense across a
+    /// suspend start over
+    pub resumes: watch::Sender<()>,
+    /// Between logind announcing a suspend and the wake up
+    pub suspended: bool,
+    /// Whether the screen locker is up, going by logind
+    pub session_locked: bool,
+    /// Latest peak of the default source while the meter is on
+    pub mic_level: Option<f32>,
     pub focused_window_name: Option<String>,
+    /// The binding mode sway is in, unless it is the default one
+    pub binding_mode: Option<String>,
+    /// Refreshed whenever the window switcher opens
+    pub windows: Vec<Window>,
+    /// The app_ids of the windows on each workspace, by workspace name,
+    /// empty for windows without one
+    pub workspace_apps: BTreeMap<String, Vec<String>>,
+    /// The windows on the focused workspace
+    pub taskbar: Vec<TaskbarWindow>,
+    /// Streams recording the microphone, a camera or the screen
+    pub privacy: Vec<PrivacyStream>,
+    /// A newer release than the one running, if the check is enabled
+    pub update: Option<Release>,
+    /// The color next to the bar the background is tinted towards, if
+    /// sampling it is enabled
+    pub ambient: Option<Color>,
     pub backlights: Vec<Backlight>,
     pub power_supply: Vec<PowerSupply>,
     pub clock: chrono::DateTime<chrono::Local>,
+    pub systemd_timers: Vec<SystemdTimer>,
+    pub containers: Vec<Container>,
+    pub sensors: Vec<Sensor>,
+    pub throttling: Option<Throttling>,
+    pub cpu: Option<CpuStats>,
+    /// Index into the configured CPU views of the one shown
+    pub cpu_view: usize,
+    pub memory: Option<MemoryStats>,
+    pub nvme_drives: Vec<NvmeHealth>,
+    pub hosts: Vec<Host>,
+    /// Sampled when a tooltip comes up, while it is up
+    pub top_processes: Option<TopProcesses>,
+    pub domains: Vec<Domain>,
+    pub phones: Vec<Phone>,
+    pub camera_users: Vec<String>,
+    /// Subscriptions that stopped working, by name
+    pub disconnected: BTreeMap<String, ModuleStatus>,
+    /// Latest output of each custom module, by its name in the config
+    pub custom: HashMap<String, CustomOutput>,
+    /// Latest contents of each watch module, by its name in the config
+    pub watched: HashMap<String, CustomOutput>,
+    /// Latest payload of each MQTT module, by its name in the config
+    pub mqtt: HashMap<String, CustomOutput>,
+    /// Modules from other crates by their names, see Bar::builder
+    pub external_modules: BTreeMap<String, SharedModule>,
+    /// Keyboard input goes here while it is open
+    pub palette: Option<Palette>,
+    /// Open below the widget that opened it, until an entry or anything
+    /// else on the bar is clicked
+    pub menu: Option<Menu>,
+    /// The widget picked with the arrow keys in bar mode, as its index into
+    /// bar_mode_widgets. Otherwise keyboard input goes here, like the palette
+    pub bar_mode: Option<usize>,
+    /// Names of the modules the user toggled off
+    pub hidden_modules: HashSet<String>,
+    /// The whole bar, hidden through the IPC socket
+    pub hidden: bool,
+    /// Pointer input is ignored, set through the IPC socket
+    pub locked: bool,
+    pub autohide: Autohide,
+    pub hit_regions: HitRegions,
+    pub config: Config,
+    pub throttle: Throttle,
+    pub battery_alert: BatteryAlert,
+    /// Of each power supply, in the same order
+    pub battery_levels: Vec<BatteryLevel>,
+    pub timer: Timer,
+    pub night_light: NightLight,
+    /// Fonts of a reloaded config, sent to the renderer with the next state
+    pub reload_font: Option<FontConfig>,
+    /// Escape was pressed with nothing open, the keyboard goes back to the
+    /// window it came from with the next state
+    pub release_keyboard: bool,
+}
+
+/// Holds back redraws for subscriptions with a throttle configured
+#[derive(Debug, Clone, Default)]
+pub struct Throttle {
+    /// When changes from each subscription were last drawn
+    last_drawn: HashMap<&'static str, Instant>,
+    /// Subscriptions with changes waiting for the deadline
+    pending: HashSet<&'static str>,
+    /// When the earliest held back change may be drawn
+    deadline: Option<Instant>,
+}
+
+impl Throttle {
+    /// Whether a change from the source has to wait, setting the deadline
+    /// for it if so
+    fn hold_back(&mut self, source: &'static str, config: &Config) -> bool {
+        let Some(interval) = config.throttle.get(source) else {
+            return false;
+        };
+        let next = match self.last_drawn.get(source) {
+            Some(last_drawn) => *last_drawn + Duration::from_millis(*interval),
+            None => return false,
+        };
+        if next <= Instant::now() {
+            return false;
+        }
+        self.pending.insert(source);
+        self.deadline = Some(self.deadline.map_or(next, |deadline| deadline.min(next)));
+        true
+    }
+
+    /// Everything held back goes out with a redraw, whatever caused it
+    fn drawn(&mut self, source: Option<&'static str>) {
+        let now = Instant::now();
+        for source in self.pending.drain().chain(source) {
+            self.last_drawn.insert(source, now);
+        }
+        self.deadline = None;
+    }
+}
+
+/// A popup menu listing the palette entries of a mode
+#[derive(Debug, Clone)]
+pub struct Menu {
+    mode: PaletteMode,
+    /// Surface pixels from the left of the bar
+    x: u32,
+    /// The entry picked that asks first, the menu only shows yes and cancel
+    /// for it
+    confirm: Option<PaletteEntry>,
+}
+
+/// The pointer resting on a widget with a tooltip
+#[derive(Debug, Clone)]
+pub struct Hover {
+    /// Where the pointer got onto the widget in surface pixels, the tooltip
+    /// is put there and its text looked up by it
+    x: f32,
+    since: Instant,
+    stage: TooltipStage,
+}
+
+/// How far along a tooltip is in coming up. When the top processes go in
+/// the CPU tooltip, they are measured over TOP_PROCESSES_WINDOW before it
+/// shows, and the bar is laid out with them once so the tooltip text the
+/// renderer hands back has them
+#[derive(Debug, Clone)]
+enum TooltipStage {
+    Resting,
+    Sampled(ProcessSample),
+    Measured,
+    Shown,
+}
+
+impl Hover {
+    /// When the tooltip goes to its next stage
+    fn due(&self, sample_processes: bool) -> Option<Instant> {
+        match self.stage {
+            TooltipStage::Resting if sample_processes => {
+                Some(self.since + TOOLTIP_DELAY - TOP_PROCESSES_WINDOW)
+            }
+            TooltipStage::Resting | TooltipStage::Sampled(_) => Some(self.since + TOOLTIP_DELAY),
+            TooltipStage::Measured => Some(self.since + TOOLTIP_DELAY + TOOLTIP_SETTLE),
+            TooltipStage::Shown => None,
+        }
+    }
+
+    fn shown(&self) -> bool {
+        matches!(self.stage, TooltipStage::Shown)
+    }
+}
+
+/// Whether an autohiding bar is out, brought out by the pointer touching
+/// the edge or the show command
+#[derive(Debug, Clone, Default)]
+pub struct Autohide {
+    enabled: bool,
+    revealed: bool,
+    /// When the bar slides away again, unset while the pointer is on it
+    hide_at: Option<Instant>,
+}
+
+impl Autohide {
+    fn new(config: &AutohideConfig) -> Self {
+        Self {
+            enabled: config.enabled,
+            ..Self::default()
+        }
+    }
+
+    /// Whether the bar is away, while autohide is on at all
+    fn hidden(&self) -> bool {
+        self.enabled && !self.revealed
+    }
+
+    /// The pointer is on the bar or the edge, it stays out until it leaves
+    fn hold(&mut self) {
+        self.revealed = true;
+        self.hide_at = None;
+    }
+
+    /// Out for the configured delay, unless the pointer gets onto it
+    fn reveal(&mut self, config: &AutohideConfig) {
+        self.revealed = true;
+        self.hide_at = Some(Instant::now() + Duration::from_millis(config.delay));
+    }
+
+    fn release(&mut self, config: &AutohideConfig) {
+        if self.revealed {
+            self.reveal(config);
+        }
+    }
+
+    fn hide(&mut self) {
+        self.revealed = false;
+        self.hide_at = None;
+    }
+}
+
+/// Where a battery stands against the low and critical percentages. Getting
+/// out of a level takes the hysteresis on top of its percentage
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum BatteryLevel {
+    #[default]
+    Normal,
+    Low,
+    Critical,
+}
+
+impl BatteryLevel {
+    fn next(self, power_supply: &PowerSupply, config: &BatteryConfig) -> Self {
+        let capacity = match power_supply {
+            PowerSupply::Battery { status, capacity, .. }
+                if !matches!(status, PowerSupplyStatus::Charging | PowerSupplyStatus::Full) =>
+            {
+                *capacity
+            }
+            _ => return Self::Normal,
+        };
+        let at = |level: Self, threshold: usize| {
+            capacity <= threshold || (self >= level && capacity <= threshold + config.hysteresis)
+        };
+        if at(Self::Critical, config.critical) {
+            Self::Critical
+        } else if at(Self::Low, config.low) {
+            Self::Low
+        } else {
+            Self::Normal
+        }
+    }
+}
+
+/// The low battery notification and suspend countdown, kept across battery
+/// updates
+#[derive(Debug, Clone, Default)]
+pub struct BatteryAlert {
+    /// Set once the notification went out, until the battery charges or
+    /// goes back above the threshold
+    notified: bool,
+    /// When the system suspends, while the countdown runs
+    suspend_at: Option<Instant>,
+    /// Keeps a cancelled countdown from starting again on the next update
+    cancelled: bool,
+}
+
+impl BatteryAlert {
+    fn update(&mut self, power_supplies: &[PowerSupply], config: &BatteryConfig) {
+        let plugged = power_supplies
+            .iter()
+            .any(|power_supply| matches!(power_supply, PowerSupply::Mains { online: true }));
+        // The emptiest battery runs out first
+        let capacity = power_supplies
+            .iter()
+            .filter_map(|power_supply| match power_supply {
+                PowerSupply::Battery { status, capacity, .. }
+                    if !matches!(status, PowerSupplyStatus::Charging | PowerSupplyStatus::Full) =>
+                {
+                    Some(*capacity)
+                }
+                _ => None,
+            })
+            .min();
+        let capacity = match capacity {
+            Some(capacity) if !plugged => capacity,
+            _ => {
+                *self = Self::default();
+                return;
+            }
+        };
+        if capacity > config.notify + config.hysteresis {
+            self.notified = false;
+        } else if !self.notified {
+            self.notified = true;
+            Action::Spawn(format!(
+                "notify-send -u critical -i battery-caution 'Battery low' '{capacity}% left'"
+            ))
+            .run();
+        }
+        match config.suspend {
+            Some(suspend) if capacity <= suspend => {
+                if self.suspend_at.is_none() && !self.cancelled {
+                    self.suspend_at =
+                        Some(Instant::now() + Duration::from_secs(config.suspend_countdown));
+                }
+            }
+            _ => {
+                self.suspend_at = None;
+                self.cancelled = false;
+            }
+        }
+    }
+
+    fn cancel(&mut self) {
+        if self.suspend_at.take().is_some() {
+            self.cancelled = true;
+        }
+    }
+
+    /// Whole seconds left on the countdown, rounded up
+    fn remaining(&self) -> Option<u64> {
+        let remaining = self.suspend_at?.saturating_duration_since(Instant::now());
+        Some(remaining.as_secs() + (remaining.subsec_nanos() > 0) as u64)
+    }
+
+    /// When the shown seconds next change, or the countdown runs out
+    fn next_tick(&self) -> Option<Instant> {
+        let suspend_at = self.suspend_at?;
+        let remaining = suspend_at.saturating_duration_since(Instant::now());
+        Some(suspend_at - Duration::from_secs(remaining.as_secs()))
+    }
+
+    /// Suspends if the countdown ran out. If the battery is still low after
+    /// resuming, the next update starts a new countdown
+    fn tick(&mut self) {
+        if self.suspend_at.is_some_and(|suspend_at| suspend_at <= Instant::now()) {
+            self.suspend_at = None;
+            Action::Spawn("systemctl suspend".to_string()).run();
+        }
+    }
 }
 
 #[derive(Debug)]
 pub enum Message {
     Sway(SwayMessage),
     Mpd(MpdMessage),
+    Mpris(MprisMessage),
     Network(NetworkMessage),
     Audio(AudioMessage),
+    Mic(MicMessage),
     Backlight(BacklightMessage),
     Battery(BatteryMessage),
     ClockMessage(ClockMessage),
-    PointerPress { pos: Vec2 },
-    PointerRelease { pos: Vec2 },
+    Systemd(SystemdMessage),
+    Docker(DockerMessage),
+    Thermal(ThermalMessage),
+    Cpu(CpuMessage),
+    Memory(MemoryMessage),
+    Nvme(NvmeMessage),
+    Hosts(HostsMessage),
+    Libvirt(LibvirtMessage),
+    KdeConnect(KdeConnectMessage),
+    Camera(CameraMessage),
+    Custom(CustomMessage),
+    Watch(WatchMessage),
+    Mqtt(MqttMessage),
+    Taskbar(TaskbarMessage),
+    Privacy(PrivacyMessage),
+    Update(UpdateMessage),
+    Ambient(AmbientMessage),
+    Ipc(IpcMessage),
+    Logind(LogindMessage),
+    /// For the module from another crate by this name, see ModuleSender
+    External {
+        name: Arc<str>,
+        message: ModuleMessage,
+    },
+    /// A subscription stopped or started working again
+    ModuleStatus { name: String, status: ModuleStatus },
+    /// Tells the watchdog a generator that has nothing to send is still
+    /// alive, the supervisor drops it
+    Heartbeat,
+    PointerPress { pos: Vec2, button: u32 },
+    PointerRelease { pos: Vec2, button: u32 },
+    /// Vertical scroll in surface pixels, positive is down. Wheels also
+    /// send the notches turned, touchpads leave discrete at 0
+    PointerScroll { pos: Vec2, delta: f32, discrete: i32 },
+    /// A three finger swipe across the bar ended, dx being how far it went
+    /// to the right in surface pixels
+    PointerSwipe { dx: f32 },
+    PointerMotion { pos: Vec2 },
+    PointerLeave,
+    /// The pointer got onto or off the hot zone of an autohiding bar
+    EdgeEnter,
+    EdgeLeave,
+    /// A click on the OSD surface
+    OsdPress,
+    /// A click on the line of the menu surface, counted from the top
+    MenuPress {
+        line: usize,
+    },
+    Key(KeyInput),
 }
 
+/// Linux input event codes for the mouse buttons
+pub const BTN_LEFT: u32 = 0x110;
+pub const BTN_RIGHT: u32 = 0x111;
+pub const BTN_MIDDLE: u32 = 0x112;
+
+/// Scroll distance running one scroll action, what a wheel click usually is
+const SCROLL_STEP: f32 = 10.;
+
+/// How long the pointer has to rest on a widget for its tooltip to show
+const TOOLTIP_DELAY: Duration = Duration::from_millis(500);
+
+/// How long the CPU time of processes is measured over for the tooltip
+const TOP_PROCESSES_WINDOW: Duration = Duration::from_millis(250);
+
+/// Time for the renderer to lay out the bar with the top processes before
+/// their tooltip shows
+const TOOLTIP_SETTLE: Duration = Duration::from_millis(50);
+
+/// Processes listed for each of CPU and memory
+const TOP_PROCESSES: usize = 3;
+
 impl State {
-    pub fn new() -> Self {
+    pub fn new(
+        config: Config,
+        hit_regions: HitRegions,
+        external_modules: BTreeMap<String, SharedModule>,
+    ) -> Self {
+        let layout = &config.layout;
+        for name in layout.left.iter().chain(&layout.center).chain(&layout.right) {
+            if widget::find(name).is_none()
+                && !is_custom_module(&config, name)
+                && !is_watch_module(&config, name)
+                && !is_mqtt_module(&config, name)
+                && !external_modules.contains_key(&**name) {
+                log::warn!("Unknown module {name:?} in the layout, it won't be shown");
+            }
+        }
+        let autohide = Autohide::new(&config.autohide);
+        let mic_meter = watch::Sender::new(config.mic.enabled);
+        let timer =
+            Timer::Stopped(Duration::from_secs(config.timer.duration).min(timer::MAX_LENGTH));
+        let night_light = NightLight::new(&config.night_light);
         Self {
+            config,
+            hit_regions,
+            sensors: vec![],
+            throttling: None,
+            cpu: None,
+            cpu_view: 0,
+            memory: None,
+            nvme_drives: vec![],
+            hosts: vec![],
+            top_processes: None,
+            domains: vec![],
+            phones: vec![],
+            camera_users: vec![],
+            disconnected: BTreeMap::new(),
+            custom: HashMap::new(),
+            watched: HashMap::new(),
+            mqtt: HashMap::new(),
+            external_modules,
+            palette: None,
+            menu: None,
+            bar_mode: None,
+            hidden_modules: HashSet::new(),
+            hidden: false,
+            locked: false,
+            autohide,
+            throttle: Throttle::default(),
+            battery_alert: BatteryAlert::default(),
+            battery_levels: Vec::new(),
+            timer,
+            night_light,
             clock: chrono::Local::now(),
+            systemd_timers: vec![],
+            containers: vec![],
             power_supply: vec![],
             backlights: vec![],
             focused_window_name: None,
+            binding_mode: None,
+            windows: vec![],
+            workspace_apps: BTreeMap::new(),
+            taskbar: Vec::new(),
+            privacy: Vec::new(),
+            update: None,
+            ambient: None,
             workspaces: Vec::new(),
+            workspace_slide: None,
+            workspace_history: Vec::new(),
             mpd_status: None,
             mpd_current_song: None,
+            mpd_cover: None,
+            mpris_players: vec![],
             press_position: Vec2 { x: 0., y: 0. },
+            scroll_delta: 0.,
+            hover: None,
             segments: vec![],
             networks: vec![],
             audio_state: AudioState::default(),
+            mic_meter,
+            resumes: watch::Sender::new(()),
+            suspended: false,
+            session_locked: false,
+            mic_level: None,
+            reload_font: None,
+            release_keyboard: false,
         }
     }
 
-    pub fn to_renderable_state(&self) -> RenderState {
-        let mut left = Vec::new();
-        for workspace in self.workspaces.iter() {
-            if let Some(name) = &workspace.name {
-                left.push(Renderable::Text {
-                    text: name.to_string(),
-                    fg: if workspace.visible {
-                        0xffFFffFF
-                    } else {
-                        0xff111111
-                    },
-                    bg: if workspace.visible {
-                        0xff111111
-                    } else {
-                        0xff000000
-                    },
-                })
-            } else {
-                left.push(Renderable::Text {
-                    text: workspace.num.to_string(),
-                    fg: 0xffFFffFF,
-                    bg: 0,
-                });
-            }
-            left.push(Renderable::Space(1.))
-        }
-        left.push(Renderable::Space(1.));
-        if let Some(mpd_status) = &self.mpd_status {
-            if let Some((elapsed, total)) = mpd_status.time {
-                let completed = elapsed.as_secs_f32() / total.as_secs_f32();
-                left.push(Renderable::Box {
-                    fg: 0xff00ffff,
-                    bg: 0xff00ffff,
-                    width: 10.,
-                    height: 10.,
-                    skip: 0.,
-                });
-                left.push(if mpd_status.state == mpd::status::State::Play {
-                    Renderable::Box {
-                        fg: 0xffff00ff,
-                        bg: 0xffff00ff,
-                        width: 10. * completed,
-                        height: 10.,
-                        skip: 10.,
-                    }
-                } else {
-                    Renderable::Box {
-                        fg: 0xffffffff,
-                        bg: 0xffffffff,
-                        width: 10. * completed,
-                        height: 10.,
-                        skip: 10.,
-                    }
-                });
-            }
+    pub(crate) fn scroll_sensitivity(&self, module: &str) -> f32 {
+        self.config.scroll_sensitivity.get(module).copied().unwrap_or(1.)
+    }
+
+    fn lock(&mut self, locked: bool) {
+        self.locked = locked;
+        // Tooltips don't come up while locked, one that is up goes away
+        if locked {
+            self.hover = None;
+            self.scroll_delta = 0.;
+            self.bar_mode = None;
         }
+    }
 
-        left.push(Renderable::Space(1.));
+    /// Catches up on what went stale during a suspend, the clock is shown
+    /// right away rather than once its subscription polls again
+    fn resume(&mut self) {
+        self.clock = chrono::Local::now();
+        self.resumes.send_replace(());
+    }
 
-        if let Some(song) = &self.mpd_current_song {
-            if let Some(name) = &song.title {
-                let mut trunc_name = name.clone();
-                trunc_name.truncate(trunc_name.floor_char_boundary(30));
-                if name.len() > 30 {
-                    trunc_name = trunc_name + "...";
-                }
-                left.push(Renderable::Text {
-                    text: trunc_name,
-                    fg: 0xffffffff,
-                    bg: 0x00000000,
-                })
-            }
+    fn shown(&self, module: &str) -> bool {
+        !self.hidden_modules.contains(module)
+    }
+
+    /// Hidden through the IPC socket, or autohidden unless something needs
+    /// the bar to stay out, like the palette
+    fn bar_hidden(&self) -> bool {
+        self.hidden
+            || (self.autohide.hidden() && self.palette.is_none() && self.bar_mode.is_none())
+    }
+
+    /// Opens the palette in the mode, or closes it if it already is open in
+    /// that mode
+    fn toggle_palette(&mut self, mode: PaletteMode) {
+        self.palette = match &self.palette {
+            Some(palette) if palette.mode == mode => None,
+            _ => Some(Palette::new(mode)),
         }
+    }
 
-        let mut center = Vec::new();
-        if let Some(window_name) = &self.focused_window_name {
-            let mut trunc_name = window_name.clone();
-            trunc_name.truncate(trunc_name.floor_char_boundary(30));
-            if window_name.len() > 30 {
-                trunc_name = trunc_name + "...";
-            }
-            center.push(Renderable::Text {
-                text: trunc_name,
-                fg: 0xffffffff,
-                bg: 0x00000000,
-            })
+    /// Opens the menu of the mode below the last press, or closes it if it
+    /// already is open in that mode
+    fn toggle_menu(&mut self, mode: PaletteMode) {
+        self.menu = match &self.menu {
+            Some(menu) if menu.mode == mode => None,
+            _ => Some(Menu {
+                mode,
+                x: self.press_position.x.max(0.) as u32,
+                confirm: None,
+            }),
         }
+    }
 
-        let mut right = Vec::new();
+    /// The entries of the open menu, top to bottom
+    fn menu_entries(&self) -> Vec<PaletteEntry> {
+        let Some(menu) = &self.menu else {
+            return vec![];
+        };
+        match &menu.confirm {
+            Some(confirm) => vec![
+                PaletteEntry {
+                    label: format!("yes, {}", confirm.label),
+                    action: confirm.action.clone(),
+                },
+                PaletteEntry {
+                    label: "cancel".to_string(),
+                    action: Action::CloseMenu,
+                },
+            ],
+            None => self.mode_entries(Some(menu.mode)),
+        }
+    }
 
-        for network in self.networks.iter() {
-            match network {
-                Network::Wifi {
-                    if_index: _,
-                    if_name: _,
-                    ssid,
-                    up: _,
-                    down: _,
-                    up_rate,
-                    down_rate,
-                } => {
-                    right.push(Renderable::Text {
-                        text: format!(
-                            "{} {}↓ {}↑",
-                            if let Some(ssid) = ssid { ssid } else { "" }.to_string(),
-                            display_bytes(*up_rate) + "/s",
-                            display_bytes(*down_rate) + "/s",
-                        ),
-                        fg: 0xffffffff,
-                        bg: 0x00000000,
-                    });
-                }
-                Network::Network {
-                    if_index: _,
-                    name,
-                    up: _,
-                    down: _,
-                    up_rate,
-                    down_rate,
-                } => {
-                    if name == "lo" {
-                        continue;
-                    }
-                    right.push(Renderable::Text {
-                        text: format!(
-                            "{} {}↓ {}↑",
-                            name,
-                            display_bytes(*up_rate) + "/s",
-                            display_bytes(*down_rate) + "/s",
-                        ),
-                        fg: 0xffffffff,
-                        bg: 0x00000000,
+    /// Runs the menu entry on the line, entries that ask first swap the
+    /// menu for their confirmation
+    fn press_menu(&mut self, line: usize) {
+        let Some(entry) = self.menu_entries().into_iter().nth(line) else {
+            return;
+        };
+        match entry.action {
+            Action::Confirm { label, action } => {
+                if let Some(menu) = &mut self.menu {
+                    menu.confirm = Some(PaletteEntry {
+                        label,
+                        action: *action,
                     });
                 }
             }
-            right.push(Renderable::Space(1.0))
+            action => {
+                self.menu = None;
+                self.run_action(action);
+            }
         }
+    }
 
-        for sink_volume in self.audio_state.sink_volume.iter() {
-            right.push(Renderable::Box {
-                fg: 0x000f0fff,
-                bg: 0x000f0fff,
-                width: 1.,
-                height: 1.,
-                skip: 0.0,
-            });
-            right.push(Renderable::Box {
-                fg: 0x0000ffff,
-                bg: 0x0000ffff,
-                width: 1.,
-                height: sink_volume.cbrt(),
-                skip: 1.0,
-            });
+    /// Leaves bar mode, or enters it on the leftmost widget
+    fn toggle_bar_mode(&mut self) {
+        self.bar_mode = match self.bar_mode {
+            Some(_) => None,
+            None if self.locked => None,
+            None => Some(0),
         }
+    }
 
-        for backlight in &self.backlights {
-            right.push(Renderable::Box {
-                fg: 0x44444444,
-                bg: 0x44444444,
-                width: 1.,
-                height: 1.,
-                skip: 0.0,
-            });
-            right.push(Renderable::Box {
-                fg: 0xffffffff,
-                bg: 0xffffffff,
-                width: 1.,
-                height: backlight.brightness as f32 / backlight.max_brightness as f32,
-                skip: 1.0,
-            });
+    /// Moves the highlight, runs the widget's click or scroll actions and
+    /// leaves bar mode on Escape
+    fn bar_mode_key(&mut self, widget: usize, key: &KeyInput) {
+        let (count, on_click, on_scroll) = {
+            let regions = self
+                .hit_regions
+                .read()
+                .expect("The hit regions lock to never be poisoned");
+            let widgets = bar_mode_widgets(&regions);
+            let picked = widgets.get(widget).or(widgets.last());
+            (
+                widgets.len(),
+                picked.and_then(|region| region.on_click.clone()),
+                picked.and_then(|region| region.on_scroll.clone()),
+            )
+        };
+        // Widgets can go away under the highlight
+        let widget = widget.min(count.saturating_sub(1));
+        match key.keysym {
+            Keysym::Escape => self.bar_mode = None,
+            Keysym::Right | Keysym::Tab if !key.shift => {
+                self.bar_mode = Some((widget + 1).min(count.saturating_sub(1)));
+            }
+            // Shift+Tab usually arrives as ISO_Left_Tab
+            Keysym::Left | Keysym::ISO_Left_Tab | Keysym::Tab => {
+                self.bar_mode = Some(widget.saturating_sub(1));
+            }
+            Keysym::Home => self.bar_mode = Some(0),
+            Keysym::End => self.bar_mode = Some(count.saturating_sub(1)),
+            Keysym::Return | Keysym::KP_Enter | Keysym::space => {
+                if let Some(action) = on_click {
+                    self.run_action(action);
+                }
+            }
+            Keysym::Up | Keysym::Down => {
+                if let Some(on_scroll) = on_scroll {
+                    self.run_action(if key.keysym == Keysym::Up {
+                        on_scroll.up
+                    } else {
+                        on_scroll.down
+                    });
+                }
+            }
+            _ => {}
         }
+    }
 
-        for power_supply in &self.power_supply {
-            right.push(Renderable::Space(1.0));
-            right.push(match power_supply {
-                PowerSupply::Battery { status, capacity } => Renderable::Text {
-                    text: format!("{capacity}%"),
-                    fg: match status {
-                        PowerSupplyStatus::Charging => 0x0000ffff,
-                        PowerSupplyStatus::Full => 0x0000ffff,
-                        _ => 0xffffffff,
-                    },
-                    bg: 0x00000000,
-                },
-                PowerSupply::Mains { online } => Renderable::Text {
-                    text: if *online {
-                        "Plugged".to_string()
+    /// Everything the palette can fuzzy match, in the order shown before
+    /// anything is typed
+    fn palette_entries(&self) -> Vec<PaletteEntry> {
+        self.mode_entries(self.palette.as_ref().map(|palette| palette.mode))
+    }
+
+    /// The entries of the mode, the palette and menus list the same ones
+    fn mode_entries(&self, mode: Option<PaletteMode>) -> Vec<PaletteEntry> {
+        if mode == Some(PaletteMode::Governors) {
+            let Some(cpu) = &self.cpu else {
+                return vec![];
+            };
+            return cpu
+                .available_governors
+                .iter()
+                .map(|governor| PaletteEntry {
+                    label: if cpu.governor.as_ref() == Some(governor) {
+                        format!("{governor} (current)")
                     } else {
-                        continue;
+                        governor.clone()
                     },
-                    fg: 0xffffffff,
-                    bg: 0x00000000,
+                    action: Action::Spawn(
+                        self.config.cpu.governor_command.replace("{governor}", governor),
+                    ),
+                })
+                .collect();
+        }
+        if mode == Some(PaletteMode::Profiles) {
+            return self
+                .audio_state
+                .bluetooth_devices
+                .iter()
+                .flat_map(|device| {
+                    device.profiles.iter().map(|profile| PaletteEntry {
+                        label: if device.active == Some(profile.index) {
+                            format!("{}: {} (current)", device.description, profile.description)
+                        } else {
+                            format!("{}: {}", device.description, profile.description)
+                        },
+                        action: Action::Spawn(format!(
+                            "wpctl set-profile {} {}",
+                            device.id, profile.index
+                        )),
+                    })
+                })
+                .collect();
+        }
+        if mode == Some(PaletteMode::Workspace) {
+            let Some(focused) = self.workspaces.iter().find(|workspace| workspace.focused) else {
+                return vec![];
+            };
+            let mut outputs = self
+                .workspaces
+                .iter()
+                .map(|workspace| &workspace.output)
+                .filter(|output| **output != focused.output)
+                .collect::<Vec<_>>();
+            outputs.sort();
+            outputs.dedup();
+            return outputs
+                .into_iter()
+                .map(|output| PaletteEntry {
+                    label: format!("move to {output}"),
+                    action: Action::SwayCommand(format!(
+                        "move workspace to output {}",
+                        quote(output)
+                    )),
+                })
+                .collect();
+        }
+        if mode == Some(PaletteMode::Power) {
+            return self
+                .config
+                .power
+                .commands
+                .iter()
+                .map(|(label, command)| PaletteEntry {
+                    label: label.clone(),
+                    action: Action::Confirm {
+                        label: label.clone(),
+                        action: Box::new(Action::Spawn(command.clone())),
+                    },
+                })
+                .collect();
+        }
+        if mode == Some(PaletteMode::Confirm) {
+            let Some(confirm) = self
+                .palette
+                .as_ref()
+                .and_then(|palette| palette.confirm.clone())
+            else {
+                return vec![];
+            };
+            return vec![
+                PaletteEntry {
+                    label: format!("yes, {}", confirm.label),
+                    action: confirm.action,
                 },
-            })
+                PaletteEntry {
+                    label: "cancel".to_string(),
+                    action: Action::ClosePalette,
+                },
+            ];
         }
+        if mode == Some(PaletteMode::Windows) {
+            return self
+                .windows
+                .iter()
+                .map(|window| PaletteEntry {
+                    label: format!(
+                        "{}: {} ({})",
+                        window.app_id.as_deref().unwrap_or("?"),
+                        truncate(window.name.as_deref().unwrap_or(""), 40),
+                        window.workspace
+                    ),
+                    action: Action::SwayCommand(format!("[con_id={}] focus", window.id)),
+                })
+                .collect();
+        }
+        let workspaces = self.workspaces.iter().map(|workspace| {
+            let name = workspace
+                .name
+                .clone()
+                .unwrap_or_else(|| workspace.num.to_string());
+            PaletteEntry {
+                label: format!("workspace {name}"),
+                action: Action::Spawn(format!(
+                    "swaymsg workspace '{}'",
+                    name.replace('\'', "'\\''")
+                )),
+            }
+        });
+        let commands = self
+            .config
+            .palette
+            .commands
+            .iter()
+            .map(|(label, command)| PaletteEntry {
+                label: label.clone(),
+                action: Action::Spawn(command.clone()),
+            });
+        let custom_modules = self.config.custom.keys().map(|name| format!("custom/{name}"));
+        let watch_modules = self.config.watch.keys().map(|name| format!("watch/{name}"));
+        let mqtt_modules = self.config.mqtt.modules.keys().map(|name| format!("mqtt/{name}"));
+        let modules = WIDGETS
+            .iter()
+            .map(|widget| widget.name.to_string())
+            .chain(custom_modules)
+            .chain(watch_modules)
+            .chain(mqtt_modules)
+            .map(|module| PaletteEntry {
+                label: format!("toggle {module}"),
+                action: Action::ToggleModule(module),
+            });
+        workspaces.chain(commands).chain(modules).collect()
+    }
+
+    /// Actions that change what the bar shows are handled here, the rest run
+    /// on their own
+    fn run_action(&mut self, action: Action) {
+        match action {
+            Action::ToggleModule(module) => {
+                if !self.hidden_modules.remove(&module) {
+                    self.hidden_modules.insert(module);
+                }
+            }
+            Action::CancelSuspend => self.battery_alert.cancel(),
+            Action::TogglePalette(mode) => self.toggle_palette(mode),
+            Action::ClosePalette => self.palette = None,
+            Action::ToggleMenu(mode) => self.toggle_menu(mode),
+            Action::CloseMenu => self.menu = None,
+            Action::Confirm { label, action } => {
+                self.palette = Some(Palette::confirm(label, *action));
+            }
+            Action::CycleWorkspace(offset) => self.cycle_workspace(offset),
+            Action::ModuleClick(name) => {
+                if let Some(module) = self.external_modules.get(&name) {
+                    module
+                        .lock()
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .on_click();
+                }
+            }
+            Action::ToggleMicMeter => self.mic_meter.send_modify(|enabled| *enabled = !*enabled),
+            Action::CycleCpuView => {
+                self.cpu_view = (self.cpu_view + 1) % self.config.thermal.cpu_views.len().max(1);
+            }
+            Action::Timer(action) => {
+                self.timer
+                    .run(action, Duration::from_secs(self.config.timer.duration));
+            }
+            Action::NightLight(action) => self.night_light.run(action, &self.config.night_light),
+            action => action.run(),
+        }
+    }
 
-        right.push(Renderable::Space(1.0));
-        right.push(Renderable::Text {
-            text: self.clock.to_rfc2822(),
-            fg: 0xffffffff,
-            bg: 0x00000000,
+    fn resort_workspaces(&mut self) {
+        sort_workspaces(
+            &mut self.workspaces,
+            self.config.workspaces.sort,
+            &self.workspace_history,
+        );
+    }
+
+    /// Switches to the workspace offset places from the focused one, in the
+    /// order the bar shows them. By number when that order follows the focus,
+    /// or every step would only go back and forth between two
+    fn cycle_workspace(&mut self, offset: i32) {
+        let config = &self.config.workspaces;
+        let mut candidates = self
+            .workspaces
+            .iter()
+            .enumerate()
+            .filter(|(_, workspace)| {
+                workspace.focused || !(config.scroll_skip_empty && workspace.focus.is_empty())
+            })
+            .map(|(index, _)| index)
+            .collect::<Vec<_>>();
+        if config.sort == WorkspaceSort::Recent {
+            candidates.sort_by_key(|index| self.workspaces[*index].num);
+        }
+        let Some(current) = candidates
+            .iter()
+            .position(|index| self.workspaces[*index].focused)
+        else {
+            return;
+        };
+        let len = candidates.len() as i32;
+        let target = current as i32 + offset;
+        let target = if config.scroll_wrap {
+            target.rem_euclid(len)
+        } else if (0..len).contains(&target) {
+            target
+        } else {
+            return;
+        };
+        let target = candidates[target as usize];
+        if self.workspaces[target].focused {
+            return;
+        }
+        // Sway confirms with an event later, but fast scrolling moves on
+        // from here before that
+        for (index, workspace) in self.workspaces.iter_mut().enumerate() {
+            workspace.focused = index == target;
+        }
+        let workspace = &self.workspaces[target];
+        run_command(match &workspace.name {
+            Some(name) => format!("workspace {}", quote(name)),
+            None => format!("workspace number {}", workspace.num),
         });
+    }
+
+    /// Renders the named module, None for names that aren't modules
+    fn render_module(&self, name: &str) -> Option<Vec<Renderable>> {
+        if let Some(widget) = widget::find(name) {
+            return Some((widget.render)(self));
+        }
+        Some(match name {
+            _ if is_custom_module(&self.config, name) => self.custom_module(name),
+            _ if is_watch_module(&self.config, name) => self.watch_module(name),
+            _ if is_mqtt_module(&self.config, name) => self.mqtt_module(name),
+            _ => self
+                .external_modules
+                .get(name)?
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .render(&self.config.theme.style(name)),
+        })
+    }
+
+    /// The bindings of the module in the config, over what the module binds
+    /// on its own
+    fn mouse_bindings(&self, name: &str) -> MouseBindings {
+        let defaults = match name {
+            "workspaces" => MouseBindings {
+                left: None,
+                middle: Some(Action::SwayCommand("workspace back_and_forth".to_string())),
+                right: Some(Action::TogglePalette(PaletteMode::Workspace)),
+            },
+            "bluetooth" => MouseBindings {
+                left: None,
+                middle: None,
+                right: Some(Action::TogglePalette(PaletteMode::Profiles)),
+            },
+            "timer" => MouseBindings {
+                left: None,
+                middle: None,
+                right: Some(Action::Timer(TimerAction::Reset)),
+            },
+            _ => MouseBindings {
+                left: self
+                    .external_modules
+                    .get(name)
+                    .filter(|module| {
+                        module
+                            .lock()
+                            .unwrap_or_else(PoisonError::into_inner)
+                            .clickable()
+                    })
+                    .map(|_| Action::ModuleClick(name.to_string())),
+                ..MouseBindings::default()
+            },
+        };
+        self.config.mouse.get(name).cloned().unwrap_or_default().or(defaults)
+    }
+
+    /// Widest the module may get in bar heights, going by the config over
+    /// the defaults of the modules showing titles
+    fn max_width(&self, name: &str) -> Option<f32> {
+        let default = match name {
+            "window" => Some(240),
+            "mpd" => Some(400),
+            _ => None,
+        };
+        let max_width = self.config.max_width.get(name).copied().or(default)?;
+        Some(max_width as f32 / BAR_HEIGHT as f32)
+    }
+
+    /// The shown modules of a section in order, with a gap between the
+    /// modules that have something to show
+    fn render_section(&self, names: &[Arc<str>]) -> Vec<Renderable> {
+        let mut section = Vec::new();
+        for name in names.iter().filter(|name| self.shown(name)) {
+            let mut module = match self.render_module(name) {
+                Some(module) => module,
+                None => continue,
+            };
+            if module.is_empty() {
+                continue;
+            }
+            if let Some(max_width) = self.max_width(name) {
+                module = vec![Renderable::Ellipsize {
+                    max_width,
+                    children: module,
+                }];
+            }
+            let bindings = self.mouse_bindings(name);
+            if !bindings.is_empty() {
+                module = vec![Renderable::Bindings {
+                    bindings,
+                    children: module,
+                }];
+            }
+            if self.locked {
+                dim_interactive(&mut module, false);
+            }
+            if !section.is_empty() {
+                section.push(Renderable::Space(1.));
+            }
+            let theme = &self.config.theme;
+            let children = match theme.modules.get(&**name) {
+                Some(style) if style.background.is_some() || style.border.is_some() => {
+                    vec![Renderable::Pill {
+                        bg: style.background.unwrap_or(Color::TRANSPARENT).into(),
+                        radius: theme.pill_radius,
+                        border: style.border.unwrap_or(Color::TRANSPARENT).into(),
+                        border_width: if style.border.is_some() {
+                            theme.border_width
+                        } else {
+                            0.
+                        },
+                        children: module,
+                    }]
+                }
+                _ => module,
+            };
+            section.push(Renderable::Module {
+                name: name.clone(),
+                children,
+            });
+        }
+        section
+    }
+
+    fn custom_module(&self, name: &str) -> Vec<Renderable> {
+        let style = self.config.theme.style(name);
+        let name = name.strip_prefix("custom/").unwrap_or(name);
+        let config = &self.config.custom[name];
+        render_custom_output(self.custom.get(name), &config.on_click, config.markup, style)
+    }
 
+    fn watch_module(&self, name: &str) -> Vec<Renderable> {
+        let style = self.config.theme.style(name);
+        let name = name.strip_prefix("watch/").unwrap_or(name);
+        let on_click = &self.config.watch[name].on_click;
+        render_custom_output(self.watched.get(name), on_click, false, style)
+    }
+
+    fn mqtt_module(&self, name: &str) -> Vec<Renderable> {
+        let style = self.config.theme.style(name);
+        let name = name.strip_prefix("mqtt/").unwrap_or(name);
+        let on_click = &self.config.mqtt.modules[name].on_click;
+        render_custom_output(self.mqtt.get(name), on_click, false, style)
+    }
+
+    pub fn to_renderable_state(&self) -> RenderState {
+        let layout = &self.config.layout;
         RenderState {
-            left,
-            right,
-            center,
+            left: self.render_section(&layout.left),
+            // The palette takes over the center while it is open
+            center: match &self.palette {
+                Some(palette) => {
+                    let style = self.config.theme.style("palette");
+                    palette.to_renderables(&self.palette_entries(), style.fg, style.dim)
+                }
+                None => self.render_section(&layout.center),
+            },
+            right: self.render_section(&layout.right),
+            keyboard_focus: self.palette.is_some() || self.bar_mode.is_some(),
+            background: self.background().into(),
+            tooltip: self.tooltip(),
+            osd: self.osd(),
+            menu: self.menu(),
+            hidden: self.bar_hidden(),
+            highlight: self.bar_mode.map(|widget| Highlight {
+                widget,
+                color: self.config.theme.accent.into(),
+            }),
+        }
+    }
+
+    /// The background of the theme, tinted towards what is next to the bar
+    fn background(&self) -> Color {
+        let background = self.config.theme.background;
+        match self.ambient {
+            Some(ambient) => background.mix(ambient, self.config.ambient.strength.clamp(0., 1.)),
+            None => background,
+        }
+    }
+
+    /// The suspend countdown, over the windows so it can't be missed
+    fn osd(&self) -> Option<Tooltip> {
+        let remaining = self.battery_alert.remaining()?;
+        Some(Tooltip {
+            text: format!("Battery low, suspending in {remaining}s\nClick here to cancel"),
+            x: 0,
+            fg: self.config.theme.background.into(),
+            bg: self.config.theme.style("battery").urgent,
+        })
+    }
+
+    /// The open menu, in the colors of the palette
+    fn menu(&self) -> Option<Tooltip> {
+        let menu = self.menu.as_ref()?;
+        let style = self.config.theme.style("palette");
+        let labels: Vec<String> = self
+            .menu_entries()
+            .into_iter()
+            .map(|entry| entry.label)
+            .collect();
+        Some(Tooltip {
+            text: labels.join("\n"),
+            x: menu.x,
+            fg: style.fg,
+            bg: style.bg,
+        })
+    }
+
+    /// The tooltip of the hovered widget, once the pointer rested on it
+    fn tooltip(&self) -> Option<Tooltip> {
+        if self.bar_hidden() {
+            return None;
+        }
+        let hover = self.hover.as_ref().filter(|hover| hover.shown())?;
+        let text = self
+            .hit_regions
+            .read()
+            .expect("The hit regions lock to never be poisoned")
+            .iter()
+            .find(|region| region.tooltip.is_some() && region.x.contains(&hover.x))?
+            .tooltip
+            .clone()?;
+        Some(Tooltip {
+            text,
+            x: hover.x.max(0.) as u32,
+            fg: self.config.theme.foreground.into(),
+            bg: self.config.theme.background.into(),
+        })
+    }
+
+    /// Whether tooltips wait for the top processes, which only the CPU and
+    /// memory tooltips list. Which widget is hovered is only known by the
+    /// tooltip text, so every tooltip waits while either is shown
+    fn samples_processes(&self) -> bool {
+        (self.cpu.is_some() && self.shown("thermal"))
+            || (self.memory.is_some() && self.shown("memory"))
+    }
+
+    /// Takes the tooltip to its next stage, returns whether that has to be
+    /// drawn. Without a sample the tooltip shows without the top processes
+    async fn advance_tooltip(&mut self, sample_processes: bool) -> bool {
+        let Some(hover) = &mut self.hover else {
+            return false;
+        };
+        match std::mem::replace(&mut hover.stage, TooltipStage::Shown) {
+            TooltipStage::Resting if sample_processes => {
+                let Some(sample) = ProcessSample::take_blocking().await else {
+                    return true;
+                };
+                hover.stage = TooltipStage::Sampled(sample);
+                false
+            }
+            TooltipStage::Sampled(earlier) => {
+                if let Some(sample) = ProcessSample::take_blocking().await {
+                    self.top_processes = Some(sample.top(&earlier, TOP_PROCESSES));
+                }
+                hover.stage = TooltipStage::Measured;
+                true
+            }
+            _ => true,
+        }
+    }
+
+    /// Follows the pointer from widget to widget, returns whether a shown
+    /// tooltip went away so the bar has to be redrawn
+    fn hover(&mut self, pos: Option<Vec2>) -> bool {
+        let regions = self
+            .hit_regions
+            .read()
+            .expect("The hit regions lock to never be poisoned");
+        let region = pos.and_then(|pos| {
+            regions
+                .iter()
+                .find(|region| region.tooltip.is_some() && region.x.contains(&pos.x))
+        });
+        let shown = self.hover.as_ref().is_some_and(Hover::shown);
+        match (region, pos) {
+            // Still on the same widget
+            (Some(region), _) if self.hover.as_ref().is_some_and(|hover| region.x.contains(&hover.x)) => false,
+            (Some(_), Some(pos)) => {
+                self.hover = Some(Hover {
+                    x: pos.x,
+                    since: Instant::now(),
+                    stage: TooltipStage::Resting,
+                });
+                self.top_processes = None;
+                shown
+            }
+            _ => {
+                self.hover = None;
+                self.top_processes = None;
+                shown
+            }
         }
     }
 
-    pub async fn run_event_loop<S: StreamExt<Item = Message> + std::marker::Unpin>(
+    /// Messages come tagged with the name of the subscription they are from
+    pub async fn run_event_loop<S: StreamExt<Item = (&'static str, Message)> + std::marker::Unpin>(
         mut self,
         mut message_receiver: S,
-        render_sender: Sender<RenderState>,
+        render_sender: Sender<RenderCommand>,
+        mut occluded: watch::Receiver<bool>,
     ) {
         render_sender
-            .send(self.to_renderable_state())
+            .send(RenderCommand::Draw(self.to_renderable_state()))
             .await
             .expect("To be able to send render requests without drama, when initializing");
-        while let Some(message) = message_receiver.next().await {
-            self.update(message);
+        loop {
+            let deadline = self.throttle.deadline;
+            // Nobody sees the bar while suspended or locked, though the
+            // countdown goes on while locked so a low battery still suspends
+            let paused = self.suspended || self.session_locked;
+            let countdown_tick = self.battery_alert.next_tick().filter(|_| !self.suspended);
+            let timer_tick = self
+                .timer
+                .next_tick(Duration::from_secs(self.config.timer.flash))
+                .filter(|_| !self.suspended);
+            let sample_processes = self.samples_processes();
+            let tooltip_due = self
+                .hover
+                .as_ref()
+                .and_then(|hover| hover.due(sample_processes))
+                .filter(|_| !paused);
+            let autohide_due = self.autohide.hide_at.filter(|_| !paused);
+            let mut source = None;
+            let hidden = self.bar_hidden();
+            tokio::select! {
+                message = message_receiver.next() => match message {
+                    // Pointer motion only redraws when a tooltip has to go
+                    // or the bar comes out
+                    Some((_, Message::PointerMotion { pos })) => {
+                        // Only dropped while the renderer is behind anyway
+                        let _ = render_sender.try_send(RenderCommand::Input);
+                        self.autohide.hold();
+                        let hover_changed = !self.locked && self.hover(Some(pos));
+                        if !hover_changed && self.bar_hidden() == hidden {
+                            continue;
+                        }
+                    }
+                    Some((_, Message::PointerLeave)) => {
+                        self.autohide.release(&self.config.autohide);
+                        if !self.hover(None) {
+                            continue;
+                        }
+                    }
+                    Some((message_source, message)) => {
+                        if message_source == "display" {
+                            let _ = render_sender.try_send(RenderCommand::Input);
+                        }
+                        crash::record(message_source, &message);
+                        if let Err(payload) = catch_unwind(AssertUnwindSafe(|| self.update(message))) {
+                            // Nothing runs the state again, so the report
+                            // is all there is to go by
+                            crash::report("state", Some(&self));
+                            resume_unwind(payload);
+                        }
+                        if self.throttle.hold_back(message_source, &self.config) {
+                            continue;
+                        }
+                        source = Some(message_source);
+                    }
+                    None => break,
+                },
+                // Uncovering the bar sends the state it missed below
+                Ok(()) = occluded.changed() => {}
+                // Held back changes are due
+                _ = sleep_until(deadline.unwrap_or_else(Instant::now).into()), if deadline.is_some() => {}
+                // The suspend countdown redraws every second
+                _ = sleep_until(countdown_tick.unwrap_or_else(Instant::now).into()), if countdown_tick.is_some() => {
+                    self.battery_alert.tick();
+                }
+                // So does a running timer, and it runs out in here
+                _ = sleep_until(timer_tick.unwrap_or_else(Instant::now).into()), if timer_tick.is_some() => {
+                    if self.timer.tick() && self.config.timer.notify {
+                        Action::Spawn("notify-send -i alarm-symbolic 'Timer' 'Time is up'".to_string()).run();
+                    }
+                }
+                _ = sleep_until(tooltip_due.unwrap_or_else(Instant::now).into()), if tooltip_due.is_some() => {
+                    if !self.advance_tooltip(sample_processes).await {
+                        continue;
+                    }
+                }
+                _ = sleep_until(autohide_due.unwrap_or_else(Instant::now).into()), if autohide_due.is_some() => {
+                    self.autohide.hide();
+                }
+            }
+            // Nothing gets drawn while the bar is covered, so building
+            // states would be wasted. A hidden bar counts as covered, but
+            // still has to hear that it is shown again
+            if *occluded.borrow() && self.bar_hidden() == hidden {
+                // Uncovering draws whatever is held back anyway
+                self.throttle.deadline = None;
+                continue;
+            }
+            self.throttle.drawn(source);
+            if let Some(font) = self.reload_font.take() {
+                render_sender
+                    .send(RenderCommand::ReloadFont(font))
+                    .await
+                    .expect("To be able to send render requests without drama");
+            }
+            if std::mem::take(&mut self.release_keyboard) {
+                render_sender
+                    .send(RenderCommand::ReleaseKeyboard)
+                    .await
+                    .expect("To be able to send render requests without drama");
+            }
             render_sender
-                .send(self.to_renderable_state())
+                .send(RenderCommand::Draw(self.to_renderable_state()))
                 .await
                 .expect("To be able to send render requests without drama");
         }
@@ -293,8 +1422,11 @@ impl State {
         match message {
             Message::Sway(sway_message) => match sway_message {
                 SwayMessage::WorkspaceAdd(workspace) => {
+                    if workspace.focused {
+                        self.workspace_history.insert(0, workspace.id);
+                    }
                     self.workspaces.push(workspace);
-                    self.workspaces.sort_by_key(|v| v.num);
+                    self.resort_workspaces();
                 }
                 SwayMessage::WorkspaceDel(id) => {
                     self.workspaces = self
@@ -302,9 +1434,20 @@ impl State {
                         .clone()
                         .into_iter()
                         .filter(|v| v.id != id)
-                        .collect()
+                        .collect();
+                    self.workspace_history.retain(|v| *v != id);
                 }
                 SwayMessage::WorkspaceChangeFocus { id, focus, focused } => {
+                    if focused {
+                        let output = self.workspaces.iter().find(|v| v.id == id).map(|v| &v.output);
+                        if let Some(previous) = self
+                            .workspaces
+                            .iter()
+                            .find(|v| v.focused && v.id != id && Some(&v.output) == output)
+                        {
+                            self.workspace_slide = Some((previous.id, Instant::now()));
+                        }
+                    }
                     if let Some(workspace) =
                         &mut self.workspaces.iter_mut().filter(|v| v.id == id).next()
                     {
@@ -313,6 +1456,11 @@ impl State {
                     } else {
                         log::error!("Couldn't find the workspace when changing focus");
                     }
+                    if focused {
+                        self.workspace_history.retain(|v| *v != id);
+                        self.workspace_history.insert(0, id);
+                        self.resort_workspaces();
+                    }
                 }
                 SwayMessage::WorkspaceRename { id, name } => {
                     if let Some(workspace) =
@@ -320,6 +1468,7 @@ impl State {
                     {
                         workspace.name = name;
                     }
+                    self.resort_workspaces();
                 }
                 SwayMessage::WorkspaceChangeUrgency { id, urgent } => {
                     if let Some(workspace) =
@@ -338,6 +1487,16 @@ impl State {
                 SwayMessage::WindowFocusedChange { window_name } => {
                     self.focused_window_name = window_name
                 }
+                SwayMessage::WindowList(windows) => self.windows = windows,
+                SwayMessage::WorkspaceApps(apps) => self.workspace_apps = apps,
+                SwayMessage::Mode(mode) => self.binding_mode = mode,
+                SwayMessage::Command(command) => match command.as_str() {
+                    "palette" => self.toggle_palette(PaletteMode::Commands),
+                    "windows" => self.toggle_palette(PaletteMode::Windows),
+                    "power" => self.toggle_palette(PaletteMode::Power),
+                    "bar" => self.toggle_bar_mode(),
+                    _ => log::warn!("Unknown sway-shell command {command:?} from a sway binding"),
+                },
             },
             Message::Mpd(mpd_message) => match mpd_message {
                 MpdMessage::MpdPlayerUpdate { status } => {
@@ -349,16 +1508,171 @@ impl State {
                 MpdMessage::MpdSongUpdate { song } => {
                     self.mpd_current_song = song;
                 }
+                MpdMessage::MpdCoverUpdate { cover } => {
+                    self.mpd_cover = cover;
+                }
             },
-            Message::PointerPress { pos } => self.press_position = pos,
-            Message::PointerRelease { pos } => {
+            // Motion still holds an autohiding bar up, so it doesn't slide
+            // away under a finger
+            Message::PointerPress { .. }
+            | Message::PointerRelease { .. }
+            | Message::PointerScroll { .. }
+            | Message::PointerSwipe { .. }
+                if self.locked => {}
+            Message::PointerMotion { .. } if self.locked => self.autohide.hold(),
+            Message::PointerPress { pos, button: _ } => {
+                self.press_position = pos;
+                // Tooltips get in the way of whatever the click opens
+                self.hover = None;
+            }
+            Message::PointerRelease { pos, button } => {
                 self.segments
                     .push(Segment::LINE(Line(self.press_position, pos)));
+                // Widgets without an action for the button leave the click
+                // to the one they are in
+                let action = self
+                    .hit_regions
+                    .read()
+                    .expect("The hit regions lock to never be poisoned")
+                    .iter()
+                    .filter(|region| region.x.contains(&pos.x))
+                    .find_map(|region| match button {
+                        BTN_LEFT => region.on_click.clone(),
+                        BTN_MIDDLE => region.on_middle_click.clone(),
+                        BTN_RIGHT => region.on_right_click.clone(),
+                        _ => None,
+                    });
+                // A click anywhere else on the bar closes the menu
+                if !matches!(action, Some(Action::ToggleMenu(_))) {
+                    self.menu = None;
+                }
+                if let Some(action) = action {
+                    // Whatever was clicked, the palette has done its job
+                    if !matches!(action, Action::TogglePalette(_)) {
+                        self.palette = None;
+                    }
+                    self.run_action(action);
+                }
+            }
+            // Swiping left brings in the workspace to the right, like
+            // pushing the current one away
+            Message::PointerSwipe { dx } => {
+                self.run_action(Action::CycleWorkspace(if dx < 0. { 1 } else { -1 }))
+            }
+            Message::PointerScroll { pos, delta, discrete } => {
+                let on_scroll = self
+                    .hit_regions
+                    .read()
+                    .expect("The hit regions lock to never be poisoned")
+                    .iter()
+                    .filter(|region| region.x.contains(&pos.x))
+                    .find_map(|region| region.on_scroll.clone());
+                match on_scroll {
+                    Some(on_scroll) => {
+                        let steps = if discrete != 0 {
+                            // A notch is a step, however far the compositor
+                            // makes it scroll
+                            self.scroll_delta = 0.;
+                            discrete
+                        } else {
+                            // Touchpads send many small deltas, which add up
+                            // to steps
+                            self.scroll_delta += delta * on_scroll.sensitivity;
+                            let steps = (self.scroll_delta / SCROLL_STEP).trunc();
+                            self.scroll_delta -= steps * SCROLL_STEP;
+                            steps as i32
+                        };
+                        let action = if steps < 0 { &on_scroll.up } else { &on_scroll.down };
+                        for _ in 0..steps.abs() {
+                            self.run_action(action.clone());
+                        }
+                    }
+                    // Leftovers from another widget shouldn't carry over
+                    None => self.scroll_delta = 0.,
+                }
+            }
+            // Handled in run_event_loop to skip redrawing
+            Message::PointerMotion { pos } => {
+                self.autohide.hold();
+                self.hover(Some(pos));
+            }
+            Message::PointerLeave => {
+                self.autohide.release(&self.config.autohide);
+                self.hover(None);
+            }
+            Message::EdgeEnter => self.autohide.hold(),
+            Message::EdgeLeave => self.autohide.release(&self.config.autohide),
+            // The suspend countdown is all the OSD shows
+            Message::OsdPress => self.battery_alert.cancel(),
+            Message::MenuPress { line } => self.press_menu(line),
+            Message::Key(key) => {
+                let entries = self.palette_entries();
+                if let Some(palette) = &mut self.palette {
+                    match palette.handle_key(&key, &entries) {
+                        PaletteEvent::None => {}
+                        PaletteEvent::Close => self.palette = None,
+                        PaletteEvent::Run(action) => {
+                            self.palette = None;
+                            self.run_action(action);
+                        }
+                    }
+                } else if let Some(widget) = self.bar_mode {
+                    self.bar_mode_key(widget, &key);
+                } else if key.keysym == Keysym::Escape {
+                    self.release_keyboard = true;
+                }
             }
             Message::Network(network_message) => self.networks = network_message,
             Message::Audio(audio_message) => match audio_message {
-                AudioMessage::SinkVolume(items) => self.audio_state.sink_volume = items,
+                AudioMessage::DefaultSink(name) => self.audio_state.default_sink_name = name,
+                AudioMessage::SinkVolume(items) => self.audio_state.volume = items,
+                AudioMessage::SinkMute(muted) => self.audio_state.muted = muted,
+                AudioMessage::DefaultSinkDevice(device) => {
+                    self.audio_state.default_sink_device = device
+                }
                 AudioMessage::SourceVolume(items) => self.audio_state.source_volume = items,
+                AudioMessage::BluetoothDevice(device) => {
+                    let devices = &mut self.audio_state.bluetooth_devices;
+                    match devices.iter_mut().find(|v| v.id == device.id) {
+                        Some(existing) => *existing = device,
+                        None => devices.push(device),
+                    }
+                }
+                AudioMessage::GlobalRemoved(id) => {
+                    self.audio_state.bluetooth_devices.retain(|v| v.id != id);
+                    self.audio_state.output_routes.remove(&id);
+                }
+                AudioMessage::JackChanged {
+                    description,
+                    connected,
+                } => {
+                    let text = if connected {
+                        format!("{description} connected")
+                    } else {
+                        format!("{description} disconnected")
+                    };
+                    self.audio_state.jack_osd = Some((text, std::time::Instant::now()));
+                }
+                AudioMessage::JackOsdExpired => {
+                    // Another jack event may have happened in the meantime
+                    if self
+                        .audio_state
+                        .jack_osd
+                        .as_ref()
+                        .is_some_and(|(_, shown_at)| shown_at.elapsed() >= JACK_OSD_DURATION)
+                    {
+                        self.audio_state.jack_osd = None;
+                    }
+                }
+                AudioMessage::OutputRoute {
+                    device,
+                    description,
+                } => {
+                    self.audio_state.output_routes.insert(device, description);
+                }
+            },
+            Message::Mic(mic_message) => match mic_message {
+                MicMessage::Level(level) => self.mic_level = level,
             },
             Message::Backlight(backlight_message) => match backlight_message {
                 BacklightMessage::BacklightsInit(backlights) => self.backlights = backlights,
@@ -367,35 +1681,320 @@ impl State {
                 }
             },
             Message::Battery(battery_message) => match battery_message {
-                BatteryMessage::UpdatePowerSupplies(items) => self.power_supply = items,
+                BatteryMessage::UpdatePowerSupplies(items) => {
+                    self.battery_alert.update(&items, &self.config.battery);
+                    self.battery_levels = items
+                        .iter()
+                        .enumerate()
+                        .map(|(i, power_supply)| {
+                            let level = self.battery_levels.get(i).copied().unwrap_or_default();
+                            level.next(power_supply, &self.config.battery)
+                        })
+                        .collect();
+                    self.power_supply = items;
+                }
             },
             Message::ClockMessage(clock_message) => match clock_message {
                 ClockMessage::TimeUpdate(x) => self.clock = x,
             },
+            Message::Systemd(systemd_message) => match systemd_message {
+                SystemdMessage::TimersUpdate(timers) => self.systemd_timers = timers,
+            },
+            Message::Docker(docker_message) => match docker_message {
+                DockerMessage::ContainersUpdate(containers) => self.containers = containers,
+            },
+            Message::Thermal(thermal_message) => match thermal_message {
+                ThermalMessage::UpdateSensors(sensors) => self.sensors = sensors,
+                ThermalMessage::UpdateThrottling(throttling) => self.throttling = throttling,
+            },
+            Message::Mpris(mpris_message) => match mpris_message {
+                MprisMessage::PlayersUpdate(players) => self.mpris_players = players,
+            },
+            Message::Cpu(cpu_message) => match cpu_message {
+                CpuMessage::Update(stats) => self.cpu = Some(stats),
+            },
+            Message::Memory(memory_message) => match memory_message {
+                MemoryMessage::Update(stats) => self.memory = Some(stats),
+            },
+            Message::Nvme(nvme_message) => match nvme_message {
+                NvmeMessage::Update(drives) => self.nvme_drives = drives,
+            },
+            Message::Hosts(hosts_message) => match hosts_message {
+                HostsMessage::Update(hosts) => self.hosts = hosts,
+            },
+            Message::Libvirt(libvirt_message) => match libvirt_message {
+                LibvirtMessage::DomainsUpdate(domains) => self.domains = domains,
+            },
+            Message::KdeConnect(kdeconnect_message) => match kdeconnect_message {
+                KdeConnectMessage::DevicesUpdate(phones) => self.phones = phones,
+            },
+            Message::Camera(camera_message) => match camera_message {
+                CameraMessage::UsersUpdate(users) => self.camera_users = users,
+            },
+            Message::Logind(LogindMessage::Sleep(sleeping)) => {
+                self.suspended = sleeping;
+                if sleeping {
+                    self.hover = None;
+                } else {
+                    self.resume();
+                }
+            }
+            Message::Logind(LogindMessage::Locked(locked)) => {
+                self.session_locked = locked;
+                if locked {
+                    self.hover = None;
+                }
+            }
+            Message::External { name, message } => {
+                if let Some(module) = self.external_modules.get(&*name) {
+                    module
+                        .lock()
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .update(message);
+                }
+            }
+            Message::Heartbeat => {}
+            Message::ModuleStatus { name, status } => match status {
+                ModuleStatus::Connected => {
+                    self.disconnected.remove(&name);
+                }
+                ModuleStatus::Disconnected { .. } => {
+                    self.disconnected.insert(name, status);
+                }
+            },
+            Message::Ipc(ipc_message) => match ipc_message {
+                IpcMessage::Show => {
+                    self.hidden = false;
+                    self.autohide.reveal(&self.config.autohide);
+                }
+                IpcMessage::Hide => {
+                    self.hidden = true;
+                    self.autohide.hide();
+                }
+                IpcMessage::ToggleModule(module) => self.run_action(Action::ToggleModule(module)),
+                IpcMessage::Lock => self.lock(true),
+                IpcMessage::Unlock => self.lock(false),
+                IpcMessage::ToggleLock => self.lock(!self.locked),
+                IpcMessage::SetTheme(theme) => self.config.theme = theme,
+                IpcMessage::Timer(action) => self.run_action(Action::Timer(action)),
+                IpcMessage::Reload(config) => {
+                    if config.font != self.config.font {
+                        self.reload_font = Some(config.font.clone());
+                    }
+                    self.config = *config;
+                }
+            },
+            Message::Custom(custom_message) => match custom_message {
+                CustomMessage::Output { name, output } => {
+                    self.custom.insert(name, output);
+                }
+            },
+            Message::Taskbar(TaskbarMessage::Windows(windows)) => self.taskbar = windows,
+            Message::Privacy(PrivacyMessage::Streams(streams)) => self.privacy = streams,
+            Message::Update(UpdateMessage::Available(release)) => self.update = release,
+            Message::Ambient(AmbientMessage::Color(color)) => self.ambient = Some(color),
+            Message::Mqtt(mqtt_message) => match mqtt_message {
+                MqttMessage::Output { name, output } => {
+                    self.mqtt.insert(name, output);
+                }
+            },
+            Message::Watch(watch_message) => match watch_message {
+                WatchMessage::Output { name, output } => {
+                    self.watched.insert(name, output);
+                }
+            },
+        }
+    }
+}
+
+/// Output of a custom or watch module, which shows nothing without text
+/// With markup the text is parsed by markup::parse, the class still picks
+/// the color of whatever isn't in a span
+fn render_custom_output(
+    output: Option<&CustomOutput>,
+    on_click: &Option<String>,
+    markup: bool,
+    style: Style,
+) -> Vec<Renderable> {
+    let Some(output) = output.filter(|output| !output.text.is_empty()) else {
+        return vec![];
+    };
+    let fg = match output.class.as_deref() {
+        Some("good") => style.good,
+        Some("warning") => style.warning,
+        Some("urgent" | "critical") => style.urgent,
+        Some("dim") => style.dim,
+        Some("accent") => style.accent,
+        _ => style.fg,
+    };
+    vec![Renderable::Region {
+        tooltip: output.tooltip.clone(),
+        on_click: on_click.clone().map(Action::Spawn),
+        on_scroll: None,
+        children: if markup {
+            markup::parse(&output.text, fg, style.bg)
+        } else {
+            vec![Renderable::Text {
+                text: output.text.clone().into(),
+                fg,
+                bg: style.bg,
+            }]
+        },
+    }]
+}
+
+/// Halves the opacity of what reacts to the pointer, showing that it won't
+/// while the bar is locked
+fn dim_interactive(renderables: &mut [Renderable], interactive: bool) {
+    let dim = |color: &mut u32| {
+        let alpha = *color >> 24;
+        *color = (*color & 0x00ffffff) | (alpha / 2) << 24;
+    };
+    for renderable in renderables {
+        match renderable {
+            Renderable::Text { fg, bg, .. } | Renderable::Box { fg, bg, .. } if interactive => {
+                dim(fg);
+                dim(bg);
+            }
+            Renderable::Region {
+                on_click,
+                on_scroll,
+                children,
+                ..
+            } => dim_interactive(children, interactive || on_click.is_some() || on_scroll.is_some()),
+            Renderable::Pill { children, .. }
+            | Renderable::Pulse(children)
+            | Renderable::Bold(children)
+            | Renderable::Ellipsize { children, .. }
+            | Renderable::Module { children, .. } => dim_interactive(children, interactive),
+            Renderable::Bindings { children, .. } => dim_interactive(children, true),
+            Renderable::Slide { bg, children, .. } => {
+                if interactive {
+                    dim(bg);
+                }
+                dim_interactive(children, interactive)
+            }
+            _ => {}
         }
     }
 }
 
-const UNITS: [(&str, u64); 5] = [
-    ("B", 1),
-    ("KiB", 1024),
-    ("MiB", 1024),
-    ("GiB", 1024),
-    ("TiB", 1024),
-];
-
-fn display_bytes(x: u64) -> String {
-    let mut scaled_size = x;
-    let mut current_unit_idx = 0;
-    while scaled_size
-        > (UNITS
-            .get(current_unit_idx + 1)
-            .map(|unit| unit.1)
-            .unwrap_or(u64::MAX))
-    {
-        current_unit_idx += 1;
-        scaled_size /= UNITS[current_unit_idx].1
-    }
-    let display_str = format!("{scaled_size} {}", UNITS[current_unit_idx].0);
-    format!("{display_str:>8}")
+/// Puts the workspaces in the configured order, history has their ids with
+/// the most recently focused first
+fn sort_workspaces(workspaces: &mut [Workspace], sort: WorkspaceSort, history: &[i64]) {
+    match sort {
+        WorkspaceSort::Numeric => workspaces.sort_by_key(|workspace| workspace.num),
+        WorkspaceSort::Alphabetical => workspaces.sort_by_cached_key(|workspace| {
+            let name = match &workspace.name {
+                Some(name) => name.to_lowercase(),
+                None => workspace.num.to_string(),
+            };
+            (name, workspace.num)
+        }),
+        WorkspaceSort::Output => {
+            workspaces.sort_by(|a, b| a.output.cmp(&b.output).then(a.num.cmp(&b.num)))
+        }
+        WorkspaceSort::Recent => workspaces.sort_by_key(|workspace| {
+            let focused = history.iter().position(|id| *id == workspace.id);
+            (focused.unwrap_or(usize::MAX), workspace.num)
+        }),
+    }
+}
+
+/// mqtt/<name> for modules with a topic in the config
+fn is_mqtt_module(config: &Config, name: &str) -> bool {
+    name.strip_prefix("mqtt/")
+        .is_some_and(|name| config.mqtt.modules.contains_key(name))
+}
+
+/// watch/<name> for modules with a file in the config
+fn is_watch_module(config: &Config, name: &str) -> bool {
+    name.strip_prefix("watch/")
+        .is_some_and(|name| config.watch.contains_key(name))
+}
+
+/// custom/<name> for modules with a command in the config
+fn is_custom_module(config: &Config, name: &str) -> bool {
+    name.strip_prefix("custom/")
+        .is_some_and(|name| config.custom.contains_key(name))
+}
+
+#[cfg(test)]
+mod tests {
+    use swayipc::Rect;
+
+    use super::*;
+
+    fn workspace(id: i64, num: i32, name: &str, output: &str) -> Workspace {
+        Workspace {
+            id,
+            num,
+            name: Some(name.to_string()),
+            layout: "splith".to_string(),
+            visible: false,
+            focused: false,
+            urgent: false,
+            representation: None,
+            orientation: "none".to_string(),
+            rect: Rect {
+                x: 0,
+                y: 0,
+                width: 1920,
+                height: 1080,
+            },
+            output: output.to_string(),
+            focus: Vec::new(),
+        }
+    }
+
+    fn workspaces() -> Vec<Workspace> {
+        vec![
+            workspace(1, 3, "3", "HDMI-A-1"),
+            workspace(2, -1, "mail", "eDP-1"),
+            workspace(3, 10, "10", "eDP-1"),
+            workspace(4, 1, "1", "HDMI-A-1"),
+            workspace(5, 2, "2: Web", "eDP-1"),
+        ]
+    }
+
+    fn ids(workspaces: &[Workspace]) -> Vec<i64> {
+        workspaces.iter().map(|workspace| workspace.id).collect()
+    }
+
+    #[test]
+    fn numeric_puts_unnumbered_workspaces_first() {
+        let mut workspaces = workspaces();
+        sort_workspaces(&mut workspaces, WorkspaceSort::Numeric, &[]);
+        assert_eq!(ids(&workspaces), [2, 4, 5, 1, 3]);
+    }
+
+    #[test]
+    fn alphabetical_ignores_case() {
+        let mut workspaces = workspaces();
+        workspaces.push(workspace(6, -1, "Music", "eDP-1"));
+        sort_workspaces(&mut workspaces, WorkspaceSort::Alphabetical, &[]);
+        assert_eq!(ids(&workspaces), [4, 3, 5, 1, 2, 6]);
+    }
+
+    #[test]
+    fn output_groups_by_output_then_number() {
+        let mut workspaces = workspaces();
+        sort_workspaces(&mut workspaces, WorkspaceSort::Output, &[]);
+        assert_eq!(ids(&workspaces), [4, 1, 2, 5, 3]);
+    }
+
+    #[test]
+    fn recent_puts_unfocused_workspaces_last_by_number() {
+        let mut workspaces = workspaces();
+        sort_workspaces(&mut workspaces, WorkspaceSort::Recent, &[3, 1]);
+        assert_eq!(ids(&workspaces), [3, 1, 2, 4, 5]);
+    }
+
+    #[test]
+    fn sorting_again_follows_the_new_history() {
+        let mut workspaces = workspaces();
+        sort_workspaces(&mut workspaces, WorkspaceSort::Recent, &[3, 1]);
+        sort_workspaces(&mut workspaces, WorkspaceSort::Recent, &[5, 3, 1]);
+        assert_eq!(ids(&workspaces), [5, 3, 1, 2, 4]);
+    }
 }
@@ -0,0 +1,385 @@
+use std::{
+    any::Any,
+    collections::hash_map::RandomState,
+    fmt::Debug,
+    hash::{BuildHasher, Hasher},
+    panic::{AssertUnwindSafe, catch_unwind},
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+    time::{Duration, Instant},
+};
+
+use tokio::{
+    runtime::Handle,
+    sync::{
+        mpsc::{Receiver, Sender, channel},
+        watch,
+    },
+    task::{JoinError, JoinHandle},
+    time::Interval,
+};
+
+use crate::{
+    crash,
+    error::ShellError,
+    renderer::Renderable,
+    state::{Message, State},
+    widget::Widget,
+};
+
+/// How long a subscription waits before running its generator again
+#[derive(Debug, Clone, Copy)]
+pub struct Backoff {
+    /// The wait after the first failure, doubling with every one after
+    pub initial: Duration,
+    pub max: Duration,
+}
+
+impl Backoff {
+    pub const DEFAULT: Self = Self {
+        initial: Duration::from_secs(1),
+        max: Duration::from_secs(300),
+    };
+
+    /// For what is slow to come back or costly to poll for
+    pub fn starting_at(initial: Duration) -> Self {
+        Self {
+            initial,
+            max: Self::DEFAULT.max.max(initial),
+        }
+    }
+
+    /// The wait after this many failures in a row, up to a quarter shorter
+    /// at random so subscriptions that fail together don't retry in lockstep
+    pub fn delay(&self, failures: u32) -> Duration {
+        let delay = self
+            .initial
+            .saturating_mul(2u32.saturating_pow(failures.saturating_sub(1)))
+            .min(self.max);
+        // Every RandomState is keyed differently, which is all the
+        // randomness this needs
+        let jitter = RandomState::new().build_hasher().finish() as f64 / u64::MAX as f64;
+        delay.mul_f64(1. - jitter / 4.)
+    }
+}
+
+/// A generator running this long before it returned had connected, so its
+/// failures start over from the initial wait
+const RESET_AFTER: Duration = Duration::from_secs(60);
+
+/// Runs the watchdog aborted since startup
+static WATCHDOG_RESTARTS: AtomicUsize = AtomicUsize::new(0);
+
+/// How often the watchdog had to restart a hung subscription, for the debug
+/// overlay
+pub fn watchdog_restarts() -> usize {
+    WATCHDOG_RESTARTS.load(Ordering::Relaxed)
+}
+
+/// Tries again after the delays of the backoff until the attempt works.
+/// Gives up with the last error once the next try would be past
+/// give_up_after, counted from the first one
+pub fn retry<T, E: Debug>(
+    what: &str,
+    backoff: &Backoff,
+    give_up_after: Option<Duration>,
+    mut attempt: impl FnMut() -> Result<T, E>,
+) -> Result<T, E> {
+    let started = Instant::now();
+    let mut failures = 0;
+    loop {
+        let e = match attempt() {
+            Ok(value) => return Ok(value),
+            Err(e) => e,
+        };
+        failures += 1;
+        let delay = backoff.delay(failures);
+        if give_up_after.is_some_and(|limit| started.elapsed() + delay > limit) {
+            return Err(e);
+        }
+        log::warn!("Could not {what}, trying again in {delay:?}: {e:?}");
+        thread::sleep(delay);
+    }
+}
+
+/// thread::sleep for generators on a blocking thread that poll, which wakes
+/// up early when the system resumes. The clock stands still during a
+/// suspend, so the sleep would otherwise go on for what was left of it
+pub fn sleep_unless_resumed(resumes: &mut watch::Receiver<()>, duration: Duration) {
+    let woke = Handle::current().block_on(tokio::time::timeout(duration, resumes.changed()));
+    // The state is gone along with the sender, the bar is on its way out
+    if let Ok(Err(_)) = woke {
+        thread::sleep(duration);
+    }
+}
+
+/// sleep_unless_resumed for generators on the runtime polling on an
+/// interval, which starts over from the resume
+pub async fn tick_unless_resumed(interval: &mut Interval, resumes: &mut watch::Receiver<()>) {
+    tokio::select! {
+        _ = interval.tick() => {}
+        Ok(()) = resumes.changed() => interval.reset(),
+    }
+}
+
+/// Whether a subscription is running, sent when that changes
+#[derive(Debug, Clone)]
+pub enum ModuleStatus {
+    /// Messages come through again after a failure
+    Connected,
+    Disconnected { error: String, retry_in: Duration },
+}
+
+/// Sends the generator's messages on, with a Connected status before the
+/// first one after a failure
+fn forward(rt: &Handle, name: String, sender: Sender<Message>) -> Sender<Message> {
+    let (generator_sender, mut generator_receiver) = channel(1);
+    rt.spawn(async move {
+        let mut disconnected = false;
+        while let Some(message) = generator_receiver.recv().await {
+            match &message {
+                Message::ModuleStatus {
+                    status: ModuleStatus::Disconnected { .. },
+                    ..
+                } => disconnected = true,
+                Message::Heartbeat => continue,
+                _ if disconnected => {
+                    disconnected = false;
+                    let connected = Message::ModuleStatus {
+                        name: name.clone(),
+                        status: ModuleStatus::Connected,
+                    };
+                    if sender.send(connected).await.is_err() {
+                        return;
+                    }
+                }
+                _ => {}
+            }
+            if sender.send(message).await.is_err() {
+                return;
+            }
+        }
+    });
+    generator_sender
+}
+
+/// Counts the failure, giving the status to send and how long to wait
+fn failed(
+    name: &str,
+    backoff: &Backoff,
+    failures: &mut u32,
+    ran_for: Duration,
+    result: Result<(), ShellError>,
+) -> (Message, Duration) {
+    if ran_for >= RESET_AFTER {
+        *failures = 0;
+    }
+    *failures += 1;
+    let retry_in = backoff.delay(*failures);
+    let error = match result {
+        Ok(()) => "Returned without an error".to_string(),
+        Err(e) => e.to_string(),
+    };
+    log::error!("{name} subscription returned, this should never happen, retrying in {retry_in:?}: {error}");
+    let status = Message::ModuleStatus {
+        name: name.to_string(),
+        status: ModuleStatus::Disconnected { error, retry_in },
+    };
+    (status, retry_in)
+}
+
+/// Writes the crash report for the panic that stopped the generator
+fn panicked(name: &str, payload: Box<dyn Any + Send>) -> ShellError {
+    crash::report(name, None);
+    ShellError::Panicked(crash::panic_message(&*payload))
+}
+
+/// Runs the generator on a blocking thread again whenever it returns or
+/// panics, waiting longer after every failure in a row. Its messages go to
+/// sender, along with a ModuleStatus by this name when it stops or starts
+/// working
+pub fn supervise_blocking<E: Into<ShellError>>(
+    rt: &Handle,
+    name: impl Into<String>,
+    backoff: Backoff,
+    sender: Sender<Message>,
+    mut generator: impl FnMut(Sender<Message>) -> Result<(), E> + Send + 'static,
+) {
+    let name = name.into();
+    let sender = forward(rt, name.clone(), sender);
+    rt.spawn_blocking(move || {
+        let mut failures = 0;
+        loop {
+            let started = Instant::now();
+            let result = match catch_unwind(AssertUnwindSafe(|| generator(sender.clone()))) {
+                Ok(result) => result.map_err(Into::into),
+                Err(payload) => Err(panicked(&name, payload)),
+            };
+            let (status, retry_in) = failed(&name, &backoff, &mut failures, started.elapsed(), result);
+            // Nobody is left to run for
+            if sender.blocking_send(status).is_err() {
+                return;
+            }
+            thread::sleep(retry_in);
+        }
+    });
+}
+
+/// What the task of a generator ended with, None when the runtime is
+/// shutting down
+fn joined<E: Into<ShellError>>(
+    name: &str,
+    result: Result<Result<(), E>, JoinError>,
+) -> Option<Result<(), ShellError>> {
+    match result {
+        Ok(result) => Some(result.map_err(Into::into)),
+        Err(e) if e.is_panic() => Some(Err(panicked(name, e.into_panic()))),
+        Err(_) => None,
+    }
+}
+
+/// Forwards the messages of a run until it returns, or aborts it once it
+/// goes the deadline without sending any. None when nobody is left to
+/// forward to
+async fn watch_run<E: Into<ShellError>>(
+    name: &str,
+    deadline: Duration,
+    mut run: JoinHandle<Result<(), E>>,
+    mut receiver: Receiver<Message>,
+    sender: &Sender<Message>,
+) -> Option<Result<(), ShellError>> {
+    loop {
+        // Every message sleeps from the start again
+        tokio::select! {
+            // What the run sent before it returned still goes through
+            biased;
+            message = receiver.recv() => match message {
+                Some(Message::Heartbeat) => {}
+                Some(message) => {
+                    if sender.send(message).await.is_err() {
+                        run.abort();
+                        return None;
+                    }
+                }
+                None => return joined(name, run.await),
+            },
+            result = &mut run => return joined(name, result),
+            _ = tokio::time::sleep(deadline) => {
+                run.abort();
+                WATCHDOG_RESTARTS.fetch_add(1, Ordering::Relaxed);
+                return Some(Err(ShellError::Unresponsive(deadline)));
+            }
+        }
+    }
+}
+
+fn supervise_runs<
+    E: Into<ShellError> + Send + 'static,
+    F: Future<Output = Result<(), E>> + Send + 'static,
+>(
+    rt: &Handle,
+    name: String,
+    backoff: Backoff,
+    deadline: Option<Duration>,
+    sender: Sender<Message>,
+    mut generator: impl FnMut(Sender<Message>) -> F + Send + 'static,
+) {
+    let sender = forward(rt, name.clone(), sender);
+    rt.spawn(async move {
+        let mut failures = 0;
+        loop {
+            let started = Instant::now();
+            // A task of its own, so a panic ends up in the JoinError and the
+            // watchdog can abort it
+            let result = match deadline {
+                Some(deadline) => {
+                    let (run_sender, receiver) = channel(1);
+                    let run = tokio::spawn(generator(run_sender));
+                    watch_run(&name, deadline, run, receiver, &sender).await
+                }
+                None => joined(&name, tokio::spawn(generator(sender.clone())).await),
+            };
+            let Some(result) = result else {
+                return;
+            };
+            let (status, retry_in) = failed(&name, &backoff, &mut failures, started.elapsed(), result);
+            if sender.send(status).await.is_err() {
+                return;
+            }
+            tokio::time::sleep(retry_in).await;
+        }
+    });
+}
+
+/// supervise_blocking for generators running on the runtime
+pub fn supervise<
+    E: Into<ShellError> + Send + 'static,
+    F: Future<Output = Result<(), E>> + Send + 'static,
+>(
+    rt: &Handle,
+    name: impl Into<String>,
+    backoff: Backoff,
+    sender: Sender<Message>,
+    generator: impl FnMut(Sender<Message>) -> F + Send + 'static,
+) {
+    supervise_runs(rt, name.into(), backoff, None, sender, generator);
+}
+
+/// supervise that also runs the generator again when it hangs, going the
+/// deadline without a message or a Message::Heartbeat. Only for generators
+/// on the runtime, as a thread stuck in a syscall can't be stopped
+pub fn supervise_watched<
+    E: Into<ShellError> + Send + 'static,
+    F: Future<Output = Result<(), E>> + Send + 'static,
+>(
+    rt: &Handle,
+    name: impl Into<String>,
+    backoff: Backoff,
+    deadline: Duration,
+    sender: Sender<Message>,
+    generator: impl FnMut(Sender<Message>) -> F + Send + 'static,
+) {
+    supervise_runs(rt, name.into(), backoff, Some(deadline), sender, generator);
+}
+
+/// Names the subscriptions that are down, nothing while all work
+pub const STATUS: Widget = Widget {
+    name: "status",
+    render: render_status,
+};
+
+fn render_status(state: &State) -> Vec<Renderable> {
+    let style = state.config.theme.style("status");
+    let mut module = Vec::new();
+    if state.disconnected.is_empty() {
+        return module;
+    }
+    let names = state
+        .disconnected
+        .keys()
+        .map(String::as_str)
+        .collect::<Vec<_>>();
+    let tooltip = state
+        .disconnected
+        .iter()
+        .filter_map(|(name, status)| match status {
+            ModuleStatus::Disconnected { error, retry_in } => Some(format!(
+                "{name}: {error}, retrying in {}s",
+                retry_in.as_secs()
+            )),
+            ModuleStatus::Connected => None,
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    module.push(Renderable::Region {
+        tooltip: Some(tooltip),
+        on_click: None,
+        on_scroll: None,
+        children: vec![Renderable::Text {
+            text: format!("\u{f0026} {}: disconnected", names.join(", ")).into(),
+            fg: style.warning,
+            bg: style.bg,
+        }],
+    });
+    module
+}
@@ -0,0 +1,154 @@
+use std::ops::Deref;
+
+/// A surface together with the objects it holds raw pointers into, like a
+/// wgpu surface made from a wl_surface and the connection's display. The
+/// surface is always destroyed before them, whether the pair is dropped or
+/// replaced
+pub struct BoundSurface<S, O> {
+    // Fields are dropped in declaration order, so this has to stay first
+    surface: S,
+    owner: O,
+}
+
+impl<S, O> BoundSurface<S, O> {
+    pub fn new(owner: O, make_surface: impl FnOnce(&O) -> S) -> Self {
+        Self {
+            surface: make_surface(&owner),
+            owner,
+        }
+    }
+
+    pub fn owner(&self) -> &O {
+        &self.owner
+    }
+
+    /// Makes the new surface before tearing the old pair down, surface first
+    pub fn replace(&mut self, owner: O, make_surface: impl FnOnce(&O) -> S) {
+        *self = Self::new(owner, make_surface);
+    }
+}
+
+impl<S, O> Deref for BoundSurface<S, O> {
+    type Target = S;
+
+    fn deref(&self) -> &Self::Target {
+        &self.surface
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use super::*;
+
+    type Log = Rc<RefCell<Vec<String>>>;
+
+    /// Logs its creation and destruction
+    struct Tracked {
+        name: String,
+        log: Log,
+    }
+
+    impl Tracked {
+        fn new(name: impl Into<String>, log: &Log) -> Self {
+            let name = name.into();
+            log.borrow_mut().push(format!("create {name}"));
+            Self {
+                name,
+                log: log.clone(),
+            }
+        }
+    }
+
+    impl Drop for Tracked {
+        fn drop(&mut self) {
+            self.log.borrow_mut().push(format!("drop {}", self.name));
+        }
+    }
+
+    fn bound(index: usize, log: &Log) -> BoundSurface<Tracked, Tracked> {
+        BoundSurface::new(Tracked::new(format!("owner {index}"), log), |owner| {
+            Tracked::new(format!("surface of {}", owner.name), log)
+        })
+    }
+
+    #[test]
+    fn surface_dropped_before_owner() {
+        let log = Log::default();
+        drop(bound(0, &log));
+        assert_eq!(
+            *log.borrow(),
+            [
+                "create owner 0",
+                "create surface of owner 0",
+                "drop surface of owner 0",
+                "drop owner 0",
+            ]
+        );
+    }
+
+    #[test]
+    fn replace_drops_old_surface_before_old_owner() {
+        let log = Log::default();
+        let mut surface = bound(0, &log);
+        surface.replace(Tracked::new("owner 1", &log), |owner| {
+            Tracked::new(format!("surface of {}", owner.name), &log)
+        });
+        assert_eq!(surface.name, "surface of owner 1");
+        assert_eq!(surface.owner().name, "owner 1");
+        assert_eq!(
+            log.borrow()[2..],
+            [
+                "create owner 1",
+                "create surface of owner 1",
+                "drop surface of owner 0",
+                "drop owner 0",
+            ]
+        );
+    }
+
+    #[test]
+    fn repeated_create_destroy_cycles() {
+        let log = Log::default();
+        for i in 0..1000 {
+            drop(bound(i, &log));
+        }
+        let log = log.borrow();
+        assert_eq!(log.len(), 4000);
+        for (i, cycle) in log.chunks(4).enumerate() {
+            assert_eq!(
+                cycle,
+                [
+                    format!("create owner {i}"),
+                    format!("create surface of owner {i}"),
+                    format!("drop surface of owner {i}"),
+                    format!("drop owner {i}"),
+                ]
+            );
+        }
+    }
+
+    #[test]
+    fn repeated_replace_cycles() {
+        let log = Log::default();
+        let mut surface = bound(0, &log);
+        for i in 1..1000 {
+            surface.replace(Tracked::new(format!("owner {i}"), &log), |owner| {
+                Tracked::new(format!("surface of {}", owner.name), &log)
+            });
+        }
+        drop(surface);
+        let log = log.borrow();
+        // Every owner outlives its surface and nothing is left behind
+        for i in 0..1000 {
+            let position = |entry: String| log.iter().position(|logged| *logged == entry);
+            let surface_dropped = position(format!("drop surface of owner {i}")).unwrap();
+            let owner_dropped = position(format!("drop owner {i}")).unwrap();
+            assert!(surface_dropped < owner_dropped, "{i}");
+        }
+        let creates = log.iter().filter(|entry| entry.starts_with("create")).count();
+        let drops = log.iter().filter(|entry| entry.starts_with("drop")).count();
+        assert_eq!(creates, drops);
+    }
+}
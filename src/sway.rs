@@ -1,12 +1,13 @@
-use std::fmt::Display;
+use std::{collections::BTreeMap, fmt::Display};
 
-use swayipc::{Event, EventType, Node, Rect, WorkspaceChange};
+use swayipc::{Event, EventType, Node, NodeType, Rect, WorkspaceChange};
 use tokio::{
     runtime::Handle,
     sync::mpsc::{channel, error::SendError, Sender},
 };
 
 use crate::state::Message;
+use crate::supervisor::{Backoff, supervise_blocking};
 
 #[derive(Debug)]
 pub enum WorkspaceFromNodeError {
@@ -34,9 +35,84 @@ pub enum SwayMessage {
         id: i64,
         urgent: bool,
     },
-    WindowFocusedChange {window_name: Option<String>}
+    WindowFocusedChange {window_name: Option<String>},
+    /// A binding ran `nop sway-shell <command>`, with the command trimmed
+    Command(String),
+    /// Every window in the tree, sent before the command opening the window
+    /// switcher so it lists them fresh
+    WindowList(Vec<Window>),
+    /// The app_ids of the windows on each workspace, by workspace name. Sent
+    /// again whenever windows open, close or move
+    WorkspaceApps(BTreeMap<String, Vec<String>>),
+    /// The binding mode, or submap on Hyprland, None for the default one
+    Mode(Option<String>),
 }
 
+/// Talks to sway over swayipc
+#[derive(Debug)]
+pub struct Sway;
+
+/// A window as listed by the window switcher
+#[derive(Clone, Debug)]
+pub struct Window {
+    pub id: i64,
+    pub name: Option<String>,
+    /// Wayland app_id, or the class for xwayland windows
+    pub app_id: Option<String>,
+    pub workspace: String,
+}
+
+impl Window {
+    /// Collects the windows below the node, workspace being the name of the
+    /// workspace the node is on
+    fn collect(node: Node, workspace: Option<&str>, windows: &mut Vec<Window>) {
+        let workspace = match node.node_type {
+            NodeType::Workspace => match node.name.as_deref() {
+                // sway keeps the scratchpad on a hidden workspace
+                Some("__i3_scratch") => Some("scratchpad".to_string()),
+                name => name.map(str::to_string),
+            },
+            _ => workspace.map(str::to_string),
+        };
+        let is_window = matches!(node.node_type, NodeType::Con | NodeType::FloatingCon)
+            && node.nodes.is_empty()
+            && node.floating_nodes.is_empty();
+        if is_window {
+            windows.push(Window {
+                id: node.id,
+                name: node.name,
+                app_id: node.app_id.or_else(|| {
+                    node.window_properties.and_then(|properties| properties.class)
+                }),
+                workspace: workspace.unwrap_or_default(),
+            });
+            return;
+        }
+        for child in node.nodes.into_iter().chain(node.floating_nodes) {
+            Self::collect(child, workspace.as_deref(), windows);
+        }
+    }
+}
+
+/// The app_ids of the windows in the tree, by the name of their workspace.
+/// Windows without one are in there as empty ones
+fn workspace_apps(tree: Node) -> BTreeMap<String, Vec<String>> {
+    let mut windows = Vec::new();
+    Window::collect(tree, None, &mut windows);
+    let mut apps: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for window in windows {
+        // Windows without one still count
+        apps.entry(window.workspace)
+            .or_default()
+            .push(window.app_id.unwrap_or_default());
+    }
+    apps
+}
+
+/// Prefix of the sway binding commands meant for the bar, sway ignores them
+/// as nop but still reports them to binding event subscribers
+const COMMAND_PREFIX: &str = "nop sway-shell ";
+
 #[derive(Clone, Debug)]
 pub struct Workspace {
     pub id: i64,
@@ -101,7 +177,7 @@ impl From<swayipc::Workspace> for Workspace {
 }
 
 #[derive(Debug)]
-enum SwayError {
+pub enum SwayError {
     ConnectionError(swayipc::Error),
     ChannelError(SendError<Message>),
 }
@@ -127,14 +203,42 @@ impl From<SendError<Message>> for SwayError {
     }
 }
 
+/// Quotes an argument of a sway command, like a workspace name
+pub fn quote(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Runs a sway command on a connection of its own, without waiting for it
+pub fn run_command(command: String) {
+    std::thread::spawn(move || {
+        let result = swayipc::Connection::new().and_then(|mut conn| conn.run_command(&command));
+        match result {
+            Ok(outcomes) => {
+                for outcome in outcomes {
+                    if let Err(e) = outcome {
+                        log::error!("Sway could not run {command:?}: {e}");
+                    }
+                }
+            }
+            Err(e) => log::error!("Could not send {command:?} to sway: {e}"),
+        }
+    });
+}
+
 fn sway_generator(output: Sender<Message>) -> Result<(), SwayError> {
     let mut conn = swayipc::Connection::new()?;
     for workspace in conn.get_workspaces()?.into_iter().map(|v| v.into()) {
         output.blocking_send(Message::Sway(SwayMessage::WorkspaceAdd(workspace)))?;
     }
+    // Window events only arrive on changes, so the already focused window is
+    // picked up from the tree
+    output.blocking_send(Message::Sway(SwayMessage::WindowFocusedChange {
+        window_name: conn.get_tree()?.find_focused(|v| v.focused && matches!(v.node_type, NodeType::Con | NodeType::FloatingCon)).and_then(|v| v.name),
+    }))?;
+    output.blocking_send(Message::Sway(SwayMessage::WorkspaceApps(workspace_apps(conn.get_tree()?))))?;
 
 
-    for event in conn.subscribe([EventType::Workspace, EventType::Window])? {
+    for event in conn.subscribe([EventType::Workspace, EventType::Window, EventType::Binding, EventType::Mode])? {
         match event {
             Err(e) => {
                 log::error!("{e:?}");
@@ -146,30 +250,78 @@ fn sway_generator(output: Sender<Message>) -> Result<(), SwayError> {
                             swayipc::WindowChange::Focus => {
                                 output.blocking_send(Message::Sway(SwayMessage::WindowFocusedChange { window_name: window_event.container.name  }))?
                             },
+                            swayipc::WindowChange::Title => {
+                                // Title changes are sent for every window, only the focused one is shown
+                                if window_event.container.focused {
+                                    output.blocking_send(Message::Sway(SwayMessage::WindowFocusedChange { window_name: window_event.container.name }))?
+                                }
+                            },
+                            swayipc::WindowChange::Close => {
+                                // If another window takes the focus a Focus event follows, otherwise
+                                // the workspace is now empty and the title has to go
+                                if window_event.container.focused {
+                                    output.blocking_send(Message::Sway(SwayMessage::WindowFocusedChange { window_name: None }))?
+                                }
+                                // The subscribed connection can't be queried anymore
+                                let tree = swayipc::Connection::new()?.get_tree()?;
+                                output.blocking_send(Message::Sway(SwayMessage::WorkspaceApps(workspace_apps(tree))))?
+                            },
+                            swayipc::WindowChange::New | swayipc::WindowChange::Move => {
+                                let tree = swayipc::Connection::new()?.get_tree()?;
+                                output.blocking_send(Message::Sway(SwayMessage::WorkspaceApps(workspace_apps(tree))))?
+                            },
                             _ => {log::info!("Unknown Window Change");},
                         }
                     },
 
+                    Event::Mode(mode_event) => {
+                        let mode = (mode_event.change != "default").then_some(mode_event.change);
+                        output.blocking_send(Message::Sway(SwayMessage::Mode(mode)))?;
+                    },
+
+                    Event::Binding(binding_event) => {
+                        if let Some(command) = binding_event.binding.command.strip_prefix(COMMAND_PREFIX) {
+                            if command.trim() == "windows" {
+                                // The subscribed connection can't be queried anymore. Without
+                                // the tree the switcher lists the windows it had before
+                                match swayipc::Connection::new().and_then(|mut conn| conn.get_tree()) {
+                                    Ok(tree) => {
+                                        let mut windows = Vec::new();
+                                        Window::collect(tree, None, &mut windows);
+                                        output.blocking_send(Message::Sway(SwayMessage::WindowList(windows)))?;
+                                    }
+                                    Err(e) => log::error!("Could not get the windows from sway: {e}"),
+                                }
+                            }
+                            output.blocking_send(Message::Sway(SwayMessage::Command(command.trim().to_string())))?;
+                        }
+                    },
+
                     Event::Workspace(workspace_event) => match workspace_event.change {
                         WorkspaceChange::Init => {
-                            output.blocking_send(Message::Sway(SwayMessage::WorkspaceAdd(
-                                workspace_event
-                                    .current
-                                    .expect("Workspace to not be null when it is created")
-                                    .try_into()
-                                    .expect("This to be a workspace"),
-                            )))?;
+                            // An odd event is skipped, it isn't worth reconnecting over
+                            match workspace_event.current.map(Workspace::try_from) {
+                                Some(Ok(workspace)) => {
+                                    output.blocking_send(Message::Sway(SwayMessage::WorkspaceAdd(workspace)))?;
+                                }
+                                Some(Err(e)) => log::error!("Created workspace is not a workspace: {e:?}"),
+                                None => log::error!("Workspace created without the workspace"),
+                            }
                         }
                         WorkspaceChange::Empty => {
-                            output.blocking_send(Message::Sway(SwayMessage::WorkspaceDel(
-                                workspace_event
-                                    .current
-                                    .expect("Workspace not null when emptying")
-                                    .id,
-                            )))?;
+                            match workspace_event.current {
+                                Some(workspace) => {
+                                    output.blocking_send(Message::Sway(SwayMessage::WorkspaceDel(workspace.id)))?;
+                                }
+                                None => log::error!("Workspace emptied without the workspace"),
+                            }
                         }
                         WorkspaceChange::Focus => {
                             if let Some(workspace) = workspace_event.current {
+                                // Focusing an empty workspace doesn't emit a window focus event
+                                if workspace.focused && workspace.nodes.is_empty() && workspace.floating_nodes.is_empty() {
+                                    output.blocking_send(Message::Sway(SwayMessage::WindowFocusedChange { window_name: None }))?;
+                                }
                                 output.blocking_send(Message::Sway(SwayMessage::WorkspaceChangeFocus {
                                     id: workspace.id,
                                     focus: workspace.focus,
@@ -197,15 +349,18 @@ fn sway_generator(output: Sender<Message>) -> Result<(), SwayError> {
                             log::info!("Workspace moved, do nothing");
                         }
                         WorkspaceChange::Rename => {
-                            output.blocking_send(
-                                workspace_event
-                                    .current
-                                    .map(|v| Message::Sway(SwayMessage::WorkspaceRename {
-                                        id: v.id,
-                                        name: v.name,
-                                    }))
-                                    .expect("Workspace not null when emptying"),
-                            )?;
+                            match workspace_event.current {
+                                Some(workspace) => {
+                                    output.blocking_send(Message::Sway(SwayMessage::WorkspaceRename {
+                                        id: workspace.id,
+                                        name: workspace.name,
+                                    }))?;
+                                }
+                                None => log::error!("Workspace renamed without the workspace"),
+                            }
+                            // The windows are listed by workspace name
+                            let tree = swayipc::Connection::new()?.get_tree()?;
+                            output.blocking_send(Message::Sway(SwayMessage::WorkspaceApps(workspace_apps(tree))))?;
                         }
                         WorkspaceChange::Urgent => {
                             if let Some(workspace) = workspace_event.current {
@@ -239,19 +394,6 @@ fn sway_generator(output: Sender<Message>) -> Result<(), SwayError> {
 
 pub fn sway_subscription(rt: Handle) -> tokio_stream::wrappers::ReceiverStream<Message> {
     let (sender, receiver) = channel(1);
-    rt.spawn_blocking(move || {
-        loop {
-            match 
-            sway_generator(sender.clone()) {
-                Ok(()) => {},
-                Err(e) => {
-                    log::error!(
-                        "Sway subscription event loop returned, this should never happen trying to reconnect {:?}", e
-                    );
-                }
-                ,
-            }
-        }
-    });
+    supervise_blocking(&rt, "sway", Backoff::DEFAULT, sender, sway_generator);
     tokio_stream::wrappers::ReceiverStream::new(receiver)
 }
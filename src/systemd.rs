@@ -0,0 +1,249 @@
+use std::time::Duration;
+
+use tokio::{
+    runtime::Handle,
+    sync::{
+        mpsc::{Sender, channel, error::SendError},
+        watch,
+    },
+};
+use tokio_stream::wrappers::ReceiverStream;
+use zbus::{Connection, zvariant::OwnedObjectPath};
+
+use crate::{
+    files::read_string_from_file_path,
+    renderer::Renderable,
+    state::{Message, State},
+    supervisor::{Backoff, supervise, tick_unless_resumed},
+    widget::Widget,
+};
+
+#[derive(Debug)]
+pub enum SystemdError {
+    DbusError(zbus::Error),
+    SendError(SendError<Message>),
+}
+
+impl std::fmt::Display for SystemdError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SystemdError::DbusError(e) => write!(f, "D-Bus Error: {e}"),
+            SystemdError::SendError(e) => write!(f, "Channel Error: {e}"),
+        }
+    }
+}
+
+impl From<zbus::Error> for SystemdError {
+    fn from(value: zbus::Error) -> Self {
+        Self::DbusError(value)
+    }
+}
+
+impl From<SendError<Message>> for SystemdError {
+    fn from(value: SendError<Message>) -> Self {
+        Self::SendError(value)
+    }
+}
+
+#[derive(Debug)]
+pub enum SystemdMessage {
+    TimersUpdate(Vec<SystemdTimer>),
+}
+
+#[derive(Debug, Clone)]
+pub struct SystemdTimer {
+    /// Name of the timer unit, e.g. borg-backup.timer
+    pub name: String,
+    /// The unit that gets activated when the timer elapses
+    pub unit: String,
+    pub next_elapse: Option<chrono::DateTime<chrono::Local>>,
+    pub last_trigger: Option<chrono::DateTime<chrono::Local>>,
+}
+
+/// One entry of the ListUnitsByPatterns result, the fields are
+/// name, description, load state, active state, sub state, followed unit,
+/// object path, job id, job type, job object path
+type UnitStatus = (
+    String,
+    String,
+    String,
+    String,
+    String,
+    String,
+    OwnedObjectPath,
+    u32,
+    String,
+    OwnedObjectPath,
+);
+
+#[zbus::proxy(
+    interface = "org.freedesktop.systemd1.Manager",
+    default_service = "org.freedesktop.systemd1",
+    default_path = "/org/freedesktop/systemd1"
+)]
+trait Manager {
+    fn list_units_by_patterns(
+        &self,
+        states: &[&str],
+        patterns: &[&str],
+    ) -> zbus::Result<Vec<UnitStatus>>;
+}
+
+#[zbus::proxy(
+    interface = "org.freedesktop.systemd1.Timer",
+    default_service = "org.freedesktop.systemd1"
+)]
+trait Timer {
+    #[zbus(property)]
+    fn unit(&self) -> zbus::Result<String>;
+
+    #[zbus(property, name = "NextElapseUSecRealtime")]
+    fn next_elapse_usec_realtime(&self) -> zbus::Result<u64>;
+
+    #[zbus(property, name = "NextElapseUSecMonotonic")]
+    fn next_elapse_usec_monotonic(&self) -> zbus::Result<u64>;
+
+    #[zbus(property, name = "LastTriggerUSec")]
+    fn last_trigger_usec(&self) -> zbus::Result<u64>;
+}
+
+/// systemd uses both 0 and u64::MAX to signal that a timestamp is not set
+fn usec_to_local(usec: u64) -> Option<chrono::DateTime<chrono::Local>> {
+    if usec == 0 || usec == u64::MAX {
+        return None;
+    }
+    chrono::DateTime::from_timestamp_micros(usec as i64).map(|v| v.with_timezone(&chrono::Local))
+}
+
+/// Monotonic timestamps are relative to boot, /proc/uptime is close enough to
+/// CLOCK_MONOTONIC for displaying minutes on a bar
+fn monotonic_usec_to_local(usec: u64) -> Option<chrono::DateTime<chrono::Local>> {
+    if usec == 0 || usec == u64::MAX {
+        return None;
+    }
+    let uptime: f64 = read_string_from_file_path("/proc/uptime")
+        .ok()?
+        .split_whitespace()
+        .next()?
+        .parse()
+        .ok()?;
+    let boot = chrono::Local::now() - chrono::Duration::milliseconds((uptime * 1000.) as i64);
+    Some(boot + chrono::Duration::microseconds(usec as i64))
+}
+
+async fn list_timers(conn: &Connection) -> Result<Vec<SystemdTimer>, SystemdError> {
+    let manager = ManagerProxy::new(conn).await?;
+    let mut timers = Vec::new();
+    for (name, _, _, _, _, _, path, _, _, _) in manager
+        .list_units_by_patterns(&["active"], &["*.timer"])
+        .await?
+    {
+        let timer = TimerProxy::builder(conn).path(path)?.build().await?;
+        let next_elapse = match usec_to_local(timer.next_elapse_usec_realtime().await?) {
+            Some(x) => Some(x),
+            None => monotonic_usec_to_local(timer.next_elapse_usec_monotonic().await?),
+        };
+        timers.push(SystemdTimer {
+            name,
+            unit: timer.unit().await?,
+            next_elapse,
+            last_trigger: usec_to_local(timer.last_trigger_usec().await?),
+        });
+    }
+    Ok(timers)
+}
+
+async fn systemd_generator(
+    sender: Sender<Message>,
+    mut resumes: watch::Receiver<()>,
+) -> Result<(), SystemdError> {
+    let user_conn = Connection::session().await?;
+    // System timers are where backups usually live, but not having access
+    // to the system bus shouldn't stop the user timers from showing up
+    let system_conn = match Connection::system().await {
+        Ok(conn) => Some(conn),
+        Err(e) => {
+            log::error!("Could not connect to the system bus, only showing user timers: {e}");
+            None
+        }
+    };
+    let mut interval = tokio::time::interval(Duration::from_secs(60));
+    resumes.mark_unchanged();
+    loop {
+        tick_unless_resumed(&mut interval, &mut resumes).await;
+        let mut timers = list_timers(&user_conn).await?;
+        if let Some(system_conn) = &system_conn {
+            timers.extend(list_timers(system_conn).await?);
+        }
+        // Timers which aren't scheduled go to the back
+        timers.sort_by_key(|v| (v.next_elapse.is_none(), v.next_elapse));
+        sender
+            .send(Message::Systemd(SystemdMessage::TimersUpdate(timers)))
+            .await?;
+    }
+}
+
+pub fn systemd_subscription(rt: Handle, resumes: watch::Receiver<()>) -> ReceiverStream<Message> {
+    let (sender, receiver) = channel(1);
+    supervise(&rt, "systemd", Backoff::DEFAULT, sender, move |sender| {
+        systemd_generator(sender, resumes.clone())
+    });
+    ReceiverStream::new(receiver)
+}
+
+/// The next systemd timer to elapse
+pub const WIDGET: Widget = Widget {
+    name: "systemd",
+    render,
+};
+
+fn render(state: &State) -> Vec<Renderable> {
+    let style = state.config.theme.style("systemd");
+    let mut module = Vec::new();
+    if let Some(next_timer) = state.systemd_timers.first() {
+        if let Some(next_elapse) = next_timer.next_elapse {
+            module.push(Renderable::Region {
+                tooltip: Some(
+                    state
+                        .systemd_timers
+                        .iter()
+                        .map(|timer| {
+                            format!(
+                                "{} ({}): next {}, last {}",
+                                timer.name,
+                                timer.unit,
+                                display_timer_time(timer.next_elapse),
+                                display_timer_time(timer.last_trigger),
+                            )
+                        })
+                        .collect::<Vec<_>>()
+                        .join("\n"),
+                ),
+                on_click: None,
+                on_scroll: None,
+                children: vec![Renderable::Text {
+                    text: format!(
+                        "{} {}",
+                        next_timer.name.trim_end_matches(".timer"),
+                        if next_elapse.date_naive() == state.clock.date_naive() {
+                            next_elapse.format("%H:%M")
+                        } else {
+                            next_elapse.format("%a %H:%M")
+                        }
+                    )
+                    .into(),
+                    fg: style.fg,
+                    bg: style.bg,
+                }],
+            });
+        }
+    }
+    module
+}
+
+fn display_timer_time(time: Option<chrono::DateTime<chrono::Local>>) -> String {
+    match time {
+        Some(time) => time.format("%a %F %H:%M").to_string(),
+        None => "n/a".to_string(),
+    }
+}
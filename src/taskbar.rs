@@ -0,0 +1,179 @@
+use swayipc::{EventType, Node, NodeType};
+use tokio::{
+    runtime::Handle,
+    sync::mpsc::{Sender, channel, error::SendError},
+};
+use tokio_stream::wrappers::ReceiverStream;
+
+use crate::{
+    action::{Action, MouseBindings},
+    compositor::app_icon,
+    renderer::Renderable,
+    state::{Message, State},
+    supervisor::{Backoff, supervise_blocking},
+    widget::Widget,
+};
+
+#[derive(Debug)]
+pub enum TaskbarError {
+    ConnectionError(swayipc::Error),
+    SendError(SendError<Message>),
+}
+
+impl std::fmt::Display for TaskbarError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TaskbarError::ConnectionError(e) => write!(f, "Connection Error: {e}"),
+            TaskbarError::SendError(e) => write!(f, "Channel Error: {e}"),
+        }
+    }
+}
+
+impl From<swayipc::Error> for TaskbarError {
+    fn from(value: swayipc::Error) -> Self {
+        Self::ConnectionError(value)
+    }
+}
+
+impl From<SendError<Message>> for TaskbarError {
+    fn from(value: SendError<Message>) -> Self {
+        Self::SendError(value)
+    }
+}
+
+/// A window on the focused workspace, told apart from the others by its id
+#[derive(Debug, Clone, PartialEq)]
+pub struct TaskbarWindow {
+    /// The con_id sway commands pick the window by
+    pub id: i64,
+    pub title: String,
+    /// Wayland app_id, or the class for xwayland windows
+    pub app_id: Option<String>,
+    pub focused: bool,
+    pub urgent: bool,
+}
+
+#[derive(Debug)]
+pub enum TaskbarMessage {
+    /// The windows on the focused workspace, tiled ones first in tree order
+    Windows(Vec<TaskbarWindow>),
+}
+
+fn collect(node: Node, windows: &mut Vec<TaskbarWindow>) {
+    let is_window = matches!(node.node_type, NodeType::Con | NodeType::FloatingCon)
+        && node.nodes.is_empty()
+        && node.floating_nodes.is_empty();
+    if is_window {
+        windows.push(TaskbarWindow {
+            id: node.id,
+            title: node.name.unwrap_or_default(),
+            app_id: node.app_id.or_else(|| {
+                node.window_properties
+                    .and_then(|properties| properties.class)
+            }),
+            focused: node.focused,
+            urgent: node.urgent,
+        });
+        return;
+    }
+    for child in node.nodes.into_iter().chain(node.floating_nodes) {
+        collect(child, windows);
+    }
+}
+
+/// Follows the focus down from the root to the focused workspace
+fn focused_windows(tree: Node) -> Vec<TaskbarWindow> {
+    let mut windows = Vec::new();
+    if let Some(workspace) = tree.find_focused(|node| node.node_type == NodeType::Workspace) {
+        collect(workspace, &mut windows);
+    }
+    windows
+}
+
+fn taskbar_generator(sender: Sender<Message>) -> Result<(), TaskbarError> {
+    // The subscribed connection can't be queried anymore
+    let mut query = swayipc::Connection::new()?;
+    let events =
+        swayipc::Connection::new()?.subscribe([EventType::Window, EventType::Workspace])?;
+    let mut windows = focused_windows(query.get_tree()?);
+    sender.blocking_send(Message::Taskbar(TaskbarMessage::Windows(windows.clone())))?;
+    for event in events {
+        event?;
+        // Title changes of windows elsewhere come in all the time, and
+        // change nothing here
+        let current = focused_windows(query.get_tree()?);
+        if current != windows {
+            windows = current;
+            sender.blocking_send(Message::Taskbar(TaskbarMessage::Windows(windows.clone())))?;
+        }
+    }
+    Ok(())
+}
+
+pub fn taskbar_subscription(rt: Handle) -> ReceiverStream<Message> {
+    let (sender, receiver) = channel(1);
+    supervise_blocking(&rt, "taskbar", Backoff::DEFAULT, sender, taskbar_generator);
+    ReceiverStream::new(receiver)
+}
+
+/// Widest a window button of the taskbar gets, in bar heights
+const TASKBAR_TITLE_WIDTH: f32 = 12.;
+
+/// A button per window on the focused workspace, with the glyph of its
+/// app and its title cut to TASKBAR_TITLE_WIDTH. Clicking focuses the
+/// window, right clicking closes it
+pub const WIDGET: Widget = Widget {
+    name: "taskbar",
+    render,
+};
+
+fn render(state: &State) -> Vec<Renderable> {
+    let style = state.config.theme.style("taskbar");
+    let mut module = Vec::new();
+    for window in &state.taskbar {
+        if !module.is_empty() {
+            module.push(Renderable::Space(1.));
+        }
+        let icon = window
+            .app_id
+            .as_deref()
+            .and_then(|app_id| app_icon(state, app_id));
+        let text = match icon {
+            Some(icon) => format!("{icon} {}", window.title),
+            None => window.title.clone(),
+        };
+        let bg = if window.focused { style.dim } else { style.bg };
+        let text = Renderable::Text {
+            text: text.into(),
+            fg: match (window.urgent, window.focused) {
+                (true, _) => style.urgent,
+                (false, true) => style.fg,
+                (false, false) => style.dim,
+            },
+            bg,
+        };
+        let button = Renderable::Ellipsize {
+            max_width: TASKBAR_TITLE_WIDTH,
+            children: vec![if window.urgent {
+                Renderable::Pulse(vec![text])
+            } else {
+                text
+            }],
+        };
+        let criteria = format!("[con_id={}]", window.id);
+        module.push(Renderable::Bindings {
+            bindings: MouseBindings {
+                left: Some(Action::SwayCommand(format!("{criteria} focus"))),
+                middle: None,
+                right: Some(Action::SwayCommand(format!("{criteria} kill"))),
+            },
+            children: vec![Renderable::Region {
+                tooltip: Some(window.title.clone()),
+                on_click: None,
+                on_scroll: None,
+                children: vec![button],
+            }],
+        });
+    }
+    module
+}
@@ -0,0 +1,335 @@
+use std::ops::Range;
+
+use smithay_client_toolkit::seat::keyboard::Keysym;
+
+use crate::renderer::Renderable;
+
+/// A key press as forwarded from the wayland keyboard to the state
+#[derive(Debug, Clone)]
+pub struct KeyInput {
+    pub keysym: Keysym,
+    /// Text the key produces with the current layout and modifiers
+    pub utf8: Option<String>,
+    pub ctrl: bool,
+    pub shift: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TextInputEvent {
+    /// The key didn't change anything
+    None,
+    Changed,
+    Submit,
+    Cancel,
+}
+
+/// Single line text input shared by the popups. Positions are byte offsets
+/// which always lie on char boundaries
+#[derive(Debug, Clone, Default)]
+pub struct TextInput {
+    text: String,
+    cursor: usize,
+    /// The other end of the selection, the cursor being the moving end
+    anchor: Option<usize>,
+}
+
+impl TextInput {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn set_text(&mut self, text: &str) {
+        self.text = text.to_string();
+        self.cursor = self.text.len();
+        self.anchor = None;
+    }
+
+    pub fn selection(&self) -> Option<Range<usize>> {
+        let anchor = self.anchor.filter(|anchor| *anchor != self.cursor)?;
+        Some(anchor.min(self.cursor)..anchor.max(self.cursor))
+    }
+
+    fn prev_boundary(&self, from: usize) -> usize {
+        self.text[..from]
+            .char_indices()
+            .next_back()
+            .map(|(i, _)| i)
+            .unwrap_or(0)
+    }
+
+    fn next_boundary(&self, from: usize) -> usize {
+        self.text[from..]
+            .chars()
+            .next()
+            .map(|c| from + c.len_utf8())
+            .unwrap_or(from)
+    }
+
+    /// Start of the word before the cursor, skipping whitespace first
+    fn prev_word_boundary(&self, from: usize) -> usize {
+        let before = self.text[..from].trim_end();
+        before
+            .char_indices()
+            .rev()
+            .find(|(_, c)| c.is_whitespace())
+            .map(|(i, c)| i + c.len_utf8())
+            .unwrap_or(0)
+    }
+
+    /// End of the word after the cursor, skipping whitespace first
+    fn next_word_boundary(&self, from: usize) -> usize {
+        let after = &self.text[from..];
+        let start = from + after.len() - after.trim_start().len();
+        self.text[start..]
+            .char_indices()
+            .find(|(_, c)| c.is_whitespace())
+            .map(|(i, _)| start + i)
+            .unwrap_or(self.text.len())
+    }
+
+    fn move_cursor(&mut self, to: usize, extend_selection: bool) {
+        if extend_selection {
+            self.anchor.get_or_insert(self.cursor);
+        } else {
+            self.anchor = None;
+        }
+        self.cursor = to;
+    }
+
+    /// Removes the selection, returning whether there was one
+    fn delete_selection(&mut self) -> bool {
+        match self.selection() {
+            Some(selection) => {
+                self.cursor = selection.start;
+                self.text.replace_range(selection, "");
+                self.anchor = None;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn delete_range(&mut self, range: Range<usize>) -> TextInputEvent {
+        if range.is_empty() {
+            return TextInputEvent::None;
+        }
+        self.cursor = range.start;
+        self.text.replace_range(range, "");
+        TextInputEvent::Changed
+    }
+
+    pub fn insert(&mut self, text: &str) {
+        self.delete_selection();
+        self.text.insert_str(self.cursor, text);
+        self.cursor += text.len();
+    }
+
+    pub fn handle_key(&mut self, key: &KeyInput) -> TextInputEvent {
+        match key.keysym {
+            Keysym::Return | Keysym::KP_Enter => TextInputEvent::Submit,
+            Keysym::Escape => TextInputEvent::Cancel,
+            Keysym::Left => {
+                let to = match self.selection() {
+                    Some(selection) if !key.shift => selection.start,
+                    _ if key.ctrl => self.prev_word_boundary(self.cursor),
+                    _ => self.prev_boundary(self.cursor),
+                };
+                self.move_cursor(to, key.shift);
+                TextInputEvent::None
+            }
+            Keysym::Right => {
+                let to = match self.selection() {
+                    Some(selection) if !key.shift => selection.end,
+                    _ if key.ctrl => self.next_word_boundary(self.cursor),
+                    _ => self.next_boundary(self.cursor),
+                };
+                self.move_cursor(to, key.shift);
+                TextInputEvent::None
+            }
+            Keysym::Home => {
+                self.move_cursor(0, key.shift);
+                TextInputEvent::None
+            }
+            Keysym::End => {
+                self.move_cursor(self.text.len(), key.shift);
+                TextInputEvent::None
+            }
+            Keysym::BackSpace => {
+                if self.delete_selection() {
+                    return TextInputEvent::Changed;
+                }
+                let from = if key.ctrl {
+                    self.prev_word_boundary(self.cursor)
+                } else {
+                    self.prev_boundary(self.cursor)
+                };
+                self.delete_range(from..self.cursor)
+            }
+            Keysym::Delete => {
+                if self.delete_selection() {
+                    return TextInputEvent::Changed;
+                }
+                self.delete_range(self.cursor..self.next_boundary(self.cursor))
+            }
+            Keysym::a if key.ctrl => {
+                self.anchor = Some(0);
+                self.cursor = self.text.len();
+                TextInputEvent::None
+            }
+            Keysym::u if key.ctrl => {
+                self.anchor = None;
+                self.delete_range(0..self.cursor)
+            }
+            _ if key.ctrl => TextInputEvent::None,
+            _ => match key
+                .utf8
+                .as_deref()
+                .filter(|text| !text.is_empty() && !text.chars().any(char::is_control))
+            {
+                Some(text) => {
+                    self.insert(text);
+                    TextInputEvent::Changed
+                }
+                None => TextInputEvent::None,
+            },
+        }
+    }
+
+    /// The text with the selection drawn inverted and a bar for the cursor
+    pub fn to_renderables(&self, fg: u32, bg: u32) -> Vec<Renderable> {
+        let cursor = Renderable::Box {
+            fg,
+            bg: fg,
+            width: 0.08,
+            height: 1.,
+            skip: 0.08,
+        };
+        let text = |range: Range<usize>, fg: u32, bg: u32| Renderable::Text {
+            text: self.text[range].to_string().into(),
+            fg,
+            bg,
+        };
+        let selection = self.selection().unwrap_or(self.cursor..self.cursor);
+        let mut renderables = vec![text(0..selection.start, fg, bg)];
+        if self.cursor == selection.start {
+            renderables.push(cursor.clone());
+        }
+        renderables.push(text(selection.clone(), bg, fg));
+        if self.cursor != selection.start {
+            renderables.push(cursor);
+        }
+        renderables.push(text(selection.end..self.text.len(), fg, bg));
+        renderables.retain(|v| !matches!(v, Renderable::Text { text, .. } if text.is_empty()));
+        renderables
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(keysym: Keysym, ctrl: bool, shift: bool) -> KeyInput {
+        KeyInput {
+            keysym,
+            utf8: None,
+            ctrl,
+            shift,
+        }
+    }
+
+    fn input(text: &str, cursor: usize) -> TextInput {
+        let mut input = TextInput::new();
+        input.set_text(text);
+        input.cursor = cursor;
+        input
+    }
+
+    #[test]
+    fn moves_by_char_boundaries() {
+        let mut input = input("aé", 3);
+        input.handle_key(&key(Keysym::Left, false, false));
+        assert_eq!(input.cursor, 1);
+        input.handle_key(&key(Keysym::Left, false, false));
+        assert_eq!(input.cursor, 0);
+        input.handle_key(&key(Keysym::Left, false, false));
+        assert_eq!(input.cursor, 0);
+        input.handle_key(&key(Keysym::Right, false, false));
+        input.handle_key(&key(Keysym::Right, false, false));
+        assert_eq!(input.cursor, 3);
+    }
+
+    #[test]
+    fn moves_by_words_with_ctrl() {
+        let mut input = input("foo  bar baz", 0);
+        input.handle_key(&key(Keysym::Right, true, false));
+        assert_eq!(input.cursor, 3);
+        input.handle_key(&key(Keysym::Right, true, false));
+        assert_eq!(input.cursor, 8);
+        input.handle_key(&key(Keysym::Right, true, false));
+        assert_eq!(input.cursor, 12);
+        input.handle_key(&key(Keysym::Right, true, false));
+        assert_eq!(input.cursor, 12);
+        input.handle_key(&key(Keysym::Left, true, false));
+        assert_eq!(input.cursor, 9);
+        input.handle_key(&key(Keysym::Left, true, false));
+        assert_eq!(input.cursor, 5);
+        input.handle_key(&key(Keysym::Left, true, false));
+        assert_eq!(input.cursor, 0);
+    }
+
+    #[test]
+    fn shift_selects_and_arrows_collapse() {
+        let mut input = input("hello world", 0);
+        input.handle_key(&key(Keysym::Right, true, true));
+        assert_eq!(input.selection(), Some(0..5));
+        input.handle_key(&key(Keysym::Left, false, false));
+        assert_eq!(input.cursor, 0);
+        assert_eq!(input.selection(), None);
+        input.handle_key(&key(Keysym::End, false, true));
+        input.handle_key(&key(Keysym::Right, false, false));
+        assert_eq!(input.cursor, 11);
+        assert_eq!(input.selection(), None);
+    }
+
+    #[test]
+    fn deletes_words_and_selections() {
+        let mut input = input("foo bar", 7);
+        assert_eq!(
+            input.handle_key(&key(Keysym::BackSpace, true, false)),
+            TextInputEvent::Changed
+        );
+        assert_eq!(input.text(), "foo ");
+        input.handle_key(&key(Keysym::a, true, false));
+        input.handle_key(&key(Keysym::Delete, false, false));
+        assert_eq!(input.text(), "");
+        assert_eq!(
+            input.handle_key(&key(Keysym::BackSpace, false, false)),
+            TextInputEvent::None
+        );
+    }
+
+    #[test]
+    fn typing_replaces_the_selection() {
+        let mut input = input("abc", 3);
+        input.handle_key(&key(Keysym::Left, false, true));
+        input.handle_key(&KeyInput {
+            utf8: Some("x".to_string()),
+            ..key(Keysym::x, false, false)
+        });
+        assert_eq!(input.text(), "abx");
+        assert_eq!(input.cursor, 3);
+    }
+
+    #[test]
+    fn escape_cancels() {
+        let mut input = input("abc", 3);
+        assert_eq!(
+            input.handle_key(&key(Keysym::Escape, false, false)),
+            TextInputEvent::Cancel
+        );
+    }
+}
@@ -0,0 +1,136 @@
+use std::collections::HashMap;
+
+use serde::Deserialize;
+
+/// A color in the 0xAABBGGRR layout the renderer uploads as is, written as
+/// "#rrggbb" or "#rrggbbaa" in the config
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Color(pub u32);
+
+impl Color {
+    pub const TRANSPARENT: Self = Self(0x00000000);
+
+    pub const fn rgba(r: u8, g: u8, b: u8, a: u8) -> Self {
+        Self(u32::from_le_bytes([r, g, b, a]))
+    }
+
+    pub fn parse(text: &str) -> Option<Self> {
+        let hex = text.strip_prefix('#')?;
+        if !hex.is_ascii() {
+            return None;
+        }
+        let channel = |i: usize| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok();
+        let alpha = match hex.len() {
+            6 => 0xff,
+            8 => channel(6)?,
+            _ => return None,
+        };
+        Some(Self::rgba(channel(0)?, channel(2)?, channel(4)?, alpha))
+    }
+
+    /// The color amount of the way towards other, keeping its own alpha
+    pub fn mix(self, other: Self, amount: f32) -> Self {
+        let [r, g, b, a] = self.0.to_le_bytes();
+        let [to_r, to_g, to_b, _] = other.0.to_le_bytes();
+        let channel =
+            |from: u8, to: u8| (from as f32 + (to as f32 - from as f32) * amount).round() as u8;
+        Self::rgba(channel(r, to_r), channel(g, to_g), channel(b, to_b), a)
+    }
+}
+
+impl TryFrom<String> for Color {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        Self::parse(&value).ok_or_else(|| format!("{value:?} is not a #rrggbb or #rrggbbaa color"))
+    }
+}
+
+impl From<Color> for u32 {
+    fn from(value: Color) -> Self {
+        value.0
+    }
+}
+
+/// Colors of the bar, read from the [theme] table of the config
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct Theme {
+    /// What the bar is cleared to, can be translucent
+    pub background: Color,
+    pub foreground: Color,
+    /// Things that are there but not important, like hidden workspaces
+    pub dim: Color,
+    /// Things that are active, like a playing song
+    pub accent: Color,
+    /// Things going well, like a charging battery
+    pub good: Color,
+    pub warning: Color,
+    /// Things that need attention now
+    pub urgent: Color,
+    /// Corner radius of module backgrounds in bar heights, 0 for square
+    /// corners and 0.5 for round ends
+    pub pill_radius: f32,
+    /// Width of module outlines in bar heights
+    pub border_width: f32,
+    /// Overrides by module name, see widget::WIDGETS
+    pub modules: HashMap<String, ModuleStyle>,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self {
+            background: Color::rgba(0x00, 0x00, 0x00, 0xff),
+            foreground: Color::rgba(0xff, 0xff, 0xff, 0xff),
+            dim: Color::rgba(0x11, 0x11, 0x11, 0xff),
+            accent: Color::rgba(0xff, 0x00, 0xff, 0xff),
+            good: Color::rgba(0xff, 0xff, 0x00, 0xff),
+            warning: Color::rgba(0xff, 0xaa, 0x00, 0xff),
+            urgent: Color::rgba(0xff, 0x00, 0x00, 0xff),
+            pill_radius: 0.3,
+            border_width: 0.06,
+            modules: HashMap::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ModuleStyle {
+    pub foreground: Option<Color>,
+    /// Drawn as a pill behind the whole module, none unless set
+    pub background: Option<Color>,
+    /// Outline of the pill, none unless set
+    pub border: Option<Color>,
+    pub accent: Option<Color>,
+}
+
+/// The colors one module draws with, the theme with its overrides applied
+#[derive(Debug, Clone, Copy)]
+pub struct Style {
+    pub fg: u32,
+    pub bg: u32,
+    pub dim: u32,
+    pub accent: u32,
+    pub good: u32,
+    pub warning: u32,
+    pub urgent: u32,
+}
+
+impl Theme {
+    pub fn style(&self, module: &str) -> Style {
+        let overrides = self.modules.get(module).cloned().unwrap_or_default();
+        Style {
+            fg: overrides.foreground.unwrap_or(self.foreground).into(),
+            // The module's background is a pill drawn behind all of it, as
+            // behind each text it would leave square edges
+            bg: Color::TRANSPARENT.into(),
+            dim: self.dim.into(),
+            accent: overrides.accent.unwrap_or(self.accent).into(),
+            good: self.good.into(),
+            warning: self.warning.into(),
+            urgent: self.urgent.into(),
+        }
+    }
+}
@@ -0,0 +1,385 @@
+use std::{collections::BTreeMap, fs, path::Path, time::Duration};
+
+use tokio::{
+    runtime::Handle,
+    sync::{
+        mpsc::{Sender, channel, error::SendError},
+        watch,
+    },
+};
+use tokio_stream::wrappers::ReceiverStream;
+
+use crate::{
+    action::Action,
+    config::CpuView,
+    files::{ReadIntError, read_int_from_file_path, read_string_from_file_path},
+    format::{display_bytes, display_frequency, display_percent},
+    renderer::Renderable,
+    state::{Message, State},
+    supervisor::{Backoff, sleep_unless_resumed, supervise_blocking},
+    widget::Widget,
+};
+
+#[derive(Debug)]
+pub enum ThermalError {
+    StdIoError(std::io::Error),
+    SendError(SendError<Message>),
+}
+
+impl std::fmt::Display for ThermalError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ThermalError::StdIoError(e) => write!(f, "IO Error: {e}"),
+            ThermalError::SendError(e) => write!(f, "Channel Error: {e}"),
+        }
+    }
+}
+
+impl From<std::io::Error> for ThermalError {
+    fn from(value: std::io::Error) -> Self {
+        Self::StdIoError(value)
+    }
+}
+
+impl From<SendError<Message>> for ThermalError {
+    fn from(value: SendError<Message>) -> Self {
+        Self::SendError(value)
+    }
+}
+
+#[derive(Debug)]
+pub enum ThermalMessage {
+    UpdateSensors(Vec<Sensor>),
+    /// None if the CPU doesn't count throttle events
+    UpdateThrottling(Option<Throttling>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SensorKind {
+    Cpu,
+    Gpu,
+    Other,
+}
+
+impl SensorKind {
+    /// Classifies a hwmon chip or thermal zone by the name the driver gives it
+    fn from_name(name: &str) -> Self {
+        match name {
+            "coretemp" | "k10temp" | "zenpower" | "cpu_thermal" | "x86_pkg_temp" => Self::Cpu,
+            "amdgpu" | "radeon" | "nouveau" | "i915" | "xe" => Self::Gpu,
+            _ => Self::Other,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Sensor {
+    pub kind: SensorKind,
+    /// hwmon chip name, or thermal zone type
+    pub name: String,
+    pub label: Option<String>,
+    /// In degrees celsius
+    pub temperature: f32,
+}
+
+/// Thermal throttle events of the CPU since boot, counted by Intel CPUs
+#[derive(Debug, Clone, Default)]
+pub struct Throttling {
+    /// Summed over every physical core, SMT siblings report the count of
+    /// the core they share
+    pub core_events: usize,
+    /// Every core reports the count of its package, so this is the highest
+    pub package_events: usize,
+    /// Whether the counts went up since the last poll
+    pub active: bool,
+}
+
+/// Reads the counters from every cpu*/thermal_throttle directory, comparing
+/// them with the previous poll
+fn throttling(previous: Option<&Throttling>) -> Result<Option<Throttling>, ThermalError> {
+    let mut throttling: Option<Throttling> = None;
+    // By package and core id, which the siblings of a core share
+    let mut cores = BTreeMap::new();
+    for cpu_dir in fs::read_dir("/sys/devices/system/cpu")? {
+        let cpu_dir = cpu_dir?.path();
+        let throttle_dir = cpu_dir.join("thermal_throttle");
+        let (Ok(core_events), Ok(package_events)) = (
+            read_int_from_file_path(throttle_dir.join("core_throttle_count")),
+            read_int_from_file_path(throttle_dir.join("package_throttle_count")),
+        ) else {
+            continue;
+        };
+        let topology_dir = cpu_dir.join("topology");
+        // Without a topology every cpu is taken as a core of its own
+        let core = match (
+            read_int_from_file_path(topology_dir.join("physical_package_id")),
+            read_int_from_file_path(topology_dir.join("core_id")),
+        ) {
+            (Ok(package), Ok(core)) => (package, core, None),
+            _ => (0, 0, Some(cpu_dir.clone())),
+        };
+        cores.insert(core, core_events);
+        let throttling = throttling.get_or_insert_default();
+        throttling.package_events = throttling.package_events.max(package_events);
+    }
+    if let Some(throttling) = &mut throttling {
+        throttling.core_events = cores.values().sum();
+    }
+    if let (Some(throttling), Some(previous)) = (&mut throttling, previous) {
+        throttling.active = throttling.core_events > previous.core_events
+            || throttling.package_events > previous.package_events;
+    }
+    Ok(throttling)
+}
+
+/// The kernel reports temperatures in millidegrees celsius
+fn read_temperature<P: AsRef<Path>>(path: P) -> Result<f32, ReadIntError> {
+    Ok(read_int_from_file_path(path)? as f32 / 1000.)
+}
+
+fn hwmon_sensors() -> Result<Vec<Sensor>, ThermalError> {
+    let mut sensors = Vec::new();
+    for hwmon_dir in fs::read_dir("/sys/class/hwmon")? {
+        let hwmon_dir = hwmon_dir?.path();
+        let name = match read_string_from_file_path(hwmon_dir.join("name")) {
+            Ok(name) => name.trim().to_string(),
+            Err(_) => continue,
+        };
+        for entry in fs::read_dir(&hwmon_dir)? {
+            let file_name = entry?.file_name().to_string_lossy().to_string();
+            let prefix = match file_name
+                .strip_suffix("_input")
+                .filter(|prefix| prefix.starts_with("temp"))
+            {
+                Some(prefix) => prefix.to_string(),
+                None => continue,
+            };
+            let temperature = match read_temperature(hwmon_dir.join(&file_name)) {
+                Ok(temperature) => temperature,
+                Err(e) => {
+                    log::error!("Could not read {hwmon_dir:?}/{file_name}: {e:?}");
+                    continue;
+                }
+            };
+            sensors.push(Sensor {
+                kind: SensorKind::from_name(&name),
+                name: name.clone(),
+                label: read_string_from_file_path(hwmon_dir.join(format!("{prefix}_label")))
+                    .ok()
+                    .map(|label| label.trim().to_string()),
+                temperature,
+            });
+        }
+    }
+    Ok(sensors)
+}
+
+fn thermal_zone_sensors() -> Result<Vec<Sensor>, ThermalError> {
+    let mut sensors = Vec::new();
+    for zone_dir in fs::read_dir("/sys/class/thermal")? {
+        let zone_dir = zone_dir?.path();
+        if !zone_dir
+            .file_name()
+            .is_some_and(|name| name.to_string_lossy().starts_with("thermal_zone"))
+        {
+            continue;
+        }
+        let name = match read_string_from_file_path(zone_dir.join("type")) {
+            Ok(name) => name.trim().to_string(),
+            Err(_) => continue,
+        };
+        match read_temperature(zone_dir.join("temp")) {
+            Ok(temperature) => sensors.push(Sensor {
+                kind: SensorKind::from_name(&name),
+                name,
+                label: None,
+                temperature,
+            }),
+            Err(e) => log::error!("Could not read the temperature of {zone_dir:?}: {e:?}"),
+        }
+    }
+    Ok(sensors)
+}
+
+fn thermal_generator(
+    sender: Sender<Message>,
+    resumes: &mut watch::Receiver<()>,
+) -> Result<(), ThermalError> {
+    let mut previous_throttling = None;
+    loop {
+        let mut sensors = hwmon_sensors()?;
+        // Thermal zones mostly duplicate hwmon, only use them for whatever
+        // hwmon didn't cover
+        let hwmon_kinds: Vec<SensorKind> = sensors.iter().map(|v| v.kind).collect();
+        sensors.extend(
+            thermal_zone_sensors()?
+                .into_iter()
+                .filter(|zone| !hwmon_kinds.contains(&zone.kind)),
+        );
+        sender.blocking_send(Message::Thermal(ThermalMessage::UpdateSensors(sensors)))?;
+        let throttling = throttling(previous_throttling.as_ref())?;
+        sender.blocking_send(Message::Thermal(ThermalMessage::UpdateThrottling(
+            throttling.clone(),
+        )))?;
+        previous_throttling = throttling;
+        sleep_unless_resumed(resumes, Duration::from_secs(5));
+    }
+}
+
+pub fn thermal_subscription(
+    rt: Handle,
+    mut resumes: watch::Receiver<()>,
+) -> ReceiverStream<Message> {
+    let (sender, receiver) = channel(1);
+    supervise_blocking(&rt, "thermal", Backoff::DEFAULT, sender, move |sender| {
+        thermal_generator(sender, &mut resumes)
+    });
+    ReceiverStream::new(receiver)
+}
+
+/// The hottest CPU and GPU sensors, the CPU one with the configured CpuView
+pub const WIDGET: Widget = Widget {
+    name: "thermal",
+    render,
+};
+
+fn render(state: &State) -> Vec<Renderable> {
+    let style = state.config.theme.style("thermal");
+    let mut module = Vec::new();
+    for kind in [SensorKind::Cpu, SensorKind::Gpu] {
+        let hottest = state
+            .sensors
+            .iter()
+            .filter(|sensor| sensor.kind == kind)
+            .max_by(|a, b| a.temperature.total_cmp(&b.temperature));
+        // The CPU entry can show more than the temperature, see CpuView
+        if hottest.is_none() && (kind != SensorKind::Cpu || state.cpu.is_none()) {
+            continue;
+        }
+        let thermal_config = &state.config.thermal;
+        if !module.is_empty() {
+            module.push(Renderable::Space(1.));
+        }
+        let mut tooltip = state
+            .sensors
+            .iter()
+            .filter(|sensor| sensor.kind == kind)
+            .map(|sensor| match &sensor.label {
+                Some(label) => {
+                    format!("{} {}: {:.1}°C", sensor.name, label, sensor.temperature)
+                }
+                None => format!("{}: {:.1}°C", sensor.name, sensor.temperature),
+            })
+            .collect::<Vec<_>>();
+        let temperature = hottest.map(|sensor| Renderable::Text {
+            text: format!(
+                "{} {:.0}°",
+                if kind == SensorKind::Cpu {
+                    "cpu"
+                } else {
+                    "gpu"
+                },
+                sensor.temperature
+            )
+            .into(),
+            fg: if sensor.temperature >= thermal_config.critical {
+                style.urgent
+            } else if sensor.temperature >= thermal_config.warning {
+                style.warning
+            } else {
+                style.fg
+            },
+            bg: style.bg,
+        });
+        if kind == SensorKind::Gpu {
+            module.push(Renderable::Region {
+                tooltip: Some(tooltip.join("\n")),
+                on_click: None,
+                on_scroll: None,
+                children: temperature.into_iter().collect(),
+            });
+            continue;
+        }
+        let mut children = Vec::new();
+        if let Some(throttling) = &state.throttling {
+            tooltip.push(format!(
+                "Throttled {} times on cores and {} times on the package since boot",
+                throttling.core_events, throttling.package_events
+            ));
+            if throttling.active {
+                // A fire from the nerd fonts
+                children.push(Renderable::Pulse(vec![Renderable::Text {
+                    text: "\u{f0238} ".into(),
+                    fg: style.warning,
+                    bg: style.bg,
+                }]));
+            }
+        }
+        if let Some(cpu) = &state.cpu {
+            tooltip.push(format!("Usage {}", display_percent(cpu.usage)));
+            if let (Some(max), Some(average)) = (cpu.max_frequency(), cpu.average_frequency()) {
+                tooltip.push(format!(
+                    "Frequency {} highest, {} on average",
+                    display_frequency(max),
+                    display_frequency(average)
+                ));
+            }
+        }
+        if let Some(top) = &state.top_processes {
+            if !top.cpu.is_empty() {
+                tooltip.push(format!(
+                    "Top CPU: {}",
+                    top.cpu
+                        .iter()
+                        .map(|(name, usage)| format!("{name} {usage:.0}%"))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ));
+            }
+            if !top.memory.is_empty() {
+                tooltip.push(format!(
+                    "Top memory: {}",
+                    top.memory
+                        .iter()
+                        .map(|(name, memory)| format!("{name} {}", display_bytes(*memory).trim()))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ));
+            }
+        }
+        let view = |view: &CpuView| match view {
+            CpuView::Temperature => temperature.clone(),
+            CpuView::Usage => state.cpu.as_ref().map(|cpu| Renderable::Text {
+                text: format!("cpu {}", display_percent(cpu.usage)).into(),
+                fg: style.fg,
+                bg: style.bg,
+            }),
+            CpuView::Frequency => state.cpu.as_ref().and_then(|cpu| {
+                Some(Renderable::Text {
+                    text: format!(
+                        "cpu {}/{}",
+                        display_frequency(cpu.max_frequency()?),
+                        display_frequency(cpu.average_frequency()?)
+                    )
+                    .into(),
+                    fg: style.fg,
+                    bg: style.bg,
+                })
+            }),
+        };
+        // The selected view, or the next one with something to show
+        let views = &thermal_config.cpu_views;
+        let shown = (0..views.len())
+            .map(|offset| &views[(state.cpu_view + offset) % views.len()])
+            .find_map(view)
+            .or(temperature);
+        children.extend(shown);
+        module.push(Renderable::Region {
+            tooltip: Some(tooltip.join("\n")),
+            on_click: (views.len() > 1).then_some(Action::CycleCpuView),
+            on_scroll: None,
+            children,
+        });
+    }
+    module
+}
@@ -0,0 +1,262 @@
+use std::time::{Duration, Instant};
+
+use serde::Deserialize;
+
+use crate::{
+    action::{Action, ScrollActions},
+    renderer::Renderable,
+    state::State,
+    widget::Widget,
+};
+
+/// What clicks, scrolling and the IPC socket do to the timer module
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TimerAction {
+    Start,
+    Pause,
+    /// Pauses a running timer and starts any other
+    Toggle,
+    /// Stops the timer, back at the configured length
+    Reset,
+    /// Lengthens the timer by this many seconds, shortens it when negative
+    Adjust(i64),
+}
+
+/// Longest the timer runs for, however much is added through the IPC
+/// socket or the config. Keeps the end well within what an Instant holds
+pub const MAX_LENGTH: Duration = Duration::from_secs(24 * 60 * 60);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Timer {
+    /// Waiting to be started, with the time it runs for
+    Stopped(Duration),
+    Running {
+        ends_at: Instant,
+    },
+    Paused(Duration),
+    /// Ran out at the instant, and stays at zero until started or reset
+    Expired(Instant),
+}
+
+impl Timer {
+    /// The time left, rounded up to whole seconds like it is shown
+    pub fn remaining(&self) -> Duration {
+        let remaining = match self {
+            Self::Stopped(left) | Self::Paused(left) => *left,
+            Self::Running { ends_at } => ends_at.saturating_duration_since(Instant::now()),
+            Self::Expired(_) => Duration::ZERO,
+        };
+        Duration::from_secs(remaining.as_secs() + (remaining.subsec_nanos() > 0) as u64)
+    }
+
+    /// Whether it ran out less than flash ago
+    pub fn flashing(&self, flash: Duration) -> bool {
+        matches!(self, Self::Expired(at) if at.elapsed() < flash)
+    }
+
+    /// Running out left from now, or paused with it in the unlikely case
+    /// that the end can't be told
+    fn running(now: Instant, left: Duration) -> Self {
+        match now.checked_add(left.min(MAX_LENGTH)) {
+            Some(ends_at) => Self::Running { ends_at },
+            None => Self::Paused(left),
+        }
+    }
+
+    pub fn run(&mut self, action: TimerAction, length: Duration) {
+        let now = Instant::now();
+        let length = length.min(MAX_LENGTH);
+        *self = match (action, *self) {
+            (
+                TimerAction::Start | TimerAction::Toggle,
+                Self::Stopped(left) | Self::Paused(left),
+            ) => Self::running(now, left),
+            (TimerAction::Start | TimerAction::Toggle, Self::Expired(_)) => {
+                Self::running(now, length)
+            }
+            (TimerAction::Pause | TimerAction::Toggle, Self::Running { ends_at }) => {
+                Self::Paused(ends_at.saturating_duration_since(now))
+            }
+            (TimerAction::Reset, _) => Self::Stopped(length),
+            (TimerAction::Adjust(seconds), timer) => {
+                let adjust = |left: Duration| {
+                    let change = Duration::from_secs(seconds.unsigned_abs());
+                    let left = if seconds < 0 {
+                        left.saturating_sub(change)
+                    } else {
+                        left.saturating_add(change)
+                    };
+                    // Shortened to nothing, it would only run out right away
+                    left.clamp(Duration::from_secs(1), MAX_LENGTH)
+                };
+                match timer {
+                    Self::Stopped(left) => Self::Stopped(adjust(left)),
+                    Self::Paused(left) => Self::Paused(adjust(left)),
+                    Self::Running { ends_at } => {
+                        Self::running(now, adjust(ends_at.saturating_duration_since(now)))
+                    }
+                    Self::Expired(_) => Self::Stopped(adjust(length)),
+                }
+            }
+            (_, timer) => timer,
+        };
+    }
+
+    /// When the shown seconds next change, the timer runs out or stops
+    /// flashing
+    pub fn next_tick(&self, flash: Duration) -> Option<Instant> {
+        match self {
+            Self::Running { ends_at } => {
+                let remaining = ends_at.saturating_duration_since(Instant::now());
+                Some(*ends_at - Duration::from_secs(remaining.as_secs()))
+            }
+            Self::Expired(at) => at.checked_add(flash).filter(|end| *end > Instant::now()),
+            Self::Stopped(_) | Self::Paused(_) => None,
+        }
+    }
+
+    /// Expires a timer that ran out, returning whether it just did
+    pub fn tick(&mut self) -> bool {
+        match self {
+            Self::Running { ends_at } if *ends_at <= Instant::now() => {
+                *self = Self::Expired(*ends_at);
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+/// The time left on the timer, flashing for a while once it runs out.
+/// Clicking starts and pauses it, scrolling changes how long it runs
+pub const WIDGET: Widget = Widget {
+    name: "timer",
+    render,
+};
+
+fn render(state: &State) -> Vec<Renderable> {
+    let style = state.config.theme.style("timer");
+    let remaining = state.timer.remaining().as_secs();
+    let text = match remaining / 3600 {
+        0 => format!("{:02}:{:02}", remaining / 60, remaining % 60),
+        hours => format!("{hours}:{:02}:{:02}", remaining / 60 % 60, remaining % 60),
+    };
+    let (fg, tooltip) = match state.timer {
+        Timer::Stopped(_) => (style.dim, "Timer, click to start"),
+        Timer::Running { .. } => (style.fg, "Timer running, click to pause"),
+        Timer::Paused(_) => (style.dim, "Timer paused, click to go on"),
+        Timer::Expired(_) => (style.urgent, "Time is up, click to start again"),
+    };
+    let text = format!("\u{f051b} {text}");
+    let flash = Duration::from_secs(state.config.timer.flash);
+    let children = if state.timer.flashing(flash) {
+        vec![Renderable::Pulse(vec![Renderable::Text {
+            text: text.into(),
+            fg: state.config.theme.background.into(),
+            bg: style.urgent,
+        }])]
+    } else {
+        vec![Renderable::Text {
+            text: text.into(),
+            fg,
+            bg: style.bg,
+        }]
+    };
+    // Negated for scrolling down, so it has to stay well within an i64
+    let step = state.config.timer.step.min(MAX_LENGTH.as_secs()) as i64;
+    vec![Renderable::Region {
+        tooltip: Some(format!("{tooltip}, right click to reset")),
+        on_click: Some(Action::Timer(TimerAction::Toggle)),
+        on_scroll: Some(ScrollActions {
+            up: Action::Timer(TimerAction::Adjust(step)),
+            down: Action::Timer(TimerAction::Adjust(-step)),
+            sensitivity: state.scroll_sensitivity("timer"),
+        }),
+        children,
+    }]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LENGTH: Duration = Duration::from_secs(25 * 60);
+
+    fn run(mut timer: Timer, action: TimerAction) -> Timer {
+        timer.run(action, LENGTH);
+        timer
+    }
+
+    #[test]
+    fn starts_pauses_and_resets() {
+        let running = run(Timer::Stopped(LENGTH), TimerAction::Start);
+        assert!(matches!(running, Timer::Running { .. }));
+        assert_eq!(running.remaining(), LENGTH);
+        let paused = run(running, TimerAction::Toggle);
+        assert!(matches!(paused, Timer::Paused(_)));
+        assert_eq!(paused.remaining(), LENGTH);
+        assert!(matches!(
+            run(paused, TimerAction::Toggle),
+            Timer::Running { .. }
+        ));
+        // Pausing what isn't running changes nothing
+        assert_eq!(run(paused, TimerAction::Pause), paused);
+        assert_eq!(run(running, TimerAction::Reset), Timer::Stopped(LENGTH));
+    }
+
+    #[test]
+    fn starts_over_once_expired() {
+        let mut timer = Timer::Running {
+            ends_at: Instant::now(),
+        };
+        assert!(timer.tick());
+        assert!(matches!(timer, Timer::Expired(_)));
+        assert!(!timer.tick());
+        assert_eq!(timer.remaining(), Duration::ZERO);
+        assert_eq!(run(timer, TimerAction::Start).remaining(), LENGTH);
+        assert_eq!(
+            run(timer, TimerAction::Adjust(60)),
+            Timer::Stopped(LENGTH + Duration::from_secs(60))
+        );
+    }
+
+    #[test]
+    fn adjusts_within_limits() {
+        let stopped = Timer::Stopped(LENGTH);
+        assert_eq!(
+            run(stopped, TimerAction::Adjust(-60)),
+            Timer::Stopped(LENGTH - Duration::from_secs(60))
+        );
+        assert_eq!(
+            run(stopped, TimerAction::Adjust(i64::MIN)),
+            Timer::Stopped(Duration::from_secs(1))
+        );
+        assert_eq!(
+            run(stopped, TimerAction::Adjust(i64::MAX)),
+            Timer::Stopped(MAX_LENGTH)
+        );
+        assert_eq!(
+            run(Timer::Paused(LENGTH), TimerAction::Adjust(i64::MAX)),
+            Timer::Paused(MAX_LENGTH)
+        );
+        // Like 1e19 seconds sent through the IPC socket
+        let running = run(
+            run(stopped, TimerAction::Start),
+            TimerAction::Adjust(i64::MAX),
+        );
+        assert_eq!(running.remaining(), MAX_LENGTH);
+    }
+
+    #[test]
+    fn clamps_long_lengths() {
+        let mut timer = Timer::Stopped(Duration::MAX);
+        timer.run(TimerAction::Start, Duration::MAX);
+        assert_eq!(timer.remaining(), MAX_LENGTH);
+        timer.run(TimerAction::Reset, Duration::MAX);
+        assert_eq!(timer, Timer::Stopped(MAX_LENGTH));
+        let expired = Timer::Expired(Instant::now());
+        assert!(expired.next_tick(Duration::MAX).is_none());
+        assert!(expired.flashing(Duration::MAX));
+    }
+}
@@ -0,0 +1,255 @@
+use std::{
+    collections::HashMap,
+    process::Command,
+    string::FromUtf8Error,
+    time::{Duration, SystemTime},
+};
+
+use tinyjson::JsonValue;
+use tokio::{
+    runtime::Handle,
+    sync::{
+        mpsc::{Sender, channel, error::SendError},
+        watch,
+    },
+};
+use tokio_stream::wrappers::ReceiverStream;
+
+use crate::{
+    action::Action,
+    config::UpdateConfig,
+    renderer::Renderable,
+    state::{Message, State},
+    supervisor::{Backoff, sleep_unless_resumed, supervise_blocking},
+    widget::Widget,
+};
+
+#[derive(Debug)]
+pub enum UpdateError {
+    StdIoError(std::io::Error),
+    Utf8Error(FromUtf8Error),
+    JsonError(tinyjson::JsonParseError),
+    /// curl failed, with what it printed to stderr
+    CommandError(String),
+    /// The answer has no tag_name, like when the repository has no releases
+    NoRelease,
+    SendError(SendError<Message>),
+}
+
+impl std::fmt::Display for UpdateError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UpdateError::StdIoError(e) => write!(f, "IO Error: {e}"),
+            UpdateError::Utf8Error(e) => write!(f, "The output of curl is not UTF-8: {e}"),
+            UpdateError::JsonError(e) => write!(f, "JSON Error: {e}"),
+            UpdateError::CommandError(stderr) => write!(f, "curl failed: {stderr}"),
+            UpdateError::NoRelease => write!(f, "The repository has no release"),
+            UpdateError::SendError(e) => write!(f, "Channel Error: {e}"),
+        }
+    }
+}
+
+impl From<std::io::Error> for UpdateError {
+    fn from(value: std::io::Error) -> Self {
+        Self::StdIoError(value)
+    }
+}
+
+impl From<FromUtf8Error> for UpdateError {
+    fn from(value: FromUtf8Error) -> Self {
+        Self::Utf8Error(value)
+    }
+}
+
+impl From<tinyjson::JsonParseError> for UpdateError {
+    fn from(value: tinyjson::JsonParseError) -> Self {
+        Self::JsonError(value)
+    }
+}
+
+impl From<SendError<Message>> for UpdateError {
+    fn from(value: SendError<Message>) -> Self {
+        Self::SendError(value)
+    }
+}
+
+/// The latest release of the repository
+#[derive(Debug, Clone)]
+pub struct Release {
+    /// Like v0.2.0
+    pub tag: String,
+    /// The release notes, markdown as written on GitHub
+    pub changelog: String,
+    /// The release page
+    pub url: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum UpdateMessage {
+    /// A release newer than the running version, None while up to date
+    Available(Option<Release>),
+}
+
+/// The numbers of a version like v1.2.3-rc1, pre-release suffixes are
+/// dropped
+fn version_numbers(version: &str) -> Vec<u64> {
+    version
+        .trim_start_matches('v')
+        .split('.')
+        .map_while(|part| {
+            let digits = part
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(part.len());
+            part[..digits].parse().ok()
+        })
+        .collect()
+}
+
+fn latest_release(repository: &str) -> Result<Release, UpdateError> {
+    let url = format!("https://api.github.com/repos/{repository}/releases/latest");
+    let output = Command::new("curl")
+        .args(["-fsSL", "-H", "Accept: application/vnd.github+json", &url])
+        .output()?;
+    if !output.status.success() {
+        return Err(UpdateError::CommandError(String::from_utf8(output.stderr)?));
+    }
+    let json: JsonValue = String::from_utf8(output.stdout)?.parse()?;
+    let release = json.get::<HashMap<String, JsonValue>>();
+    let field = |name: &str| {
+        release
+            .and_then(|release| release.get(name))
+            .and_then(|value| value.get::<String>())
+            .cloned()
+    };
+    Ok(Release {
+        tag: field("tag_name").ok_or(UpdateError::NoRelease)?,
+        changelog: field("body").unwrap_or_default(),
+        url: field("html_url"),
+    })
+}
+
+/// How long until the next check, going by the wall clock since the last one
+/// as the monotonic clock stands still while suspended
+fn until_due(checked: SystemTime, now: SystemTime, interval: Duration) -> Duration {
+    // A clock set back counts as no time passed
+    interval.saturating_sub(now.duration_since(checked).unwrap_or_default())
+}
+
+fn update_generator(
+    sender: &Sender<Message>,
+    config: &UpdateConfig,
+    resumes: &mut watch::Receiver<()>,
+) -> Result<(), UpdateError> {
+    let running = version_numbers(env!("CARGO_PKG_VERSION"));
+    let interval = Duration::from_secs(config.interval);
+    loop {
+        let release = latest_release(&config.repository)?;
+        let newer = version_numbers(&release.tag) > running;
+        sender.blocking_send(Message::Update(UpdateMessage::Available(
+            newer.then_some(release),
+        )))?;
+        let checked = SystemTime::now();
+        // A resume only asks again once the interval is over, laptops
+        // suspending often would otherwise ask many times a day
+        loop {
+            let left = until_due(checked, SystemTime::now(), interval);
+            if left.is_zero() {
+                break;
+            }
+            sleep_unless_resumed(resumes, left);
+        }
+    }
+}
+
+/// Asks GitHub for the latest release every interval, nothing at all unless
+/// enabled in the config
+pub fn update_subscription(
+    rt: Handle,
+    config: UpdateConfig,
+    mut resumes: watch::Receiver<()>,
+) -> ReceiverStream<Message> {
+    let (sender, receiver) = channel(1);
+    if !config.enabled {
+        return ReceiverStream::new(receiver);
+    }
+    // Offline at startup is common, no point in asking again right away
+    let backoff = Backoff::starting_at(Duration::from_secs(600));
+    supervise_blocking(&rt, "update", backoff, sender, move |sender| {
+        update_generator(&sender, &config, &mut resumes)
+    });
+    ReceiverStream::new(receiver)
+}
+
+/// Lines of release notes the update tooltip shows at most
+const UPDATE_CHANGELOG_LINES: usize = 20;
+
+/// A dim arrow while a newer release is out, with its changelog in the
+/// tooltip and the release page a click away
+pub const WIDGET: Widget = Widget {
+    name: "update",
+    render,
+};
+
+fn render(state: &State) -> Vec<Renderable> {
+    let Some(release) = &state.update else {
+        return vec![];
+    };
+    let style = state.config.theme.style("update");
+    let mut tooltip = format!("sway-shell {} is available", release.tag);
+    let changelog: Vec<&str> = release
+        .changelog
+        .lines()
+        .take(UPDATE_CHANGELOG_LINES)
+        .collect();
+    if !changelog.is_empty() {
+        tooltip.push_str("\n\n");
+        tooltip.push_str(changelog.join("\n").trim_end());
+    }
+    vec![Renderable::Region {
+        tooltip: Some(tooltip),
+        // The URL comes from the release feed, so it is handed to
+        // xdg-open as it is and only if it is a web page
+        on_click: release
+            .url
+            .as_ref()
+            .filter(|url| url.starts_with("https://"))
+            .map(|url| Action::Exec(vec!["xdg-open".to_string(), url.clone()])),
+        on_scroll: None,
+        children: vec![Renderable::Text {
+            text: "\u{f06b0}".into(),
+            fg: style.dim,
+            bg: style.bg,
+        }],
+    }]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WEEK: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+    #[test]
+    fn waits_out_the_rest_of_the_interval() {
+        let checked = SystemTime::UNIX_EPOCH + WEEK;
+        let now = checked + Duration::from_secs(60 * 60);
+        assert_eq!(
+            until_due(checked, now, WEEK),
+            WEEK - Duration::from_secs(60 * 60)
+        );
+    }
+
+    #[test]
+    fn is_due_once_the_interval_passed() {
+        let checked = SystemTime::UNIX_EPOCH + WEEK;
+        assert_eq!(until_due(checked, checked + WEEK, WEEK), Duration::ZERO);
+        assert_eq!(until_due(checked, checked + 2 * WEEK, WEEK), Duration::ZERO);
+    }
+
+    #[test]
+    fn waits_the_whole_interval_after_the_clock_went_back() {
+        let checked = SystemTime::UNIX_EPOCH + WEEK;
+        let now = checked - Duration::from_secs(60);
+        assert_eq!(until_due(checked, now, WEEK), WEEK);
+    }
+}
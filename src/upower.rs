@@ -0,0 +1,178 @@
+use std::time::Duration;
+
+use tokio::sync::mpsc::Sender;
+use tokio_stream::StreamExt;
+use zbus::{
+    Connection, MatchRule, MessageStream, message::Type, proxy::CacheProperties,
+    zvariant::OwnedObjectPath,
+};
+
+use crate::{
+    battery::{BatteryError, BatteryMessage, PowerSupply, PowerSupplyStatus},
+    state::Message,
+};
+
+const UPOWER_SERVICE: &str = "org.freedesktop.UPower";
+
+#[zbus::proxy(
+    interface = "org.freedesktop.UPower",
+    default_service = "org.freedesktop.UPower",
+    default_path = "/org/freedesktop/UPower"
+)]
+trait UPower {
+    /// Everything but the display device, which only sums up the others
+    fn enumerate_devices(&self) -> zbus::Result<Vec<OwnedObjectPath>>;
+}
+
+#[zbus::proxy(
+    interface = "org.freedesktop.UPower.Device",
+    default_service = "org.freedesktop.UPower"
+)]
+trait Device {
+    #[zbus(property, name = "Type")]
+    fn kind(&self) -> zbus::Result<u32>;
+
+    /// Whether it powers the computer, rather than a peripheral
+    #[zbus(property)]
+    fn power_supply(&self) -> zbus::Result<bool>;
+
+    #[zbus(property)]
+    fn is_present(&self) -> zbus::Result<bool>;
+
+    #[zbus(property)]
+    fn online(&self) -> zbus::Result<bool>;
+
+    #[zbus(property)]
+    fn percentage(&self) -> zbus::Result<f64>;
+
+    #[zbus(property)]
+    fn state(&self) -> zbus::Result<u32>;
+
+    /// Seconds, 0 while unknown
+    #[zbus(property)]
+    fn time_to_empty(&self) -> zbus::Result<i64>;
+
+    #[zbus(property)]
+    fn time_to_full(&self) -> zbus::Result<i64>;
+
+    /// Watts going in or out
+    #[zbus(property)]
+    fn energy_rate(&self) -> zbus::Result<f64>;
+
+    #[zbus(property)]
+    fn model(&self) -> zbus::Result<String>;
+}
+
+/// Names of the UPower device types with a battery that aren't the
+/// computer's own
+fn peripheral_kind(kind: u32) -> &'static str {
+    match kind {
+        5 => "mouse",
+        6 => "keyboard",
+        8 => "phone",
+        10 => "tablet",
+        12 => "controller",
+        13 => "pen",
+        14 => "touchpad",
+        17 => "headset",
+        18 => "speakers",
+        19 => "headphones",
+        _ => "device",
+    }
+}
+
+fn seconds(seconds: i64) -> Option<Duration> {
+    u64::try_from(seconds)
+        .ok()
+        .filter(|seconds| *seconds > 0)
+        .map(Duration::from_secs)
+}
+
+async fn power_supply(device: &DeviceProxy<'_>) -> Result<Option<PowerSupply>, BatteryError> {
+    let kind = device.kind().await?;
+    // Line power
+    if kind == 1 {
+        return Ok(Some(PowerSupply::Mains {
+            online: device.online().await?,
+        }));
+    }
+    let capacity = device.percentage().await?.round() as usize;
+    // Batteries and UPSes running the computer
+    if matches!(kind, 2 | 3) && device.power_supply().await? {
+        if !device.is_present().await? {
+            return Ok(None);
+        }
+        let status = match device.state().await? {
+            1 => PowerSupplyStatus::Charging,
+            2 | 3 => PowerSupplyStatus::Discharging,
+            4 => PowerSupplyStatus::Full,
+            5 | 6 => PowerSupplyStatus::NotCharging,
+            _ => PowerSupplyStatus::Unknown,
+        };
+        let time_remaining = match status {
+            PowerSupplyStatus::Charging => seconds(device.time_to_full().await?),
+            PowerSupplyStatus::Discharging => seconds(device.time_to_empty().await?),
+            _ => None,
+        };
+        let power = device.energy_rate().await?;
+        return Ok(Some(PowerSupply::Battery {
+            status,
+            capacity,
+            time_remaining,
+            power: (power > 0.).then_some(power as f32),
+        }));
+    }
+    // Monitors and the like report no charge at all
+    if kind == 0 || capacity == 0 {
+        return Ok(None);
+    }
+    let model = device.model().await?;
+    Ok(Some(PowerSupply::Peripheral {
+        kind: peripheral_kind(kind).to_string(),
+        model: (!model.is_empty()).then_some(model),
+        capacity,
+    }))
+}
+
+async fn power_supplies(
+    conn: &Connection,
+    upower: &UPowerProxy<'_>,
+) -> Result<Vec<PowerSupply>, BatteryError> {
+    let mut power_supplies = Vec::new();
+    for path in upower.enumerate_devices().await? {
+        // Read once, caching would subscribe to every device's changes
+        let device = DeviceProxy::builder(conn)
+            .path(path)?
+            .cache_properties(CacheProperties::No)
+            .build()
+            .await?;
+        power_supplies.extend(power_supply(&device).await?);
+    }
+    Ok(power_supplies)
+}
+
+/// Reads every device again on whatever UPower signals, added and removed
+/// devices as well as changed properties, there are only ever a few
+pub async fn upower_generator(sender: Sender<Message>) -> Result<(), BatteryError> {
+    let conn = Connection::system().await?;
+    let upower = UPowerProxy::new(&conn).await?;
+    let rule = MatchRule::builder()
+        .msg_type(Type::Signal)
+        .sender(UPOWER_SERVICE)?
+        .build();
+    let mut signals = MessageStream::for_match_rule(rule, &conn, None).await?;
+    loop {
+        let power_supplies = power_supplies(&conn, &upower).await?;
+        sender
+            .send(Message::Battery(BatteryMessage::UpdatePowerSupplies(
+                power_supplies,
+            )))
+            .await?;
+        match signals.next().await {
+            Some(signal) => {
+                signal?;
+            }
+            None => return Ok(()),
+        }
+    }
+}
@@ -0,0 +1,127 @@
+use std::{collections::BTreeMap, fs, io::ErrorKind, path::Path, time::Duration};
+
+use inotify::{Inotify, WatchMask};
+use tokio::{
+    runtime::Handle,
+    sync::mpsc::{Sender, channel, error::SendError},
+};
+use tokio_stream::wrappers::ReceiverStream;
+
+use crate::{
+    config::WatchConfig,
+    custom::{CustomOutput, fill_template},
+    state::Message,
+    supervisor::{Backoff, supervise_blocking},
+};
+
+#[derive(Debug)]
+pub enum WatchError {
+    StdIoError(std::io::Error),
+    /// The path has no parent directory to watch, like /
+    NoDirectory(String),
+    SendError(SendError<Message>),
+}
+
+impl std::fmt::Display for WatchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WatchError::StdIoError(e) => write!(f, "IO Error: {e}"),
+            WatchError::NoDirectory(path) => write!(f, "{path} has no directory to watch"),
+            WatchError::SendError(e) => write!(f, "Channel Error: {e}"),
+        }
+    }
+}
+
+impl From<std::io::Error> for WatchError {
+    fn from(value: std::io::Error) -> Self {
+        Self::StdIoError(value)
+    }
+}
+
+impl From<SendError<Message>> for WatchError {
+    fn from(value: SendError<Message>) -> Self {
+        Self::SendError(value)
+    }
+}
+
+#[derive(Debug)]
+pub enum WatchMessage {
+    /// New contents of the file of the module with this name in the config
+    Output { name: String, output: CustomOutput },
+}
+
+/// Fills in {contents} with the whole file and {1}, {2} and so on with its
+/// lines, trimmed. Lines past the end of the file are left empty
+fn fill_contents(template: &str, contents: &str) -> String {
+    let lines = contents.lines().collect::<Vec<_>>();
+    fill_template(template, |key| match key {
+        "contents" => Some(contents.trim().to_string()),
+        _ => {
+            let line = key.parse::<usize>().ok().filter(|&line| line > 0)?;
+            Some(lines.get(line - 1).map_or("", |line| line.trim()).to_string())
+        }
+    })
+}
+
+/// A missing file is shown as nothing, it may well be written later
+fn read_output(path: &Path, config: &WatchConfig) -> Result<CustomOutput, WatchError> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(CustomOutput::default()),
+        Err(e) => return Err(e.into()),
+    };
+    Ok(CustomOutput {
+        text: fill_contents(&config.format, &contents),
+        tooltip: config
+            .tooltip
+            .as_ref()
+            .map(|tooltip| fill_contents(tooltip, &contents))
+            .filter(|tooltip| !tooltip.is_empty()),
+        class: None,
+    })
+}
+
+fn watch_generator(sender: &Sender<Message>, name: &str, config: &WatchConfig) -> Result<(), WatchError> {
+    let path = Path::new(&config.path);
+    let (Some(directory), Some(file_name)) = (path.parent(), path.file_name()) else {
+        return Err(WatchError::NoDirectory(config.path.clone()));
+    };
+    // The directory is watched rather than the file, as scripts and editors
+    // tend to write a new file and rename it over the old one
+    let mut inotify = Inotify::init()?;
+    inotify.watches().add(
+        directory,
+        WatchMask::CLOSE_WRITE | WatchMask::MOVED_TO | WatchMask::CREATE | WatchMask::DELETE | WatchMask::MOVED_FROM,
+    )?;
+    let mut buffer = [0; 4096];
+    loop {
+        sender.blocking_send(Message::Watch(WatchMessage::Output {
+            name: name.to_string(),
+            output: read_output(path, config)?,
+        }))?;
+        loop {
+            let mut events = inotify.read_events_blocking(&mut buffer)?;
+            if events.any(|event| event.name == Some(file_name)) {
+                break;
+            }
+        }
+    }
+}
+
+/// Failing watches are set up again after this
+const RESTART_DELAY: Duration = Duration::from_secs(5);
+
+/// Watches the file of every watch module on a thread of its own
+pub fn watch_subscription(rt: Handle, config: BTreeMap<String, WatchConfig>) -> ReceiverStream<Message> {
+    let (sender, receiver) = channel(1);
+    for (name, config) in config {
+        supervise_blocking(
+            &rt,
+            format!("watch/{name}"),
+            Backoff::starting_at(RESTART_DELAY),
+            sender.clone(),
+            move |sender| watch_generator(&sender, &name, &config),
+        );
+    }
+    ReceiverStream::new(receiver)
+}
@@ -0,0 +1,66 @@
+use crate::{
+    audio, backlight, battery, camera, clock, compositor, cpu, docker, hosts, kdeconnect, libvirt,
+    memory, mic, mpd, network, night_light, nvme, palette, privacy, renderer::Renderable,
+    state::State, supervisor, systemd, taskbar, thermal, timer, update,
+};
+
+/// A built in module, drawn from the state by the file of its subscription
+#[derive(Debug, Clone, Copy)]
+pub struct Widget {
+    /// Its name in the layout
+    pub name: &'static str,
+    pub render: fn(&State) -> Vec<Renderable>,
+}
+
+/// Every built in module, for the layout and the palette toggles
+pub static WIDGETS: [Widget; 28] = [
+    compositor::WORKSPACES,
+    mpd::WIDGET,
+    compositor::WINDOW,
+    compositor::MODE,
+    taskbar::WIDGET,
+    camera::WIDGET,
+    privacy::WIDGET,
+    network::WIDGET,
+    audio::WIDGET,
+    mic::WIDGET,
+    audio::BLUETOOTH,
+    backlight::WIDGET,
+    battery::WIDGET,
+    thermal::WIDGET,
+    cpu::GOVERNOR,
+    memory::WIDGET,
+    nvme::WIDGET,
+    docker::WIDGET,
+    hosts::WIDGET,
+    kdeconnect::WIDGET,
+    libvirt::WIDGET,
+    systemd::WIDGET,
+    clock::WIDGET,
+    update::WIDGET,
+    supervisor::STATUS,
+    palette::POWER,
+    timer::WIDGET,
+    night_light::WIDGET,
+];
+
+/// The built in module with the name, None for the other names
+pub fn find(name: &str) -> Option<&'static Widget> {
+    WIDGETS.iter().find(|widget| widget.name == name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_are_unique() {
+        for (i, widget) in WIDGETS.iter().enumerate() {
+            assert!(
+                WIDGETS[..i].iter().all(|other| other.name != widget.name),
+                "{} is registered twice",
+                widget.name
+            );
+        }
+    }
+}
@@ -0,0 +1,793 @@
+use std::{
+    collections::BTreeMap,
+    sync::{LazyLock, Mutex},
+};
+
+use swayipc::Rect;
+use tokio::{
+    runtime::Handle,
+    sync::{
+        mpsc::{Sender, channel, error::SendError},
+        watch,
+    },
+};
+use tokio_stream::wrappers::ReceiverStream;
+use wayland_client::{
+    ConnectError, Connection, Dispatch, DispatchError, Proxy, QueueHandle, WEnum, delegate_noop,
+    event_created_child,
+    globals::{GlobalError, GlobalListContents, registry_queue_init},
+    protocol::{
+        wl_output::{self, WlOutput},
+        wl_registry::{self, WlRegistry},
+        wl_seat::WlSeat,
+    },
+};
+use wayland_protocols::ext::workspace::v1::client::{
+    ext_workspace_group_handle_v1::{self, ExtWorkspaceGroupHandleV1},
+    ext_workspace_handle_v1::{self, ExtWorkspaceHandleV1},
+    ext_workspace_manager_v1::{self, ExtWorkspaceManagerV1},
+};
+use wayland_protocols_wlr::foreign_toplevel::v1::client::{
+    zwlr_foreign_toplevel_handle_v1::{self, ZwlrForeignToplevelHandleV1},
+    zwlr_foreign_toplevel_manager_v1::{self, ZwlrForeignToplevelManagerV1},
+};
+
+use crate::{
+    compositor::{Compositor, SentWorkspaces, unquote, workspace_num},
+    error::ShellError,
+    state::Message,
+    supervisor::{Backoff, supervise_blocking},
+    sway::{SwayMessage, Window, Workspace},
+    taskbar::{TaskbarMessage, TaskbarWindow},
+};
+
+/// What run_command sends its requests on, set while the workspaces
+/// subscription is connected
+static HANDLES: Mutex<Option<Handles>> = Mutex::new(None);
+
+/// The windows the workspaces subscription last saw, which the taskbar
+/// follows so its ids are of the connection run_command sends on
+static TASKBAR: LazyLock<watch::Sender<Option<Vec<TaskbarWindow>>>> =
+    LazyLock::new(|| watch::Sender::new(None));
+
+#[derive(Debug)]
+pub enum WlrError {
+    ConnectError(ConnectError),
+    GlobalError(GlobalError),
+    DispatchError(DispatchError),
+    SendError(SendError<Message>),
+    /// The compositor has neither ext-workspace nor wlr-foreign-toplevel
+    Unsupported,
+}
+
+impl std::fmt::Display for WlrError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WlrError::ConnectError(e) => write!(f, "Could not connect to the compositor: {e}"),
+            WlrError::GlobalError(e) => write!(f, "Could not list the globals: {e}"),
+            WlrError::DispatchError(e) => write!(f, "Wayland dispatch error: {e}"),
+            WlrError::SendError(e) => write!(f, "Channel Error: {e}"),
+            WlrError::Unsupported => write!(
+                f,
+                "The compositor has neither ext-workspace nor wlr-foreign-toplevel"
+            ),
+        }
+    }
+}
+
+impl From<ConnectError> for WlrError {
+    fn from(value: ConnectError) -> Self {
+        Self::ConnectError(value)
+    }
+}
+
+impl From<GlobalError> for WlrError {
+    fn from(value: GlobalError) -> Self {
+        Self::GlobalError(value)
+    }
+}
+
+impl From<DispatchError> for WlrError {
+    fn from(value: DispatchError) -> Self {
+        Self::DispatchError(value)
+    }
+}
+
+impl From<SendError<Message>> for WlrError {
+    fn from(value: SendError<Message>) -> Self {
+        Self::SendError(value)
+    }
+}
+
+/// Workspaces from ext-workspace and windows from wlr-foreign-toplevel, for
+/// compositors without the i3 IPC like River and niri. Neither protocol says
+/// which workspace a window is on, so workspaces list no windows and show
+/// no app glyphs or window counts, and the taskbar has the windows of every
+/// workspace. Bindings reach the bar through its IPC socket instead of the
+/// compositor
+#[derive(Debug)]
+pub struct Wlr;
+
+/// Whether to go by the Wayland protocols, in a Wayland session without a
+/// sway IPC socket to connect to
+pub fn selected() -> bool {
+    std::env::var_os("WAYLAND_DISPLAY").is_some() && swayipc::Connection::new().is_err()
+}
+
+#[derive(Debug)]
+struct Output {
+    proxy: WlOutput,
+    name: String,
+    x: i32,
+    y: i32,
+    width: i32,
+    height: i32,
+}
+
+impl Output {
+    fn rect(&self) -> Rect {
+        Rect {
+            x: self.x,
+            y: self.y,
+            width: self.width,
+            height: self.height,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Group {
+    outputs: Vec<WlOutput>,
+    workspaces: Vec<ExtWorkspaceHandleV1>,
+}
+
+#[derive(Debug)]
+struct WorkspaceState {
+    handle: ExtWorkspaceHandleV1,
+    name: String,
+    state: ext_workspace_handle_v1::State,
+}
+
+#[derive(Debug)]
+struct Toplevel {
+    handle: ZwlrForeignToplevelHandleV1,
+    title: String,
+    app_id: String,
+    outputs: Vec<WlOutput>,
+    activated: bool,
+}
+
+/// What run_command needs, taken from the protocol state after each change
+struct Handles {
+    conn: Connection,
+    seat: Option<WlSeat>,
+    manager: Option<ExtWorkspaceManagerV1>,
+    workspaces: Vec<(String, ExtWorkspaceHandleV1)>,
+    toplevels: BTreeMap<i64, ZwlrForeignToplevelHandleV1>,
+}
+
+impl Handles {
+    /// Runs the sway commands the bar builds that the protocols can do,
+    /// returning whether it could
+    fn run(&self, command: &str) -> bool {
+        if let Some(num) = command.strip_prefix("workspace number ") {
+            let num = num.trim().parse().ok();
+            return self.activate(|name| Some(workspace_num(name)) == num);
+        }
+        if let Some(name) = command.strip_prefix("workspace ") {
+            let wanted = unquote(name);
+            return self.activate(|name| name == wanted);
+        }
+        let Some((criteria, command)) = command
+            .strip_prefix("[con_id=")
+            .and_then(|command| command.split_once(']'))
+        else {
+            return false;
+        };
+        let Some(toplevel) = criteria
+            .parse()
+            .ok()
+            .and_then(|id: i64| self.toplevels.get(&id))
+        else {
+            return false;
+        };
+        match (command.trim(), &self.seat) {
+            ("focus", Some(seat)) => toplevel.activate(seat),
+            ("kill", _) => toplevel.close(),
+            _ => return false,
+        }
+        true
+    }
+
+    fn activate(&self, wanted: impl Fn(&str) -> bool) -> bool {
+        let (Some(manager), Some((_, workspace))) = (
+            &self.manager,
+            self.workspaces.iter().find(|(name, _)| wanted(name)),
+        ) else {
+            return false;
+        };
+        workspace.activate();
+        manager.commit();
+        true
+    }
+}
+
+/// Everything the compositor told about the workspaces and windows so far
+#[derive(Debug)]
+struct Protocols {
+    outputs: BTreeMap<u32, Output>,
+    groups: BTreeMap<i64, Group>,
+    workspaces: BTreeMap<i64, WorkspaceState>,
+    toplevels: BTreeMap<i64, Toplevel>,
+    /// Set by the done events, sending everything that changed again
+    workspaces_changed: bool,
+    toplevels_changed: bool,
+    sent: SentWorkspaces,
+}
+
+/// Object ids fit the con_id commands pick windows by, and stay the same
+/// for as long as the object is there
+fn id(proxy: &impl Proxy) -> i64 {
+    proxy.id().protocol_id() as i64
+}
+
+impl Protocols {
+    fn bind_output(
+        &mut self,
+        registry: &WlRegistry,
+        name: u32,
+        version: u32,
+        qh: &QueueHandle<Self>,
+    ) {
+        // Version 4 brings the name
+        let proxy = registry.bind::<WlOutput, _, _>(name, version.min(4), qh, name);
+        self.outputs.insert(
+            name,
+            Output {
+                proxy,
+                name: String::new(),
+                x: 0,
+                y: 0,
+                width: 0,
+                height: 0,
+            },
+        );
+    }
+
+    /// The output of the focused window, which the focused workspace is on
+    fn focused_output(&self) -> Option<&WlOutput> {
+        self.toplevels
+            .values()
+            .find(|toplevel| toplevel.activated)
+            .and_then(|toplevel| toplevel.outputs.first())
+    }
+
+    fn snapshot(&self) -> BTreeMap<i64, Workspace> {
+        let focused_output = self.focused_output();
+        let mut workspaces = BTreeMap::new();
+        for group in self.groups.values() {
+            let output = group
+                .outputs
+                .first()
+                .and_then(|output| self.outputs.values().find(|known| &known.proxy == output));
+            for handle in &group.workspaces {
+                let Some(workspace) = self.workspaces.get(&id(handle)) else {
+                    continue;
+                };
+                if workspace
+                    .state
+                    .contains(ext_workspace_handle_v1::State::Hidden)
+                {
+                    continue;
+                }
+                let active = workspace
+                    .state
+                    .contains(ext_workspace_handle_v1::State::Active);
+                // Without a focused window the first active workspace is
+                // taken to be focused
+                let focused = active
+                    && match focused_output {
+                        Some(focused) => group.outputs.contains(focused),
+                        None => !workspaces.values().any(|other: &Workspace| other.focused),
+                    };
+                workspaces.insert(
+                    id(handle),
+                    Workspace {
+                        id: id(handle),
+                        num: workspace_num(&workspace.name),
+                        name: Some(workspace.name.clone()),
+                        layout: "none".to_string(),
+                        visible: active,
+                        focused,
+                        urgent: workspace
+                            .state
+                            .contains(ext_workspace_handle_v1::State::Urgent),
+                        representation: None,
+                        orientation: "none".to_string(),
+                        rect: output.map_or(
+                            Rect {
+                                x: 0,
+                                y: 0,
+                                width: 0,
+                                height: 0,
+                            },
+                            Output::rect,
+                        ),
+                        output: output.map(|output| output.name.clone()).unwrap_or_default(),
+                        focus: Vec::new(),
+                    },
+                );
+            }
+        }
+        workspaces
+    }
+
+    fn window_name(&self) -> Option<String> {
+        self.toplevels
+            .values()
+            .find(|toplevel| toplevel.activated)
+            .map(|toplevel| toplevel.title.clone())
+    }
+
+    fn windows(&self) -> Vec<Window> {
+        self.toplevels
+            .iter()
+            .map(|(id, toplevel)| Window {
+                id: *id,
+                name: Some(toplevel.title.clone()),
+                app_id: Some(toplevel.app_id.clone()),
+                workspace: String::new(),
+            })
+            .collect()
+    }
+
+    fn taskbar(&self) -> Vec<TaskbarWindow> {
+        self.toplevels
+            .iter()
+            .map(|(id, toplevel)| TaskbarWindow {
+                id: *id,
+                title: toplevel.title.clone(),
+                app_id: Some(toplevel.app_id.clone()),
+                focused: toplevel.activated,
+                urgent: false,
+            })
+            .collect()
+    }
+
+    /// Sends what changed since the last done events
+    fn send(&mut self, sender: &Sender<Message>) -> Result<(), SendError<Message>> {
+        if !self.workspaces_changed && !self.toplevels_changed {
+            return Ok(());
+        }
+        if self.toplevels_changed {
+            sender.blocking_send(Message::Sway(SwayMessage::WindowList(self.windows())))?;
+            TASKBAR.send_replace(Some(self.taskbar()));
+        }
+        let workspaces = self.snapshot();
+        let window_name = self.window_name();
+        self.sent.sync(workspaces, window_name, None, sender)?;
+        self.workspaces_changed = false;
+        self.toplevels_changed = false;
+        Ok(())
+    }
+
+    fn handles(
+        &self,
+        conn: &Connection,
+        seat: Option<&WlSeat>,
+        manager: Option<&ExtWorkspaceManagerV1>,
+    ) -> Handles {
+        Handles {
+            conn: conn.clone(),
+            seat: seat.cloned(),
+            manager: manager.cloned(),
+            workspaces: self
+                .workspaces
+                .values()
+                .map(|workspace| (workspace.name.clone(), workspace.handle.clone()))
+                .collect(),
+            toplevels: self
+                .toplevels
+                .iter()
+                .map(|(id, toplevel)| (*id, toplevel.handle.clone()))
+                .collect(),
+        }
+    }
+}
+
+impl Dispatch<WlRegistry, GlobalListContents> for Protocols {
+    fn event(
+        state: &mut Self,
+        registry: &WlRegistry,
+        event: wl_registry::Event,
+        _: &GlobalListContents,
+        _: &Connection,
+        qh: &QueueHandle<Self>,
+    ) {
+        match event {
+            wl_registry::Event::Global {
+                name,
+                interface,
+                version,
+            } if interface == WlOutput::interface().name => {
+                state.bind_output(registry, name, version, qh);
+            }
+            wl_registry::Event::GlobalRemove { name } => {
+                if state.outputs.remove(&name).is_some() {
+                    state.workspaces_changed = true;
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<WlOutput, u32> for Protocols {
+    fn event(
+        state: &mut Self,
+        _: &WlOutput,
+        event: wl_output::Event,
+        name: &u32,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        let Some(output) = state.outputs.get_mut(name) else {
+            return;
+        };
+        match event {
+            wl_output::Event::Geometry { x, y, .. } => {
+                output.x = x;
+                output.y = y;
+            }
+            wl_output::Event::Mode {
+                flags: WEnum::Value(flags),
+                width,
+                height,
+                ..
+            } if flags.contains(wl_output::Mode::Current) => {
+                output.width = width;
+                output.height = height;
+            }
+            wl_output::Event::Name { name } => output.name = name,
+            wl_output::Event::Done => state.workspaces_changed = true,
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<ExtWorkspaceManagerV1, ()> for Protocols {
+    fn event(
+        state: &mut Self,
+        _: &ExtWorkspaceManagerV1,
+        event: ext_workspace_manager_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        match event {
+            ext_workspace_manager_v1::Event::WorkspaceGroup { workspace_group } => {
+                state.groups.insert(
+                    id(&workspace_group),
+                    Group {
+                        outputs: Vec::new(),
+                        workspaces: Vec::new(),
+                    },
+                );
+            }
+            ext_workspace_manager_v1::Event::Workspace { workspace } => {
+                state.workspaces.insert(
+                    id(&workspace),
+                    WorkspaceState {
+                        handle: workspace,
+                        name: String::new(),
+                        state: ext_workspace_handle_v1::State::empty(),
+                    },
+                );
+            }
+            ext_workspace_manager_v1::Event::Done => state.workspaces_changed = true,
+            _ => {}
+        }
+    }
+
+    event_created_child!(Protocols, ExtWorkspaceManagerV1, [
+        ext_workspace_manager_v1::EVT_WORKSPACE_GROUP_OPCODE => (ExtWorkspaceGroupHandleV1, ()),
+        ext_workspace_manager_v1::EVT_WORKSPACE_OPCODE => (ExtWorkspaceHandleV1, ()),
+    ]);
+}
+
+impl Dispatch<ExtWorkspaceGroupHandleV1, ()> for Protocols {
+    fn event(
+        state: &mut Self,
+        handle: &ExtWorkspaceGroupHandleV1,
+        event: ext_workspace_group_handle_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        if let ext_workspace_group_handle_v1::Event::Removed = event {
+            state.groups.remove(&id(handle));
+            handle.destroy();
+            return;
+        }
+        let Some(group) = state.groups.get_mut(&id(handle)) else {
+            return;
+        };
+        match event {
+            ext_workspace_group_handle_v1::Event::OutputEnter { output } => {
+                group.outputs.push(output)
+            }
+            ext_workspace_group_handle_v1::Event::OutputLeave { output } => {
+                group.outputs.retain(|entered| *entered != output)
+            }
+            ext_workspace_group_handle_v1::Event::WorkspaceEnter { workspace } => {
+                group.workspaces.push(workspace)
+            }
+            ext_workspace_group_handle_v1::Event::WorkspaceLeave { workspace } => {
+                group.workspaces.retain(|entered| *entered != workspace)
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<ExtWorkspaceHandleV1, ()> for Protocols {
+    fn event(
+        state: &mut Self,
+        handle: &ExtWorkspaceHandleV1,
+        event: ext_workspace_handle_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        if let ext_workspace_handle_v1::Event::Removed = event {
+            state.workspaces.remove(&id(handle));
+            handle.destroy();
+            return;
+        }
+        let Some(workspace) = state.workspaces.get_mut(&id(handle)) else {
+            return;
+        };
+        match event {
+            ext_workspace_handle_v1::Event::Name { name } => workspace.name = name,
+            ext_workspace_handle_v1::Event::State {
+                state: WEnum::Value(workspace_state),
+            } => workspace.state = workspace_state,
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<ZwlrForeignToplevelManagerV1, ()> for Protocols {
+    fn event(
+        state: &mut Self,
+        _: &ZwlrForeignToplevelManagerV1,
+        event: zwlr_foreign_toplevel_manager_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        if let zwlr_foreign_toplevel_manager_v1::Event::Toplevel { toplevel } = event {
+            state.toplevels.insert(
+                id(&toplevel),
+                Toplevel {
+                    handle: toplevel,
+                    title: String::new(),
+                    app_id: String::new(),
+                    outputs: Vec::new(),
+                    activated: false,
+                },
+            );
+        }
+    }
+
+    event_created_child!(Protocols, ZwlrForeignToplevelManagerV1, [
+        zwlr_foreign_toplevel_manager_v1::EVT_TOPLEVEL_OPCODE => (ZwlrForeignToplevelHandleV1, ()),
+    ]);
+}
+
+impl Dispatch<ZwlrForeignToplevelHandleV1, ()> for Protocols {
+    fn event(
+        state: &mut Self,
+        handle: &ZwlrForeignToplevelHandleV1,
+        event: zwlr_foreign_toplevel_handle_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        if let zwlr_foreign_toplevel_handle_v1::Event::Closed = event {
+            state.toplevels.remove(&id(handle));
+            state.toplevels_changed = true;
+            handle.destroy();
+            return;
+        }
+        let Some(toplevel) = state.toplevels.get_mut(&id(handle)) else {
+            return;
+        };
+        match event {
+            zwlr_foreign_toplevel_handle_v1::Event::Title { title } => toplevel.title = title,
+            zwlr_foreign_toplevel_handle_v1::Event::AppId { app_id } => toplevel.app_id = app_id,
+            zwlr_foreign_toplevel_handle_v1::Event::OutputEnter { output } => {
+                toplevel.outputs.push(output)
+            }
+            zwlr_foreign_toplevel_handle_v1::Event::OutputLeave { output } => {
+                toplevel.outputs.retain(|entered| *entered != output)
+            }
+            // An array of the states, each a u32
+            zwlr_foreign_toplevel_handle_v1::Event::State { state } => {
+                toplevel.activated = state.chunks_exact(4).any(|chunk| {
+                    u32::from_ne_bytes([chunk[0], chunk[1], chunk[2], chunk[3]])
+                        == zwlr_foreign_toplevel_handle_v1::State::Activated as u32
+                });
+            }
+            // The focused window decides the focused workspace
+            zwlr_foreign_toplevel_handle_v1::Event::Done => {
+                state.toplevels_changed = true;
+                state.workspaces_changed = true;
+            }
+            _ => {}
+        }
+    }
+}
+
+delegate_noop!(Protocols: ignore WlSeat);
+
+fn wlr_generator(sender: Sender<Message>) -> Result<(), WlrError> {
+    let conn = Connection::connect_to_env()?;
+    let (globals, mut event_queue) = registry_queue_init::<Protocols>(&conn)?;
+    let qh = event_queue.handle();
+    let mut protocols = Protocols {
+        outputs: BTreeMap::new(),
+        groups: BTreeMap::new(),
+        workspaces: BTreeMap::new(),
+        toplevels: BTreeMap::new(),
+        workspaces_changed: true,
+        toplevels_changed: true,
+        sent: SentWorkspaces::default(),
+    };
+    let manager = globals
+        .bind::<ExtWorkspaceManagerV1, _, _>(&qh, 1..=1, ())
+        .ok();
+    let toplevel_manager = globals
+        .bind::<ZwlrForeignToplevelManagerV1, _, _>(&qh, 1..=3, ())
+        .ok();
+    if manager.is_none() && toplevel_manager.is_none() {
+        return Err(WlrError::Unsupported);
+    }
+    let seat = globals.bind::<WlSeat, _, _>(&qh, 1..=1, ()).ok();
+    globals.contents().with_list(|list| {
+        for global in list {
+            if global.interface == WlOutput::interface().name {
+                protocols.bind_output(globals.registry(), global.name, global.version, &qh);
+            }
+        }
+    });
+    event_queue.roundtrip(&mut protocols)?;
+    loop {
+        protocols.send(&sender)?;
+        *HANDLES.lock().expect("The handles to not be poisoned") =
+            Some(protocols.handles(&conn, seat.as_ref(), manager.as_ref()));
+        event_queue.blocking_dispatch(&mut protocols)?;
+    }
+}
+
+/// Follows the windows of the workspaces subscription, which runs alongside
+fn wlr_taskbar_generator(sender: Sender<Message>) -> Result<(), WlrError> {
+    let mut taskbars = TASKBAR.subscribe();
+    let mut windows = None;
+    loop {
+        let current = taskbars.borrow_and_update().clone();
+        if let Some(current) = current
+            && windows.as_ref() != Some(&current)
+        {
+            sender.blocking_send(Message::Taskbar(TaskbarMessage::Windows(current.clone())))?;
+            windows = Some(current);
+        }
+        // The sender is static, so this only returns once there is more
+        let _ = Handle::current().block_on(taskbars.changed());
+    }
+}
+
+impl Compositor for Wlr {
+    fn name(&self) -> &'static str {
+        "the Wayland compositor"
+    }
+
+    fn connect(&self) -> Result<(), ShellError> {
+        Connection::connect_to_env()
+            .map(|_| ())
+            .map_err(|e| WlrError::from(e).into())
+    }
+
+    fn subscription(&self, rt: Handle) -> ReceiverStream<Message> {
+        let (sender, receiver) = channel(1);
+        supervise_blocking(&rt, "wlr", Backoff::DEFAULT, sender, wlr_generator);
+        ReceiverStream::new(receiver)
+    }
+
+    fn taskbar_subscription(&self, rt: Handle) -> ReceiverStream<Message> {
+        let (sender, receiver) = channel(1);
+        supervise_blocking(
+            &rt,
+            "taskbar",
+            Backoff::DEFAULT,
+            sender,
+            wlr_taskbar_generator,
+        );
+        ReceiverStream::new(receiver)
+    }
+
+    fn run_command(&self, command: String) {
+        let handles = HANDLES.lock().expect("The handles to not be poisoned");
+        let Some(handles) = handles.as_ref() else {
+            log::error!("Not connected to the compositor, could not run {command:?}");
+            return;
+        };
+        if !handles.run(&command) {
+            log::warn!("{command:?} can't be run over the Wayland protocols");
+            return;
+        }
+        if let Err(e) = handles.conn.flush() {
+            log::error!("Could not flush the Wayland connection: {e}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::net::UnixStream;
+
+    use super::*;
+
+    /// Handles on a connection with nothing on the other end, the requests
+    /// of their inert proxies go nowhere
+    fn handles(workspaces: &[&str], toplevels: &[i64], seat: bool) -> Handles {
+        let (socket, _) = UnixStream::pair().unwrap();
+        let conn = Connection::from_socket(socket).unwrap();
+        let backend = conn.backend().downgrade();
+        Handles {
+            seat: seat.then(|| WlSeat::inert(backend.clone())),
+            manager: Some(ExtWorkspaceManagerV1::inert(backend.clone())),
+            workspaces: workspaces
+                .iter()
+                .map(|name| {
+                    (
+                        name.to_string(),
+                        ExtWorkspaceHandleV1::inert(backend.clone()),
+                    )
+                })
+                .collect(),
+            toplevels: toplevels
+                .iter()
+                .map(|id| (*id, ZwlrForeignToplevelHandleV1::inert(backend.clone())))
+                .collect(),
+            conn,
+        }
+    }
+
+    #[test]
+    fn runs_workspace_commands() {
+        let mut handles = handles(&["1: web", "2: code", "chat"], &[], false);
+        assert!(handles.run("workspace number 2"));
+        assert!(handles.run("workspace \"1: web\""));
+        assert!(handles.run("workspace chat"));
+        assert!(!handles.run("workspace number 3"));
+        assert!(!handles.run("workspace music"));
+        // Workspaces can't be switched without ext-workspace
+        handles.manager = None;
+        assert!(!handles.run("workspace number 2"));
+    }
+
+    #[test]
+    fn runs_window_commands() {
+        let with_seat = handles(&[], &[5], true);
+        assert!(with_seat.run("[con_id=5] focus"));
+        assert!(with_seat.run("[con_id=5] kill"));
+        assert!(!with_seat.run("[con_id=6] focus"));
+        assert!(!with_seat.run("[con_id=5] floating toggle"));
+        assert!(!with_seat.run("exec foot"));
+        // Activating takes a seat, closing doesn't
+        let without_seat = handles(&[], &[5], false);
+        assert!(!without_seat.run("[con_id=5] focus"));
+        assert!(without_seat.run("[con_id=5] kill"));
+    }
+}
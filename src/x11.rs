@@ -0,0 +1,299 @@
+use std::{
+    num::NonZeroU32,
+    ptr::NonNull,
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+use raw_window_handle::{RawDisplayHandle, RawWindowHandle, XcbDisplayHandle, XcbWindowHandle};
+use tokio::sync::mpsc::Sender;
+use x11rb::{
+    COPY_DEPTH_FROM_PARENT,
+    connection::Connection,
+    errors::{ConnectError, ConnectionError, ReplyError, ReplyOrIdError},
+    protocol::{
+        Event,
+        xproto::{AtomEnum, ConnectionExt as _, CreateWindowAux, EventMask, PropMode, WindowClass},
+    },
+    wrapper::ConnectionExt as _,
+    xcb_ffi::XCBConnection,
+};
+
+use crate::{
+    config::BarPosition,
+    font::Vec2,
+    layer::DisplayMessage,
+    renderer::RenderCommand,
+    state::{BTN_LEFT, BTN_MIDDLE, BTN_RIGHT, Message},
+};
+
+/// Surface pixels a wheel notch scrolls, as much as on Wayland
+const NOTCH: f32 = 15.;
+
+#[derive(Debug)]
+pub enum X11Error {
+    ConnectError(ConnectError),
+    ConnectionError(ConnectionError),
+    ReplyError(ReplyError),
+    ReplyOrIdError(ReplyOrIdError),
+}
+
+impl From<ConnectError> for X11Error {
+    fn from(value: ConnectError) -> Self {
+        Self::ConnectError(value)
+    }
+}
+
+impl From<ConnectionError> for X11Error {
+    fn from(value: ConnectionError) -> Self {
+        Self::ConnectionError(value)
+    }
+}
+
+impl From<ReplyError> for X11Error {
+    fn from(value: ReplyError) -> Self {
+        Self::ReplyError(value)
+    }
+}
+
+impl From<ReplyOrIdError> for X11Error {
+    fn from(value: ReplyOrIdError) -> Self {
+        Self::ReplyOrIdError(value)
+    }
+}
+
+/// Whether to show the bar on X11, which is only done without a Wayland
+/// compositor to run in, like in an i3 session
+pub fn selected() -> bool {
+    std::env::var_os("WAYLAND_DISPLAY").is_none() && std::env::var_os("DISPLAY").is_some()
+}
+
+/// A dock window along an edge of the first screen, which window managers
+/// keep windows away from by its strut
+#[derive(Debug)]
+pub struct X11Window {
+    conn: XCBConnection,
+    screen: usize,
+    window: u32,
+    position: BarPosition,
+    /// Along the bar, as the window was last configured
+    width: AtomicU32,
+    height: u32,
+}
+
+impl X11Window {
+    pub fn new(height: u32, position: BarPosition) -> Result<Self, X11Error> {
+        let (conn, screen_num) = XCBConnection::connect(None)?;
+        let screen = &conn.setup().roots[screen_num];
+        let (screen_width, screen_height) = (
+            screen.width_in_pixels as u32,
+            screen.height_in_pixels as u32,
+        );
+        let (width, _) = position.along((screen_width, screen_height));
+        let (x, y) = match position {
+            BarPosition::Top | BarPosition::Left => (0, 0),
+            BarPosition::Right => (screen_width - height, 0),
+        };
+        let (window_width, window_height) = position.along((width, height));
+        let window = conn.generate_id()?;
+        conn.create_window(
+            COPY_DEPTH_FROM_PARENT,
+            window,
+            screen.root,
+            x as i16,
+            y as i16,
+            window_width as u16,
+            window_height as u16,
+            0,
+            WindowClass::INPUT_OUTPUT,
+            screen.root_visual,
+            &CreateWindowAux::new().event_mask(
+                EventMask::EXPOSURE
+                    | EventMask::STRUCTURE_NOTIFY
+                    | EventMask::BUTTON_PRESS
+                    | EventMask::BUTTON_RELEASE
+                    | EventMask::POINTER_MOTION
+                    | EventMask::ENTER_WINDOW
+                    | EventMask::LEAVE_WINDOW,
+            ),
+        )?;
+        let atom = |name: &str| -> Result<u32, X11Error> {
+            Ok(conn.intern_atom(false, name.as_bytes())?.reply()?.atom)
+        };
+        conn.change_property32(
+            PropMode::REPLACE,
+            window,
+            atom("_NET_WM_WINDOW_TYPE")?,
+            AtomEnum::ATOM,
+            &[atom("_NET_WM_WINDOW_TYPE_DOCK")?],
+        )?;
+        conn.change_property32(
+            PropMode::REPLACE,
+            window,
+            atom("_NET_WM_STATE")?,
+            AtomEnum::ATOM,
+            &[atom("_NET_WM_STATE_STICKY")?, atom("_NET_WM_STATE_ABOVE")?],
+        )?;
+        // Left, right, top and bottom, then where along those edges
+        let strut: [u32; 12] = match position {
+            BarPosition::Top => [0, 0, height, 0, 0, 0, 0, 0, 0, width - 1, 0, 0],
+            BarPosition::Left => [height, 0, 0, 0, 0, width - 1, 0, 0, 0, 0, 0, 0],
+            BarPosition::Right => [0, height, 0, 0, 0, 0, 0, width - 1, 0, 0, 0, 0],
+        };
+        conn.change_property32(
+            PropMode::REPLACE,
+            window,
+            atom("_NET_WM_STRUT_PARTIAL")?,
+            AtomEnum::CARDINAL,
+            &strut,
+        )?;
+        // For window managers from before the partial one
+        conn.change_property32(
+            PropMode::REPLACE,
+            window,
+            atom("_NET_WM_STRUT")?,
+            AtomEnum::CARDINAL,
+            &strut[..4],
+        )?;
+        conn.change_property8(
+            PropMode::REPLACE,
+            window,
+            AtomEnum::WM_NAME,
+            AtomEnum::STRING,
+            b"sway-shell",
+        )?;
+        conn.change_property8(
+            PropMode::REPLACE,
+            window,
+            AtomEnum::WM_CLASS,
+            AtomEnum::STRING,
+            b"sway-shell\0sway-shell\0",
+        )?;
+        conn.map_window(window)?;
+        conn.flush()?;
+        Ok(Self {
+            conn,
+            screen: screen_num,
+            window,
+            position,
+            width: AtomicU32::new(width),
+            height,
+        })
+    }
+
+    /// For the wgpu surface, which keeps pointers into the connection
+    pub fn raw_handles(&self) -> (RawDisplayHandle, RawWindowHandle) {
+        (
+            RawDisplayHandle::Xcb(XcbDisplayHandle::new(
+                NonNull::new(self.conn.get_raw_xcb_connection()),
+                self.screen as i32,
+            )),
+            RawWindowHandle::Xcb(XcbWindowHandle::new(
+                NonZeroU32::new(self.window).expect("X11 window ids to not be 0"),
+            )),
+        )
+    }
+
+    /// Hidden through the IPC socket, unmapped as there is no sliding off
+    /// the screen here
+    pub fn set_hidden(&self, hidden: bool) {
+        let result = if hidden {
+            self.conn.unmap_window(self.window).map(|_| ())
+        } else {
+            self.conn.map_window(self.window).map(|_| ())
+        };
+        if let Err(e) = result.and_then(|_| self.conn.flush()) {
+            log::error!(
+                "Could not {} the X11 window: {e}",
+                if hidden { "unmap" } else { "map" }
+            );
+        }
+    }
+
+    fn configure(&self) -> RenderCommand {
+        RenderCommand::Display(DisplayMessage::Configure {
+            width: self.width.load(Ordering::Relaxed),
+            height: self.height,
+        })
+    }
+
+    /// A pointer position in the window with x along the bar
+    fn bar_point(&self, x: i16, y: i16) -> Vec2 {
+        let (x, y) = self.position.along((x, y));
+        Vec2 {
+            x: x as f32,
+            y: y as f32,
+        }
+    }
+
+    /// Takes the pointer events over to the state and the size over to
+    /// the renderer, which is drawn again on every expose as X11 keeps no
+    /// copy of the window. Keys are left to the window manager, a dock
+    /// doesn't get the focus
+    pub fn run_event_loop(
+        &self,
+        render_sender: Sender<RenderCommand>,
+        state_sender: Sender<Message>,
+    ) -> Result<(), X11Error> {
+        render_sender
+            .blocking_send(self.configure())
+            .expect("The renderer to be running");
+        loop {
+            let message = match self.conn.wait_for_event()? {
+                Event::Expose(event) if event.count == 0 => {
+                    let _ = render_sender.blocking_send(self.configure());
+                    continue;
+                }
+                Event::ConfigureNotify(event) => {
+                    let (width, _) = self.position.along((event.width, event.height));
+                    self.width.store(width as u32, Ordering::Relaxed);
+                    let _ = render_sender.blocking_send(self.configure());
+                    continue;
+                }
+                Event::EnterNotify(event) => Message::PointerMotion {
+                    pos: self.bar_point(event.event_x, event.event_y),
+                },
+                Event::MotionNotify(event) => Message::PointerMotion {
+                    pos: self.bar_point(event.event_x, event.event_y),
+                },
+                Event::LeaveNotify(_) => Message::PointerLeave,
+                // The wheel comes as buttons 4 and 5
+                Event::ButtonPress(event) if matches!(event.detail, 4 | 5) => {
+                    let discrete = if event.detail == 4 { -1 } else { 1 };
+                    Message::PointerScroll {
+                        pos: self.bar_point(event.event_x, event.event_y),
+                        delta: discrete as f32 * NOTCH,
+                        discrete,
+                    }
+                }
+                Event::ButtonPress(event) => match button(event.detail) {
+                    Some(button) => Message::PointerPress {
+                        pos: self.bar_point(event.event_x, event.event_y),
+                        button,
+                    },
+                    None => continue,
+                },
+                Event::ButtonRelease(event) => match button(event.detail) {
+                    Some(button) => Message::PointerRelease {
+                        pos: self.bar_point(event.event_x, event.event_y),
+                        button,
+                    },
+                    None => continue,
+                },
+                _ => continue,
+            };
+            if state_sender.blocking_send(message).is_err() {
+                log::error!("The state is gone, could not send it a message");
+            }
+        }
+    }
+}
+
+/// The Linux input event code of a core protocol button
+fn button(detail: u8) -> Option<u32> {
+    match detail {
+        1 => Some(BTN_LEFT),
+        2 => Some(BTN_MIDDLE),
+        3 => Some(BTN_RIGHT),
+        _ => None,
+    }
+}